@@ -1,7 +1,16 @@
+#[cfg(feature = "felib")]
 use std::env;
+#[cfg(feature = "felib")]
 use std::path::PathBuf;
 
 fn main() {
+    // Without the FELib only the simulated boards are available
+    #[cfg(feature = "felib")]
+    felib();
+}
+
+#[cfg(feature = "felib")]
+fn felib() {
     // Tell cargo to look for shared libraries in the specified directory
     println!("cargo:rustc-link-search=/usr/local/lib");
 
@@ -0,0 +1,202 @@
+use crate::NotificationSettings;
+use serde::Deserialize;
+use std::io::{stdout, Write};
+use time::{macros::format_description, OffsetDateTime};
+
+/// How urgently an alarm needs the operator's attention.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Severity::Info => "INFO",
+            Severity::Warning => "WARN",
+            Severity::Critical => "CRIT",
+        }
+    }
+}
+
+/// Which part of the system raised an alarm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlarmKind {
+    Hardware,
+    DataQuality,
+    Disk,
+    Rate,
+}
+
+impl AlarmKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            AlarmKind::Hardware => "Hardware",
+            AlarmKind::DataQuality => "Data quality",
+            AlarmKind::Disk => "Disk",
+            AlarmKind::Rate => "Rate",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Alarm {
+    pub kind: AlarmKind,
+    pub severity: Severity,
+    pub message: String,
+    /// Run during which the alarm was (last) raised
+    pub run_num: usize,
+    /// Time the alarm was first raised
+    pub raised: OffsetDateTime,
+    /// Number of times the alarm was raised while still unacknowledged
+    pub count: usize,
+    pub acknowledged: bool,
+}
+
+impl Alarm {
+    /// One-line description used in the alarm list and the run summary.
+    pub fn summary(&self) -> String {
+        let time = self
+            .raised
+            .format(format_description!("[hour]:[minute]:[second]"))
+            .unwrap_or_default();
+        let mut line = format!(
+            "[{}] {} {}: {}",
+            time,
+            self.severity.label(),
+            self.kind.label(),
+            self.message
+        );
+        if self.count > 1 {
+            line.push_str(&format!(" (x{})", self.count));
+        }
+        line
+    }
+}
+
+/// Accumulates alarms over the lifetime of the DAQ so they can be reviewed
+/// and acknowledged without blocking data taking.
+#[derive(Debug, Default)]
+pub struct AlarmCenter {
+    alarms: Vec<Alarm>,
+    selected: usize,
+    notifications: NotificationSettings,
+}
+
+impl AlarmCenter {
+    pub fn new(notifications: NotificationSettings) -> Self {
+        Self {
+            notifications,
+            ..Default::default()
+        }
+    }
+
+    /// Raise an alarm. Repeats of an identical unacknowledged alarm only bump
+    /// its count so a persisting condition doesn't flood the list.
+    pub fn raise(&mut self, kind: AlarmKind, severity: Severity, message: String, run_num: usize) {
+        if let Some(alarm) = self.alarms.iter_mut().find(|a| {
+            !a.acknowledged && a.kind == kind && a.severity == severity && a.message == message
+        }) {
+            alarm.count += 1;
+            alarm.run_num = run_num;
+            return;
+        }
+
+        log::warn!("{} alarm ({}): {}", kind.label(), severity.label(), message);
+        self.notify(kind, severity, &message);
+        self.alarms.push(Alarm {
+            kind,
+            severity,
+            message,
+            run_num,
+            raised: OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc()),
+            count: 1,
+            acknowledged: false,
+        });
+        // Keep the newest alarm in view
+        self.selected = self.alarms.len() - 1;
+    }
+
+    /// Ring the terminal bell and/or show a desktop notification for a new
+    /// alarm, depending on which severities are configured for each.
+    fn notify(&self, kind: AlarmKind, severity: Severity, message: &str) {
+        if self.notifications.bell.contains(&severity) {
+            let mut out = stdout();
+            let _ = out.write_all(b"\x07").and_then(|_| out.flush());
+        }
+        #[cfg(feature = "desktop-notifications")]
+        if self.notifications.desktop.contains(&severity) {
+            let summary = format!("cliq {} alarm: {}", severity.label(), kind.label());
+            let body = message.to_string();
+            // Talking to the notification daemon can block, keep it off the UI thread
+            std::thread::spawn(move || {
+                if let Err(e) = notify_rust::Notification::new()
+                    .appname("cliq")
+                    .summary(&summary)
+                    .body(&body)
+                    .show()
+                {
+                    log::warn!("Failed to show desktop notification: {}", e);
+                }
+            });
+        }
+        #[cfg(not(feature = "desktop-notifications"))]
+        let _ = (kind, message);
+    }
+
+    pub fn alarms(&self) -> &[Alarm] {
+        &self.alarms
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Select the alarm at `index`, ignoring indices past the end of the list.
+    pub fn select(&mut self, index: usize) {
+        if index < self.alarms.len() {
+            self.selected = index;
+        }
+    }
+
+    pub fn select_next(&mut self) {
+        if self.selected + 1 < self.alarms.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn acknowledge_selected(&mut self) {
+        if let Some(alarm) = self.alarms.get_mut(self.selected) {
+            alarm.acknowledged = true;
+        }
+    }
+
+    pub fn acknowledge_all(&mut self) {
+        for alarm in self.alarms.iter_mut() {
+            alarm.acknowledged = true;
+        }
+    }
+
+    /// Number of alarms still awaiting acknowledgement.
+    pub fn unacknowledged(&self) -> usize {
+        self.alarms.iter().filter(|a| !a.acknowledged).count()
+    }
+
+    /// Whether any critical alarm is still awaiting acknowledgement.
+    pub fn has_unacknowledged_critical(&self) -> bool {
+        self.alarms
+            .iter()
+            .any(|a| !a.acknowledged && a.severity == Severity::Critical)
+    }
+
+    /// Alarms raised during the given run, for the run summary.
+    pub fn for_run(&self, run_num: usize) -> impl Iterator<Item = &Alarm> {
+        self.alarms.iter().filter(move |a| a.run_num == run_num)
+    }
+}
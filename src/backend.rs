@@ -0,0 +1,59 @@
+use crate::{EndpointStats, EventWrapper, FELibReturn, RawBlock};
+use std::sync::OnceLock;
+
+/// The digitizer calls cliq makes, answered by the CAEN FELib or by the
+/// simulated boards. Handles are FELib's raw `u64` handles.
+pub trait Backend: Send + Sync {
+    /// Short name for the log, e.g. "FELib"
+    fn name(&self) -> &'static str;
+    fn lib_info(&self) -> Result<String, FELibReturn>;
+    fn lib_version(&self) -> Result<String, FELibReturn>;
+    /// Details of the calling thread's last error, see `felib::last_error`
+    fn last_error(&self) -> Result<String, FELibReturn>;
+    fn devices_discovery(&self) -> Result<String, FELibReturn>;
+    fn open(&self, url: &str) -> Result<u64, FELibReturn>;
+    fn close(&self, handle: u64) -> Result<(), FELibReturn>;
+    fn impl_lib_version(&self, handle: u64) -> Result<String, FELibReturn>;
+    fn device_tree(&self, handle: u64) -> Result<String, FELibReturn>;
+    fn get_value(&self, handle: u64, path: &str) -> Result<String, FELibReturn>;
+    fn set_value(&self, handle: u64, path: &str, value: &str) -> Result<(), FELibReturn>;
+    fn send_command(&self, handle: u64, path: &str) -> Result<(), FELibReturn>;
+    fn set_read_data_format(&self, handle: u64, format: &str) -> Result<(), FELibReturn>;
+    /// Read one event, see `felib::read_data`
+    fn read_data(
+        &self,
+        handle: u64,
+        timeout_ms: i32,
+        data: &mut EventWrapper,
+        fine_timestamp: bool,
+    ) -> FELibReturn;
+    /// Read one DPP-PSD hit, see `felib::read_dpp_psd`
+    fn read_dpp_psd(&self, handle: u64, timeout_ms: i32, data: &mut EventWrapper) -> FELibReturn;
+    /// Read one block of the raw endpoint, see `felib::read_raw`
+    fn read_raw(&self, handle: u64, timeout_ms: i32, block: &mut RawBlock) -> FELibReturn;
+    /// Read the stats endpoint's counters, see `felib::read_stats`
+    fn read_stats(&self, handle: u64, timeout_ms: i32, stats: &mut EndpointStats) -> FELibReturn;
+    /// Wait up to `timeout_ms` for an event to read, see `felib::has_data`
+    fn has_data(&self, handle: u64, timeout_ms: i32) -> Result<(), FELibReturn>;
+    fn get_handle(&self, handle: u64, path: &str) -> Result<u64, FELibReturn>;
+    fn get_parent_handle(&self, handle: u64, path: &str) -> Result<u64, FELibReturn>;
+}
+
+static BACKEND: OnceLock<Box<dyn Backend>> = OnceLock::new();
+
+/// Answer every `felib` call with `backend` from now on. Only the first
+/// call has an effect and it has to come before any board is opened.
+pub fn set_backend(backend: Box<dyn Backend>) {
+    let name = backend.name();
+    if BACKEND.set(backend).is_err() {
+        log::warn!("Digitizer backend already chosen, ignoring {}", name);
+    }
+}
+
+/// The backend the `felib` wrappers use, the CAEN FELib unless another one
+/// was set.
+pub fn backend() -> &'static dyn Backend {
+    BACKEND
+        .get_or_init(|| Box::new(crate::felib::FELib))
+        .as_ref()
+}
@@ -0,0 +1,187 @@
+use crate::{delta_decode, delta_encode, read_str_attr, Compressed, Compressor, DatasetParams};
+use anyhow::{anyhow, Result};
+use hdf5::{filters::blosc_set_nthreads, File};
+use ndarray::{s, Array3};
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// Codecs and levels tried, roughly from fastest to smallest
+const CODECS: &[(Compressor, u8)] = &[
+    (Compressor::None, 0),
+    (Compressor::Lz4, 1),
+    (Compressor::Lz4, 5),
+    (Compressor::Lz4, 9),
+    (Compressor::Zstd, 1),
+    (Compressor::Zstd, 3),
+    (Compressor::Zstd, 5),
+    (Compressor::Zstd, 9),
+];
+
+/// Size and speed of one codec on a sample of a data file's waveforms.
+#[derive(Debug, Clone)]
+pub struct CompressionResult {
+    pub compressor: Compressor,
+    pub level: u8,
+    /// Waveforms stored as first differences, see `delta_encode`
+    pub delta: bool,
+    /// Size on disk in bytes
+    pub size: u64,
+    /// Uncompressed size over size on disk
+    pub ratio: f64,
+    /// Uncompressed MB/s written and read back
+    pub write_rate: f64,
+    pub read_rate: f64,
+}
+
+impl std::fmt::Display for CompressionResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let encoding = if self.delta { "delta" } else { "raw" };
+        write!(
+            f,
+            "{:<6} {:>5} {:<8} {:>10.2} {:>7.2} {:>12.1} {:>12.1}",
+            format!("{:?}", self.compressor),
+            self.level,
+            encoding,
+            self.size as f64 / (1024.0 * 1024.0),
+            self.ratio,
+            self.write_rate,
+            self.read_rate
+        )
+    }
+}
+
+impl CompressionResult {
+    /// Column names matching `Display`.
+    pub fn header() -> String {
+        format!(
+            "{:<6} {:>5} {:<8} {:>10} {:>7} {:>12} {:>12}",
+            "codec", "level", "encoding", "size MB", "ratio", "write MB/s", "read MB/s"
+        )
+    }
+}
+
+/// Up to `events` events of the waveforms of each board of a data file,
+/// decoded if the file is delta encoded, with the chunk shape they were
+/// stored with.
+fn sample_waveforms(path: &Path, events: usize) -> Result<Vec<(Array3<u16>, Vec<usize>)>> {
+    let file = File::open(path)?;
+    let delta = read_str_attr(&file, "waveform_encoding").is_ok_and(|e| e == "delta");
+    let mut groups = Vec::new();
+    while let Ok(group) = file.group(&format!("board{}", groups.len())) {
+        groups.push(group);
+    }
+    // Datasets are allocated for the most events a file can hold, only the
+    // saved ones are worth compressing
+    let saved: usize = file.attr("saved_events")?.read_scalar()?;
+    let per_board = saved / groups.len().max(1);
+    let mut samples = Vec::new();
+    for group in groups {
+        let Ok(dataset) = group
+            .dataset("waveforms")
+            .or_else(|_| group.dataset("waveforms_decimated"))
+        else {
+            continue;
+        };
+        let rows = events.min(per_board).min(dataset.shape()[0]);
+        if rows == 0 {
+            continue;
+        }
+        let mut waveforms: Array3<u16> = dataset.read_slice(s![0..rows, .., ..])?;
+        if delta {
+            waveforms.outer_iter_mut().for_each(delta_decode);
+        }
+        let chunk = dataset
+            .chunk()
+            .unwrap_or_else(|| waveforms.shape().to_vec());
+        samples.push((waveforms, chunk));
+    }
+    if samples.is_empty() {
+        return Err(anyhow!("{} has no saved waveforms", path.display()));
+    }
+    Ok(samples)
+}
+
+/// Store up to `events` events of each board of a data file with every codec
+/// in `CODECS`, raw and delta encoded, in a scratch file and measure the
+/// size on disk and the write and read speed of each.
+pub fn bench_compression(
+    path: &Path,
+    events: usize,
+    blosc_threads: u8,
+) -> Result<Vec<CompressionResult>> {
+    let raw = sample_waveforms(path, events)?;
+    let mut delta = raw.clone();
+    for (waveforms, _) in delta.iter_mut() {
+        waveforms.outer_iter_mut().for_each(delta_encode);
+    }
+    let bytes: usize = raw.iter().map(|(waveforms, _)| waveforms.len() * 2).sum();
+    let mb = bytes as f64 / (1024.0 * 1024.0);
+    blosc_set_nthreads(blosc_threads);
+
+    let scratch = std::env::temp_dir().join(format!("cliq-bench-{}.h5", std::process::id()));
+    let mut results = Vec::new();
+    for (is_delta, samples) in [(false, &raw), (true, &delta)] {
+        for &(compressor, level) in CODECS {
+            let measured = bench_codec(&scratch, samples, compressor, level);
+            let _ = fs::remove_file(&scratch);
+            let (size, write_time, read_time) = measured?;
+            results.push(CompressionResult {
+                compressor,
+                level,
+                delta: is_delta,
+                size,
+                ratio: bytes as f64 / size.max(1) as f64,
+                write_rate: mb / write_time.as_secs_f64(),
+                read_rate: mb / read_time.as_secs_f64(),
+            });
+        }
+    }
+    Ok(results)
+}
+
+/// Write `samples` to `scratch` with one codec and read them back, giving
+/// the size on disk and the write and read times.
+fn bench_codec(
+    scratch: &Path,
+    samples: &[(Array3<u16>, Vec<usize>)],
+    compressor: Compressor,
+    level: u8,
+) -> Result<(u64, Duration, Duration)> {
+    let start = Instant::now();
+    let mut size = 0;
+    {
+        let file = File::create(scratch)?;
+        for (board, (waveforms, chunk)) in samples.iter().enumerate() {
+            let (events, channels, n_samples) = waveforms.dim();
+            let params = DatasetParams {
+                buffer_capacity: events,
+                chunk_events: chunk[0].min(events),
+                chunk_channels: Some(chunk[1]),
+                chunk_samples: Some(chunk[2]),
+                compressor,
+                compression_level: level,
+            };
+            let dataset = file
+                .new_dataset::<u16>()
+                .shape(waveforms.dim())
+                .compressed(&params)
+                .chunk(params.waveform_chunk(channels, n_samples))
+                .create(format!("waveforms{}", board).as_str())?;
+            dataset.write(waveforms)?;
+            size += dataset.storage_size();
+        }
+        // closed at the end of the block, so the write time includes it
+        file.flush()?;
+    }
+    let write_time = start.elapsed();
+
+    let start = Instant::now();
+    let file = File::open(scratch)?;
+    for board in 0..samples.len() {
+        let _: Array3<u16> = file.dataset(&format!("waveforms{}", board))?.read()?;
+    }
+    Ok((size, write_time, start.elapsed()))
+}
@@ -0,0 +1,97 @@
+use anyhow::{anyhow, Result};
+use ndarray::Array2;
+use std::{fs, path::Path};
+
+/// Per-channel gain and offset corrections from a calibration file. A
+/// corrected sample is `gain * sample + offset`.
+#[derive(Debug, Clone)]
+pub struct Calibration {
+    /// Where the constants were read from
+    pub file: String,
+    /// SHA-256 of the calibration file, to tell calibrations apart
+    pub sha256: String,
+    /// `(gain, offset)` of each board's channels, by board and channel
+    boards: Vec<Vec<(f64, f64)>>,
+}
+
+impl Calibration {
+    /// Read a calibration file with one `board,channel,gain,offset` line per
+    /// channel. Blank lines and lines starting with `#` are skipped, channels
+    /// not listed are left as they are.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let mut boards: Vec<Vec<(f64, f64)>> = Vec::new();
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let [board, channel, gain, offset] = fields[..] else {
+                return Err(anyhow!("malformed calibration line: {}", line));
+            };
+            let malformed = || anyhow!("malformed calibration line: {}", line);
+            let board: usize = board.parse().map_err(|_| malformed())?;
+            let channel: usize = channel.parse().map_err(|_| malformed())?;
+            let gain: f64 = gain.parse().map_err(|_| malformed())?;
+            let offset: f64 = offset.parse().map_err(|_| malformed())?;
+
+            if boards.len() <= board {
+                boards.resize(board + 1, Vec::new());
+            }
+            let channels = &mut boards[board];
+            if channels.len() <= channel {
+                channels.resize(channel + 1, (1.0, 0.0));
+            }
+            channels[channel] = (gain, offset);
+        }
+
+        Ok(Calibration {
+            file: path.display().to_string(),
+            sha256: crate::sha256_file(path)?,
+            boards,
+        })
+    }
+
+    /// `(gain, offset)` of a channel, `(1.0, 0.0)` if it isn't calibrated.
+    pub fn constants(&self, board: usize, channel: usize) -> (f64, f64) {
+        self.boards
+            .get(board)
+            .and_then(|channels| channels.get(channel))
+            .copied()
+            .unwrap_or((1.0, 0.0))
+    }
+
+    /// Constants of a board's saved `channels` as an array of gain and offset,
+    /// one row per channel.
+    pub fn board_constants(&self, board: usize, channels: &[usize]) -> Array2<f64> {
+        Array2::from_shape_fn((channels.len(), 2), |(row, i)| {
+            let (gain, offset) = self.constants(board, channels[row]);
+            if i == 0 {
+                gain
+            } else {
+                offset
+            }
+        })
+    }
+
+    /// Correct a board's waveforms in place, one row per entry of `channels`.
+    /// Corrected samples are rounded and clamped to the ADC range.
+    pub fn apply(&self, board: usize, channels: &[usize], waveforms: &mut Array2<u16>) {
+        for (&ch, mut channel) in channels.iter().zip(waveforms.rows_mut()) {
+            let (gain, offset) = self.constants(board, ch);
+            channel.mapv_inplace(|x| {
+                (gain * x as f64 + offset)
+                    .round()
+                    .clamp(0.0, u16::MAX as f64) as u16
+            });
+        }
+    }
+
+    /// Correct derived per-channel quantities, e.g. an average waveform.
+    pub fn apply_f64(&self, board: usize, channels: &[usize], values: &mut Array2<f64>) {
+        for (&ch, mut channel) in channels.iter().zip(values.rows_mut()) {
+            let (gain, offset) = self.constants(board, ch);
+            channel.mapv_inplace(|x| gain * x + offset);
+        }
+    }
+}
@@ -1,30 +1,647 @@
+use crate::Severity;
 use confique::Config;
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::{collections::HashMap, path::Path, str::FromStr};
+use time::Time;
 
 #[derive(Config, Debug, Clone)]
+#[config(validate = Self::validate)]
 pub struct Conf {
     #[config(nested)]
     pub run_settings: RunSettings,
     #[config(nested)]
+    pub campaign: CampaignSettings,
+    #[config(nested)]
     pub board_settings: BoardSettings,
     #[config(nested)]
     pub sync_settings: SyncSettings,
     #[config(nested)]
     pub zs_settings: ZsSettings,
+    #[config(nested)]
+    pub roi_settings: RoiSettings,
+    #[config(nested)]
+    pub multires_settings: MultiResSettings,
+    #[config(nested)]
+    pub dst: DstSettings,
+    #[config(nested)]
+    pub baseline: BaselineSettings,
+    #[config(nested)]
+    pub calibration: CalibrationSettings,
+    #[config(nested)]
+    pub calibration_run: CalibrationRunSettings,
+    #[config(nested)]
+    pub ui: UiSettings,
+    #[config(nested)]
+    pub notifications: NotificationSettings,
+    #[config(nested)]
+    pub health: HealthSettings,
+    #[config(nested)]
+    pub retention: RetentionSettings,
+    #[config(nested)]
+    pub mirror: MirrorSettings,
+    #[config(nested)]
+    pub upload: UploadSettings,
+    #[config(nested)]
+    pub simulation: SimulationSettings,
+}
+
+impl Conf {
+    /// Readout timeouts of board `board`, its overrides falling back to
+    /// `run_settings`.
+    pub fn read_policy(&self, board: usize) -> ReadPolicy {
+        let run = &self.run_settings;
+        let overrides = self.board_settings.boards.get(board);
+        ReadPolicy {
+            timeout_min: overrides
+                .and_then(|b| b.read_timeout_min)
+                .unwrap_or(run.read_timeout_min),
+            timeout_max: overrides
+                .and_then(|b| b.read_timeout_max)
+                .unwrap_or(run.read_timeout_max),
+            strategy: run.read_strategy,
+            stall_timeouts: overrides
+                .and_then(|b| b.stall_timeouts)
+                .or(run.stall_timeouts),
+        }
+    }
+
+    /// Board settings are matched to boards by position, so every per-board
+    /// list must have one entry per board. Each board's start source must also
+    /// fit the way runs are started, and the arm order name every board.
+    fn validate(&self) -> Result<(), String> {
+        let num_boards = self.run_settings.boards.len();
+        let sections = [
+            ("board_settings.boards", self.board_settings.boards.len()),
+            ("sync_settings.boards", self.sync_settings.boards.len()),
+        ];
+        for (section, len) in sections {
+            if len != num_boards {
+                return Err(format!(
+                    "{} has {} entries but run_settings.boards lists {} boards",
+                    section, len, num_boards
+                ));
+            }
+        }
+
+        if let Some(order) = &self.sync_settings.arm_order {
+            let mut sorted = order.clone();
+            sorted.sort_unstable();
+            if !sorted.iter().copied().eq(0..num_boards) {
+                return Err(format!(
+                    "sync_settings.arm_order must list every board index from 0 to {} once",
+                    num_boards.saturating_sub(1)
+                ));
+            }
+        }
+
+        if self.run_settings.endpoint == EndpointType::DppPsd
+            && self.sync_settings.event_building != EventBuilding::Independent
+        {
+            return Err(String::from(
+                "DppPsd hits have no trigger IDs, they need Independent event_building",
+            ));
+        }
+        if self.sync_settings.event_building == EventBuilding::Independent
+            && self.sync_settings.offset_events > 0
+        {
+            return Err(String::from(
+                "sync_settings.offset_events needs shared triggers, it can't be used with Independent event_building",
+            ));
+        }
+
+        let extra = &self.run_settings.extra_endpoints;
+        if let Some((i, endpoint)) = extra
+            .iter()
+            .enumerate()
+            .find(|(i, endpoint)| extra[..*i].contains(endpoint))
+        {
+            return Err(format!(
+                "run_settings.extra_endpoints[{}] lists {:?} a second time",
+                i, endpoint
+            ));
+        }
+
+        if let Some(dsp_threads) = self.run_settings.dsp_threads {
+            if dsp_threads == 0 {
+                return Err(String::from("run_settings.dsp_threads must be at least 1"));
+            }
+        }
+
+        let start_mode = self.sync_settings.start_mode;
+        for (i, board) in self.sync_settings.boards.iter().enumerate() {
+            if !start_mode.accepts(&board.start_source, i == 0) {
+                return Err(format!(
+                    "sync_settings.boards[{}] start_source \"{}\" can't start with start_mode {:?}",
+                    i, board.start_source, start_mode
+                ));
+            }
+        }
+
+        let roi = &self.roi_settings;
+        if roi.enabled
+            && (roi.window_len() == 0 || roi.window_len() > self.board_settings.common.record_len)
+        {
+            return Err(format!(
+                "roi_settings pre_samples + post_samples must be between 1 and record_len ({})",
+                self.board_settings.common.record_len
+            ));
+        }
+        let record_len = self.board_settings.common.record_len;
+        if self.baseline.enabled
+            && !(1..=record_len).contains(&self.baseline.samples(&self.board_settings.common))
+        {
+            return Err(format!(
+                "baseline.samples (pre_trig_len if unset) must be between 1 and record_len ({})",
+                record_len
+            ));
+        }
+        if self.zs_settings.zs_audit_prescale > 0 && self.zs_settings.zs_mode == ZsMode::Board {
+            return Err(String::from(
+                "zs_settings.zs_audit_prescale needs software zero suppression, the boards' firmware never reads out the unsuppressed waveforms",
+            ));
+        }
+        if self.zs_settings.zs_batch_size == 0 {
+            return Err(String::from("zs_settings.zs_batch_size must be at least 1"));
+        }
+        if let Some(channel) = self
+            .zs_settings
+            .zs_thresholds
+            .iter()
+            .flat_map(|thresholds| thresholds.keys())
+            .find(|channel| channel.parse::<u32>().is_err())
+        {
+            return Err(format!(
+                "zs_settings.zs_thresholds has {:?}, keys must be channel numbers",
+                channel
+            ));
+        }
+        if self.multires_settings.enabled && self.multires_settings.decimation == 0 {
+            return Err(String::from(
+                "multires_settings.decimation must be at least 1",
+            ));
+        }
+        if self.ui.rate_history_minutes == 0 {
+            return Err(String::from("ui.rate_history_minutes must be at least 1"));
+        }
+        let dashboard = &self.ui.dashboard;
+        if !dashboard.heights.is_empty() && dashboard.heights.len() != dashboard.rows.len() {
+            return Err(format!(
+                "ui.dashboard.heights has {} entries but rows has {}",
+                dashboard.heights.len(),
+                dashboard.rows.len()
+            ));
+        }
+        for name in dashboard.rows.iter().flatten() {
+            if let DashboardWidget::Board(board) = name.parse()? {
+                if board >= num_boards {
+                    return Err(format!(
+                        "ui.dashboard widget \"{}\" but run_settings.boards lists {} boards",
+                        name, num_boards
+                    ));
+                }
+            }
+        }
+        if let Some(time) = &self.calibration_run.time {
+            parse_time_of_day(time)?;
+        }
+        if self
+            .run_settings
+            .sw_trigger_rate
+            .is_some_and(|rate| rate <= 0.0)
+        {
+            return Err(String::from("run_settings.sw_trigger_rate must be above 0"));
+        }
+        let run = &self.run_settings;
+        if run.buffer_capacity == 0
+            || [run.chunk_events, run.chunk_channels, run.chunk_samples].contains(&Some(0))
+        {
+            return Err(String::from(
+                "run_settings.buffer_capacity and the chunk sizes must be at least 1",
+            ));
+        }
+        if run.chunk_events.unwrap_or(run.buffer_capacity) > run.max_events_per_board {
+            return Err(String::from(
+                "run_settings.chunk_events (or buffer_capacity) can't exceed max_events_per_board",
+            ));
+        }
+        if [run.rollover_minutes, run.rollover_size_mb].contains(&Some(0)) {
+            return Err(String::from(
+                "run_settings.rollover_minutes and rollover_size_mb must be at least 1",
+            ));
+        }
+        if self.health.temp_warning > self.health.temp_limit {
+            return Err(String::from(
+                "health.temp_warning can't be above health.temp_limit",
+            ));
+        }
+        for board in 0..num_boards {
+            let policy = self.read_policy(board);
+            if policy.timeout_min == 0
+                || policy.timeout_min > policy.timeout_max
+                || policy.timeout_max > i32::MAX as u64
+            {
+                return Err(format!(
+                    "board {}: read_timeout_min must be at least 1 and at most read_timeout_max",
+                    board
+                ));
+            }
+            if policy.stall_timeouts == Some(0) {
+                return Err(format!(
+                    "board {}: stall_timeouts must be at least 1",
+                    board
+                ));
+            }
+        }
+        crate::validate_dir_layout(&self.run_settings.dir_layout)
+            .map_err(|e| format!("run_settings.{}", e))?;
+        if let Some(mode) = &self.run_settings.dir_mode {
+            crate::parse_dir_mode(mode).map_err(|e| format!("run_settings.{}", e))?;
+        }
+        if self.upload.endpoint.is_some() {
+            if self.upload.bucket.is_empty() {
+                return Err(String::from("upload.bucket must be set to upload"));
+            }
+            if self.upload.part_size_mb < 5 {
+                return Err(String::from("upload.part_size_mb must be at least 5"));
+            }
+            if self.upload.credentials().is_none() {
+                return Err(String::from(
+                    "upload needs access_key and secret_key, or AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY",
+                ));
+            }
+        }
+        let sim = &self.simulation;
+        if sim.event_rate <= 0.0 || sim.decay_time <= 0.0 || sim.rise_time < 0.0 {
+            return Err(String::from(
+                "simulation.event_rate and decay_time must be above 0 and rise_time not negative",
+            ));
+        }
+        if sim.rise_time == sim.decay_time {
+            return Err(String::from(
+                "simulation.rise_time and decay_time must differ",
+            ));
+        }
+        if sim.amplitude_min < 0.0 || sim.amplitude_min > sim.amplitude_max {
+            return Err(String::from(
+                "simulation.amplitude_min must be between 0 and amplitude_max",
+            ));
+        }
+        if self.run_settings.max_subruns == Some(0) || self.run_settings.max_run_events == Some(0) {
+            return Err(String::from(
+                "run_settings.max_subruns and max_run_events must be at least 1",
+            ));
+        }
+        Ok(())
+    }
 }
 
 #[derive(Config, Debug, Clone)]
 pub struct RunSettings {
+    /// Board URLs, or `serial:<number>` to find a board by serial number
     pub boards: Vec<String>,
     pub run_duration: u64,
     pub output_dir: String,
     pub campaign_num: usize,
+    /// Directories of a run below `output_dir`, from `{stream}`,
+    /// `{campaign}`, `{year}`, `{month}`, `{day}` and `{run}`
+    #[config(default = "camp{campaign}")]
+    pub dir_layout: String,
+    /// Octal permissions given to the directories cliq creates, e.g. `"2775"`
+    pub dir_mode: Option<String>,
+    /// What to do when a file a run would write already exists
+    #[config(default = "Error")]
+    pub existing_files: ExistingFiles,
+    /// Where built events are stored
+    #[config(default = "Hdf5")]
+    pub storage: Storage,
+    /// Ask for a comment on the run whenever one starts in the TUI
+    #[config(default = false)]
+    pub comment_prompt: bool,
+    /// JSON Lines file every finished run is appended to, relative to the
+    /// campaign root unless absolute, empty for no run log
+    #[config(default = "runlog.jsonl")]
+    pub run_log: String,
+    /// Add the SHA-256 of each of the run's files to its run log record
+    #[config(default = false)]
+    pub file_checksums: bool,
+    /// JSON Lines journal of every run's lifecycle steps, relative to the
+    /// campaign root unless absolute, empty for no journal
+    #[config(default = "runjournal.jsonl")]
+    pub run_journal: String,
     pub max_events_per_board: usize,
+    /// Also start the next subrun file after this many minutes
+    pub rollover_minutes: Option<u64>,
+    /// Also start the next subrun file once the current one is this large,
+    /// in MB
+    pub rollover_size_mb: Option<u64>,
+    /// End the run once it has filled this many subrun files and start the
+    /// next one right away
+    pub max_subruns: Option<usize>,
+    /// End the run once this many events have been built and start the next
+    /// one right away
+    pub max_run_events: Option<usize>,
     #[config(default = 5)]
     pub blosc_threads: u8,
+    /// Threads of the pool running zero suppression and the rest of the
+    /// event processing, one per core if unset
+    pub dsp_threads: Option<usize>,
     #[config(default = 2)]
     pub compression_level: u8,
+    #[config(default = "Zstd")]
+    pub compressor: Compressor,
+    /// Events buffered per board before they're written
+    #[config(default = 50)]
+    pub buffer_capacity: usize,
+    /// Events per HDF5 chunk, `buffer_capacity` if unset
+    pub chunk_events: Option<usize>,
+    /// Channels per waveform chunk, all saved channels if unset
+    pub chunk_channels: Option<usize>,
+    /// Samples per waveform chunk, the whole record if unset
+    pub chunk_samples: Option<usize>,
+    /// Store waveforms as first differences, which compress better
+    #[config(default = false)]
+    pub delta_encoding: bool,
+    /// Save a CRC32 of each event's waveforms as stored
+    #[config(default = false)]
+    pub waveform_checksums: bool,
+    /// Also store the waveforms in volts, calibrated, as floats of this
+    /// type, none if unset
+    pub volt_waveforms: Option<FloatType>,
+    /// Leave the channels zero suppression emptied out of the stored
+    /// waveforms
+    #[config(default = false)]
+    pub sparse_waveforms: bool,
+    #[config(default = 0)]
+    pub inter_run_gap: u64,
+    pub stream_name: Option<String>,
+    pub operator: Option<String>,
+    #[config(default = 1000)]
+    pub refresh_interval: u64,
+    /// Extra attempts at setting up a board's readout endpoint
+    #[config(default = 2)]
+    pub endpoint_retries: usize,
+    /// Endpoint the boards are read from, depending on their firmware
+    #[config(default = "Scope")]
+    pub endpoint: EndpointType,
+    /// Endpoints read next to `endpoint`, each by its own thread per board
+    #[config(default = [])]
+    pub extra_endpoints: Vec<ExtraEndpoint>,
+    /// Also save each event's timestamp, trigger ID, flags, board fail and
+    /// size as one `event_header` table per board, fast to scan offline
+    #[config(default = false)]
+    pub event_header: bool,
+    /// Read and save the timestamp in µs with its fine part, for firmware
+    /// that provides it
+    #[config(default = false)]
+    pub fine_timestamp: bool,
+    /// Trigger rate per board in Hz, for the data rate and disk usage estimates
+    pub expected_trigger_rate: Option<f64>,
+    /// Software triggers per second sent to every board during runs, none
+    /// for no periodic software trigger
+    pub sw_trigger_rate: Option<f64>,
+    /// Bounds in ms of the read timeout, which follows each board's trigger
+    /// rate. The upper bound also caps how long a stop waits for a read
+    #[config(default = 10)]
+    pub read_timeout_min: u64,
+    #[config(default = 500)]
+    pub read_timeout_max: u64,
+    /// How the readout waits for events
+    #[config(default = "ReadData")]
+    pub read_strategy: ReadStrategy,
+    /// Consecutive empty reads after which a board counts as stalled, none
+    /// to wait forever
+    pub stall_timeouts: Option<usize>,
+}
+
+impl RunSettings {
+    /// `stream_name`, or the config file's name without extension.
+    pub fn stream_name(&self, config_file: &str) -> String {
+        self.stream_name.clone().unwrap_or_else(|| {
+            Path::new(config_file)
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_else(|| config_file.to_string())
+        })
+    }
+}
+
+#[derive(Config, Debug, Clone)]
+pub struct UiSettings {
+    #[config(default = "Default")]
+    pub theme: ThemeName,
+    #[config(default = "English")]
+    pub language: Language,
+    /// Minutes of event rate history shown in the run stats panel
+    #[config(default = 10)]
+    pub rate_history_minutes: usize,
+    #[config(nested)]
+    pub dashboard: DashboardSettings,
+}
+
+/// Arrangement of the main screen's panels, as rows of named widgets.
+#[derive(Config, Debug, Clone)]
+pub struct DashboardSettings {
+    /// Widgets of each row from top to bottom, side by side within a row
+    #[config(default = [["run_stats"], ["boards"], ["alarms"]])]
+    pub rows: Vec<Vec<String>>,
+    /// Height of each row in percent of the screen, empty for equal heights
+    #[config(default = [35, 40, 25])]
+    pub heights: Vec<u16>,
+}
+
+impl DashboardSettings {
+    /// The rows' widgets. Names are checked when the config is loaded, so
+    /// unknown ones are skipped.
+    pub fn widgets(&self) -> Vec<Vec<DashboardWidget>> {
+        self.rows
+            .iter()
+            .map(|row| row.iter().filter_map(|name| name.parse().ok()).collect())
+            .collect()
+    }
+}
+
+/// A panel that can be placed on the dashboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DashboardWidget {
+    /// `run_stats`: Run status, rates and buffer usage
+    RunStats,
+    /// `boards`: Every board's panel side by side
+    Boards,
+    /// `board<N>`: The panel of board `N` on its own
+    Board(usize),
+    /// `alarms`: The alarm list
+    Alarms,
+    /// `system`: CPU, memory and write rate of the DAQ process
+    System,
+}
+
+impl FromStr for DashboardWidget {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "run_stats" => Ok(DashboardWidget::RunStats),
+            "boards" => Ok(DashboardWidget::Boards),
+            "alarms" => Ok(DashboardWidget::Alarms),
+            "system" => Ok(DashboardWidget::System),
+            _ => name
+                .strip_prefix("board")
+                .and_then(|board| board.parse().ok())
+                .map(DashboardWidget::Board)
+                .ok_or_else(|| format!("ui.dashboard has unknown widget \"{}\"", name)),
+        }
+    }
+}
+
+#[derive(Config, Debug, Clone, Default)]
+pub struct NotificationSettings {
+    /// Alarm severities that ring the terminal bell
+    #[config(default = [])]
+    pub bell: Vec<Severity>,
+    /// Alarm severities that show a desktop notification
+    #[config(default = [])]
+    pub desktop: Vec<Severity>,
+}
+
+/// Limits of the board health checks made during a run.
+#[derive(Config, Debug, Clone)]
+pub struct HealthSettings {
+    /// Hottest ADC temperature in °C that raises a warning
+    #[config(default = 75.0)]
+    pub temp_warning: f64,
+    /// Hottest ADC temperature in °C that raises a critical alarm and a
+    /// popup
+    #[config(default = 85.0)]
+    pub temp_limit: f64,
+    /// `ErrorFlags` bits that raise a critical alarm and a popup, all if
+    /// unset
+    pub error_flags_mask: Option<u32>,
+}
+
+impl HealthSettings {
+    /// The `ErrorFlags` bits watched.
+    pub fn watched_flags(&self) -> u32 {
+        self.error_flags_mask.unwrap_or(u32::MAX)
+    }
+}
+
+#[derive(Config, Debug, Clone)]
+pub struct RetentionSettings {
+    /// Age in days after which transferred subrun files leave the DAQ host,
+    /// retention is off if unset
+    pub max_age_days: Option<u64>,
+    #[config(default = "Delete")]
+    pub action: RetentionAction,
+    /// Where archived files are moved to, required for the Archive action
+    pub archive_dir: Option<String>,
+    /// Transfer manifest in the campaign directory, in `sha256sum` format
+    #[config(default = "transfer_manifest.sha256")]
+    pub manifest: String,
+}
+
+/// Second copy of every subrun file, e.g. on NFS next to a local SSD.
+#[derive(Config, Debug, Clone)]
+pub struct MirrorSettings {
+    /// Where the copies go, with the same directories as below `output_dir`,
+    /// no mirroring if unset
+    pub dir: Option<String>,
+    #[config(default = "Background")]
+    pub mode: MirrorMode,
+    /// Compare the copy's SHA-256 with the original before it takes its name
+    #[config(default = true)]
+    pub verify: bool,
+}
+
+/// Per-channel baseline of every event, from the pre-trigger region.
+#[derive(Config, Debug, Clone)]
+pub struct BaselineSettings {
+    /// Compute the baselines and save them as the `baselines` dataset
+    #[config(default = false)]
+    pub enabled: bool,
+    /// Samples at the start of the record averaged, `pre_trig_len` if unset
+    pub samples: Option<usize>,
+    /// Subtract the baseline from the saved waveforms
+    #[config(default = false)]
+    pub subtract: bool,
+    /// Level the baseline is moved to when subtracted, so pulses going below
+    /// it aren't cut off at 0
+    #[config(default = 0)]
+    pub pedestal: u16,
+}
+
+impl BaselineSettings {
+    /// Samples the baseline is averaged over.
+    pub fn samples(&self, common: &CommonSettings) -> usize {
+        self.samples.unwrap_or(common.pre_trig_len)
+    }
+}
+
+/// Upload of closed subrun files to S3 or an S3 compatible store like MinIO.
+#[derive(Config, Debug, Clone)]
+pub struct UploadSettings {
+    /// Store URL, e.g. `https://minio.lab:9000`, no uploads if unset
+    pub endpoint: Option<String>,
+    #[config(default = "")]
+    pub bucket: String,
+    /// Prepended to each file's path below `output_dir` to give its key
+    #[config(default = "")]
+    pub prefix: String,
+    #[config(default = "us-east-1")]
+    pub region: String,
+    /// Falls back to the `AWS_ACCESS_KEY_ID` environment variable
+    pub access_key: Option<String>,
+    /// Falls back to the `AWS_SECRET_ACCESS_KEY` environment variable
+    pub secret_key: Option<String>,
+    /// Size of each part of the multipart upload in MiB, at least 5
+    #[config(default = 64)]
+    pub part_size_mb: u64,
+    /// Extra attempts at each failed request
+    #[config(default = 3)]
+    pub retries: usize,
+}
+
+impl UploadSettings {
+    /// Access and secret key from the config or the environment.
+    pub fn credentials(&self) -> Option<(String, String)> {
+        let access_key = self
+            .access_key
+            .clone()
+            .or_else(|| std::env::var("AWS_ACCESS_KEY_ID").ok())?;
+        let secret_key = self
+            .secret_key
+            .clone()
+            .or_else(|| std::env::var("AWS_SECRET_ACCESS_KEY").ok())?;
+        Some((access_key, secret_key))
+    }
+}
+
+/// Boards simulated with `--simulate` or `--training` in place of hardware.
+/// Times are in samples and amplitudes in ADC counts.
+#[derive(Config, Debug, Clone)]
+pub struct SimulationSettings {
+    /// Triggers per second on every board
+    #[config(default = 50.0)]
+    pub event_rate: f64,
+    #[config(default = 3000)]
+    pub baseline: u16,
+    /// Gaussian noise on every sample
+    #[config(default = 0.0)]
+    pub noise_rms: f64,
+    #[config(default = 100.0)]
+    pub amplitude_min: f64,
+    #[config(default = 2000.0)]
+    pub amplitude_max: f64,
+    /// Rise time constant of the pulses, 0 for a step
+    #[config(default = 0.0)]
+    pub rise_time: f64,
+    #[config(default = 10.0)]
+    pub decay_time: f64,
+    /// Pulses per event, each in a random channel
+    #[config(default = 1)]
+    pub pulses_per_event: usize,
+    /// Inject random faults as in training mode
+    #[config(default = false)]
+    pub faults: bool,
 }
 
 #[derive(Config, Debug, Clone)]
@@ -34,6 +651,188 @@ pub struct ZsSettings {
     pub zs_edge: ZeroSuppressionEdge,
     pub zs_samples: isize,
     pub zs_window_size: usize,
+    /// Samples kept before each pulse found
+    #[config(default = 0)]
+    pub zs_pre_samples: usize,
+    /// Samples kept after each pulse found
+    #[config(default = 0)]
+    pub zs_post_samples: usize,
+    /// Whether cliq or the boards' firmware suppresses the waveforms
+    #[config(default = "Software")]
+    pub zs_mode: ZsMode,
+    /// Threshold in ADC counts of the firmware's channel suppression
+    /// (`ChSupprThr`), `zs_threshold` if unset
+    pub board_zs_threshold: Option<u32>,
+    /// Samples a channel must stay over the threshold for the firmware to
+    /// keep it (`ChSupprSamplesOverThreshold`), `zs_window_size` if unset
+    pub board_zs_samples: Option<u32>,
+    /// Also save the unsuppressed waveforms of every Nth suppressed trigger,
+    /// 0 turns the audit off
+    #[config(default = 0)]
+    pub zs_audit_prescale: usize,
+    /// Whether software zero suppression runs the channels of an event or
+    /// whole events in parallel
+    #[config(default = "Channel")]
+    pub zs_parallelism: ZsParallelism,
+    /// Most events taken from the queue and suppressed together with
+    /// `Event` parallelism
+    #[config(default = 64)]
+    pub zs_batch_size: usize,
+    /// Thresholds of single channels, by channel number, overriding
+    /// `zs_threshold`
+    pub zs_thresholds: Option<HashMap<String, f64>>,
+    /// Channels software zero suppression applies to, all if unset
+    pub zs_channels: Option<Vec<u32>>,
+    /// Channels always saved whole, e.g. the trigger paddle, even where
+    /// `zs_channels` lists them
+    pub zs_keep_channels: Option<Vec<u32>>,
+}
+
+impl ZsSettings {
+    /// `ChSupprThr` and `ChSupprSamplesOverThreshold` in board mode, `None`
+    /// with software zero suppression.
+    pub fn board_suppression(&self) -> Option<(u32, u32)> {
+        (self.zs_mode == ZsMode::Board).then(|| {
+            (
+                self.board_zs_threshold
+                    .unwrap_or(self.zs_threshold.round() as u32),
+                self.board_zs_samples.unwrap_or(self.zs_window_size as u32),
+            )
+        })
+    }
+
+    /// Threshold software zero suppression uses on board channel `ch`,
+    /// `None` for channels it leaves whole.
+    pub fn channel_threshold(&self, ch: usize) -> Option<f64> {
+        let listed = |channels: &Option<Vec<u32>>| {
+            channels
+                .as_ref()
+                .map(|channels| channels.contains(&(ch as u32)))
+        };
+        if listed(&self.zs_keep_channels) == Some(true) || listed(&self.zs_channels) == Some(false)
+        {
+            return None;
+        }
+        let threshold = self
+            .zs_thresholds
+            .as_ref()
+            .and_then(|thresholds| thresholds.get(&ch.to_string()));
+        Some(threshold.copied().unwrap_or(self.zs_threshold))
+    }
+}
+
+/// Saving only a window of each channel around its pulse. Pulses are found
+/// with the zero suppression threshold, edge, baseline and window settings.
+#[derive(Config, Debug, Clone)]
+pub struct RoiSettings {
+    #[config(default = false)]
+    pub enabled: bool,
+    /// Samples kept before the start of the pulse
+    #[config(default = 100)]
+    pub pre_samples: usize,
+    /// Samples kept from the start of the pulse on
+    #[config(default = 400)]
+    pub post_samples: usize,
+}
+
+impl RoiSettings {
+    /// Samples saved per channel.
+    pub fn window_len(&self) -> usize {
+        self.pre_samples + self.post_samples
+    }
+}
+
+/// Saving a decimated copy of every waveform and full resolution only for
+/// events passing a filter, for cheap quick-look scans of whole runs.
+#[derive(Config, Debug, Clone)]
+pub struct MultiResSettings {
+    #[config(default = false)]
+    pub enabled: bool,
+    /// Samples averaged into one sample of the decimated copy
+    #[config(default = 16)]
+    pub decimation: usize,
+    /// Events with a channel this many ADC counts away from its baseline keep
+    /// full resolution. The baseline uses `zs_samples`
+    #[config(default = 100.0)]
+    pub min_amplitude: f64,
+    /// Also keep every Nth event at full resolution, 0 for none
+    #[config(default = 0)]
+    pub prescale: usize,
+}
+
+/// Free-form description of the campaign, stored with every run so files
+/// can still be told apart long after the logbook is gone.
+#[derive(Config, Debug, Clone, Default)]
+pub struct CampaignSettings {
+    /// What the campaign is for, e.g. the source or the physics goal
+    pub description: Option<String>,
+    /// Detector or setup the boards were read out from
+    pub detector: Option<String>,
+    /// Short labels to search the runs by
+    #[config(default = [])]
+    pub tags: Vec<String>,
+}
+
+/// Online pulse finding, written to a reduced file per run for nearline
+/// analysis. Pulses are found with the zero suppression edge, baseline
+/// samples and window size.
+#[derive(Config, Debug, Clone)]
+pub struct DstSettings {
+    #[config(default = false)]
+    pub enabled: bool,
+    /// Threshold in ADC counts from the baseline, `zs_threshold` if unset
+    pub threshold: Option<f64>,
+    /// Samples integrated before the threshold crossing
+    #[config(default = 10)]
+    pub pre_samples: usize,
+    /// Samples integrated from the threshold crossing on
+    #[config(default = 100)]
+    pub integration_samples: usize,
+}
+
+/// Per-channel gain and offset corrections, see `Calibration`.
+#[derive(Config, Debug, Clone)]
+pub struct CalibrationSettings {
+    /// Calibration file with `board,channel,gain,offset` lines, none if unset
+    pub file: Option<String>,
+    /// Correct the saved waveforms and averages, otherwise only store the
+    /// constants next to them
+    #[config(default = false)]
+    pub apply: bool,
+}
+
+/// A calibration run inserted between physics runs once a day, to track
+/// gain and pedestal drift over long campaigns.
+#[derive(Config, Debug, Clone)]
+pub struct CalibrationRunSettings {
+    /// Local time of day as `HH:MM` after which the next run is a calibration
+    /// run, none to never insert one
+    pub time: Option<String>,
+    #[config(default = "Pulser")]
+    pub run_type: RunType,
+    /// Length of the calibration run in seconds
+    #[config(default = 300)]
+    pub duration: u64,
+    /// Trigger source of every board during the calibration run
+    #[config(default = "TestPulse")]
+    pub trig_source: String,
+}
+
+impl CalibrationRunSettings {
+    /// Time of day of the calibration run, none if it isn't scheduled.
+    pub fn time_of_day(&self) -> Option<Time> {
+        self.time
+            .as_deref()
+            .and_then(|time| parse_time_of_day(time).ok())
+    }
+}
+
+fn parse_time_of_day(time: &str) -> Result<Time, String> {
+    let invalid = || format!("calibration_run.time \"{}\" must be HH:MM", time);
+    let (hour, minute) = time.split_once(':').ok_or_else(invalid)?;
+    let hour: u8 = hour.trim().parse().map_err(|_| invalid())?;
+    let minute: u8 = minute.trim().parse().map_err(|_| invalid())?;
+    Time::from_hms(hour, minute, 0).map_err(|_| invalid())
 }
 
 #[derive(Config, Debug, Clone)]
@@ -46,6 +845,10 @@ pub struct BoardSettings {
 pub struct CommonSettings {
     pub record_len: usize,
     pub pre_trig_len: usize,
+    /// Firmware multi-window acquisition (`EnMultiWindowRun`), where one
+    /// trigger can give several windows
+    #[serde(default)]
+    pub multi_window: bool,
 }
 
 #[derive(Deserialize, Config, Debug, Clone)]
@@ -69,10 +872,57 @@ pub struct PerBoardSettings {
     pub itl_gatewidth: usize,
     pub itl_connect: ITLConnect,
     pub itl_retrig: String,
+    /// Overrides of the `run_settings` read timeout bounds and stall limit
+    #[serde(default)]
+    pub read_timeout_min: Option<u64>,
+    #[serde(default)]
+    pub read_timeout_max: Option<u64>,
+    #[serde(default)]
+    pub stall_timeouts: Option<usize>,
+}
+
+/// How a board's readout waits for its next event.
+#[derive(Deserialize, Clone, Debug, Copy, PartialEq, Eq, Default)]
+pub enum ReadStrategy {
+    /// Block in `ReadData` until an event comes or the timeout passes
+    #[default]
+    ReadData,
+    /// Wait in `HasData` and only call `ReadData` once an event is waiting
+    HasData,
+}
+
+/// Readout timeouts of one board, from `run_settings` and the board's
+/// overrides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadPolicy {
+    /// Bounds of the adaptive read timeout in ms
+    pub timeout_min: u64,
+    pub timeout_max: u64,
+    pub strategy: ReadStrategy,
+    /// Consecutive empty reads after which the board counts as stalled
+    pub stall_timeouts: Option<usize>,
 }
 
 #[derive(Config, Debug, Clone)]
 pub struct SyncSettings {
+    #[config(default = "SwFirstBoard")]
+    pub start_mode: StartMode,
+    /// Order in which boards are armed, by board index. Defaults to the last
+    /// board first so the chained boards are ready before the first board
+    pub arm_order: Option<Vec<usize>>,
+    #[config(default = "Aligned")]
+    pub event_building: EventBuilding,
+    /// Largest timestamp difference in ns between boards' parts of one event
+    /// with `Timestamp` event building
+    #[config(default = 100)]
+    pub coincidence_window_ns: u64,
+    /// Built events at the start of each run used to measure each board's
+    /// timestamp offset to board 0, 0 to not measure
+    #[config(default = 0)]
+    pub offset_events: usize,
+    /// Also save timestamps with the measured offsets subtracted
+    #[config(default = false)]
+    pub correct_timestamps: bool,
     pub boards: Vec<PerBoardSync>,
 }
 
@@ -93,6 +943,26 @@ pub enum ChannelConfig {
     List(Vec<u32>),
 }
 
+impl ChannelConfig {
+    /// Channels enabled on a board with `num_ch` channels, in order. Only
+    /// these are read out and saved.
+    pub fn enabled(&self, num_ch: usize) -> Vec<usize> {
+        match self {
+            ChannelConfig::All(_) => (0..num_ch).collect(),
+            ChannelConfig::List(channels) => {
+                let mut channels: Vec<usize> = channels
+                    .iter()
+                    .map(|&ch| ch as usize)
+                    .filter(|&ch| ch < num_ch)
+                    .collect();
+                channels.sort_unstable();
+                channels.dedup();
+                channels
+            }
+        }
+    }
+}
+
 #[derive(Deserialize, Clone, Debug)]
 #[serde(untagged)]
 pub enum DCOffsetConfig {
@@ -134,8 +1004,270 @@ pub enum ITLConnect {
     PerChannel(HashMap<String, String>),
 }
 
+/// How the acquisition of a run is started.
+#[derive(Deserialize, Clone, Debug, Copy, PartialEq, Eq)]
+pub enum StartMode {
+    /// Software start on the first board, which starts the rest down the chain
+    SwFirstBoard,
+    /// Software start sent to every board
+    SwEachBoard,
+    /// External start on the first board's SIN input
+    Sin,
+    /// External start on the first board's LVDS inputs
+    Lvds,
+}
+
+impl SyncSettings {
+    /// Board indices in the order they are armed.
+    pub fn arm_order(&self) -> Vec<usize> {
+        self.arm_order
+            .clone()
+            .unwrap_or_else(|| (0..self.boards.len()).rev().collect())
+    }
+}
+
+impl StartMode {
+    /// Whether a board with this `start_source` can be started this way. In
+    /// the chained modes only the first board gets the start itself, the
+    /// others take it from the board before them.
+    pub fn accepts(&self, start_source: &str, first_board: bool) -> bool {
+        let sources: Vec<&str> = start_source.split('|').map(str::trim).collect();
+        let has = |source: &str| sources.contains(&source);
+        let chained = has("EncodedClkIn") || has("SINlevel") || has("SINedge") || has("P0");
+        match self {
+            StartMode::SwEachBoard => has("SWcmd"),
+            _ if !first_board => chained,
+            StartMode::SwFirstBoard => has("SWcmd"),
+            StartMode::Sin => has("SINlevel") || has("SINedge"),
+            StartMode::Lvds => has("LVDS"),
+        }
+    }
+}
+
+/// Readout endpoint of the boards, which depends on the firmware they run.
+#[derive(Deserialize, Clone, Debug, Copy, PartialEq, Eq, Default)]
+pub enum EndpointType {
+    /// Waveforms from the scope firmware
+    #[default]
+    Scope,
+    /// Per-channel hits with charges and fine timestamps, no waveforms
+    DppPsd,
+    /// Zero length encoded waveforms
+    DppZle,
+}
+
+impl EndpointType {
+    /// Name of the endpoint, used to activate it
+    pub fn name(&self) -> &'static str {
+        match self {
+            EndpointType::Scope => "scope",
+            EndpointType::DppPsd => "dpppsd",
+            EndpointType::DppZle => "dppzle",
+        }
+    }
+
+    pub fn path(&self) -> String {
+        format!("/endpoint/{}", self.name())
+    }
+
+    /// Read format of waveform events, with the µs timestamp if
+    /// `fine_timestamp`. DPP-PSD hits always carry their fine timestamp.
+    pub fn read_format(&self, fine_timestamp: bool) -> &'static str {
+        match self {
+            EndpointType::DppPsd => crate::DPP_PSD_EVENT_FORMAT,
+            _ if fine_timestamp => crate::EVENT_FORMAT_FINE_TIMESTAMP,
+            EndpointType::Scope => crate::EVENT_FORMAT,
+            EndpointType::DppZle => crate::DPP_ZLE_EVENT_FORMAT,
+        }
+    }
+
+    /// Whether events come with waveforms
+    pub fn has_waveforms(&self) -> bool {
+        *self != EndpointType::DppPsd
+    }
+}
+
+/// An endpoint read alongside the main one, saved in a group of its own
+/// below each board's group.
+#[derive(Deserialize, Clone, Debug, Copy, PartialEq, Eq)]
+pub enum ExtraEndpoint {
+    /// The board's data blocks as sent, undecoded
+    Raw,
+    /// Per-channel real, dead and live time and trigger counters
+    Stats,
+}
+
+impl ExtraEndpoint {
+    /// Name of the endpoint and of its group in the files
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExtraEndpoint::Raw => "raw",
+            ExtraEndpoint::Stats => "stats",
+        }
+    }
+
+    pub fn path(&self) -> String {
+        format!("/endpoint/{}", self.name())
+    }
+
+    pub fn read_format(&self) -> &'static str {
+        match self {
+            ExtraEndpoint::Raw => crate::RAW_DATA_FORMAT,
+            ExtraEndpoint::Stats => crate::STATS_FORMAT,
+        }
+    }
+}
+
+/// How events of different boards are put together.
+#[derive(Deserialize, Clone, Debug, Copy, PartialEq, Eq)]
+pub enum EventBuilding {
+    /// Boards share triggers, events are written once every board has the
+    /// same trigger ID
+    Aligned,
+    /// Boards trigger on their own, each board's events are written as they
+    /// arrive
+    Independent,
+    /// Boards share triggers, events are written once every board has one
+    /// within `coincidence_window_ns` of the others, whatever the trigger
+    /// IDs say
+    Timestamp,
+}
+
+/// What a run was taken for, stored in each file's `run_type` attribute.
+#[derive(Deserialize, Clone, Debug, Copy, PartialEq, Eq, Default)]
+pub enum RunType {
+    #[default]
+    Physics,
+    /// Test pulses fed to the inputs, to track the gains
+    Pulser,
+    /// Baseline only, to track the pedestals
+    Pedestal,
+}
+
+impl RunType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RunType::Physics => "physics",
+            RunType::Pulser => "pulser",
+            RunType::Pedestal => "pedestal",
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, Copy)]
+pub enum RetentionAction {
+    Delete,
+    Archive,
+}
+
+/// Blosc compressor of the event datasets
+#[derive(Deserialize, Clone, Debug, Copy, PartialEq, Eq)]
+pub enum Compressor {
+    /// Best ratio, the default
+    Zstd,
+    /// Faster, for high rates
+    Lz4,
+    /// Uncompressed
+    None,
+}
+
+/// Storage engine of the built events
+#[derive(Deserialize, Clone, Debug, Copy, PartialEq, Eq)]
+pub enum Storage {
+    /// The run's HDF5 files, see `HDF5Writer`
+    Hdf5,
+    /// Process every event but write nothing, to measure the boards' and
+    /// event builder's throughput without the disks
+    Discard,
+}
+
+/// What to do when a run's data file already exists, e.g. when the run
+/// numbering missed files moved out of the campaign's directories
+#[derive(Deserialize, Clone, Debug, Copy, PartialEq, Eq, Default)]
+pub enum ExistingFiles {
+    /// Refuse to write the file, the default
+    #[default]
+    Error,
+    /// Replace the file
+    Overwrite,
+    /// Number the run after the existing one. Subrun files can't be
+    /// renumbered, so they are never overwritten either
+    Increment,
+}
+
+/// Floating point type of the waveforms stored in volts
+#[derive(Deserialize, Clone, Debug, Copy, PartialEq, Eq)]
+pub enum FloatType {
+    /// Half precision, about 3 significant digits at half the size
+    F16,
+    F32,
+}
+
+/// When closed subrun files are mirrored
+#[derive(Deserialize, Clone, Debug, Copy)]
+pub enum MirrorMode {
+    /// As each file closes, a new run only starts once the last one's files
+    /// are copied
+    Sync,
+    /// By a copier thread, without holding anything up
+    Background,
+}
+
+#[derive(Deserialize, Clone, Debug, Copy)]
+pub enum ThemeName {
+    Default,
+    HighContrast,
+    ColorBlind,
+}
+
+#[derive(Deserialize, Clone, Debug, Copy)]
+pub enum Language {
+    English,
+    Spanish,
+}
+
 #[derive(Deserialize, Clone, Debug, Copy)]
 pub enum ZeroSuppressionEdge {
     Fall,
     Rise,
 }
+
+/// Where zero suppression happens.
+#[derive(Deserialize, Clone, Debug, Copy, PartialEq, Eq, Default)]
+pub enum ZsMode {
+    /// cliq zeroes the samples outside pulses after readout
+    #[default]
+    Software,
+    /// The firmware drops quiet channels before readout (`EnChSuppr`)
+    Board,
+}
+
+impl ZsMode {
+    /// Name recorded in the `zero_suppression` file attribute
+    pub fn name(&self) -> &'static str {
+        match self {
+            ZsMode::Software => "software",
+            ZsMode::Board => "board",
+        }
+    }
+}
+
+/// What software zero suppression runs in parallel.
+#[derive(Deserialize, Clone, Debug, Copy, PartialEq, Eq, Default)]
+pub enum ZsParallelism {
+    /// The channels of each event, one small task per channel
+    #[default]
+    Channel,
+    /// Whole events of a batch, each suppressed channel by channel. Less
+    /// scheduling overhead for short records
+    Event,
+}
+
+impl ZsParallelism {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ZsParallelism::Channel => "channel",
+            ZsParallelism::Event => "event",
+        }
+    }
+}
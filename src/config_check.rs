@@ -0,0 +1,395 @@
+use crate::{Conf, DCOffsetConfig, ITLConnect, SamplesOverThr, TriggerThr, TriggerThrMode};
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{self, OpenOptions},
+    path::Path,
+};
+
+/// A board parameter the config sets, with its value after a reset and,
+/// for the string ones, the values FELib accepts.
+struct BoardParam {
+    name: &'static str,
+    default: &'static str,
+    /// Empty for numbers
+    values: &'static [&'static str],
+    /// Whether several values can be combined with `|`
+    combined: bool,
+}
+
+const fn param(name: &'static str, default: &'static str) -> BoardParam {
+    BoardParam {
+        name,
+        default,
+        values: &[],
+        combined: false,
+    }
+}
+
+const fn choice(
+    name: &'static str,
+    default: &'static str,
+    values: &'static [&'static str],
+) -> BoardParam {
+    BoardParam {
+        name,
+        default,
+        values,
+        combined: false,
+    }
+}
+
+const TRIGGER_SOURCES: &[&str] = &[
+    "TrgIn",
+    "P0",
+    "SwTrg",
+    "LVDS",
+    "ITLA",
+    "ITLB",
+    "ITLA_AND_ITLB",
+    "ITLA_OR_ITLB",
+    "EncodedClkIn",
+    "GPIO",
+    "TestPulse",
+    "UserTrg",
+];
+
+const TRUE_FALSE: &[&str] = &["True", "False"];
+
+/// The VX2740 scope firmware's parameters set from the config, with their
+/// reset values
+const BOARD_PARAMS: &[BoardParam] = &[
+    param("RecordLengthS", "4096"),
+    param("PreTriggerS", "100"),
+    BoardParam {
+        name: "AcqTriggerSource",
+        default: "SwTrg",
+        values: TRIGGER_SOURCES,
+        combined: true,
+    },
+    choice("IOlevel", "NIM", &["NIM", "TTL"]),
+    param("TestPulsePeriod", "100000"),
+    param("TestPulseWidth", "1000"),
+    param("TestPulseLowLevel", "0"),
+    param("TestPulseHighLevel", "65535"),
+    param("DCOffset", "50"),
+    param("TriggerThr", "0"),
+    choice("TriggerThrMode", "Relative", &["Relative", "Absolute"]),
+    choice("SelfTriggerEdge", "Fall", &["Fall", "Rise"]),
+    param("SamplesOverThreshold", "0"),
+    choice("ITLAMainLogic", "OR", &["OR", "AND", "Majority"]),
+    param("ITLAMajorityLev", "0"),
+    choice("ITLAPairLogic", "NONE", &["NONE", "AND", "OR"]),
+    choice("ITLAPolarity", "Direct", &["Direct", "Inverted"]),
+    param("ITLAGateWidth", "0"),
+    choice("ITLAEnRetrigger", "True", TRUE_FALSE),
+    choice("ITLConnect", "Disabled", &["Disabled", "ITLA", "ITLB"]),
+    choice("ClockSource", "Internal", &["Internal", "FPClkIn"]),
+    choice(
+        "SyncOutMode",
+        "Disabled",
+        &["Disabled", "SyncIn", "TestPulse", "IntClk", "Run", "User"],
+    ),
+    BoardParam {
+        name: "StartSource",
+        default: "SWcmd",
+        values: &["EncodedClkIn", "SINlevel", "SINedge", "SWcmd", "LVDS", "P0"],
+        combined: true,
+    },
+    choice("EnClockOutFP", "True", TRUE_FALSE),
+    choice("EnAutoDisarmAcq", "True", TRUE_FALSE),
+    choice(
+        "TrgOutMode",
+        "Disabled",
+        &[
+            "Disabled",
+            "TrgIn",
+            "P0",
+            "SwTrg",
+            "LVDS",
+            "ITLA",
+            "ITLB",
+            "ITLA_AND_ITLB",
+            "ITLA_OR_ITLB",
+            "EncodedClkIn",
+            "Run",
+            "RefClk",
+            "TestPulse",
+            "Busy",
+            "Fixed0",
+            "Fixed1",
+            "SyncIn",
+            "SIN",
+            "GPIO",
+            "AcceptTrg",
+            "TrgClk",
+        ],
+    ),
+];
+
+/// A config setting whose board parameter differs from the board's reset
+/// value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NonDefault {
+    /// Setting in the config, e.g. `board_settings.boards[0].io_level`
+    pub key: String,
+    pub param: &'static str,
+    pub default: &'static str,
+    pub value: String,
+}
+
+impl fmt::Display for NonDefault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}): {} -> {}",
+            self.key, self.param, self.default, self.value
+        )
+    }
+}
+
+/// What `cliq check` found in a config that loaded, without opening any
+/// board.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigCheck {
+    /// Settings FELib would reject and an output directory that can't be
+    /// written
+    pub problems: Vec<String>,
+    pub non_defaults: Vec<NonDefault>,
+}
+
+impl ConfigCheck {
+    pub fn run(config: &Conf) -> Self {
+        let mut check = ConfigCheck::default();
+        for (key, name, value) in board_settings(config) {
+            let Some(param) = BOARD_PARAMS.iter().find(|p| p.name == name) else {
+                continue;
+            };
+            if let Some(bad) = invalid_value(param, &value) {
+                check.problems.push(format!(
+                    "{} \"{}\" isn't a {} value, expected {}{}",
+                    key,
+                    bad,
+                    param.name,
+                    param.values.join(", "),
+                    if param.combined {
+                        " or several joined by |"
+                    } else {
+                        ""
+                    }
+                ));
+            } else if !same_value(&value, param.default) {
+                check.non_defaults.push(NonDefault {
+                    key,
+                    param: param.name,
+                    default: param.default,
+                    value,
+                });
+            }
+        }
+        if let Err(e) = check_writable(Path::new(&config.run_settings.output_dir)) {
+            check.problems.push(e);
+        }
+        check
+    }
+}
+
+impl fmt::Display for ConfigCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for problem in &self.problems {
+            writeln!(f, "error: {}", problem)?;
+        }
+        if self.non_defaults.is_empty() {
+            writeln!(f, "Every board setting is at the board's default")?;
+        } else {
+            writeln!(f, "Settings changed from the board's defaults:")?;
+            for change in &self.non_defaults {
+                writeln!(f, "  {}", change)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The value of a string parameter FELib doesn't accept, if any.
+fn invalid_value<'a>(param: &BoardParam, value: &'a str) -> Option<&'a str> {
+    if param.values.is_empty() {
+        return None;
+    }
+    let mut values = if param.combined {
+        value.split('|').map(str::trim).collect()
+    } else {
+        vec![value.trim()]
+    };
+    values.retain(|v| {
+        !param
+            .values
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(v))
+    });
+    values.first().copied()
+}
+
+/// Whether two parameter values are the same to the board, which ignores
+/// case and reads numbers as numbers.
+fn same_value(a: &str, b: &str) -> bool {
+    match (a.trim().parse::<f64>(), b.trim().parse::<f64>()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a.trim().eq_ignore_ascii_case(b.trim()),
+    }
+}
+
+/// Every board parameter the config sets, as `(setting, parameter, value)`.
+/// Per-channel settings give one entry per channel listed.
+fn board_settings(config: &Conf) -> Vec<(String, &'static str, String)> {
+    let mut settings = Vec::new();
+    let common = &config.board_settings.common;
+    settings.push((
+        String::from("board_settings.common.record_len"),
+        "RecordLengthS",
+        common.record_len.to_string(),
+    ));
+    settings.push((
+        String::from("board_settings.common.pre_trig_len"),
+        "PreTriggerS",
+        common.pre_trig_len.to_string(),
+    ));
+    settings.push((
+        String::from("calibration_run.trig_source"),
+        "AcqTriggerSource",
+        config.calibration_run.trig_source.clone(),
+    ));
+    for (i, board) in config.board_settings.boards.iter().enumerate() {
+        let key = |name: &str| format!("board_settings.boards[{}].{}", i, name);
+        let mut push = |name: &str, param, value: String| settings.push((key(name), param, value));
+        push("trig_source", "AcqTriggerSource", board.trig_source.clone());
+        push("io_level", "IOlevel", board.io_level.clone());
+        push(
+            "test_pulse_period",
+            "TestPulsePeriod",
+            board.test_pulse_period.to_string(),
+        );
+        push(
+            "test_pulse_width",
+            "TestPulseWidth",
+            board.test_pulse_width.to_string(),
+        );
+        push(
+            "test_pulse_low",
+            "TestPulseLowLevel",
+            board.test_pulse_low.to_string(),
+        );
+        push(
+            "test_pulse_high",
+            "TestPulseHighLevel",
+            board.test_pulse_high.to_string(),
+        );
+        push(
+            "trig_edge",
+            "SelfTriggerEdge",
+            format!("{:?}", board.trig_edge),
+        );
+        push("itl_logic", "ITLAMainLogic", board.itl_logic.clone());
+        push(
+            "itl_majority_level",
+            "ITLAMajorityLev",
+            board.itl_majority_level.to_string(),
+        );
+        push(
+            "itl_pair_logic",
+            "ITLAPairLogic",
+            board.itl_pair_logic.clone(),
+        );
+        push("itl_polarity", "ITLAPolarity", board.itl_polarity.clone());
+        push(
+            "itl_gatewidth",
+            "ITLAGateWidth",
+            board.itl_gatewidth.to_string(),
+        );
+        push("itl_retrig", "ITLAEnRetrigger", board.itl_retrig.clone());
+
+        let dc_offset = match &board.dc_offset {
+            DCOffsetConfig::Global(offset) => per_board(offset),
+            DCOffsetConfig::PerChannel(map) => per_channel(map),
+        };
+        let trig_thr = match &board.trig_thr {
+            TriggerThr::Global(thr) => per_board(thr),
+            TriggerThr::PerChannel(map) => per_channel(map),
+        };
+        let trig_thr_mode = match &board.trig_thr_mode {
+            TriggerThrMode::Global(mode) => per_board(mode),
+            TriggerThrMode::PerChannel(map) => per_channel(map),
+        };
+        let samples_over_thr = match &board.samples_over_thr {
+            SamplesOverThr::Global(samples) => per_board(samples),
+            SamplesOverThr::PerChannel(map) => per_channel(map),
+        };
+        let itl_connect = match &board.itl_connect {
+            ITLConnect::Global(connect) => per_board(connect),
+            ITLConnect::PerChannel(map) => per_channel(map),
+        };
+        for (name, param, values) in [
+            ("dc_offset", "DCOffset", dc_offset),
+            ("trig_thr", "TriggerThr", trig_thr),
+            ("trig_thr_mode", "TriggerThrMode", trig_thr_mode),
+            ("samples_over_thr", "SamplesOverThreshold", samples_over_thr),
+            ("itl_connect", "ITLConnect", itl_connect),
+        ] {
+            for (suffix, value) in values {
+                settings.push((format!("{}{}", key(name), suffix), param, value));
+            }
+        }
+    }
+    for (i, board) in config.sync_settings.boards.iter().enumerate() {
+        let key = |name: &str| format!("sync_settings.boards[{}].{}", i, name);
+        for (name, param, value) in [
+            ("clock_src", "ClockSource", &board.clock_src),
+            ("sync_out", "SyncOutMode", &board.sync_out),
+            ("start_source", "StartSource", &board.start_source),
+            ("clock_out_fp", "EnClockOutFP", &board.clock_out_fp),
+            ("auto_disarm", "EnAutoDisarmAcq", &board.auto_disarm),
+            ("trig_out", "TrgOutMode", &board.trig_out),
+        ] {
+            settings.push((key(name), param, value.clone()));
+        }
+    }
+    settings
+}
+
+fn per_board(value: &impl ToString) -> Vec<(String, String)> {
+    vec![(String::new(), value.to_string())]
+}
+
+/// A per-channel setting's entries by channel, as `.<channel>` key suffixes.
+fn per_channel<T: ToString>(map: &HashMap<String, T>) -> Vec<(String, String)> {
+    let mut values: Vec<_> = map
+        .iter()
+        .map(|(ch, value)| (format!(".{}", ch), value.to_string()))
+        .collect();
+    values.sort_by_key(|(ch, _)| ch[1..].parse::<usize>().unwrap_or(usize::MAX));
+    values
+}
+
+/// Make sure files can be created in the output directory, or in the
+/// directory it would be created in if it doesn't exist yet.
+fn check_writable(output_dir: &Path) -> Result<(), String> {
+    let dir = output_dir
+        .ancestors()
+        .map(|dir| {
+            if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            }
+        })
+        .find(|dir| dir.is_dir())
+        .ok_or_else(|| format!("output_dir {} has no existing parent", output_dir.display()))?;
+    let probe = dir.join(format!(".cliq-check-{}", std::process::id()));
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+        .map_err(|e| format!("output_dir {} isn't writable: {}", output_dir.display(), e))?;
+    let _ = fs::remove_file(&probe);
+    Ok(())
+}
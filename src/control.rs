@@ -0,0 +1,160 @@
+use crate::ProcessStats;
+use anyhow::{anyhow, Result};
+use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
+use log::{info, warn};
+use serde::Serialize;
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+    time::Duration,
+};
+
+/// How long a connection waits for the controller to answer a command
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A command from the experiment's run control, one per line on the control
+/// connection. Commands naming no stream apply to every stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlCommand {
+    /// Start the next run now instead of waiting out the gap or a hold
+    Start(Option<String>),
+    /// End the run in progress and hold the next one until `start`
+    Stop(Option<String>),
+    /// Every stream's state and run stats as JSON
+    Status,
+    /// Read the config file again, used from the next run on
+    Reload(Option<String>),
+}
+
+impl ControlCommand {
+    /// Parse a line such as `start`, `stop tpc` or `status`.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut words = line.split_whitespace();
+        let command = words.next().ok_or("empty command")?;
+        let stream = words.next().map(String::from);
+        if words.next().is_some() {
+            return Err(format!("too many arguments to {}", command));
+        }
+        match (command.to_lowercase().as_str(), stream) {
+            ("start", stream) => Ok(Self::Start(stream)),
+            ("stop", stream) => Ok(Self::Stop(stream)),
+            ("status", None) => Ok(Self::Status),
+            ("reload", stream) => Ok(Self::Reload(stream)),
+            ("status", Some(_)) => Err(String::from("status takes no arguments")),
+            _ => Err(format!(
+                "unknown command {}, use start, stop, status or reload",
+                command
+            )),
+        }
+    }
+}
+
+/// A stream's state and run stats as reported to run control.
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamStatus {
+    pub stream: String,
+    /// `starting`, `running`, `awaiting acknowledgement`, `between runs` or
+    /// `finished`
+    pub state: String,
+    pub campaign: usize,
+    pub run: usize,
+    pub run_type: String,
+    pub events: usize,
+    pub bytes: usize,
+    /// Average data rate of the run in MB/s
+    pub data_rate: f64,
+    pub misaligned_events: usize,
+    pub dropped_events: usize,
+    pub unacknowledged_alarms: usize,
+    /// The next run waits for a `start`
+    pub held: bool,
+    /// Scheduled run in progress or next, as `step/runs label`
+    pub schedule: Option<String>,
+    /// CPU, memory and write rate of the cliq process, the same for every
+    /// stream
+    pub process: Option<ProcessStats>,
+}
+
+/// A command waiting for the controller, with the connection to answer.
+#[derive(Debug)]
+pub struct ControlRequest {
+    pub command: ControlCommand,
+    reply: Sender<String>,
+}
+
+impl ControlRequest {
+    /// Answer the command with one line: `ok`, `error: ...` or JSON.
+    pub fn reply(self, response: String) {
+        // the connection may have timed out or closed meanwhile
+        let _ = self.reply.send(response);
+    }
+}
+
+/// Accepts run control connections on a TCP port. Each connection sends
+/// commands as lines of text and gets one line back per command; the
+/// controller carries them out between steps of the streams.
+#[derive(Debug)]
+pub struct ControlServer {
+    requests: Receiver<ControlRequest>,
+}
+
+impl ControlServer {
+    /// Listen on `addr`, e.g. `127.0.0.1:5555`.
+    pub fn bind(addr: &str) -> Result<Self> {
+        let listener = TcpListener::bind(addr)
+            .map_err(|e| anyhow!("failed to listen for run control on {}: {}", addr, e))?;
+        info!("Listening for run control on {}", addr);
+        let (tx, requests) = unbounded();
+        thread::spawn(move || {
+            for connection in listener.incoming() {
+                match connection {
+                    Ok(connection) => {
+                        let tx = tx.clone();
+                        thread::spawn(move || serve_connection(connection, tx));
+                    }
+                    Err(e) => warn!("Run control connection failed: {}", e),
+                }
+            }
+        });
+        Ok(Self { requests })
+    }
+
+    /// Commands received since the last call.
+    pub fn requests(&self) -> Vec<ControlRequest> {
+        self.requests.try_iter().collect()
+    }
+}
+
+fn serve_connection(connection: TcpStream, requests: Sender<ControlRequest>) {
+    let peer = connection
+        .peer_addr()
+        .map_or_else(|_| String::from("unknown"), |addr| addr.to_string());
+    info!("Run control connected from {}", peer);
+    let Ok(mut writer) = connection.try_clone() else {
+        return;
+    };
+    for line in BufReader::new(connection).lines() {
+        let Ok(line) = line else { break };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match ControlCommand::parse(&line) {
+            Ok(command) => {
+                info!("Run control from {}: {}", peer, line.trim());
+                let (reply, response) = bounded(1);
+                if requests.send(ControlRequest { command, reply }).is_err() {
+                    break;
+                }
+                response
+                    .recv_timeout(REPLY_TIMEOUT)
+                    .unwrap_or_else(|_| String::from("error: no answer from the DAQ"))
+            }
+            Err(e) => format!("error: {}", e),
+        };
+        if writeln!(writer, "{}", response).is_err() {
+            break;
+        }
+    }
+    info!("Run control from {} disconnected", peer);
+}
@@ -0,0 +1,180 @@
+use crate::{ControlCommand, ControlServer, Stream};
+use anyhow::Result;
+use log::info;
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Pause between steps of the streams in headless mode
+const HEADLESS_STEP_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Set by SIGINT or SIGTERM once the handlers are installed
+static QUIT_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_quit(_signal: libc::c_int) {
+    QUIT_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Turn SIGINT and SIGTERM into a graceful quit instead of killing the
+/// process with boards still armed and events unwritten. The signal is only
+/// noted here, the controller acts on it in its next step.
+pub fn install_signal_handlers() {
+    let handler: extern "C" fn(libc::c_int) = request_quit;
+    unsafe {
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, handler as libc::sighandler_t);
+    }
+}
+
+/// Drives the run cycle of every stream, for the TUI or without any
+/// terminal front end.
+#[derive(Debug)]
+pub struct Controller {
+    pub streams: Vec<Stream>,
+    /// A quit signal has been acted on
+    quitting: bool,
+    /// Run control connections, if listening
+    control: Option<ControlServer>,
+}
+
+impl Controller {
+    pub fn new(streams: Vec<Stream>) -> Self {
+        Self {
+            streams,
+            quitting: false,
+            control: None,
+        }
+    }
+
+    /// Take commands from run control through `server` from now on.
+    pub fn serve_control(&mut self, server: ControlServer) {
+        self.control = Some(server);
+    }
+
+    /// Step every stream once without blocking. Returns whether anything
+    /// shown to the operator changed.
+    pub fn step(&mut self) -> Result<bool> {
+        let mut changed = self.handle_control();
+        for stream in self.streams.iter_mut() {
+            changed |= stream.step()?;
+        }
+        // Sampled here so the rates cover the time since the last sample
+        // however seldom they're shown
+        crate::process_stats();
+        Ok(changed)
+    }
+
+    /// Carry out the commands run control sent since the last step.
+    fn handle_control(&mut self) -> bool {
+        let Some(control) = &self.control else {
+            return false;
+        };
+        let requests = control.requests();
+        let changed = !requests.is_empty();
+        for request in requests {
+            let response = self.control_command(&request.command);
+            request.reply(response);
+        }
+        changed
+    }
+
+    fn control_command(&mut self, command: &ControlCommand) -> String {
+        type Action = fn(&mut Stream) -> Result<(), String>;
+        let (name, action): (&Option<String>, Action) = match command {
+            ControlCommand::Status => {
+                let status: Vec<_> = self.streams.iter().map(Stream::status).collect();
+                return serde_json::to_string(&status).unwrap_or_else(|e| format!("error: {}", e));
+            }
+            ControlCommand::Start(name) => (name, Stream::start_next_run),
+            ControlCommand::Stop(name) => (name, Stream::stop_run),
+            ControlCommand::Reload(name) => (name, Stream::reload_config),
+        };
+        let mut found = false;
+        let mut errors = Vec::new();
+        for stream in self.streams.iter_mut() {
+            if name.as_ref().is_some_and(|name| &stream.name != name) {
+                continue;
+            }
+            found = true;
+            if let Err(e) = action(stream) {
+                errors.push(format!("[{}] {}", stream.name, e));
+            }
+        }
+        if !found {
+            format!("error: no stream {}", name.as_deref().unwrap_or_default())
+        } else if errors.is_empty() {
+            String::from("ok")
+        } else {
+            format!("error: {}", errors.join("; "))
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.streams.iter().all(|s| s.is_finished())
+    }
+
+    /// Gracefully stop every stream's run and finish.
+    pub fn quit(&mut self) {
+        for stream in self.streams.iter_mut() {
+            stream.quit();
+        }
+    }
+
+    /// Gracefully stop every stream once SIGINT or SIGTERM has come in, see
+    /// `install_signal_handlers`. Returns whether this call began the quit.
+    pub fn check_quit_signal(&mut self) -> bool {
+        if self.quitting || !QUIT_REQUESTED.load(Ordering::SeqCst) {
+            return false;
+        }
+        info!("Quit requested by signal");
+        self.quit();
+        self.quitting = true;
+        true
+    }
+
+    /// Shortest configured `refresh_interval` of the streams.
+    pub fn refresh_interval(&self) -> Duration {
+        self.streams
+            .iter()
+            .map(|s| Duration::from_millis(s.config.run_settings.refresh_interval))
+            .min()
+            .unwrap_or(Duration::from_secs(1))
+    }
+
+    /// Take runs without a terminal UI, e.g. under systemd or over ssh.
+    /// Stats are printed and logged every `stats_interval`, and SIGINT or
+    /// SIGTERM ends the runs gracefully. With nobody to ask, board failures
+    /// restart the run and alarms are acknowledged once logged.
+    pub fn run_headless(&mut self, stats_interval: Duration) -> Result<()> {
+        let mut last_stats = Instant::now();
+
+        loop {
+            if self.check_quit_signal() {
+                println!("Stopping, waiting for the runs to end");
+            }
+            self.step()?;
+            for stream in self.streams.iter_mut() {
+                stream.handle_unattended();
+            }
+            if self.is_finished() {
+                return Ok(());
+            }
+
+            if last_stats.elapsed() >= stats_interval {
+                for stream in &self.streams {
+                    let status = stream.status_line();
+                    info!("{}", status);
+                    println!("{}", status);
+                }
+                if let Some(stats) = crate::process_stats() {
+                    info!("DAQ process: {}", stats);
+                    println!("DAQ process: {}", stats);
+                }
+                last_stats = Instant::now();
+            }
+            thread::sleep(HEADLESS_STEP_INTERVAL);
+        }
+    }
+}
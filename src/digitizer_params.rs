@@ -1,5 +1,4 @@
-use crate::felib_getvalue;
-use crate::FELibReturn;
+use crate::felib;
 use log::info;
 
 const DIGITIZER_PARAMS: &[&str] = &[
@@ -140,57 +139,41 @@ const CHANNEL_PARAMS: &[&str] = &[
     "ChSupprSamplesOverThreshold",
 ];
 
-pub fn log_all(boards: &[(usize, u64)]) {
-    for &(board_id, handle) in boards {
-        let mut param_log = String::new();
-
-        for &param in DIGITIZER_PARAMS {
-            let path = format!("/par/{}", param);
-            if let Ok(value) = felib_getvalue(handle, &path) {
-                param_log.push_str(&format!("{}: {}\n", param, value));
-            }
-        }
-
-        if let Ok(numch_str) = felib_getvalue(handle, "/par/NumCh") {
-            if let Ok(total_ch) = numch_str.trim().parse::<usize>() {
-                let groups = (total_ch + 3) / 4; // 4 channels per group
-                for group in 0..groups {
-                    let ch_index = group * 4;
-                    let path = format!("/ch/{}/par/InputDelay", ch_index);
-                    if let Ok(val) = felib_getvalue(handle, &path) {
-                        param_log.push_str(&format!("InputDelay(group{}): {}\n", group, val));
-                    }
-                }
-            }
-        }
-
-        let num_channels = if let Ok(n) = felib_getvalue(handle, "/par/NumCh")
-            .and_then(|s| s.trim().parse().map_err(|_| FELibReturn::Generic))
-        {
-            n
-        } else {
-            0
-        };
-
-        if num_channels > 0 {
-            for &ch_param in CHANNEL_PARAMS {
-                for ch in 0..num_channels {
-                    let path = format!("/ch/{}/par/{}", ch, ch_param);
-                    match felib_getvalue(handle, &path) {
-                        Ok(val) => {
-                            param_log.push_str(&format!("{}[{}]: {}\n", ch_param, ch, val));
-                        }
-                        Err(_) => {
-                            continue;
-                        }
-                    }
-                }
-            }
+/// Read back every known board and channel parameter of a board as
+/// `(path, value)` pairs, skipping the ones its firmware doesn't have.
+pub fn read_parameters(handle: u64) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let mut read = |path: String| {
+        if let Ok(value) = felib::get_value(handle, &path) {
+            params.push((path, value.trim().to_string()));
         }
+    };
 
-        if param_log.ends_with('\n') {
-            param_log.pop();
+    for &param in DIGITIZER_PARAMS {
+        read(format!("/par/{}", param));
+    }
+    let num_channels = crate::get_num_channels(handle).unwrap_or(0);
+    // InputDelay is set per group of 4 channels
+    for group in 0..num_channels.div_ceil(4) {
+        read(format!("/ch/{}/par/InputDelay", group * 4));
+    }
+    for ch in 0..num_channels {
+        for &ch_param in CHANNEL_PARAMS {
+            read(format!("/ch/{}/par/{}", ch, ch_param));
         }
-        info!("Digitizer {} parameters:\n{}", board_id, param_log);
     }
+    params
+}
+
+/// Log a board's parameters as read back by `read_parameters`.
+pub fn log_parameters(board_id: usize, params: &[(String, String)]) {
+    let param_log: Vec<String> = params
+        .iter()
+        .map(|(path, value)| format!("{}: {}", path, value))
+        .collect();
+    info!(
+        "Digitizer {} parameters:\n{}",
+        board_id,
+        param_log.join("\n")
+    );
 }
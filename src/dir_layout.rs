@@ -0,0 +1,294 @@
+use crate::RunSettings;
+use anyhow::{anyhow, Result};
+use std::{
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+use time::OffsetDateTime;
+
+/// Directory in `output_dir` with one directory per campaign layout, which
+/// has one file per run number claimed
+const RESERVATIONS_DIR: &str = "run_reservations";
+
+/// Placeholders a `dir_layout` template may use
+pub const DIR_LAYOUT_PLACEHOLDERS: [&str; 6] = [
+    "{stream}",
+    "{campaign}",
+    "{year}",
+    "{month}",
+    "{day}",
+    "{run}",
+];
+
+/// Check a `dir_layout` template for unknown placeholders and a campaign.
+pub fn validate_dir_layout(template: &str) -> Result<(), String> {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("unclosed placeholder in dir_layout \"{}\"", template))?;
+        let placeholder = &rest[start..start + end + 1];
+        if !DIR_LAYOUT_PLACEHOLDERS.contains(&placeholder) {
+            return Err(format!(
+                "unknown placeholder {} in dir_layout, use one of {}",
+                placeholder,
+                DIR_LAYOUT_PLACEHOLDERS.join(", ")
+            ));
+        }
+        rest = &rest[start + end + 1..];
+    }
+    if !template.contains("{campaign}") {
+        return Err(String::from(
+            "dir_layout must contain {campaign} to keep campaigns apart",
+        ));
+    }
+    if template.starts_with('/') || template.split('/').any(|c| c.is_empty() || c == "..") {
+        return Err(format!(
+            "dir_layout \"{}\" must be a relative path below output_dir",
+            template
+        ));
+    }
+    Ok(())
+}
+
+/// Parse a `dir_mode` like `"2775"` as octal permission bits.
+pub fn parse_dir_mode(mode: &str) -> Result<u32, String> {
+    u32::from_str_radix(mode, 8)
+        .ok()
+        .filter(|&bits| bits <= 0o7777)
+        .ok_or_else(|| format!("dir_mode \"{}\" isn't an octal mode like \"2775\"", mode))
+}
+
+/// Where a stream's run files go, from the `dir_layout` template below
+/// `output_dir`. The stream and campaign are fixed for a stream, while the
+/// date and run number are filled in as each run starts.
+#[derive(Debug, Clone)]
+pub struct DirLayout {
+    output_dir: PathBuf,
+    /// Template components with `{stream}` and `{campaign}` filled in
+    components: Vec<String>,
+    dir_mode: Option<u32>,
+}
+
+impl DirLayout {
+    pub fn new(settings: &RunSettings, stream: &str, campaign: usize) -> Self {
+        let components = settings
+            .dir_layout
+            .split('/')
+            .map(|c| {
+                c.replace("{stream}", stream)
+                    .replace("{campaign}", &campaign.to_string())
+            })
+            .collect();
+        Self {
+            output_dir: PathBuf::from(&settings.output_dir),
+            components,
+            dir_mode: settings
+                .dir_mode
+                .as_deref()
+                .and_then(|mode| parse_dir_mode(mode).ok()),
+        }
+    }
+
+    /// Number of leading components that are the same for every run.
+    fn fixed_len(&self) -> usize {
+        self.components
+            .iter()
+            .take_while(|c| !c.contains('{'))
+            .count()
+    }
+
+    /// Deepest directory holding all of the campaign's runs, where the
+    /// transfer manifest lives.
+    pub fn campaign_root(&self) -> PathBuf {
+        let mut root = self.output_dir.clone();
+        root.extend(&self.components[..self.fixed_len()]);
+        root
+    }
+
+    /// The campaign's directories with the per-run parts left as
+    /// placeholders, to tell whether two streams would share them.
+    pub fn campaign_pattern(&self) -> PathBuf {
+        let mut pattern = self.output_dir.clone();
+        pattern.extend(&self.components);
+        pattern
+    }
+
+    /// Where the campaign's run numbers are claimed: a directory per
+    /// `campaign_pattern` in `output_dir`, so campaigns never share numbering
+    /// even when their layout starts with a date or run placeholder. The
+    /// name is the pattern below `output_dir` with `/` escaped.
+    fn reservations_dir(&self) -> PathBuf {
+        let name = self
+            .components
+            .join("/")
+            .replace('%', "%25")
+            .replace('/', "%2F");
+        self.output_dir.join(RESERVATIONS_DIR).join(name)
+    }
+
+    /// Directory of a run starting at `start`, created along with any missing
+    /// parents.
+    pub fn create_run_dir(&self, run: usize, start: OffsetDateTime) -> Result<PathBuf> {
+        let mut dir = self.output_dir.clone();
+        dir.extend(self.components.iter().map(|c| {
+            c.replace("{year}", &format!("{:04}", start.year()))
+                .replace("{month}", &format!("{:02}", u8::from(start.month())))
+                .replace("{day}", &format!("{:02}", start.day()))
+                .replace("{run}", &format!("{:0>6}", run))
+        }));
+        self.create_dirs(&dir)?;
+        Ok(dir)
+    }
+
+    /// Create `dir` and its missing parents, giving the new ones `dir_mode`.
+    fn create_dirs(&self, dir: &Path) -> Result<()> {
+        let missing: Vec<PathBuf> = dir
+            .ancestors()
+            .take_while(|path| !path.as_os_str().is_empty() && !path.exists())
+            .map(Path::to_path_buf)
+            .collect();
+        fs::create_dir_all(dir)
+            .map_err(|e| anyhow!("failed to create {}: {}", dir.display(), e))?;
+        if let Some(mode) = self.dir_mode {
+            for path in missing.iter().rev() {
+                fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Highest run number claimed with `reserve_run`, by any host.
+    pub fn last_reserved_run(&self) -> Result<Option<usize>> {
+        let dir = self.reservations_dir();
+        if !dir.is_dir() {
+            return Ok(None);
+        }
+        let mut last = None;
+        for entry in fs::read_dir(dir)? {
+            let name = entry?.file_name();
+            let run = name
+                .to_str()
+                .and_then(|name| name.strip_prefix("run"))
+                .and_then(|run| run.parse::<usize>().ok());
+            last = last.max(run);
+        }
+        Ok(last)
+    }
+
+    /// Claim the first run number from `first` on that isn't claimed yet by
+    /// creating its reservation file. Creating a file that must not exist is
+    /// atomic, also on NFS, so hosts sharing the campaign's storage can't
+    /// claim the same number. The file records the host, process and stream
+    /// that claimed it.
+    pub fn reserve_run(&self, first: usize, stream: &str) -> Result<usize> {
+        let dir = self.reservations_dir();
+        self.create_dirs(&dir)?;
+        let mut run = first;
+        loop {
+            let path = dir.join(format!("run{:0>6}", run));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    writeln!(
+                        file,
+                        "host={} pid={} stream={}",
+                        hostname(),
+                        std::process::id(),
+                        stream
+                    )?;
+                    return Ok(run);
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => run += 1,
+                Err(e) => return Err(anyhow!("failed to reserve {}: {}", path.display(), e)),
+            }
+        }
+    }
+
+    /// Every file in the campaign's run directories, of every run and date.
+    pub fn campaign_files(&self) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        let root = self.campaign_root();
+        if root.is_dir() {
+            collect_files(&root, &self.components[self.fixed_len()..], &mut files)?;
+        }
+        Ok(files)
+    }
+}
+
+/// Name of this host, to tell apart the hosts sharing a campaign.
+fn hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|name| name.trim().to_string())
+        .unwrap_or_else(|_| String::from("unknown"))
+}
+
+/// Add the files below `dir` whose directories match `patterns`, one per
+/// level.
+fn collect_files(dir: &Path, patterns: &[String], files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        match patterns.split_first() {
+            None if path.is_file() => files.push(path),
+            Some((pattern, rest)) if path.is_dir() => {
+                let matches = path
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|name| component_matches(pattern, name));
+                if matches {
+                    collect_files(&path, rest, files)?;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Whether a directory name fits a template component whose remaining
+/// placeholders (date and run) each stand for a number.
+fn component_matches(pattern: &str, name: &str) -> bool {
+    let Some(start) = pattern.find('{') else {
+        return pattern == name;
+    };
+    let Some(len) = pattern[start..].find('}') else {
+        return pattern == name;
+    };
+    let Some(name) = name.strip_prefix(&pattern[..start]) else {
+        return false;
+    };
+    let rest = &pattern[start + len + 1..];
+    let digits = name.len() - name.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    (1..=digits).any(|n| component_matches(rest, &name[n..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(output_dir: &Path, template: &str, campaign: usize) -> DirLayout {
+        DirLayout {
+            output_dir: output_dir.to_path_buf(),
+            components: template
+                .split('/')
+                .map(|c| c.replace("{campaign}", &campaign.to_string()))
+                .collect(),
+            dir_mode: None,
+        }
+    }
+
+    #[test]
+    fn reservations_are_per_campaign_with_leading_date() {
+        let output_dir =
+            std::env::temp_dir().join(format!("cliq-reservations-{}", std::process::id()));
+        let first = layout(&output_dir, "{year}/camp{campaign}", 1);
+        let second = layout(&output_dir, "{year}/camp{campaign}", 2);
+        assert_eq!(first.reserve_run(0, "daq").unwrap(), 0);
+        assert_eq!(first.reserve_run(0, "daq").unwrap(), 1);
+        assert_eq!(first.last_reserved_run().unwrap(), Some(1));
+        assert_eq!(second.last_reserved_run().unwrap(), None);
+        assert_eq!(second.reserve_run(0, "daq").unwrap(), 0);
+        fs::remove_dir_all(&output_dir).unwrap();
+    }
+}
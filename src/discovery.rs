@@ -0,0 +1,91 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::fs;
+use toml_edit::{ArrayOfTables, DocumentMut};
+
+/// A digitizer found on the network or USB by FELib device discovery.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct DiscoveredDevice {
+    #[serde(rename = "ModelName", default)]
+    pub model: String,
+    #[serde(rename = "SerialNum", default)]
+    pub serial: String,
+    #[serde(rename = "IPAddress", default)]
+    pub ip: String,
+    #[serde(rename = "Url", default)]
+    pub url: Option<String>,
+}
+
+impl DiscoveredDevice {
+    /// Connection string to put in `run_settings.boards`.
+    pub fn url(&self) -> String {
+        self.url
+            .clone()
+            .unwrap_or_else(|| format!("dig2://{}", self.ip))
+    }
+}
+
+/// Prefix of `run_settings.boards` entries naming a board by serial number
+pub const SERIAL_PREFIX: &str = "serial:";
+
+/// Turn the configured boards into connection URLs. Boards given as
+/// `serial:<number>` are looked up with device discovery so an address change
+/// can't swap boards (and their channel maps) between runs.
+pub fn resolve_board_urls(boards: &[String]) -> Result<Vec<String>> {
+    if !boards.iter().any(|b| b.starts_with(SERIAL_PREFIX)) {
+        return Ok(boards.to_vec());
+    }
+    let devices = discover_devices()?;
+    boards
+        .iter()
+        .map(|board| {
+            let Some(serial) = board.strip_prefix(SERIAL_PREFIX) else {
+                return Ok(board.clone());
+            };
+            let serial = serial.trim();
+            devices
+                .iter()
+                .find(|d| d.serial.trim() == serial)
+                .map(|d| d.url())
+                .ok_or_else(|| anyhow!("No board with serial number {} was discovered", serial))
+        })
+        .collect()
+}
+
+/// Ask FELib for every digitizer it can reach.
+pub fn discover_devices() -> Result<Vec<DiscoveredDevice>> {
+    let devices = crate::felib::devices_discovery()?;
+    serde_json::from_str(&devices).map_err(|e| anyhow!("Unreadable device discovery result: {}", e))
+}
+
+/// Write a board URL into `run_settings.boards` of a config file, keeping its
+/// comments and layout. `slot` past the end adds a new board, with board and
+/// sync settings copied from the last board so the sections stay in step.
+pub fn set_board_url(config_file: &str, slot: usize, url: &str) -> Result<()> {
+    let mut doc: DocumentMut = fs::read_to_string(config_file)?.parse()?;
+    let boards = doc["run_settings"]["boards"]
+        .as_array_mut()
+        .ok_or_else(|| anyhow!("{} has no run_settings.boards list", config_file))?;
+
+    if slot < boards.len() {
+        boards.replace(slot, url);
+    } else {
+        boards.push(url);
+        for section in ["board_settings", "sync_settings"] {
+            if let Some(tables) = doc[section]["boards"].as_array_of_tables_mut() {
+                copy_last(tables);
+            }
+        }
+    }
+
+    fs::write(config_file, doc.to_string())?;
+    Ok(())
+}
+
+fn copy_last(tables: &mut ArrayOfTables) {
+    if let Some(mut table) = tables.iter().last().cloned() {
+        // drop the copied comments, they describe the original board
+        table.decor_mut().clear();
+        tables.push(table);
+    }
+}
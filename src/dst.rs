@@ -0,0 +1,123 @@
+use crate::{write_scalar_attr, write_str_attr, Compressed, DatasetParams};
+use anyhow::Result;
+use hdf5::{Dataset, File, H5Type};
+use ndarray::Array1;
+use std::path::{Path, PathBuf};
+
+/// One pulse found in a channel's record, a row of the reduced file.
+#[derive(H5Type, Debug, Clone, Copy, Default, PartialEq)]
+#[repr(C)]
+pub struct Pulse {
+    pub board: u32,
+    /// Board channel
+    pub channel: u32,
+    pub trigger_id: u32,
+    /// Timestamp of the event in ns
+    pub timestamp: u64,
+    /// Sample the signal crossed the threshold at
+    pub start: u32,
+    /// Samples the signal stayed over the threshold
+    pub width: u32,
+    /// Largest excursion from the baseline in ADC counts, positive for
+    /// either edge
+    pub amplitude: f32,
+    /// Sum of the baseline-subtracted samples of the integration window,
+    /// positive for either edge
+    pub integral: f32,
+    pub baseline: f32,
+}
+
+/// Pulse finding settings recorded in the reduced file.
+#[derive(Debug, Clone, Copy)]
+pub struct DstParams {
+    pub threshold: f64,
+    pub pre_samples: usize,
+    pub integration_samples: usize,
+    pub baseline_samples: usize,
+}
+
+/// Writes a run's pulses to its reduced file, `dst_run<N>.h5` next to the
+/// data files, as one `pulses` table that grows as pulses come in.
+pub struct DstWriter {
+    file: File,
+    pulses: Dataset,
+    buffer: Vec<Pulse>,
+    buffer_capacity: usize,
+    written: usize,
+}
+
+impl DstWriter {
+    /// Create the reduced file of the run whose first data file is
+    /// `run_file`.
+    pub fn create(
+        run_file: &Path,
+        params: &DatasetParams,
+        dst: DstParams,
+        edge: &str,
+    ) -> Result<Self> {
+        let file = File::create(dst_path(run_file))?;
+        let pulses = file
+            .new_dataset::<Pulse>()
+            .shape(0..)
+            .compressed(params)
+            .chunk(params.chunk_events)
+            .create("pulses")?;
+        write_str_attr(&file, "edge", edge)?;
+        write_scalar_attr(&file, "threshold", &dst.threshold)?;
+        for (name, value) in [
+            ("pre_samples", dst.pre_samples),
+            ("integration_samples", dst.integration_samples),
+            ("baseline_samples", dst.baseline_samples),
+        ] {
+            write_scalar_attr(&file, name, &value)?;
+        }
+        Ok(Self {
+            file,
+            pulses,
+            buffer: Vec::with_capacity(params.buffer_capacity),
+            buffer_capacity: params.buffer_capacity,
+            written: 0,
+        })
+    }
+
+    /// Buffer an event's pulses, writing them out once the buffer is full.
+    pub fn append(&mut self, pulses: Vec<Pulse>) -> Result<()> {
+        self.buffer.extend(pulses);
+        if self.buffer.len() >= self.buffer_capacity {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Write the buffered pulses.
+    pub fn flush(&mut self) -> Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let count = self.buffer.len();
+        self.pulses.resize(self.written + count)?;
+        let pulses = Array1::from(std::mem::take(&mut self.buffer));
+        self.pulses
+            .write_slice(&pulses, self.written..self.written + count)?;
+        self.written += count;
+        Ok(())
+    }
+
+    /// Write the remaining pulses and close the file.
+    pub fn finish(mut self) -> Result<()> {
+        self.flush()?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+/// Reduced file of the run whose first data file is `run_file`, e.g.
+/// `dst_run000012.h5` for `run000012_00.h5`.
+pub fn dst_path(run_file: &Path) -> PathBuf {
+    let stem = run_file
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default();
+    let run = stem.rsplit_once('_').map_or(stem, |(run, _)| run);
+    run_file.with_file_name(format!("dst_{}.h5", run))
+}
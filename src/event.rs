@@ -17,6 +17,21 @@ pub struct CEvent {
     pub n_channels: usize,
 }
 
+/// Fields of a DPP-PSD hit besides its timestamp and size, which go in the
+/// `CEvent` as for scope events.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DppPsdHit {
+    pub channel: u8,
+    /// Sub-sample part of the timestamp, in 1/1024 of a sample
+    pub fine_timestamp: u16,
+    /// Charge in the long gate
+    pub energy: u16,
+    /// Charge in the short gate
+    pub energy_short: u16,
+    pub flags_low_priority: u16,
+    pub flags_high_priority: u16,
+}
+
 /// A safe wrapper that owns the allocated memory for a CEvent.
 ///
 /// The inner `c_event` field can be passed to the C function, while the owned
@@ -34,6 +49,12 @@ pub struct EventWrapper {
     // Owned memory for the per-channel arrays.
     n_samples: Box<[usize]>,
     n_allocated_samples: Box<[usize]>,
+    /// Room the board's channels that aren't kept are all read into, as
+    /// FELib writes every channel's samples whatever is allocated. Empty if
+    /// every channel is kept
+    scratch: Box<[u16]>,
+    /// Hit read from a DPP-PSD endpoint, which has no waveforms
+    pub dpp_psd: Option<DppPsdHit>,
 }
 
 unsafe impl Send for EventWrapper {}
@@ -46,22 +67,42 @@ impl EventWrapper {
     /// * `n_channels` - Number of waveforms/channels.
     /// * `waveform_len` - Number of samples per waveform.
     pub fn new(n_channels: usize, waveform_len: usize) -> Self {
-        // Create a 2D array for waveform data with dimensions (n_channels, waveform_len).
-        let mut waveform_data = Array2::<u16>::zeros((n_channels, waveform_len));
-
-        // Build a vector of pointers—one per row.
-        let mut waveform_ptrs_vec = Vec::with_capacity(n_channels);
-        for i in 0..n_channels {
-            // Get a mutable pointer to the row i.
-            let row_ptr = waveform_data.as_mut_ptr().wrapping_add(i * waveform_len);
-            waveform_ptrs_vec.push(row_ptr);
+        let channels: Vec<usize> = (0..n_channels).collect();
+        Self::with_channels(n_channels, waveform_len, &channels)
+    }
+
+    /// Create an EventWrapper holding only some of the board's channels.
+    ///
+    /// # Arguments
+    ///
+    /// * `n_channels` - Number of channels of the board, as CAEN fills one
+    ///   waveform per board channel.
+    /// * `waveform_len` - Number of samples per waveform.
+    /// * `channels` - Channels to keep, in order; row `i` of `waveform_data`
+    ///   is channel `channels[i]`.
+    pub fn with_channels(n_channels: usize, waveform_len: usize, channels: &[usize]) -> Self {
+        // Create a 2D array for waveform data with one row per kept channel.
+        let mut waveform_data = Array2::<u16>::zeros((channels.len(), waveform_len));
+
+        // Build a vector of pointers—one per board channel. Channels that
+        // aren't kept share one scratch row that is overwritten every read.
+        let scratch_len = if channels.len() < n_channels {
+            waveform_len
+        } else {
+            0
+        };
+        let mut scratch = vec![0u16; scratch_len].into_boxed_slice();
+        let mut waveform_ptrs_vec = vec![scratch.as_mut_ptr(); n_channels];
+        let mut n_allocated_samples = vec![waveform_len; n_channels].into_boxed_slice();
+        for (row, &ch) in channels.iter().enumerate() {
+            // Get a mutable pointer to the row.
+            let row_ptr = waveform_data.as_mut_ptr().wrapping_add(row * waveform_len);
+            waveform_ptrs_vec[ch] = row_ptr;
         }
         let mut waveform_ptrs = waveform_ptrs_vec.into_boxed_slice();
 
-        // Allocate the arrays for n_samples and n_allocated_samples.
-        // Here we assume that each channel is allocated with `waveform_len` samples.
+        // Allocate the array for n_samples.
         let mut n_samples = vec![0usize; n_channels].into_boxed_slice();
-        let mut n_allocated_samples = vec![waveform_len; n_channels].into_boxed_slice();
 
         // Get mutable raw pointers to pass to the C API.
         let waveform_ptr = waveform_ptrs.as_mut_ptr();
@@ -88,6 +129,67 @@ impl EventWrapper {
             waveform_ptrs,
             n_samples,
             n_allocated_samples,
+            scratch,
+            dpp_psd: None,
         }
     }
+
+    /// Create an EventWrapper for one DPP-PSD hit, without waveforms.
+    pub fn new_dpp_psd() -> Self {
+        Self {
+            dpp_psd: Some(DppPsdHit::default()),
+            ..Self::new(0, 0)
+        }
+    }
+}
+
+/// One read of the raw endpoint: the board's data as sent, undecoded.
+#[derive(Debug, Clone)]
+pub struct RawBlock {
+    /// Room for the largest block the board sends, `size` bytes of it used
+    pub data: Vec<u8>,
+    pub size: usize,
+    /// Events in the block
+    pub n_events: u32,
+}
+
+impl RawBlock {
+    /// Create a block with room for `capacity` bytes.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            data: vec![0; capacity],
+            size: 0,
+            n_events: 0,
+        }
+    }
+}
+
+/// One read of the stats endpoint, one entry per board channel.
+#[derive(Debug, Clone)]
+pub struct EndpointStats {
+    /// Times since the start of the run in ns
+    pub real_time: Vec<u64>,
+    pub dead_time: Vec<u64>,
+    pub live_time: Vec<u64>,
+    pub trigger_cnt: Vec<u32>,
+    pub saved_event_cnt: Vec<u32>,
+}
+
+impl EndpointStats {
+    pub fn new(n_channels: usize) -> Self {
+        Self {
+            real_time: vec![0; n_channels],
+            dead_time: vec![0; n_channels],
+            live_time: vec![0; n_channels],
+            trigger_cnt: vec![0; n_channels],
+            saved_event_cnt: vec![0; n_channels],
+        }
+    }
+}
+
+/// A record read from one of a board's extra endpoints, next to its events.
+#[derive(Debug, Clone)]
+pub enum EndpointRecord {
+    Raw(RawBlock),
+    Stats(EndpointStats),
 }
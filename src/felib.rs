@@ -2,10 +2,13 @@
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
 
+#[cfg(feature = "felib")]
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 
-use crate::EventWrapper;
+use crate::{Backend, EndpointStats, EventWrapper, RawBlock};
+#[cfg(feature = "felib")]
 use std::ffi::CString;
+use std::sync::Arc;
 
 #[repr(i32)]
 #[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
@@ -53,38 +56,49 @@ impl From<i32> for FELibReturn {
     }
 }
 
+impl FELibReturn {
+    /// What the return code means, after CAEN's error descriptions
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Success => "operation completed successfully",
+            Self::Generic => "unspecified error",
+            Self::InvalidParam => "invalid parameter",
+            Self::DevAlreadyOpen => "device already open",
+            Self::DevNotFound => "device not found",
+            Self::MaxDev => "maximum number of devices reached",
+            Self::Command => "command error",
+            Self::Internal => "internal library error",
+            Self::NotImplemented => "not implemented",
+            Self::InvalidHandle => "invalid handle",
+            Self::DevLibNotAvailable => "device library not available",
+            Self::Timeout => "timeout",
+            Self::Stop => "acquisition stopped",
+            Self::Disabled => "disabled",
+            Self::BadLibVer => "bad library version",
+            Self::Comm => "communication error",
+            Self::Unknown => "unknown error code",
+        }
+    }
+}
+
 impl std::fmt::Display for FELibReturn {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self)
+        write!(f, "{}", self.description())
     }
 }
 
 impl std::error::Error for FELibReturn {}
 
-pub fn felib_getlibinfo() -> Result<String, FELibReturn> {
-    let buffer_size = 1024;
-    let mut buffer = vec![0u8; buffer_size];
-    let res = unsafe { CAEN_FELib_GetLibInfo(buffer.as_mut_ptr() as *mut i8, buffer_size) };
-    let res = FELibReturn::from(res);
-    buffer.retain(|&b| b != 0);
-    match res {
-        FELibReturn::Success => Ok(String::from_utf8(buffer).unwrap()),
-        _ => Err(res),
-    }
+pub fn lib_info() -> Result<String, FELibReturn> {
+    crate::backend().lib_info()
 }
 
-pub fn felib_getlibversion() -> Result<String, FELibReturn> {
-    let mut libv = vec![0u8; 16];
-    let res = unsafe { CAEN_FELib_GetLibVersion(libv.as_mut_ptr() as *mut i8) };
-    let res = FELibReturn::from(res);
-    libv.retain(|&b| b != 0);
-    match res {
-        FELibReturn::Success => Ok(String::from_utf8(libv).unwrap()),
-        _ => Err(res),
-    }
+pub fn lib_version() -> Result<String, FELibReturn> {
+    crate::backend().lib_version()
 }
 
-pub fn felib_geterrorname(error: CAEN_FELib_ErrorCode) -> Result<String, FELibReturn> {
+#[cfg(feature = "felib")]
+pub fn error_name(error: CAEN_FELib_ErrorCode) -> Result<String, FELibReturn> {
     let mut err_name = vec![0u8; 32];
     let res = unsafe { CAEN_FELib_GetErrorName(error, err_name.as_mut_ptr() as *mut i8) };
     let res = FELibReturn::from(res);
@@ -95,9 +109,10 @@ pub fn felib_geterrorname(error: CAEN_FELib_ErrorCode) -> Result<String, FELibRe
     }
 }
 
-pub fn felib_geterrordesc(error: CAEN_FELib_ErrorCode) -> Result<String, FELibReturn> {
+#[cfg(feature = "felib")]
+pub fn error_desc(error: CAEN_FELib_ErrorCode) -> Result<String, FELibReturn> {
     let mut err_desc = vec![0u8; 256];
-    let res = unsafe { CAEN_FELib_GetErrorName(error, err_desc.as_mut_ptr() as *mut i8) };
+    let res = unsafe { CAEN_FELib_GetErrorDescription(error, err_desc.as_mut_ptr() as *mut i8) };
     let res = FELibReturn::from(res);
     err_desc.retain(|&b| b != 0);
     match res {
@@ -106,163 +121,501 @@ pub fn felib_geterrordesc(error: CAEN_FELib_ErrorCode) -> Result<String, FELibRe
     }
 }
 
-pub fn felib_getlasterror() -> Result<String, FELibReturn> {
-    let mut last_err = vec![0u8; 1024];
-    let res = unsafe { CAEN_FELib_GetLibVersion(last_err.as_mut_ptr() as *mut i8) };
-    let res = FELibReturn::from(res);
-    last_err.retain(|&b| b != 0);
-    match res {
-        FELibReturn::Success => Ok(String::from_utf8(last_err).unwrap()),
-        _ => Err(res),
-    }
+/// Details of the calling thread's last error, as the library words them.
+pub fn last_error() -> Result<String, FELibReturn> {
+    crate::backend().last_error()
 }
 
-pub fn felib_devicesdiscovery() -> Result<String, FELibReturn> {
-    let buffer_size = 1024;
-    let mut devices = vec![0u8; buffer_size];
-    let res =
-        unsafe { CAEN_FELib_DevicesDiscovery(devices.as_mut_ptr() as *mut i8, buffer_size, 5) };
-    let res = FELibReturn::from(res);
-    devices.retain(|&b| b != 0);
-    match res {
-        FELibReturn::Success => Ok(String::from_utf8(devices).unwrap()),
-        _ => Err(res),
-    }
+pub fn devices_discovery() -> Result<String, FELibReturn> {
+    crate::backend().devices_discovery()
 }
 
-pub fn felib_open(url: &str) -> Result<u64, FELibReturn> {
-    let mut handle = 0;
-    let url = CString::new(url).unwrap();
-    let res = unsafe { CAEN_FELib_Open(url.as_ptr(), &mut handle) };
-    let res = FELibReturn::from(res);
-    match res {
-        FELibReturn::Success => Ok(handle),
-        _ => Err(res),
+/// An open connection to a board. Clones share the connection, which is
+/// closed once the last clone is dropped, so a board can't be closed while a
+/// data-taking thread or endpoint still uses it.
+#[derive(Debug, Clone)]
+pub struct DeviceHandle(Arc<RawDevice>);
+
+#[derive(Debug)]
+struct RawDevice(u64);
+
+impl Drop for RawDevice {
+    fn drop(&mut self) {
+        if let Err(e) = close(self.0) {
+            log::warn!("Failed to close board handle {}: {:?}", self.0, e);
+        }
     }
 }
 
-pub fn felib_close(handle: u64) -> Result<(), FELibReturn> {
-    let res = unsafe { CAEN_FELib_Close(handle) };
-    let res = FELibReturn::from(res);
-    match res {
-        FELibReturn::Success => Ok(()),
-        _ => Err(res),
+impl DeviceHandle {
+    pub fn raw(&self) -> u64 {
+        self.0 .0
     }
-}
 
-pub fn felib_getimpllibversion(handle: u64) -> Result<String, FELibReturn> {
-    let mut libv = vec![0u8; 16];
-    let res = unsafe { CAEN_FELib_GetImplLibVersion(handle, libv.as_mut_ptr() as *mut i8) };
-    let res = FELibReturn::from(res);
-    libv.retain(|&b| b != 0);
-    match res {
-        FELibReturn::Success => Ok(String::from_utf8(libv).unwrap()),
-        _ => Err(res),
+    /// Get the handle of one of the board's endpoints, e.g. `/endpoint/scope`.
+    pub fn endpoint(&self, path: &str) -> Result<EndpointHandle, FELibReturn> {
+        Ok(EndpointHandle {
+            raw: get_handle(self.raw(), path)?,
+            _device: self.clone(),
+        })
     }
 }
 
-pub fn felib_getdevicetree(handle: u64) -> Result<String, FELibReturn> {
-    let buffer_size = 1024;
-    let mut dev_tree = vec![0u8; buffer_size];
-    let res =
-        unsafe { CAEN_FELib_GetDeviceTree(handle, dev_tree.as_mut_ptr() as *mut i8, buffer_size) };
-    let res = FELibReturn::from(res);
-    dev_tree.retain(|&b| b != 0);
-    match res {
-        FELibReturn::Success => Ok(String::from_utf8(dev_tree).unwrap()),
-        _ => Err(res),
-    }
+/// A readout endpoint of a board. It holds on to its board so the connection
+/// stays open for as long as the endpoint is in use.
+#[derive(Debug)]
+pub struct EndpointHandle {
+    raw: u64,
+    _device: DeviceHandle,
 }
 
-pub fn felib_getvalue(handle: u64, path: &str) -> Result<String, FELibReturn> {
-    let mut value = vec![0u8; 256];
-    let path = CString::new(path).unwrap();
-    let res = unsafe { CAEN_FELib_GetValue(handle, path.as_ptr(), value.as_mut_ptr() as *mut i8) };
-    let res = FELibReturn::from(res);
-    value.retain(|&b| b != 0);
-    match res {
-        FELibReturn::Success => Ok(String::from_utf8(value).unwrap()),
-        _ => Err(res),
+impl EndpointHandle {
+    pub fn raw(&self) -> u64 {
+        self.raw
     }
-}
 
-pub fn felib_setvalue(handle: u64, path: &str, value: &str) -> Result<(), FELibReturn> {
-    let path = CString::new(path).unwrap();
-    let value = CString::new(value).unwrap();
-    let res = unsafe { CAEN_FELib_SetValue(handle, path.as_ptr(), value.as_ptr()) };
-    let res = FELibReturn::from(res);
-    match res {
-        FELibReturn::Success => Ok(()),
-        _ => Err(res),
+    /// Make this the board's active endpoint.
+    pub fn activate(&self, name: &str) -> Result<(), FELibReturn> {
+        let folder = get_parent_handle(self.raw, "")?;
+        set_value(folder, "/par/activeendpoint", name)
     }
 }
 
-pub fn felib_sendcommand(handle: u64, path: &str) -> Result<(), FELibReturn> {
-    let path = CString::new(path).unwrap();
-    let res = unsafe { CAEN_FELib_SendCommand(handle, path.as_ptr()) };
-    let res = FELibReturn::from(res);
-    match res {
-        FELibReturn::Success => Ok(()),
-        _ => Err(res),
-    }
+pub fn open(url: &str) -> Result<DeviceHandle, FELibReturn> {
+    crate::backend()
+        .open(url)
+        .map(|handle| DeviceHandle(Arc::new(RawDevice(handle))))
 }
 
-pub fn felib_setreaddataformat(handle: u64, format: &str) -> Result<(), FELibReturn> {
-    let format = CString::new(format).unwrap();
-    let res = unsafe { CAEN_FELib_SetReadDataFormat(handle, format.as_ptr()) };
-    let res = FELibReturn::from(res);
-    match res {
-        FELibReturn::Success => Ok(()),
-        _ => Err(res),
-    }
+fn close(handle: u64) -> Result<(), FELibReturn> {
+    crate::backend().close(handle)
 }
 
-pub fn felib_readdata(handle: u64, data: &mut EventWrapper) -> FELibReturn {
-    let res = unsafe {
-        CAEN_FELib_ReadData(
-            handle,
-            100,
-            &mut data.c_event.timestamp,
-            &mut data.c_event.trigger_id,
-            data.c_event.waveform,
-            data.c_event.n_samples,
-            &mut data.c_event.flags,
-            &mut data.c_event.board_fail,
-            &mut data.c_event.event_size,
-        )
-    };
-    FELibReturn::from(res)
+pub fn impl_lib_version(handle: u64) -> Result<String, FELibReturn> {
+    crate::backend().impl_lib_version(handle)
 }
 
-pub fn felib_hasdata(handle: u64) -> Result<(), FELibReturn> {
-    let res = unsafe { CAEN_FELib_HasData(handle, 5) };
-    let res = FELibReturn::from(res);
-    match res {
-        FELibReturn::Success => Ok(()),
-        _ => Err(res),
-    }
+pub fn device_tree(handle: u64) -> Result<String, FELibReturn> {
+    crate::backend().device_tree(handle)
 }
 
-pub fn felib_gethandle(handle: u64, path: &str, path_handle: &mut u64) -> Result<(), FELibReturn> {
-    let path = CString::new(path).unwrap();
-    let res = unsafe { CAEN_FELib_GetHandle(handle, path.as_ptr(), path_handle) };
-    let res = FELibReturn::from(res);
-    match res {
-        FELibReturn::Success => Ok(()),
-        _ => Err(res),
-    }
+pub fn get_value(handle: u64, path: &str) -> Result<String, FELibReturn> {
+    crate::backend().get_value(handle, path)
 }
 
-pub fn felib_getparenthandle(
+pub fn set_value(handle: u64, path: &str, value: &str) -> Result<(), FELibReturn> {
+    crate::backend().set_value(handle, path, value)
+}
+
+pub fn send_command(handle: u64, path: &str) -> Result<(), FELibReturn> {
+    crate::backend().send_command(handle, path)
+}
+
+pub fn set_read_data_format(handle: u64, format: &str) -> Result<(), FELibReturn> {
+    crate::backend().set_read_data_format(handle, format)
+}
+
+/// Read one event in `EVENT_FORMAT`, or `EVENT_FORMAT_FINE_TIMESTAMP` with
+/// `fine_timestamp`, waiting up to `timeout_ms` for it.
+pub fn read_data(
     handle: u64,
-    path: &str,
-    path_handle: &mut u64,
-) -> Result<(), FELibReturn> {
-    let path = CString::new(path).unwrap();
-    let res = unsafe { CAEN_FELib_GetParentHandle(handle, path.as_ptr(), path_handle) };
-    let res = FELibReturn::from(res);
-    match res {
-        FELibReturn::Success => Ok(()),
-        _ => Err(res),
+    timeout_ms: i32,
+    data: &mut EventWrapper,
+    fine_timestamp: bool,
+) -> FELibReturn {
+    crate::backend().read_data(handle, timeout_ms, data, fine_timestamp)
+}
+
+/// Read one hit from a DPP-PSD endpoint into `data`, which must come from
+/// `EventWrapper::new_dpp_psd`, waiting up to `timeout_ms` for it.
+pub fn read_dpp_psd(handle: u64, timeout_ms: i32, data: &mut EventWrapper) -> FELibReturn {
+    crate::backend().read_dpp_psd(handle, timeout_ms, data)
+}
+
+/// Read one block in `RAW_DATA_FORMAT` from a raw endpoint into `block`,
+/// waiting up to `timeout_ms` for it.
+pub fn read_raw(handle: u64, timeout_ms: i32, block: &mut RawBlock) -> FELibReturn {
+    crate::backend().read_raw(handle, timeout_ms, block)
+}
+
+/// Read the counters in `STATS_FORMAT` from a stats endpoint into `stats`,
+/// which needs an entry per board channel, waiting up to `timeout_ms`.
+pub fn read_stats(handle: u64, timeout_ms: i32, stats: &mut EndpointStats) -> FELibReturn {
+    crate::backend().read_stats(handle, timeout_ms, stats)
+}
+
+/// Wait up to `timeout_ms` for an event to be ready on an endpoint, without
+/// reading it.
+pub fn has_data(handle: u64, timeout_ms: i32) -> Result<(), FELibReturn> {
+    crate::backend().has_data(handle, timeout_ms)
+}
+
+pub fn get_handle(handle: u64, path: &str) -> Result<u64, FELibReturn> {
+    crate::backend().get_handle(handle, path)
+}
+
+pub fn get_parent_handle(handle: u64, path: &str) -> Result<u64, FELibReturn> {
+    crate::backend().get_parent_handle(handle, path)
+}
+
+/// The CAEN FELib shared library, cliq's backend for real boards.
+#[derive(Debug, Clone, Copy)]
+pub struct FELib;
+
+#[cfg(feature = "felib")]
+impl Backend for FELib {
+    fn name(&self) -> &'static str {
+        "FELib"
+    }
+
+    fn lib_info(&self) -> Result<String, FELibReturn> {
+        let buffer_size = 1024;
+        let mut buffer = vec![0u8; buffer_size];
+        let res = unsafe { CAEN_FELib_GetLibInfo(buffer.as_mut_ptr() as *mut i8, buffer_size) };
+        let res = FELibReturn::from(res);
+        buffer.retain(|&b| b != 0);
+        match res {
+            FELibReturn::Success => Ok(String::from_utf8(buffer).unwrap()),
+            _ => Err(res),
+        }
+    }
+
+    fn lib_version(&self) -> Result<String, FELibReturn> {
+        let mut libv = vec![0u8; 16];
+        let res = unsafe { CAEN_FELib_GetLibVersion(libv.as_mut_ptr() as *mut i8) };
+        let res = FELibReturn::from(res);
+        libv.retain(|&b| b != 0);
+        match res {
+            FELibReturn::Success => Ok(String::from_utf8(libv).unwrap()),
+            _ => Err(res),
+        }
+    }
+
+    fn last_error(&self) -> Result<String, FELibReturn> {
+        let mut last_err = vec![0u8; 1024];
+        let res = unsafe { CAEN_FELib_GetLastError(last_err.as_mut_ptr() as *mut i8) };
+        let res = FELibReturn::from(res);
+        last_err.retain(|&b| b != 0);
+        match res {
+            FELibReturn::Success => Ok(String::from_utf8_lossy(&last_err).into_owned()),
+            _ => Err(res),
+        }
+    }
+
+    fn devices_discovery(&self) -> Result<String, FELibReturn> {
+        let buffer_size = 1024;
+        let mut devices = vec![0u8; buffer_size];
+        let res =
+            unsafe { CAEN_FELib_DevicesDiscovery(devices.as_mut_ptr() as *mut i8, buffer_size, 5) };
+        let res = FELibReturn::from(res);
+        devices.retain(|&b| b != 0);
+        match res {
+            FELibReturn::Success => Ok(String::from_utf8(devices).unwrap()),
+            _ => Err(res),
+        }
+    }
+
+    fn open(&self, url: &str) -> Result<u64, FELibReturn> {
+        let mut handle = 0;
+        let url = CString::new(url).unwrap();
+        let res = unsafe { CAEN_FELib_Open(url.as_ptr(), &mut handle) };
+        let res = FELibReturn::from(res);
+        match res {
+            FELibReturn::Success => Ok(handle),
+            _ => Err(res),
+        }
+    }
+
+    fn close(&self, handle: u64) -> Result<(), FELibReturn> {
+        let res = unsafe { CAEN_FELib_Close(handle) };
+        let res = FELibReturn::from(res);
+        match res {
+            FELibReturn::Success => Ok(()),
+            _ => Err(res),
+        }
+    }
+
+    fn impl_lib_version(&self, handle: u64) -> Result<String, FELibReturn> {
+        let mut libv = vec![0u8; 16];
+        let res = unsafe { CAEN_FELib_GetImplLibVersion(handle, libv.as_mut_ptr() as *mut i8) };
+        let res = FELibReturn::from(res);
+        libv.retain(|&b| b != 0);
+        match res {
+            FELibReturn::Success => Ok(String::from_utf8(libv).unwrap()),
+            _ => Err(res),
+        }
+    }
+
+    fn device_tree(&self, handle: u64) -> Result<String, FELibReturn> {
+        let buffer_size = 1024;
+        let mut dev_tree = vec![0u8; buffer_size];
+        let res = unsafe {
+            CAEN_FELib_GetDeviceTree(handle, dev_tree.as_mut_ptr() as *mut i8, buffer_size)
+        };
+        let res = FELibReturn::from(res);
+        dev_tree.retain(|&b| b != 0);
+        match res {
+            FELibReturn::Success => Ok(String::from_utf8(dev_tree).unwrap()),
+            _ => Err(res),
+        }
+    }
+
+    fn get_value(&self, handle: u64, path: &str) -> Result<String, FELibReturn> {
+        let mut value = vec![0u8; 256];
+        let path = CString::new(path).unwrap();
+        let res =
+            unsafe { CAEN_FELib_GetValue(handle, path.as_ptr(), value.as_mut_ptr() as *mut i8) };
+        let res = FELibReturn::from(res);
+        value.retain(|&b| b != 0);
+        match res {
+            FELibReturn::Success => Ok(String::from_utf8(value).unwrap()),
+            _ => Err(res),
+        }
+    }
+
+    fn set_value(&self, handle: u64, path: &str, value: &str) -> Result<(), FELibReturn> {
+        let path = CString::new(path).unwrap();
+        let value = CString::new(value).unwrap();
+        let res = unsafe { CAEN_FELib_SetValue(handle, path.as_ptr(), value.as_ptr()) };
+        let res = FELibReturn::from(res);
+        match res {
+            FELibReturn::Success => Ok(()),
+            _ => Err(res),
+        }
+    }
+
+    fn send_command(&self, handle: u64, path: &str) -> Result<(), FELibReturn> {
+        let path = CString::new(path).unwrap();
+        let res = unsafe { CAEN_FELib_SendCommand(handle, path.as_ptr()) };
+        let res = FELibReturn::from(res);
+        match res {
+            FELibReturn::Success => Ok(()),
+            _ => Err(res),
+        }
+    }
+
+    fn set_read_data_format(&self, handle: u64, format: &str) -> Result<(), FELibReturn> {
+        let format = CString::new(format).unwrap();
+        let res = unsafe { CAEN_FELib_SetReadDataFormat(handle, format.as_ptr()) };
+        let res = FELibReturn::from(res);
+        match res {
+            FELibReturn::Success => Ok(()),
+            _ => Err(res),
+        }
+    }
+
+    fn read_data(
+        &self,
+        handle: u64,
+        timeout_ms: i32,
+        data: &mut EventWrapper,
+        fine_timestamp: bool,
+    ) -> FELibReturn {
+        if fine_timestamp {
+            let res = unsafe {
+                CAEN_FELib_ReadData(
+                    handle,
+                    timeout_ms,
+                    &mut data.c_event.timestamp,
+                    &mut data.c_event.timestamp_us,
+                    &mut data.c_event.trigger_id,
+                    data.c_event.waveform,
+                    data.c_event.n_samples,
+                    &mut data.c_event.flags,
+                    &mut data.c_event.board_fail,
+                    &mut data.c_event.event_size,
+                )
+            };
+            return FELibReturn::from(res);
+        }
+        let res = unsafe {
+            CAEN_FELib_ReadData(
+                handle,
+                timeout_ms,
+                &mut data.c_event.timestamp,
+                &mut data.c_event.trigger_id,
+                data.c_event.waveform,
+                data.c_event.n_samples,
+                &mut data.c_event.flags,
+                &mut data.c_event.board_fail,
+                &mut data.c_event.event_size,
+            )
+        };
+        FELibReturn::from(res)
+    }
+
+    fn read_dpp_psd(&self, handle: u64, timeout_ms: i32, data: &mut EventWrapper) -> FELibReturn {
+        let Some(hit) = data.dpp_psd.as_mut() else {
+            return FELibReturn::InvalidParam;
+        };
+        let res = unsafe {
+            CAEN_FELib_ReadData(
+                handle,
+                timeout_ms,
+                &mut hit.channel,
+                &mut data.c_event.timestamp,
+                &mut hit.fine_timestamp,
+                &mut hit.energy,
+                &mut hit.energy_short,
+                &mut hit.flags_low_priority,
+                &mut hit.flags_high_priority,
+                &mut data.c_event.event_size,
+            )
+        };
+        FELibReturn::from(res)
+    }
+
+    fn read_raw(&self, handle: u64, timeout_ms: i32, block: &mut RawBlock) -> FELibReturn {
+        let res = unsafe {
+            CAEN_FELib_ReadData(
+                handle,
+                timeout_ms,
+                block.data.as_mut_ptr(),
+                &mut block.size,
+                &mut block.n_events,
+            )
+        };
+        FELibReturn::from(res)
+    }
+
+    fn read_stats(&self, handle: u64, timeout_ms: i32, stats: &mut EndpointStats) -> FELibReturn {
+        let res = unsafe {
+            CAEN_FELib_ReadData(
+                handle,
+                timeout_ms,
+                stats.real_time.as_mut_ptr(),
+                stats.dead_time.as_mut_ptr(),
+                stats.live_time.as_mut_ptr(),
+                stats.trigger_cnt.as_mut_ptr(),
+                stats.saved_event_cnt.as_mut_ptr(),
+            )
+        };
+        FELibReturn::from(res)
+    }
+
+    fn has_data(&self, handle: u64, timeout_ms: i32) -> Result<(), FELibReturn> {
+        let res = unsafe { CAEN_FELib_HasData(handle, timeout_ms) };
+        let res = FELibReturn::from(res);
+        match res {
+            FELibReturn::Success => Ok(()),
+            _ => Err(res),
+        }
+    }
+
+    fn get_handle(&self, handle: u64, path: &str) -> Result<u64, FELibReturn> {
+        let mut path_handle = 0;
+        let path = CString::new(path).unwrap();
+        let res = unsafe { CAEN_FELib_GetHandle(handle, path.as_ptr(), &mut path_handle) };
+        let res = FELibReturn::from(res);
+        match res {
+            FELibReturn::Success => Ok(path_handle),
+            _ => Err(res),
+        }
+    }
+
+    fn get_parent_handle(&self, handle: u64, path: &str) -> Result<u64, FELibReturn> {
+        let mut path_handle = 0;
+        let path = CString::new(path).unwrap();
+        let res = unsafe { CAEN_FELib_GetParentHandle(handle, path.as_ptr(), &mut path_handle) };
+        let res = FELibReturn::from(res);
+        match res {
+            FELibReturn::Success => Ok(path_handle),
+            _ => Err(res),
+        }
+    }
+}
+
+/// Built without the `felib` feature there is no library to call, every call
+/// fails until a simulated backend is set.
+#[cfg(not(feature = "felib"))]
+impl Backend for FELib {
+    fn name(&self) -> &'static str {
+        "FELib (not built in)"
+    }
+
+    fn lib_info(&self) -> Result<String, FELibReturn> {
+        Err(FELibReturn::DevLibNotAvailable)
+    }
+
+    fn lib_version(&self) -> Result<String, FELibReturn> {
+        Err(FELibReturn::DevLibNotAvailable)
+    }
+
+    fn last_error(&self) -> Result<String, FELibReturn> {
+        Err(FELibReturn::DevLibNotAvailable)
+    }
+
+    fn devices_discovery(&self) -> Result<String, FELibReturn> {
+        Err(FELibReturn::DevLibNotAvailable)
+    }
+
+    fn open(&self, _url: &str) -> Result<u64, FELibReturn> {
+        Err(FELibReturn::DevLibNotAvailable)
+    }
+
+    fn close(&self, _handle: u64) -> Result<(), FELibReturn> {
+        Err(FELibReturn::DevLibNotAvailable)
+    }
+
+    fn impl_lib_version(&self, _handle: u64) -> Result<String, FELibReturn> {
+        Err(FELibReturn::DevLibNotAvailable)
+    }
+
+    fn device_tree(&self, _handle: u64) -> Result<String, FELibReturn> {
+        Err(FELibReturn::DevLibNotAvailable)
+    }
+
+    fn get_value(&self, _handle: u64, _path: &str) -> Result<String, FELibReturn> {
+        Err(FELibReturn::DevLibNotAvailable)
+    }
+
+    fn set_value(&self, _handle: u64, _path: &str, _value: &str) -> Result<(), FELibReturn> {
+        Err(FELibReturn::DevLibNotAvailable)
+    }
+
+    fn send_command(&self, _handle: u64, _path: &str) -> Result<(), FELibReturn> {
+        Err(FELibReturn::DevLibNotAvailable)
+    }
+
+    fn set_read_data_format(&self, _handle: u64, _format: &str) -> Result<(), FELibReturn> {
+        Err(FELibReturn::DevLibNotAvailable)
+    }
+
+    fn read_data(&self, _: u64, _: i32, _: &mut EventWrapper, _: bool) -> FELibReturn {
+        FELibReturn::DevLibNotAvailable
+    }
+
+    fn read_dpp_psd(
+        &self,
+        _handle: u64,
+        _timeout_ms: i32,
+        _data: &mut EventWrapper,
+    ) -> FELibReturn {
+        FELibReturn::DevLibNotAvailable
+    }
+
+    fn read_raw(&self, _handle: u64, _timeout_ms: i32, _block: &mut RawBlock) -> FELibReturn {
+        FELibReturn::DevLibNotAvailable
+    }
+
+    fn read_stats(
+        &self,
+        _handle: u64,
+        _timeout_ms: i32,
+        _stats: &mut EndpointStats,
+    ) -> FELibReturn {
+        FELibReturn::DevLibNotAvailable
+    }
+
+    fn has_data(&self, _handle: u64, _timeout_ms: i32) -> Result<(), FELibReturn> {
+        Err(FELibReturn::DevLibNotAvailable)
+    }
+
+    fn get_handle(&self, _handle: u64, _path: &str) -> Result<u64, FELibReturn> {
+        Err(FELibReturn::DevLibNotAvailable)
+    }
+
+    fn get_parent_handle(&self, _handle: u64, _path: &str) -> Result<u64, FELibReturn> {
+        Err(FELibReturn::DevLibNotAvailable)
     }
 }
@@ -0,0 +1,438 @@
+use crate::Language;
+
+/// Operator-facing TUI text in one language. Text with values in it is a
+/// function so each language can order the words as it needs.
+#[derive(Debug)]
+pub struct Messages {
+    // Run status panel
+    pub run_status_title: fn(camp_num: usize, run_num: usize) -> String,
+    pub quit: &'static str,
+    pub stop_run: &'static str,
+    pub abort_run: &'static str,
+    pub elapsed_time: &'static str,
+    pub events: &'static str,
+    pub data_rate: &'static str,
+    pub buffer_length: &'static str,
+    pub misaligned_events: &'static str,
+    pub dropped_events: &'static str,
+    pub operator: &'static str,
+    pub next_run_in: &'static str,
+    pub start_now: &'static str,
+    pub hold: &'static str,
+    pub next_run_on_hold: &'static str,
+    pub resume_countdown: &'static str,
+    pub training: &'static str,
+    pub disk_rate: &'static str,
+    pub disk_full_in: &'static str,
+    pub rate_history_title: fn(minutes: usize, max: u64) -> String,
+    pub dead_time: &'static str,
+    pub board_name: fn(board: usize) -> String,
+    pub unknown: &'static str,
+
+    // DAQ process panel
+    pub system_title: &'static str,
+    pub cpu: &'static str,
+    pub memory: &'static str,
+    pub write_rate: &'static str,
+
+    // Board panels
+    pub board_status_title: fn(board: usize) -> String,
+    pub realtime_monitor: &'static str,
+    pub deadtime_monitor: &'static str,
+    pub trigger_count: &'static str,
+    pub lost_trigger_count: &'static str,
+    pub acquisition_status: &'static str,
+    pub air_in_temp: &'static str,
+    pub air_out_temp: &'static str,
+    pub hottest_adc_temp: &'static str,
+    pub fan_speeds: &'static str,
+    pub error_flags: &'static str,
+    pub read_timeout: &'static str,
+    pub read_error: &'static str,
+
+    // Alarm list
+    pub alarms_title: fn(unacknowledged: usize) -> String,
+    pub acknowledge: &'static str,
+    pub acknowledge_all: &'static str,
+    pub select: &'static str,
+
+    // Board failure dialog
+    pub board_failure: &'static str,
+    pub board_health_title: &'static str,
+    pub restart_run: &'static str,
+    pub continue_without_board: fn(board: usize) -> String,
+
+    // Device picker
+    pub boards: &'static str,
+    pub device_picker_title: &'static str,
+    pub target_board: &'static str,
+    pub replace_board: fn(board: usize) -> String,
+    pub add_board: &'static str,
+    pub save: &'static str,
+    pub close: &'static str,
+    pub discovering: &'static str,
+    pub no_devices_found: &'static str,
+    pub discovery_failed: &'static str,
+
+    // Pre-flight report
+    pub preflight: &'static str,
+    pub sw_trigger: &'static str,
+    pub comment: &'static str,
+    pub comment_title: &'static str,
+    pub comment_failed: &'static str,
+    pub board_stats_failed: &'static str,
+    pub preflight_title: &'static str,
+    pub param_changes: &'static str,
+    pub param_changes_title: &'static str,
+    pub no_param_changes: &'static str,
+    /// Heading of the config file changes in the parameter changes view
+    pub config_changes: &'static str,
+    pub config_reloaded: fn(changes: usize) -> String,
+    pub config_reload_failed: &'static str,
+    pub schedule: &'static str,
+    pub schedule_finished: fn(runs: usize) -> String,
+    pub schedule_failed: &'static str,
+    pub board_identity: fn(board: usize, model: &str, serial: &str) -> String,
+    pub board_unreachable: fn(board: usize) -> String,
+    pub firmware: &'static str,
+    pub channels_enabled: &'static str,
+    pub free_disk: &'static str,
+    pub config_hash: &'static str,
+    pub event_size: &'static str,
+    pub estimated_data_rate: &'static str,
+
+    // Alarm messages
+    pub board_alarm: fn(board: usize, message: &str) -> String,
+    pub misaligned_discarded: &'static str,
+    pub trigger_ids_skipped: &'static str,
+    pub misaligned_run_stopped: &'static str,
+    pub dropped_run_stopped: &'static str,
+    pub data_taking_error: &'static str,
+    pub board_stalled: fn(timeouts: usize) -> String,
+    pub start_timeout: &'static str,
+    pub felib_error: &'static str,
+    pub endpoint_setup_failed: &'static str,
+    pub arm_failed: &'static str,
+    pub stats_csv_failed: &'static str,
+    pub run_log_failed: &'static str,
+    pub mirror_failed: &'static str,
+    pub upload_failed: &'static str,
+    pub sw_trigger_failed: &'static str,
+    pub buffer_backlog: fn(limit: usize) -> String,
+    pub disk_low: fn(percent: f64) -> String,
+    pub board_overheating: fn(board: usize, limit: f64) -> String,
+    pub board_error_flags: fn(board: usize, flags: u32) -> String,
+    pub board_url_saved: fn(board: usize, url: &str) -> String,
+    pub preflight_problems: &'static str,
+    pub campaign_exceeds_disk: fn(needed_gb: f64, free_gb: f64) -> String,
+    pub run_aborted: fn(run_num: usize) -> String,
+    pub run_stopped_after_failure: fn(run_num: usize, board: usize) -> String,
+}
+
+impl Messages {
+    pub fn new(language: Language) -> &'static Self {
+        match language {
+            Language::English => &ENGLISH,
+            Language::Spanish => &SPANISH,
+        }
+    }
+}
+
+const ENGLISH: Messages = Messages {
+    run_status_title: |camp_num, run_num| format!(" Campaign {} Run {} Status ", camp_num, run_num),
+    quit: "Quit",
+    stop_run: "Stop run",
+    abort_run: "Abort run",
+    elapsed_time: "Elapsed time",
+    events: "Events",
+    data_rate: "Data rate",
+    buffer_length: "Buffer length",
+    misaligned_events: "Misaligned events",
+    dropped_events: "Dropped events",
+    operator: "Operator",
+    next_run_in: "Next run in",
+    start_now: "Start now",
+    hold: "Hold",
+    next_run_on_hold: "Next run on hold",
+    resume_countdown: "Resume countdown",
+    training: "TRAINING",
+    disk_rate: "Est. disk rate",
+    disk_full_in: "Disk full in",
+    rate_history_title: |minutes, max| {
+        format!(" Event rate, last {} min (peak {} Hz) ", minutes, max)
+    },
+    dead_time: "Dead time",
+    board_name: |board| format!("Board {}", board),
+    unknown: "unknown",
+    system_title: " DAQ Process ",
+    cpu: "CPU",
+    memory: "Memory",
+    write_rate: "Write rate",
+
+    board_status_title: |board| format!(" Board {} Status ", board),
+    realtime_monitor: "Realtime monitor",
+    deadtime_monitor: "Deadtime monitor",
+    trigger_count: "Trigger count",
+    lost_trigger_count: "Lost trigger count",
+    acquisition_status: "Acquisition status",
+    air_in_temp: "Incoming air temp (°C)",
+    air_out_temp: "Outgoing air temp (°C)",
+    hottest_adc_temp: "Hottest ADC temp (°C)",
+    fan_speeds: "Fan speeds (rpm)",
+    error_flags: "Error flags",
+    read_timeout: "Read timeout (ms)",
+    read_error: "err in read",
+
+    alarms_title: |unacknowledged| format!(" Alarms ({} unacknowledged) ", unacknowledged),
+    acknowledge: "Acknowledge",
+    acknowledge_all: "Acknowledge all",
+    select: "Select",
+
+    board_failure: " Board Failure ",
+    board_health_title: " Board Health ",
+    restart_run: "restart (end this run, the next resets every board)",
+    continue_without_board: |board| format!("continue this run without board {}", board),
+
+    boards: "Boards",
+    device_picker_title: " Add or replace a board ",
+    target_board: "Board",
+    replace_board: |board| format!("Replace board {}", board),
+    add_board: "Add as a new board",
+    save: "Save",
+    close: "Close",
+    discovering: "Searching for digitizers...",
+    no_devices_found: "No digitizers found",
+    discovery_failed: "Device discovery failed",
+
+    preflight: "Pre-flight",
+    sw_trigger: "Trigger",
+    comment: "Comment",
+    comment_title: " Run comment ",
+    comment_failed: "Failed to save the run comment",
+    board_stats_failed: "Failed to save the boards' end-of-run counters",
+    preflight_title: " Pre-flight report ",
+    param_changes: "Changes",
+    param_changes_title: " Board parameters changed since the last run ",
+    no_param_changes: "No board parameter changed",
+    config_changes: "Config file changes of the last reload:",
+    config_reloaded: |changes| {
+        format!(
+            "Config file reloaded with {} changed setting(s), press V to see them",
+            changes
+        )
+    },
+    config_reload_failed: "Config file changed but can't be used, keeping the current config",
+    schedule: "Schedule",
+    schedule_finished: |runs| format!("Schedule finished, all {} runs taken", runs),
+    schedule_failed: "Scheduled run's settings can't be used",
+    board_identity: |board, model, serial| format!("Board {}: {} #{}", board, model, serial),
+    board_unreachable: |board| format!("Board {}: unreachable", board),
+    firmware: "Firmware",
+    channels_enabled: "Channels enabled",
+    free_disk: "Free disk",
+    config_hash: "Config hash",
+    event_size: "Event size",
+    estimated_data_rate: "Estimated data rate",
+
+    board_alarm: |board, message| format!("Board {}: {}", board, message),
+    misaligned_discarded: "Misaligned events discarded",
+    trigger_ids_skipped: "Trigger IDs skipped, events dropped",
+    misaligned_run_stopped: "Misaligned events, run stopped",
+    dropped_run_stopped: "Events dropped, run stopped",
+    data_taking_error: "Data taking pipeline error, run stopped",
+    board_stalled: |timeouts| format!("No events in {} consecutive reads, board stalled", timeouts),
+    start_timeout: "Acquisition start signal never came, run stopped",
+    felib_error: "FELib error",
+    endpoint_setup_failed: "Endpoint setup failed, run not started",
+    arm_failed: "Arming failed, run not started",
+    stats_csv_failed: "Failed to write run statistics CSV",
+    run_log_failed: "Failed to append the run to the run log",
+    mirror_failed: "Failed to mirror subrun file",
+    upload_failed: "Failed to upload subrun file",
+    sw_trigger_failed: "Failed to send software trigger",
+    buffer_backlog: |limit| format!("Event buffer backlog above {}", limit),
+    disk_low: |percent| format!("Output disk below {:.0}% free", percent),
+    board_overheating: |board, limit| {
+        format!("Board {} ADC temperature above {:.0} °C", board, limit)
+    },
+    board_error_flags: |board, flags| format!("Board {} error flags set: {:#010x}", board, flags),
+    board_url_saved: |board, url| {
+        format!(
+            "Board {} set to {} in the config file, used from the next start",
+            board, url
+        )
+    },
+    preflight_problems: "Pre-flight check found problems, see the pre-flight report",
+    campaign_exceeds_disk: |needed_gb, free_gb| {
+        format!(
+            "Remaining runs need an estimated {:.1} GB but only {:.1} GB are free",
+            needed_gb, free_gb
+        )
+    },
+    run_aborted: |run_num| format!("Run {} aborted by operator", run_num),
+    run_stopped_after_failure: |run_num, board| {
+        format!(
+            "Run {} stopped by operator after board {} failure",
+            run_num, board
+        )
+    },
+};
+
+const SPANISH: Messages = Messages {
+    run_status_title: |camp_num, run_num| {
+        format!(" Estado de la campaña {} corrida {} ", camp_num, run_num)
+    },
+    quit: "Salir",
+    stop_run: "Detener corrida",
+    abort_run: "Abortar corrida",
+    elapsed_time: "Tiempo transcurrido",
+    events: "Eventos",
+    data_rate: "Tasa de datos",
+    buffer_length: "Longitud del búfer",
+    misaligned_events: "Eventos desalineados",
+    dropped_events: "Eventos perdidos",
+    operator: "Operador",
+    next_run_in: "Siguiente corrida en",
+    start_now: "Iniciar ya",
+    hold: "Pausar",
+    next_run_on_hold: "Siguiente corrida en pausa",
+    resume_countdown: "Reanudar cuenta atrás",
+    training: "ENTRENAMIENTO",
+    disk_rate: "Tasa estimada a disco",
+    disk_full_in: "Disco lleno en",
+    rate_history_title: |minutes, max| {
+        format!(
+            " Tasa de eventos, últimos {} min (máximo {} Hz) ",
+            minutes, max
+        )
+    },
+    dead_time: "Tiempo muerto",
+    board_name: |board| format!("Tarjeta {}", board),
+    unknown: "desconocido",
+    system_title: " Proceso DAQ ",
+    cpu: "CPU",
+    memory: "Memoria",
+    write_rate: "Tasa de escritura",
+
+    board_status_title: |board| format!(" Estado de la tarjeta {} ", board),
+    realtime_monitor: "Monitor de tiempo real",
+    deadtime_monitor: "Monitor de tiempo muerto",
+    trigger_count: "Conteo de disparos",
+    lost_trigger_count: "Disparos perdidos",
+    acquisition_status: "Estado de adquisición",
+    air_in_temp: "Temp. aire de entrada (°C)",
+    air_out_temp: "Temp. aire de salida (°C)",
+    hottest_adc_temp: "Temp. ADC más caliente (°C)",
+    fan_speeds: "Velocidad de ventiladores (rpm)",
+    error_flags: "Indicadores de error",
+    read_timeout: "Tiempo de espera de lectura (ms)",
+    read_error: "error de lectura",
+
+    alarms_title: |unacknowledged| format!(" Alarmas ({} sin confirmar) ", unacknowledged),
+    acknowledge: "Confirmar",
+    acknowledge_all: "Confirmar todas",
+    select: "Seleccionar",
+
+    board_failure: " Fallo de tarjeta ",
+    board_health_title: " Estado de la tarjeta ",
+    restart_run: "reiniciar (terminar esta corrida, la siguiente reinicia todas las tarjetas)",
+    continue_without_board: |board| format!("continuar esta corrida sin la tarjeta {}", board),
+
+    boards: "Tarjetas",
+    device_picker_title: " Añadir o reemplazar una tarjeta ",
+    target_board: "Tarjeta",
+    replace_board: |board| format!("Reemplazar la tarjeta {}", board),
+    add_board: "Añadir como tarjeta nueva",
+    save: "Guardar",
+    close: "Cerrar",
+    discovering: "Buscando digitalizadores...",
+    no_devices_found: "No se encontraron digitalizadores",
+    discovery_failed: "Falló la búsqueda de dispositivos",
+
+    preflight: "Verificación previa",
+    sw_trigger: "Disparo",
+    comment: "Comentario",
+    comment_title: " Comentario de la corrida ",
+    comment_failed: "No se pudo guardar el comentario de la corrida",
+    board_stats_failed: "No se pudieron guardar los contadores de fin de corrida de las tarjetas",
+    preflight_title: " Informe de verificación previa ",
+    param_changes: "Cambios",
+    param_changes_title: " Parámetros de tarjeta cambiados desde la corrida anterior ",
+    no_param_changes: "Ningún parámetro de tarjeta cambió",
+    config_changes: "Cambios del archivo de configuración de la última recarga:",
+    config_reloaded: |changes| {
+        format!(
+            "Archivo de configuración recargado con {} ajuste(s) cambiado(s), pulse V para verlos",
+            changes
+        )
+    },
+    config_reload_failed:
+        "El archivo de configuración cambió pero no se puede usar, se mantiene la configuración actual",
+    schedule: "Programa",
+    schedule_finished: |runs| format!("Programa terminado, se tomaron las {} corridas", runs),
+    schedule_failed: "Los ajustes de la corrida programada no se pueden usar",
+    board_identity: |board, model, serial| format!("Tarjeta {}: {} #{}", board, model, serial),
+    board_unreachable: |board| format!("Tarjeta {}: sin respuesta", board),
+    firmware: "Firmware",
+    channels_enabled: "Canales habilitados",
+    free_disk: "Disco libre",
+    config_hash: "Hash de la configuración",
+    event_size: "Tamaño de evento",
+    estimated_data_rate: "Tasa de datos estimada",
+
+    board_alarm: |board, message| format!("Tarjeta {}: {}", board, message),
+    misaligned_discarded: "Eventos desalineados descartados",
+    trigger_ids_skipped: "IDs de disparo saltados, eventos perdidos",
+    misaligned_run_stopped: "Eventos desalineados, corrida detenida",
+    dropped_run_stopped: "Eventos perdidos, corrida detenida",
+    data_taking_error: "Error en la toma de datos, corrida detenida",
+    board_stalled: |timeouts| {
+        format!(
+            "Sin eventos en {} lecturas seguidas, tarjeta detenida",
+            timeouts
+        )
+    },
+    start_timeout: "Nunca llegó la señal de inicio de adquisición, corrida detenida",
+    felib_error: "Error de FELib",
+    endpoint_setup_failed: "Falló la configuración del endpoint, corrida no iniciada",
+    arm_failed: "Falló el armado, corrida no iniciada",
+    stats_csv_failed: "No se pudo escribir el CSV de estadísticas de la corrida",
+    run_log_failed: "No se pudo añadir la corrida al registro de corridas",
+    mirror_failed: "No se pudo copiar el archivo de subcorrida al espejo",
+    upload_failed: "No se pudo subir el archivo de subcorrida",
+    sw_trigger_failed: "No se pudo enviar el disparo por software",
+    buffer_backlog: |limit| format!("Más de {} eventos pendientes en el búfer", limit),
+    disk_low: |percent| format!("Menos del {:.0}% libre en el disco de salida", percent),
+    board_overheating: |board, limit| {
+        format!(
+            "Temperatura del ADC de la tarjeta {} sobre {:.0} °C",
+            board, limit
+        )
+    },
+    board_error_flags: |board, flags| {
+        format!(
+            "Tarjeta {} con indicadores de error activos: {:#010x}",
+            board, flags
+        )
+    },
+    board_url_saved: |board, url| {
+        format!(
+            "Tarjeta {} cambiada a {} en el archivo de configuración, se usará al reiniciar",
+            board, url
+        )
+    },
+    preflight_problems: "La verificación previa encontró problemas, vea el informe",
+    campaign_exceeds_disk: |needed_gb, free_gb| {
+        format!(
+            "Las corridas restantes necesitan unos {:.1} GB pero solo quedan {:.1} GB libres",
+            needed_gb, free_gb
+        )
+    },
+    run_aborted: |run_num| format!("Corrida {} abortada por el operador", run_num),
+    run_stopped_after_failure: |run_num, board| {
+        format!(
+            "Corrida {} detenida por el operador tras el fallo de la tarjeta {}",
+            run_num, board
+        )
+    },
+};
@@ -0,0 +1,224 @@
+use anyhow::{anyhow, Result};
+use std::collections::{BTreeMap, HashMap};
+use toml_edit::{value, Array, ArrayOfTables, DocumentMut, Item, Table, Value};
+
+/// One board's parameters from a flat `path=value` dump, by lowercase
+/// parameter name since CAEN paths aren't case sensitive.
+#[derive(Debug, Default)]
+struct ParamDump {
+    board: HashMap<String, String>,
+    channels: BTreeMap<usize, HashMap<String, String>>,
+}
+
+impl ParamDump {
+    /// Read `/par/<name>=<value>` and `/ch/<n>/par/<name>=<value>` lines.
+    /// Channel ranges as `/ch/0..63/par/<name>` are expanded, other paths
+    /// (endpoints, LVDS groups) and `#` comment lines are skipped.
+    fn parse(text: &str) -> Result<Self> {
+        let mut dump = ParamDump::default();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (path, val) = line
+                .split_once('=')
+                .ok_or_else(|| anyhow!("malformed parameter line: {}", line))?;
+            let path = path.trim().to_lowercase();
+            let val = val.trim().to_string();
+            if let Some(name) = path.strip_prefix("/par/") {
+                dump.board.insert(name.to_string(), val);
+            } else if let Some((channels, name)) = path
+                .strip_prefix("/ch/")
+                .and_then(|rest| rest.split_once("/par/"))
+            {
+                let malformed = || anyhow!("malformed channel path: {}", path);
+                let (first, last) = match channels.split_once("..") {
+                    Some((first, last)) => (first, last),
+                    None => (channels, channels),
+                };
+                let first: usize = first.parse().map_err(|_| malformed())?;
+                let last: usize = last.parse().map_err(|_| malformed())?;
+                for ch in first..=last {
+                    dump.channels
+                        .entry(ch)
+                        .or_default()
+                        .insert(name.to_string(), val.clone());
+                }
+            }
+        }
+        Ok(dump)
+    }
+
+    fn board_param(&self, name: &str) -> Result<&str> {
+        self.board
+            .get(&name.to_lowercase())
+            .map(String::as_str)
+            .ok_or_else(|| anyhow!("the dump has no /par/{}", name))
+    }
+
+    /// A channel parameter of every channel that has it, by channel.
+    fn channel_param(&self, name: &str) -> Result<BTreeMap<usize, &str>> {
+        let values: BTreeMap<usize, &str> = self
+            .channels
+            .iter()
+            .filter_map(|(&ch, params)| Some((ch, params.get(&name.to_lowercase())?.as_str())))
+            .collect();
+        if values.is_empty() {
+            return Err(anyhow!("the dump has no /ch/<n>/par/{}", name));
+        }
+        Ok(values)
+    }
+}
+
+fn integer(name: &str, raw: &str) -> Result<Value> {
+    // CAEN reports some integers with a fractional part, e.g. "1000.000000"
+    raw.parse::<f64>()
+        .map(|x| Value::from(x.round() as i64))
+        .map_err(|_| anyhow!("{} \"{}\" isn't a number", name, raw))
+}
+
+fn float(name: &str, raw: &str) -> Result<Value> {
+    raw.parse::<f64>()
+        .map(Value::from)
+        .map_err(|_| anyhow!("{} \"{}\" isn't a number", name, raw))
+}
+
+fn string(_name: &str, raw: &str) -> Result<Value> {
+    Ok(Value::from(raw))
+}
+
+/// A per-channel setting as one value when every channel agrees, otherwise a
+/// table keyed by channel number like the config's per-channel sections.
+fn per_channel(
+    dump: &ParamDump,
+    name: &str,
+    convert: fn(&str, &str) -> Result<Value>,
+) -> Result<Item> {
+    let values = dump.channel_param(name)?;
+    let mut raw = values.values();
+    let first = raw.next().copied().unwrap_or_default();
+    if raw.all(|&v| v == first) {
+        return Ok(value(convert(name, first)?));
+    }
+    let mut table = Table::new();
+    for (ch, raw) in values {
+        table.insert(&ch.to_string(), value(convert(name, raw)?));
+    }
+    Ok(Item::Table(table))
+}
+
+/// The `[[board_settings.boards]]` entry reproducing one dumped board.
+fn board_table(dump: &ParamDump) -> Result<Table> {
+    let mut table = Table::new();
+
+    let enabled = dump.channel_param("ChEnable")?;
+    let en_chans: Vec<i64> = enabled
+        .iter()
+        .filter(|(_, v)| v.eq_ignore_ascii_case("true"))
+        .map(|(&ch, _)| ch as i64)
+        .collect();
+    if en_chans.len() == enabled.len() {
+        table.insert("en_chans", value(true));
+    } else {
+        table.insert("en_chans", value(en_chans.into_iter().collect::<Array>()));
+    }
+
+    table.insert("trig_source", value(dump.board_param("AcqTriggerSource")?));
+    table.insert("io_level", value(dump.board_param("IOlevel")?));
+    for (key, param) in [
+        ("test_pulse_period", "TestPulsePeriod"),
+        ("test_pulse_width", "TestPulseWidth"),
+        ("test_pulse_low", "TestPulseLowLevel"),
+        ("test_pulse_high", "TestPulseHighLevel"),
+    ] {
+        table.insert(key, value(integer(param, dump.board_param(param)?)?));
+    }
+    table.insert("dc_offset", per_channel(dump, "DCOffset", float)?);
+    table.insert("trig_thr", per_channel(dump, "TriggerThr", integer)?);
+    table.insert(
+        "trig_thr_mode",
+        per_channel(dump, "TriggerThrMode", string)?,
+    );
+    // cliq sets one trigger edge for the whole board
+    let edge = dump
+        .channel_param("SelfTriggerEdge")?
+        .into_values()
+        .next()
+        .unwrap_or("Fall");
+    let edge = if edge.eq_ignore_ascii_case("rise") {
+        "Rise"
+    } else {
+        "Fall"
+    };
+    table.insert("trig_edge", value(edge));
+    table.insert(
+        "samples_over_thr",
+        per_channel(dump, "SamplesOverThreshold", integer)?,
+    );
+    table.insert("itl_logic", value(dump.board_param("ITLAMainLogic")?));
+    table.insert(
+        "itl_majority_level",
+        value(integer(
+            "ITLAMajorityLev",
+            dump.board_param("ITLAMajorityLev")?,
+        )?),
+    );
+    table.insert("itl_pair_logic", value(dump.board_param("ITLAPairLogic")?));
+    table.insert("itl_polarity", value(dump.board_param("ITLAPolarity")?));
+    table.insert(
+        "itl_gatewidth",
+        value(integer(
+            "ITLAGateWidth",
+            dump.board_param("ITLAGateWidth")?,
+        )?),
+    );
+    table.insert("itl_retrig", value(dump.board_param("ITLAEnRetrigger")?));
+    table.insert("itl_connect", per_channel(dump, "ITLConnect", string)?);
+    Ok(table)
+}
+
+/// Convert flat `path=value` parameter dumps, one per board in board order,
+/// into cliq's `board_settings` TOML. The common settings come from the first
+/// board. Run and sync settings have no CAEN equivalent and are left out.
+pub fn import_param_dumps(dumps: &[String]) -> Result<String> {
+    let dumps = dumps
+        .iter()
+        .map(|text| ParamDump::parse(text))
+        .collect::<Result<Vec<_>>>()?;
+    let first = dumps
+        .first()
+        .ok_or_else(|| anyhow!("no parameter dumps to import"))?;
+
+    let mut common = Table::new();
+    common.insert(
+        "record_len",
+        value(integer(
+            "RecordLengthS",
+            first.board_param("RecordLengthS")?,
+        )?),
+    );
+    common.insert(
+        "pre_trig_len",
+        value(integer("PreTriggerS", first.board_param("PreTriggerS")?)?),
+    );
+    if let Ok(multi_window) = first.board_param("EnMultiWindowRun") {
+        common.insert(
+            "multi_window",
+            value(multi_window.eq_ignore_ascii_case("true")),
+        );
+    }
+
+    let mut boards = ArrayOfTables::new();
+    for (i, dump) in dumps.iter().enumerate() {
+        let table = board_table(dump).map_err(|e| anyhow!("board {}: {}", i, e))?;
+        boards.push(table);
+    }
+
+    let mut board_settings = Table::new();
+    board_settings.set_implicit(true);
+    board_settings.insert("common", Item::Table(common));
+    board_settings.insert("boards", Item::ArrayOfTables(boards));
+    let mut doc = DocumentMut::new();
+    doc.insert("board_settings", Item::Table(board_settings));
+    Ok(doc.to_string())
+}
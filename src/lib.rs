@@ -1,15 +1,68 @@
+mod alarms;
+mod backend;
+mod bench;
+mod calibration;
 mod config;
+mod config_check;
+mod control;
+mod controller;
 mod digitizer_params;
+mod dir_layout;
+mod discovery;
+mod dst;
 mod event;
-mod felib;
+pub mod felib;
+mod i18n;
+mod import;
+mod mirror;
+mod param_cache;
+mod param_diff;
+mod preflight;
+mod report;
+mod retention;
+mod run_writer;
+mod runlog;
+mod schedule;
+mod simulator;
+mod sysmon;
+mod theme;
 mod tui;
+mod ui_state;
+mod upload;
 mod utils;
 mod writer;
 
+pub use alarms::*;
+pub use backend::*;
+pub use bench::*;
+pub use calibration::*;
 pub use config::*;
+pub use config_check::*;
+pub use control::*;
+pub use controller::*;
+pub use digitizer_params::{log_parameters, read_parameters};
+pub use dir_layout::*;
+pub use discovery::*;
+pub use dst::*;
 pub use event::*;
-pub use felib::*;
+pub use felib::FELibReturn;
+pub use i18n::*;
+pub use import::*;
+pub use mirror::*;
+pub use param_cache::*;
+pub use param_diff::*;
+pub use preflight::*;
+pub use report::*;
+pub use retention::*;
+pub use run_writer::*;
+pub use runlog::*;
+pub use schedule::*;
+pub use simulator::*;
+pub use sysmon::*;
+pub use theme::*;
 pub use tui::*;
+pub use ui_state::*;
+pub use upload::*;
 pub use utils::*;
 pub use writer::*;
 
@@ -31,3 +84,55 @@ pub const EVENT_FORMAT: &str = " \
         { \"name\" : \"EVENT_SIZE\", \"type\" : \"SIZE_T\" } \
     ] \
 ";
+
+/// `EVENT_FORMAT` plus the timestamp in µs as a double, which keeps the
+/// fine timestamp when the firmware provides it
+pub const EVENT_FORMAT_FINE_TIMESTAMP: &str = " \
+    [ \
+        { \"name\" : \"TIMESTAMP_NS\", \"type\" : \"U64\" }, \
+        { \"name\" : \"TIMESTAMP_US\", \"type\" : \"DOUBLE\" }, \
+        { \"name\" : \"TRIGGER_ID\", \"type\" : \"U32\" }, \
+        { \"name\" : \"WAVEFORM\", \"type\" : \"U16\", \"dim\" : 2 }, \
+        { \"name\" : \"WAVEFORM_SIZE\", \"type\" : \"SIZE_T\", \"dim\" : 1 }, \
+        { \"name\" : \"FLAGS\", \"type\" : \"U16\" }, \
+        { \"name\" : \"BOARD_FAIL\", \"type\" : \"BOOL\" }, \
+        { \"name\" : \"EVENT_SIZE\", \"type\" : \"SIZE_T\" } \
+    ] \
+";
+
+/// Read format of the DPP-ZLE endpoint, the same fields as the scope
+pub const DPP_ZLE_EVENT_FORMAT: &str = EVENT_FORMAT;
+
+pub const DPP_PSD_EVENT_FORMAT: &str = " \
+    [ \
+        { \"name\" : \"CHANNEL\", \"type\" : \"U8\" }, \
+        { \"name\" : \"TIMESTAMP_NS\", \"type\" : \"U64\" }, \
+        { \"name\" : \"FINE_TIMESTAMP\", \"type\" : \"U16\" }, \
+        { \"name\" : \"ENERGY\", \"type\" : \"U16\" }, \
+        { \"name\" : \"ENERGY_SHORT\", \"type\" : \"U16\" }, \
+        { \"name\" : \"FLAGS_LOW_PRIORITY\", \"type\" : \"U16\" }, \
+        { \"name\" : \"FLAGS_HIGH_PRIORITY\", \"type\" : \"U16\" }, \
+        { \"name\" : \"EVENT_SIZE\", \"type\" : \"SIZE_T\" } \
+    ] \
+";
+
+/// Blocks of the raw endpoint: the board's data as sent, its size in bytes
+/// and the number of events in it.
+pub const RAW_DATA_FORMAT: &str = " \
+    [ \
+        { \"name\" : \"DATA\", \"type\" : \"U8\", \"dim\" : 1 }, \
+        { \"name\" : \"SIZE\", \"type\" : \"SIZE_T\" }, \
+        { \"name\" : \"N_EVENTS\", \"type\" : \"U32\" } \
+    ] \
+";
+
+/// Per-channel counters of the stats endpoint, times in ns.
+pub const STATS_FORMAT: &str = " \
+    [ \
+        { \"name\" : \"REAL_TIME\", \"type\" : \"U64\", \"dim\" : 1 }, \
+        { \"name\" : \"DEAD_TIME\", \"type\" : \"U64\", \"dim\" : 1 }, \
+        { \"name\" : \"LIVE_TIME\", \"type\" : \"U64\", \"dim\" : 1 }, \
+        { \"name\" : \"TRIGGER_CNT\", \"type\" : \"U32\", \"dim\" : 1 }, \
+        { \"name\" : \"SAVED_EVENT_CNT\", \"type\" : \"U32\", \"dim\" : 1 } \
+    ] \
+";
@@ -1,32 +1,368 @@
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand};
 use cliq::*;
 use confique::Config;
+use crossterm::{
+    event::{DisableMouseCapture, EnableMouseCapture},
+    execute,
+};
 use simplelog::{format_description, ConfigBuilder, WriteLogger};
-use std::fs::OpenOptions;
+use std::{
+    collections::HashSet,
+    fs::{self, OpenOptions},
+    io::{stdin, stdout, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 /// LAr DAQ program
 #[derive(Parser, Debug)]
+#[command(subcommand_negates_reqs = true)]
 struct Args {
-    /// Config file used for data acquisition
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Config file used for data acquisition. Repeat to run several
+    /// independent streams, one per config file
+    #[arg(long, short, required = true)]
+    pub config: Vec<String>,
+    /// Operator name or shift ID recorded with every run. Overrides
+    /// `operator` in the config files; prompted for if neither is given
     #[arg(long, short)]
-    pub config: String,
+    pub operator: Option<String>,
+    /// Training mode: run against simulated digitizers that randomly
+    /// inject board dropouts, misalignment, full disks and hot ADCs
+    #[arg(long)]
+    pub training: bool,
+    /// Run against simulated digitizers producing the pulses of the first
+    /// config's `[simulation]` section, without CAEN hardware or FELib
+    #[arg(long)]
+    pub simulate: bool,
+    /// Run without the terminal UI, printing stats to stdout and the log,
+    /// e.g. under systemd or over ssh. SIGINT or SIGTERM ends the runs
+    #[arg(long)]
+    pub headless: bool,
+    /// Seconds between stats printouts in headless mode
+    #[arg(long, default_value_t = 10)]
+    pub stats_interval: u64,
+    /// Listen for run control commands on this address, e.g.
+    /// 127.0.0.1:5555
+    #[arg(long)]
+    pub control: Option<String>,
+    /// Take the runs of this schedule file one after the other, each with
+    /// its own length and settings, then finish
+    #[arg(long)]
+    pub schedule: Option<PathBuf>,
     /// Optional number of runs if indefinite isn't desired
     runs: Option<usize>,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Summarize a campaign's runs (uptime, data volume, failures, rate trend)
+    Report {
+        /// Campaign number to report on
+        #[arg(long)]
+        campaign: usize,
+        /// Config file whose output_dir holds the campaign
+        #[arg(long, short)]
+        config: String,
+        /// Only include runs that ended in the last N days
+        #[arg(long)]
+        days: Option<u64>,
+        #[arg(long, value_enum, default_value_t = ReportFormat::Markdown)]
+        format: ReportFormat,
+        /// Write the report to this file instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Configure a board as for a run and write back its full parameter set
+    /// as `path=value` lines, for cross-checks with CAEN's tools
+    ExportConfig {
+        /// Config file the board is configured from
+        #[arg(long, short)]
+        config: String,
+        /// Index of the board in run_settings.boards
+        #[arg(long)]
+        board: usize,
+        /// Write the parameters to this file instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// List the board settings that differ between two runs of a campaign
+    DiffParams {
+        /// Campaign number of the runs
+        #[arg(long)]
+        campaign: usize,
+        /// Config file whose output_dir holds the campaign
+        #[arg(long, short)]
+        config: String,
+        run_a: usize,
+        run_b: usize,
+    },
+    /// Re-compress a sample of a data file's waveforms with several codecs
+    /// and levels and compare size and speed, to choose `compressor` and
+    /// `compression_level`
+    BenchCompress {
+        file: PathBuf,
+        /// Events of each board to compress
+        #[arg(long, default_value_t = 1000)]
+        events: usize,
+        /// Blosc threads, as `blosc_threads`
+        #[arg(long, default_value_t = 1)]
+        threads: u8,
+    },
+    /// Check a config without opening any board: the values FELib accepts,
+    /// the output directory, and the settings changed from the boards'
+    /// defaults
+    Check {
+        #[arg(long, short)]
+        config: String,
+    },
+    /// Convert flat `path=value` parameter dumps from CAEN's tools into
+    /// cliq's board settings
+    ImportConfig {
+        /// One dump per board, in board order
+        #[arg(required = true)]
+        dumps: Vec<PathBuf>,
+        /// Write the settings to this file instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+}
+
+/// Write a campaign report without touching any digitizer.
+fn report(
+    campaign: usize,
+    config: &str,
+    days: Option<u64>,
+    format: ReportFormat,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    let stream = Conf::from_file(config)?.run_settings;
+    let dirs = DirLayout::new(&stream, &stream.stream_name(config), campaign);
+    let since = days.map(|d| Duration::from_secs(d * 24 * 60 * 60));
+    let report = CampaignReport::collect(&dirs, campaign, since)?.render(format);
+    match output {
+        Some(path) => fs::write(path, report)?,
+        None => print!("{}", report),
+    }
+    Ok(())
+}
+
+/// Configure one board exactly as the start of a run would and dump every
+/// parameter read back from it.
+fn export_config(config_file: &str, board: usize, output: Option<PathBuf>) -> Result<()> {
+    let config = Conf::from_file(config_file)?;
+    let board_urls = resolve_board_urls(&config.run_settings.boards)?;
+    let url = board_urls.get(board).ok_or_else(|| {
+        anyhow!(
+            "{} lists {} boards, there is no board {}",
+            config_file,
+            board_urls.len(),
+            board
+        )
+    })?;
+    let dev_handle = felib::open(url)?;
+    let handle = dev_handle.raw();
+    felib::send_command(handle, "/cmd/reset")?;
+    let num_ch = get_num_channels(handle)?;
+    configure_board(board, handle, num_ch, &config)?;
+    configure_sync(handle, board, board_urls.len(), &config)?;
+
+    let mut dump = format!(
+        "# Board {} ({}) configured from {}\n",
+        board, url, config_file
+    );
+    for (path, value) in read_parameters(handle) {
+        dump.push_str(&format!("{}={}\n", path, value));
+    }
+    match output {
+        Some(path) => fs::write(path, dump)?,
+        None => print!("{}", dump),
+    }
+    Ok(())
+}
+
+/// Print the board settings stored with run `run_a` that differ in `run_b`.
+fn diff_run_params(campaign: usize, config: &str, run_a: usize, run_b: usize) -> Result<()> {
+    let stream = Conf::from_file(config)?.run_settings;
+    let dirs = DirLayout::new(&stream, &stream.stream_name(config), campaign);
+    let changes = diff_params(
+        &run_board_params(&dirs, run_a)?,
+        &run_board_params(&dirs, run_b)?,
+    );
+    if changes.is_empty() {
+        println!(
+            "No board parameter changed between run {} and run {}",
+            run_a, run_b
+        );
+    }
+    for change in changes {
+        println!("{}", change);
+    }
+    Ok(())
+}
+
+/// Print the size and speed of each codec on a sample of `file`.
+fn bench_compress(file: &Path, events: usize, threads: u8) -> Result<()> {
+    let results = bench_compression(file, events, threads)?;
+    println!("{}", CompressionResult::header());
+    for result in results {
+        println!("{}", result);
+    }
+    Ok(())
+}
+
+/// Load and check a config, failing if anything would stop a run.
+fn check_config(config_file: &str) -> Result<()> {
+    let config = Conf::from_file(config_file).map_err(|e| anyhow!("{}: {}", config_file, e))?;
+    let check = ConfigCheck::run(&config);
+    print!("{}", check);
+    if !check.problems.is_empty() {
+        return Err(anyhow!(
+            "{} has {} problem(s)",
+            config_file,
+            check.problems.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Write the `board_settings` sections reproducing the dumped boards.
+fn import_config(dumps: &[PathBuf], output: Option<PathBuf>) -> Result<()> {
+    let texts = dumps
+        .iter()
+        .map(|path| fs::read_to_string(path).map_err(|e| anyhow!("{}: {}", path.display(), e)))
+        .collect::<Result<Vec<_>>>()?;
+    let settings = import_param_dumps(&texts)?;
+    match output {
+        Some(path) => fs::write(path, settings)?,
+        None => print!("{}", settings),
+    }
+    Ok(())
+}
+
+/// Ask on the terminal for the operator until a non-empty answer is given.
+fn prompt_operator() -> Result<String> {
+    loop {
+        print!("Operator name/shift ID: ");
+        stdout().flush()?;
+        let mut line = String::new();
+        if stdin().read_line(&mut line)? == 0 {
+            return Err(anyhow!("No operator given"));
+        }
+        let operator = line.trim();
+        if !operator.is_empty() {
+            return Ok(operator.to_string());
+        }
+    }
+}
+
 fn main() -> Result<()> {
     let args = Args::parse();
-    let config = Conf::from_file(&args.config)?;
+    match args.command {
+        Some(Command::Report {
+            campaign,
+            config,
+            days,
+            format,
+            output,
+        }) => return report(campaign, &config, days, format, output),
+        Some(Command::ExportConfig {
+            config,
+            board,
+            output,
+        }) => return export_config(&config, board, output),
+        Some(Command::DiffParams {
+            campaign,
+            config,
+            run_a,
+            run_b,
+        }) => return diff_run_params(campaign, &config, run_a, run_b),
+        Some(Command::BenchCompress {
+            file,
+            events,
+            threads,
+        }) => return bench_compress(&file, events, threads),
+        Some(Command::Check { config }) => return check_config(&config),
+        Some(Command::ImportConfig { dumps, output }) => return import_config(&dumps, output),
+        None => {}
+    }
 
-    // List of board connection strings. Add as many as needed.
-    let board_urls = &config.run_settings.boards;
+    let mut configs = args
+        .config
+        .iter()
+        .map(Conf::from_file)
+        .collect::<Result<Vec<_>, _>>()?;
 
-    // Open boards and store their handles along with an assigned board ID.
-    let mut boards = Vec::new();
-    for (i, url) in board_urls.iter().enumerate() {
-        let dev_handle = felib_open(url)?;
-        boards.push((i, dev_handle));
+    // Every run must record who took it
+    if args.operator.is_some() {
+        for config in configs.iter_mut() {
+            config.run_settings.operator = args.operator.clone();
+        }
+    } else if configs.iter().any(|c| c.run_settings.operator.is_none()) {
+        if args.headless {
+            return Err(anyhow!(
+                "No operator given, use --operator or set operator in the config"
+            ));
+        }
+        let operator = prompt_operator()?;
+        for config in configs.iter_mut() {
+            config
+                .run_settings
+                .operator
+                .get_or_insert_with(|| operator.clone());
+        }
+    }
+
+    // Every scheduled run must give a usable config before the first starts
+    let schedule = args
+        .schedule
+        .as_deref()
+        .map(Schedule::from_file)
+        .transpose()?;
+    if let Some(schedule) = &schedule {
+        for file in &args.config {
+            schedule
+                .check(&fs::read_to_string(file)?)
+                .map_err(|e| anyhow!("{}: {}", file, e))?;
+        }
+    }
+
+    // The event processing pool is shared by every stream and the Blosc
+    // threads are set for the whole process, so they must fit the host together
+    let dsp_threads: usize = configs
+        .iter()
+        .filter_map(|c| c.run_settings.dsp_threads)
+        .sum();
+    if dsp_threads > 0 {
+        let blosc_threads = configs
+            .iter()
+            .map(|c| c.run_settings.blosc_threads as usize)
+            .max()
+            .unwrap_or(0);
+        let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+        if dsp_threads + blosc_threads > cores {
+            return Err(anyhow!(
+                "dsp_threads of all streams ({}) and blosc_threads ({}) add up to more than the {} cores of this host",
+                dsp_threads,
+                blosc_threads,
+                cores
+            ));
+        }
+    }
+
+    // Streams writing to the same campaign directories would clash on run numbers
+    let mut campaigns = HashSet::new();
+    for (file, config) in args.config.iter().zip(&configs) {
+        let settings = &config.run_settings;
+        let dirs = DirLayout::new(settings, &settings.stream_name(file), settings.campaign_num);
+        if !campaigns.insert(dirs.campaign_pattern()) {
+            return Err(anyhow!(
+                "{} shares its output_dir, dir_layout and campaign_num with another stream",
+                file
+            ));
+        }
     }
 
     let log_file = OpenOptions::new()
@@ -42,10 +378,73 @@ fn main() -> Result<()> {
         .build();
 
     WriteLogger::init(simplelog::LevelFilter::Debug, log_config, log_file).unwrap();
+    // Started before the boards are opened so probing them is logged too
+    if args.training {
+        enable_simulation(configs[0].simulation.clone(), true);
+        log::info!("Training mode: using simulated digitizers with fault injection");
+    } else if args.simulate {
+        enable_simulation(configs[0].simulation.clone(), false);
+        log::info!("Simulating digitizers");
+    }
+    log::info!("Digitizer backend: {}", backend().name());
+    // One pool does the event processing of every stream
+    if dsp_threads > 0 {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(dsp_threads)
+            .build_global()?;
+        log::info!("Event processing pool of {} threads", dsp_threads);
+    }
+
+    let mut streams = Vec::new();
+    for (config, config_file) in configs.into_iter().zip(args.config) {
+        // List of board connection strings, with boards given by serial
+        // number looked up on the network
+        let board_urls = resolve_board_urls(&config.run_settings.boards)?;
+
+        // Open boards and store their handles along with an assigned board ID.
+        let mut boards = Vec::new();
+        for (i, url) in board_urls.iter().enumerate() {
+            let dev_handle = felib::open(url)?;
+            boards.push((i, dev_handle));
+        }
+        streams.push(Stream::new(
+            config,
+            boards,
+            args.runs,
+            config_file,
+            schedule.clone(),
+        ));
+    }
+
+    let control = args
+        .control
+        .as_deref()
+        .map(ControlServer::bind)
+        .transpose()?;
 
+    // From here on a signal quits gracefully: runs are ended, the last
+    // buffers written, the boards disarmed and the terminal restored
+    install_signal_handlers();
+    if args.headless {
+        log::info!("Running headless");
+        let mut controller = Controller::new(streams);
+        if let Some(control) = control {
+            controller.serve_control(control);
+        }
+        let status = controller.run_headless(Duration::from_secs(args.stats_interval));
+        println!("\nTTFN!");
+        return status;
+    }
+
+    let mut tui = Tui::new(streams);
+    if let Some(control) = control {
+        tui.controller.serve_control(control);
+    }
     let mut terminal = ratatui::init();
-    let config_file = args.config.clone();
-    let status = Tui::new(config, boards, args.runs, config_file).run(&mut terminal);
+    // The TUI stays usable from the keyboard if the terminal has no mouse support
+    let _ = execute!(stdout(), EnableMouseCapture);
+    let status = tui.run(&mut terminal);
+    let _ = execute!(stdout(), DisableMouseCapture);
     ratatui::restore();
 
     println!("\nTTFN!");
@@ -0,0 +1,110 @@
+use crate::{sha256_file, MirrorMode, MirrorSettings};
+use anyhow::{anyhow, Result};
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use log::{info, warn};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    thread,
+};
+
+/// Copy `src` to `dest` through a temporary `.part` file that only takes the
+/// final name once complete and, with `verify`, once its SHA-256 matches the
+/// original.
+pub fn mirror_file(src: &Path, dest: &Path, verify: bool) -> Result<()> {
+    if let Some(dir) = dest.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut part = dest.as_os_str().to_owned();
+    part.push(".part");
+    let part = PathBuf::from(part);
+    fs::copy(src, &part)?;
+    if verify && sha256_file(src)? != sha256_file(&part)? {
+        let _ = fs::remove_file(&part);
+        return Err(anyhow!("checksum mismatch copying to {}", dest.display()));
+    }
+    fs::rename(&part, dest)?;
+    Ok(())
+}
+
+/// The part of a mirror shared with its background copier.
+#[derive(Debug, Clone)]
+struct Copier {
+    output_dir: PathBuf,
+    dir: PathBuf,
+    verify: bool,
+    failures: Sender<String>,
+}
+
+impl Copier {
+    fn copy(&self, path: &Path) {
+        let relative = path.strip_prefix(&self.output_dir).unwrap_or(path);
+        let dest = self.dir.join(relative);
+        match mirror_file(path, &dest, self.verify) {
+            Ok(()) => info!("Mirrored {} to {}", path.display(), dest.display()),
+            Err(e) => {
+                warn!("Failed to mirror {}: {}", path.display(), e);
+                let _ = self.failures.send(format!("{}: {}", path.display(), e));
+            }
+        }
+    }
+}
+
+/// Keeps a second copy of every closed subrun file below `mirror.dir`, in the
+/// same directories as below `output_dir`, so one failed disk loses nothing.
+#[derive(Debug)]
+pub struct Mirror {
+    copier: Copier,
+    /// Queue of the copier thread in Background mode
+    queue: Option<Sender<PathBuf>>,
+    failures: Receiver<String>,
+}
+
+impl Mirror {
+    /// The configured mirror, `None` if `mirror.dir` is unset.
+    pub fn new(settings: &MirrorSettings, output_dir: &str) -> Option<Self> {
+        let dir = settings.dir.as_ref()?;
+        let (failure_tx, failures) = unbounded();
+        let copier = Copier {
+            output_dir: PathBuf::from(output_dir),
+            dir: PathBuf::from(dir),
+            verify: settings.verify,
+            failures: failure_tx,
+        };
+        let queue = match settings.mode {
+            MirrorMode::Sync => None,
+            MirrorMode::Background => {
+                let (tx, rx) = unbounded::<PathBuf>();
+                let background = copier.clone();
+                // ends once the mirror and with it the queue is dropped
+                thread::spawn(move || {
+                    for path in rx {
+                        background.copy(&path);
+                    }
+                });
+                Some(tx)
+            }
+        };
+        Some(Self {
+            copier,
+            queue,
+            failures,
+        })
+    }
+
+    /// Mirror a subrun file the writer has closed, right away in Sync mode
+    /// or queued for the copier thread in Background mode.
+    pub fn file_closed(&self, path: &Path) {
+        match &self.queue {
+            Some(queue) => {
+                let _ = queue.send(path.to_path_buf());
+            }
+            None => self.copier.copy(path),
+        }
+    }
+
+    /// Copies that failed since the last call.
+    pub fn failures(&self) -> Vec<String> {
+        self.failures.try_iter().collect()
+    }
+}
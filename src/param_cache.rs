@@ -0,0 +1,96 @@
+use crate::FELibReturn;
+use std::{
+    collections::HashMap,
+    sync::{Condvar, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+/// Parameter reads shared by every monitoring view, so the TUI panels and
+/// health checks don't compete with data readout for the board's bandwidth.
+static PARAM_CACHE: OnceLock<ParamCache> = OnceLock::new();
+
+/// How long a monitor reading is reused
+const DEFAULT_TTL: Duration = Duration::from_secs(1);
+/// Temperatures change slowly
+const TEMPERATURE_TTL: Duration = Duration::from_secs(5);
+/// Board identity and layout don't change until the board is reset
+const STATIC_TTL: Duration = Duration::from_secs(3600);
+
+type Key = (u64, String);
+
+#[derive(Debug)]
+enum Entry {
+    /// A read is in flight, later callers wait for it instead of reading again
+    Pending,
+    Ready {
+        value: Result<String, FELibReturn>,
+        fetched: Instant,
+    },
+}
+
+#[derive(Debug, Default)]
+struct ParamCache {
+    entries: Mutex<HashMap<Key, Entry>>,
+    ready: Condvar,
+}
+
+fn ttl(path: &str) -> Duration {
+    match path {
+        "/par/NumCh" | "/par/ModelName" | "/par/SerialNum" | "/par/FPGA_FwVer" => STATIC_TTL,
+        p if p.starts_with("/par/TempSens") => TEMPERATURE_TTL,
+        _ => DEFAULT_TTL,
+    }
+}
+
+/// Read a parameter through the cache. A fresh enough value (or error) is
+/// returned as is, and identical reads from several threads at once are
+/// coalesced into one FELib call.
+pub fn get_value_cached(handle: u64, path: &str) -> Result<String, FELibReturn> {
+    let cache = PARAM_CACHE.get_or_init(ParamCache::default);
+    let key = (handle, path.to_string());
+    {
+        let mut entries = cache.entries.lock().unwrap();
+        loop {
+            match entries.get(&key) {
+                Some(Entry::Ready { value, fetched }) if fetched.elapsed() < ttl(path) => {
+                    return value.clone();
+                }
+                Some(Entry::Pending) => entries = cache.ready.wait(entries).unwrap(),
+                _ => break,
+            }
+        }
+        entries.insert(key.clone(), Entry::Pending);
+    }
+
+    // If the read panics, drop the placeholder and wake the waiters so they
+    // read for themselves instead of waiting forever
+    let pending = scopeguard::guard_on_unwind(&key, |key| {
+        if let Ok(mut entries) = cache.entries.lock() {
+            entries.remove(key);
+        }
+        cache.ready.notify_all();
+    });
+    let value = crate::felib::get_value(handle, path);
+    drop(pending);
+    cache.entries.lock().unwrap().insert(
+        key,
+        Entry::Ready {
+            value: value.clone(),
+            fetched: Instant::now(),
+        },
+    );
+    cache.ready.notify_all();
+    value
+}
+
+/// Forget every cached value of a board, e.g. after it was reset or
+/// reconfigured. Reads in flight are left to finish.
+pub fn invalidate_cached_values(handle: u64) {
+    if let Some(cache) = PARAM_CACHE.get() {
+        cache
+            .entries
+            .lock()
+            .unwrap()
+            .retain(|(h, _), entry| *h != handle || matches!(entry, Entry::Pending));
+    }
+}
@@ -0,0 +1,187 @@
+use crate::{read_str_attr, DirLayout};
+use anyhow::{anyhow, Result};
+use hdf5::File;
+use std::{collections::BTreeMap, path::Path};
+
+/// Parameters that are readings rather than settings, like counters and
+/// sensors, which differ between any two runs.
+const READINGS: &[&str] = &[
+    "AcquisitionStatus",
+    "RealtimeMonitor",
+    "DeadtimeMonitor",
+    "LivetimeMonitor",
+    "TriggerCnt",
+    "LostTriggerCnt",
+    "LicenseRemainingTime",
+    "TempSens",
+    "VInSens",
+    "VOutSens",
+    "IOutSens",
+    "FreqSens",
+    "DutyCycleSens",
+    "SpeedSensFan",
+    "ErrorFlags",
+    "SelfTrgRate",
+    "ChStatus",
+];
+
+/// A board parameter whose value differs between two runs, `None` where a
+/// run has no value for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParamChange {
+    /// Index of the board group in the files
+    pub board: usize,
+    pub path: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+impl std::fmt::Display for ParamChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = |v: &Option<String>| v.clone().unwrap_or_else(|| String::from("(none)"));
+        write!(
+            f,
+            "board {} {}: {} -> {}",
+            self.board,
+            self.path,
+            value(&self.before),
+            value(&self.after)
+        )
+    }
+}
+
+/// Whether a parameter is a reading that changes on its own.
+fn is_reading(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    READINGS.iter().any(|reading| name.starts_with(reading))
+}
+
+/// The settings that differ between two parameter snapshots, each one list
+/// of `(path, value)` pairs per board, leaving out the readings.
+pub fn diff_params(
+    before: &[Vec<(String, String)>],
+    after: &[Vec<(String, String)>],
+) -> Vec<ParamChange> {
+    let mut changes = Vec::new();
+    for board in 0..before.len().max(after.len()) {
+        let snapshot = |params: &[Vec<(String, String)>]| -> BTreeMap<String, String> {
+            params
+                .get(board)
+                .into_iter()
+                .flatten()
+                .filter(|(path, _)| !is_reading(path))
+                .cloned()
+                .collect()
+        };
+        let (mut before, after) = (snapshot(before), snapshot(after));
+        for (path, value) in after {
+            match before.remove(&path) {
+                Some(old) if old == value => {}
+                old => changes.push(ParamChange {
+                    board,
+                    path,
+                    before: old,
+                    after: Some(value),
+                }),
+            }
+        }
+        changes.extend(before.into_iter().map(|(path, old)| ParamChange {
+            board,
+            path,
+            before: Some(old),
+            after: None,
+        }));
+    }
+    changes.sort_by(|a, b| (a.board, &a.path).cmp(&(b.board, &b.path)));
+    changes
+}
+
+/// The settings that differ between two versions of a config file, as
+/// `setting: old -> new` lines with `(none)` where a version doesn't set it.
+/// Tables in arrays, like `board_settings.boards`, are compared per entry.
+pub fn diff_config(before: &str, after: &str) -> Result<Vec<String>> {
+    let flat = |text: &str| -> Result<BTreeMap<String, String>> {
+        let mut settings = BTreeMap::new();
+        flatten_toml("", &toml::from_str(text)?, &mut settings);
+        Ok(settings)
+    };
+    let (mut before, after) = (flat(before)?, flat(after)?);
+    let none = || String::from("(none)");
+    let mut changes = Vec::new();
+    for (key, value) in after {
+        match before.remove(&key) {
+            Some(old) if old == value => {}
+            old => changes.push(format!(
+                "{}: {} -> {}",
+                key,
+                old.unwrap_or_else(none),
+                value
+            )),
+        }
+    }
+    changes.extend(
+        before
+            .into_iter()
+            .map(|(key, old)| format!("{}: {} -> {}", key, old, none())),
+    );
+    changes.sort();
+    Ok(changes)
+}
+
+pub(crate) fn flatten_toml(
+    prefix: &str,
+    value: &toml::Value,
+    settings: &mut BTreeMap<String, String>,
+) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten_toml(&key, value, settings);
+            }
+        }
+        toml::Value::Array(entries) if entries.iter().all(toml::Value::is_table) => {
+            for (i, entry) in entries.iter().enumerate() {
+                flatten_toml(&format!("{}[{}]", prefix, i), entry, settings);
+            }
+        }
+        value => {
+            settings.insert(prefix.to_string(), value.to_string());
+        }
+    }
+}
+
+/// The parameter snapshot stored in a data file's `board_params` groups.
+pub fn read_board_params(path: &Path) -> Result<Vec<Vec<(String, String)>>> {
+    let file = File::open(path)?;
+    let mut boards = Vec::new();
+    while let Ok(group) = file.group(&format!("board{}/board_params", boards.len())) {
+        let mut params = Vec::new();
+        for name in group.attr_names()? {
+            let value = read_str_attr(&group, &name)?;
+            params.push((name, value));
+        }
+        params.sort();
+        boards.push(params);
+    }
+    if boards.is_empty() {
+        return Err(anyhow!("{} has no board_params", path.display()));
+    }
+    Ok(boards)
+}
+
+/// The parameter snapshot of a run, from its first subrun file among the
+/// campaign's files.
+pub fn run_board_params(dirs: &DirLayout, run: usize) -> Result<Vec<Vec<(String, String)>>> {
+    let name = crate::subrun_file_name(run, 0);
+    let path = dirs
+        .campaign_files()?
+        .into_iter()
+        .find(|path| path.file_name().is_some_and(|n| n == name.as_str()))
+        .ok_or_else(|| anyhow!("no data file of run {} in the campaign", run))?;
+    read_board_params(&path)
+}
@@ -0,0 +1,179 @@
+use crate::{felib, Conf, FELibReturn, Messages};
+use std::{path::Path, time::Duration};
+
+/// Bytes per waveform sample read out from the boards
+const SAMPLE_BYTES: usize = 2;
+
+/// What a board reported just before arming.
+#[derive(Debug, Clone)]
+pub struct BoardPreflight {
+    pub board: usize,
+    /// Model and serial number, or why the board couldn't be read
+    pub identity: Result<(String, String), FELibReturn>,
+    pub firmware: Option<String>,
+    pub channels_enabled: usize,
+    pub num_ch: usize,
+    /// Bytes of waveform data per event
+    pub event_size: usize,
+    /// Expected trigger rate in Hz, from the config or the test pulse period
+    pub trigger_rate: Option<f64>,
+}
+
+impl BoardPreflight {
+    pub fn reachable(&self) -> bool {
+        self.identity.is_ok()
+    }
+}
+
+/// Summary of a run about to start, logged and shown to the operator before
+/// the boards are armed.
+#[derive(Debug, Clone)]
+pub struct PreflightReport {
+    pub boards: Vec<BoardPreflight>,
+    /// Available and total bytes on the output disk
+    pub free_disk: Option<(u64, u64)>,
+    /// Start of the config file's SHA-256, to tell configurations apart
+    pub config_hash: Option<String>,
+}
+
+impl PreflightReport {
+    /// Read the boards and work out what the run will look like. Boards must
+    /// already be configured so `board_channels` holds their channel counts.
+    pub fn collect(
+        boards: &[(usize, felib::DeviceHandle)],
+        board_channels: &[usize],
+        config: &Conf,
+        config_file: &str,
+    ) -> Self {
+        let record_len = config.board_settings.common.record_len;
+        let boards: Vec<_> = boards
+            .iter()
+            .map(|&(i, ref dev)| {
+                let handle = dev.raw();
+                let read =
+                    |path| crate::get_value_cached(handle, path).map(|v| v.trim().to_string());
+                let settings = &config.board_settings.boards[i];
+                let num_ch = board_channels[i];
+                let channels_enabled = settings.en_chans.enabled(num_ch).len();
+                BoardPreflight {
+                    board: i,
+                    identity: read("/par/ModelName").and_then(|m| Ok((m, read("/par/SerialNum")?))),
+                    firmware: read("/par/FPGA_FwVer").ok(),
+                    channels_enabled,
+                    num_ch,
+                    event_size: channels_enabled * record_len * SAMPLE_BYTES,
+                    trigger_rate: config.run_settings.expected_trigger_rate.or_else(|| {
+                        settings
+                            .trig_source
+                            .split('|')
+                            .any(|s| s.trim() == "TestPulse")
+                            .then_some(settings.test_pulse_period)
+                            .filter(|&period| period > 0)
+                            .map(|period| 1e9 / period as f64)
+                    }),
+                }
+            })
+            .collect();
+
+        PreflightReport {
+            boards,
+            free_disk: crate::disk_space(Path::new(&config.run_settings.output_dir)),
+            config_hash: crate::sha256_file(Path::new(config_file))
+                .ok()
+                .map(|hash| hash[..12].to_string()),
+        }
+    }
+
+    /// Expected data rate in MB/s, if every board's trigger rate is known.
+    pub fn data_rate(&self) -> Option<f64> {
+        self.boards
+            .iter()
+            .map(|b| Some(b.trigger_rate? * b.event_size as f64))
+            .sum::<Option<f64>>()
+            .map(|bytes| bytes / (1024.0 * 1024.0))
+    }
+
+    /// Bytes of waveform data per event, all boards together
+    pub fn event_size(&self) -> usize {
+        self.boards.iter().map(|b| b.event_size).sum()
+    }
+
+    /// Whether something in the report should stop the operator, e.g. a board
+    /// that no longer answers.
+    pub fn has_problems(&self) -> bool {
+        self.boards.iter().any(|b| !b.reachable()) || self.free_disk.is_none()
+    }
+
+    /// The report as plain text for the log, in the words of the pre-flight
+    /// dialog.
+    pub fn text(&self, msg: &Messages) -> String {
+        let mut lines = Vec::new();
+        for board in &self.boards {
+            lines.push(match &board.identity {
+                Ok((model, serial)) => format!(
+                    "{}, {}: {}, {}: {}/{}",
+                    (msg.board_identity)(board.board, model, serial),
+                    msg.firmware,
+                    board.firmware.as_deref().unwrap_or(msg.unknown),
+                    msg.channels_enabled,
+                    board.channels_enabled,
+                    board.num_ch
+                ),
+                Err(e) => format!("{} ({:?})", (msg.board_unreachable)(board.board), e),
+            });
+        }
+        lines.push(match self.free_disk {
+            Some((avail, total)) => format!(
+                "{}: {:.1} / {:.1} GB",
+                msg.free_disk,
+                avail as f64 / 1e9,
+                total as f64 / 1e9
+            ),
+            None => format!("{}: {}", msg.free_disk, msg.unknown),
+        });
+        lines.push(format!(
+            "{}: {}",
+            msg.config_hash,
+            self.config_hash.as_deref().unwrap_or(msg.unknown)
+        ));
+        lines.push(format!("{}: {} B", msg.event_size, self.event_size()));
+        lines.push(format!(
+            "{}: {}",
+            msg.estimated_data_rate,
+            self.data_rate()
+                .map(|rate| format!("{:.2} MB/s", rate))
+                .unwrap_or_else(|| msg.unknown.into())
+        ));
+        lines.join("\n")
+    }
+}
+
+/// How fast the output disk fills up at the expected data rate.
+#[derive(Debug, Clone, Copy)]
+pub struct DiskEstimate {
+    /// Expected MB/s written to disk, after compression
+    pub rate: f64,
+    /// Bytes still free on the output disk
+    pub available: u64,
+}
+
+impl DiskEstimate {
+    /// `compression_ratio` is bytes on disk per byte read from the boards.
+    pub fn new(report: &PreflightReport, compression_ratio: f64, available: u64) -> Option<Self> {
+        Some(DiskEstimate {
+            rate: report.data_rate()? * compression_ratio,
+            available,
+        })
+    }
+
+    /// Time until the disk is full, `None` if nothing is written.
+    pub fn time_to_full(&self) -> Option<Duration> {
+        (self.rate > 0.0)
+            .then(|| Duration::from_secs_f64(self.available as f64 / (self.rate * 1024.0 * 1024.0)))
+    }
+
+    /// Bytes written over `duration` of data taking.
+    pub fn volume(&self, duration: Duration) -> u64 {
+        (self.rate * 1024.0 * 1024.0 * duration.as_secs_f64()) as u64
+    }
+}
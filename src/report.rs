@@ -0,0 +1,363 @@
+use crate::{read_str_attr, DirLayout};
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use hdf5::File;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+use time::{macros::format_description, OffsetDateTime, UtcOffset};
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+/// What is known about one run from the files in its campaign directory.
+#[derive(Debug, Default)]
+pub struct RunReport {
+    pub run_num: usize,
+    pub subruns: usize,
+    /// Size of the run's data files on disk
+    pub bytes_on_disk: u64,
+    /// `run_status` of the last subrun file
+    pub status: Option<String>,
+    pub operator: Option<String>,
+    /// The campaign's description, detector and comma-separated tags as
+    /// stored in the run's first file
+    pub description: Option<String>,
+    pub detector: Option<String>,
+    pub tags: Option<String>,
+    /// When the stats CSV was written, i.e. when the run ended
+    pub ended: Option<SystemTime>,
+    pub duration: f64,
+    pub events: usize,
+    pub max_buffer_len: usize,
+    pub misaligned_events: usize,
+    pub dropped_events: usize,
+}
+
+impl RunReport {
+    pub fn started(&self) -> Option<SystemTime> {
+        self.ended
+            .and_then(|t| t.checked_sub(Duration::from_secs_f64(self.duration)))
+    }
+
+    pub fn event_rate(&self) -> f64 {
+        if self.duration > 0.0 {
+            self.events as f64 / self.duration
+        } else {
+            0.0
+        }
+    }
+
+    pub fn data_rate(&self) -> f64 {
+        if self.duration > 0.0 {
+            self.bytes_on_disk as f64 / self.duration / (1024.0 * 1024.0)
+        } else {
+            0.0
+        }
+    }
+
+    /// Whether anything went wrong during the run.
+    pub fn has_problems(&self) -> bool {
+        self.status.as_deref() != Some(crate::RUN_STATUS_GOOD)
+            || self.misaligned_events > 0
+            || self.dropped_events > 0
+    }
+
+    /// Fill in the run statistics from the last row of its stats CSV.
+    fn read_stats(&mut self, path: &Path) -> Result<()> {
+        let contents = fs::read_to_string(path)?;
+        for line in contents.lines().skip(1) {
+            let fields: Vec<&str> = line.split(',').collect();
+            if fields.len() < 8 {
+                return Err(anyhow!("malformed line in {}", path.display()));
+            }
+            self.duration = fields[0].parse()?;
+            self.events = fields[1].parse()?;
+            self.max_buffer_len = self.max_buffer_len.max(fields[5].parse()?);
+            self.misaligned_events = fields[6].parse()?;
+            self.dropped_events = fields[7].parse()?;
+        }
+        self.ended = fs::metadata(path)?.modified().ok();
+        Ok(())
+    }
+}
+
+/// Aggregated view of a campaign's runs for shift reports.
+#[derive(Debug)]
+pub struct CampaignReport {
+    pub campaign: usize,
+    pub runs: Vec<RunReport>,
+}
+
+impl CampaignReport {
+    /// Collect every run in the campaign's directories, keeping only runs
+    /// that ended within `since` if given.
+    pub fn collect(dirs: &DirLayout, campaign: usize, since: Option<Duration>) -> Result<Self> {
+        let mut runs: BTreeMap<usize, RunReport> = BTreeMap::new();
+        let mut data_files: BTreeMap<usize, Vec<PathBuf>> = BTreeMap::new();
+
+        for path in dirs.campaign_files()? {
+            let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            if let Some(run_num) = name
+                .strip_prefix("stats_run")
+                .and_then(|n| n.strip_suffix(".csv"))
+                .and_then(|n| n.parse::<usize>().ok())
+            {
+                let run = runs.entry(run_num).or_default();
+                run.run_num = run_num;
+                run.read_stats(&path)?;
+            } else if let Some(run_num) = name
+                .strip_prefix("run")
+                .filter(|_| name.ends_with(".h5"))
+                .and_then(|n| n.split('_').next())
+                .and_then(|n| n.parse::<usize>().ok())
+            {
+                data_files.entry(run_num).or_default().push(path);
+            }
+        }
+
+        for (run_num, mut files) in data_files {
+            files.sort();
+            let run = runs.entry(run_num).or_default();
+            run.run_num = run_num;
+            run.subruns = files.len();
+            for file in &files {
+                run.bytes_on_disk += fs::metadata(file)?.len();
+            }
+            if let Ok(first) = File::open(&files[0]) {
+                run.operator = read_str_attr(&first, "operator").ok();
+                run.description = read_str_attr(&first, "campaign_description").ok();
+                run.detector = read_str_attr(&first, "detector").ok();
+                run.tags = read_str_attr(&first, "tags").ok();
+            }
+            if let Ok(last) = File::open(files.last().unwrap()) {
+                run.status = read_str_attr(&last, "run_status").ok();
+            }
+        }
+
+        let mut runs: Vec<RunReport> = runs.into_values().collect();
+        if let Some(since) = since {
+            let cutoff = SystemTime::now() - since;
+            runs.retain(|r| r.ended.is_some_and(|t| t >= cutoff));
+        }
+        Ok(Self { campaign, runs })
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.runs.iter().map(|r| r.bytes_on_disk).sum()
+    }
+
+    pub fn total_events(&self) -> usize {
+        self.runs.iter().map(|r| r.events).sum()
+    }
+
+    /// Fraction of the time between the first run starting and the last run
+    /// ending that was spent taking data.
+    pub fn uptime(&self) -> Option<f64> {
+        let start = self.runs.iter().filter_map(|r| r.started()).min()?;
+        let end = self.runs.iter().filter_map(|r| r.ended).max()?;
+        let span = end.duration_since(start).ok()?.as_secs_f64();
+        let live: f64 = self.runs.iter().map(|r| r.duration).sum();
+        (span > 0.0).then(|| (live / span).min(1.0))
+    }
+
+    /// One block character per run showing its data rate relative to the
+    /// fastest run.
+    pub fn rate_trend(&self) -> String {
+        const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+        let max = self.runs.iter().map(|r| r.data_rate()).fold(0.0, f64::max);
+        self.runs
+            .iter()
+            .map(|r| {
+                if max > 0.0 {
+                    BARS[((r.data_rate() / max) * 7.0).round() as usize]
+                } else {
+                    BARS[0]
+                }
+            })
+            .collect()
+    }
+
+    /// The different values of a run field across the campaign, in run
+    /// order, joined with `; `. Runs without it are skipped.
+    fn distinct(&self, field: impl Fn(&RunReport) -> Option<&str>) -> Option<String> {
+        let mut values: Vec<&str> = Vec::new();
+        for value in self.runs.iter().filter_map(field) {
+            if !values.contains(&value) {
+                values.push(value);
+            }
+        }
+        (!values.is_empty()).then(|| values.join("; "))
+    }
+
+    fn summary_rows(&self) -> Vec<(&'static str, String)> {
+        let problems = self.runs.iter().filter(|r| r.has_problems()).count();
+        let metadata = [
+            ("Description", self.distinct(|r| r.description.as_deref())),
+            ("Detector", self.distinct(|r| r.detector.as_deref())),
+            ("Tags", self.distinct(|r| r.tags.as_deref())),
+        ];
+        let mut rows: Vec<(&'static str, String)> = metadata
+            .into_iter()
+            .filter_map(|(label, value)| Some((label, value?)))
+            .collect();
+        rows.extend([
+            ("Runs", self.runs.len().to_string()),
+            (
+                "Uptime",
+                self.uptime()
+                    .map(|u| format!("{:.1}%", u * 100.0))
+                    .unwrap_or_else(|| String::from("n/a")),
+            ),
+            ("Events", self.total_events().to_string()),
+            (
+                "Data volume",
+                format!("{:.2} GB", self.total_bytes() as f64 / 1024f64.powi(3)),
+            ),
+            ("Runs with problems", problems.to_string()),
+            ("Data rate trend", self.rate_trend()),
+        ]);
+        rows
+    }
+
+    fn run_row(run: &RunReport) -> [String; 10] {
+        [
+            run.run_num.to_string(),
+            run.started()
+                .map(format_time)
+                .unwrap_or_else(|| String::from("?")),
+            format!("{:.0}", run.duration),
+            run.operator.clone().unwrap_or_else(|| String::from("?")),
+            run.status.clone().unwrap_or_else(|| String::from("?")),
+            run.events.to_string(),
+            format!("{:.1}", run.event_rate()),
+            format!("{:.2}", run.data_rate()),
+            run.misaligned_events.to_string(),
+            run.dropped_events.to_string(),
+        ]
+    }
+
+    const RUN_HEADER: [&'static str; 10] = [
+        "Run",
+        "Start",
+        "Duration (s)",
+        "Operator",
+        "Status",
+        "Events",
+        "Event rate (Hz)",
+        "Data rate (MB/s)",
+        "Misaligned",
+        "Dropped",
+    ];
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = format!("# Campaign {} report\n\n", self.campaign);
+        out.push_str("| | |\n|---|---|\n");
+        for (label, value) in self.summary_rows() {
+            out.push_str(&format!("| {} | {} |\n", label, value));
+        }
+
+        out.push_str("\n## Runs\n\n");
+        out.push_str(&format!("| {} |\n", Self::RUN_HEADER.join(" | ")));
+        out.push_str(&format!("|{}\n", "---|".repeat(Self::RUN_HEADER.len())));
+        for run in &self.runs {
+            out.push_str(&format!("| {} |\n", Self::run_row(run).join(" | ")));
+        }
+
+        out.push_str("\n## Failures\n\n");
+        let failures: Vec<&RunReport> = self.runs.iter().filter(|r| r.has_problems()).collect();
+        if failures.is_empty() {
+            out.push_str("None\n");
+        }
+        for run in failures {
+            out.push_str(&format!(
+                "- Run {}: {}\n",
+                run.run_num,
+                problem_summary(run)
+            ));
+        }
+        out
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Campaign {} report</title></head>\n<body>\n<h1>Campaign {} report</h1>\n<table>\n",
+            self.campaign, self.campaign
+        );
+        for (label, value) in self.summary_rows() {
+            out.push_str(&format!(
+                "<tr><th>{}</th><td>{}</td></tr>\n",
+                label,
+                escape_html(&value)
+            ));
+        }
+        out.push_str("</table>\n<h2>Runs</h2>\n<table>\n<tr>");
+        for column in Self::RUN_HEADER {
+            out.push_str(&format!("<th>{}</th>", column));
+        }
+        out.push_str("</tr>\n");
+        for run in &self.runs {
+            out.push_str("<tr>");
+            for cell in Self::run_row(run) {
+                out.push_str(&format!("<td>{}</td>", escape_html(&cell)));
+            }
+            out.push_str("</tr>\n");
+        }
+        out.push_str("</table>\n<h2>Failures</h2>\n<ul>\n");
+        for run in self.runs.iter().filter(|r| r.has_problems()) {
+            out.push_str(&format!(
+                "<li>Run {}: {}</li>\n",
+                run.run_num,
+                escape_html(&problem_summary(run))
+            ));
+        }
+        out.push_str("</ul>\n</body>\n</html>\n");
+        out
+    }
+
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Markdown => self.to_markdown(),
+            ReportFormat::Html => self.to_html(),
+        }
+    }
+}
+
+fn problem_summary(run: &RunReport) -> String {
+    let mut problems = Vec::new();
+    match run.status.as_deref() {
+        Some(crate::RUN_STATUS_GOOD) => {}
+        Some(status) => problems.push(format!("status {}", status)),
+        None => problems.push(String::from("no run status")),
+    }
+    if run.misaligned_events > 0 {
+        problems.push(format!("{} misaligned events", run.misaligned_events));
+    }
+    if run.dropped_events > 0 {
+        problems.push(format!("{} dropped events", run.dropped_events));
+    }
+    problems.join(", ")
+}
+
+fn format_time(time: SystemTime) -> String {
+    let time = OffsetDateTime::from(time);
+    let time = UtcOffset::current_local_offset()
+        .map(|offset| time.to_offset(offset))
+        .unwrap_or(time);
+    time.format(format_description!("[year]-[month]-[day] [hour]:[minute]"))
+        .unwrap_or_default()
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
@@ -0,0 +1,147 @@
+use crate::{DirLayout, RetentionAction, RetentionSettings};
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// What a retention pass did in one campaign directory.
+#[derive(Debug, Default)]
+pub struct RetentionSummary {
+    pub removed: Vec<PathBuf>,
+    /// Old files kept because they have no matching manifest entry
+    pub unconfirmed: Vec<PathBuf>,
+    pub bytes_freed: u64,
+}
+
+/// SHA-256 of a file as lowercase hex, read in chunks so subrun files don't
+/// have to fit in memory.
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Parse a transfer manifest in `sha256sum` format, one `<checksum>  <file>`
+/// line per file confirmed at the remote end.
+fn read_manifest(path: &Path) -> Result<HashMap<String, String>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut checksums = HashMap::new();
+    for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+        let (checksum, file) = line
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| anyhow!("malformed manifest line: {}", line))?;
+        // sha256sum marks binary mode with a leading '*'
+        let file = file.trim_start().trim_start_matches('*');
+        let name = Path::new(file)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or(file);
+        checksums.insert(name.to_string(), checksum.to_lowercase());
+    }
+    Ok(checksums)
+}
+
+/// Move a file, falling back to copy and delete across file systems.
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to)?;
+        fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+/// Delete or archive the campaign's subrun files that are older than the
+/// configured age, but only those whose checksum matches the transfer
+/// manifest in the campaign root. Anything else is left alone.
+pub fn apply_retention(dirs: &DirLayout, settings: &RetentionSettings) -> Result<RetentionSummary> {
+    let mut summary = RetentionSummary::default();
+    let Some(max_age_days) = settings.max_age_days else {
+        return Ok(summary);
+    };
+    let max_age = Duration::from_secs(max_age_days * 24 * 60 * 60);
+    let camp_dir = dirs.campaign_root();
+    let manifest = read_manifest(&camp_dir.join(&settings.manifest))?;
+
+    let archive_dir = match settings.action {
+        RetentionAction::Delete => None,
+        RetentionAction::Archive => {
+            let archive_dir = settings
+                .archive_dir
+                .as_ref()
+                .ok_or_else(|| anyhow!("retention action Archive needs archive_dir"))?;
+            let camp_name = camp_dir.file_name().unwrap_or_default();
+            let dir = Path::new(archive_dir).join(camp_name);
+            fs::create_dir_all(&dir)?;
+            Some(dir)
+        }
+    };
+
+    for path in dirs.campaign_files()? {
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if !(name.starts_with("run") && name.ends_with(".h5")) {
+            continue;
+        }
+        let metadata = fs::metadata(&path)?;
+        let age = SystemTime::now()
+            .duration_since(metadata.modified()?)
+            .unwrap_or_default();
+        if age < max_age {
+            continue;
+        }
+
+        let confirmed = match manifest.get(name) {
+            Some(expected) => &sha256_file(&path)? == expected,
+            None => false,
+        };
+        if !confirmed {
+            summary.unconfirmed.push(path);
+            continue;
+        }
+
+        match &archive_dir {
+            Some(dir) => {
+                // keep the run directories below the campaign root
+                let dest = dir.join(path.strip_prefix(&camp_dir).unwrap_or(Path::new(name)));
+                if let Some(parent) = dest.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                move_file(&path, &dest)?
+            }
+            None => fs::remove_file(&path)?,
+        }
+        summary.bytes_freed += metadata.len();
+        summary.removed.push(path);
+    }
+
+    for path in &summary.removed {
+        info!("Retention: removed {} from the DAQ host", path.display());
+    }
+    if !summary.unconfirmed.is_empty() {
+        warn!(
+            "Retention: keeping {} file(s) older than {} days without a confirmed transfer",
+            summary.unconfirmed.len(),
+            max_age_days
+        );
+    }
+    Ok(summary)
+}
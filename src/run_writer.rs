@@ -0,0 +1,190 @@
+use crate::{
+    DatasetParams, DppPsdHit, EndpointRecord, EventRecord, HDF5Writer, RolloverPolicy,
+    StorageLayout, WaveformAverage,
+};
+use anyhow::Result;
+use log::info;
+use std::path::PathBuf;
+
+/// Where event processing stores a run: the events, hits and endpoint
+/// records as they come, and the averages and status at its end. The HDF5
+/// files are written by `HDF5Writer`, other storage engines implement the
+/// same calls.
+pub trait RunWriter: Sized {
+    /// Start the run's storage at `run_file`, the path its first HDF5 file
+    /// would have, with the arguments of `HDF5Writer::new`.
+    fn create(
+        run_file: PathBuf,
+        channel_maps: Vec<Vec<usize>>,
+        n_samples: usize,
+        max_events_per_board: usize,
+        n_threads: u8,
+        params: DatasetParams,
+        layout: StorageLayout,
+    ) -> Result<Self>;
+    fn set_rollover_policy(&mut self, policy: RolloverPolicy);
+    /// Record the operator with the run
+    fn set_operator(&mut self, operator: &str) -> Result<()>;
+    fn append_event(&mut self, board: usize, event: &EventRecord) -> Result<()>;
+    fn append_hit(&mut self, board: usize, timestamp: u64, hit: &DppPsdHit) -> Result<()>;
+    fn append_endpoint_record(&mut self, board: usize, record: &EndpointRecord) -> Result<()>;
+    /// Record the boards' measured timestamp offsets to board 0, in ns
+    fn set_timestamp_offsets(&mut self, offsets: Vec<i64>) -> Result<()>;
+    /// Channels saved of a board, in the order of its waveform rows
+    fn channel_map(&self, board: usize) -> &[usize];
+    /// Index of the subrun being written, from 0
+    fn subrun(&self) -> usize;
+    /// Close the current subrun and continue in the next
+    fn rollover(&mut self) -> Result<()>;
+    /// Write out everything buffered
+    fn flush(&mut self) -> Result<()>;
+    fn write_averages(&mut self, averages: &[WaveformAverage]) -> Result<()>;
+    /// Flush and record how the run ended, one of the `RUN_STATUS_*` values
+    fn finalize(&mut self, status: &str) -> Result<()>;
+}
+
+/// Stores nothing, for rate tests of everything before the disks. Events
+/// are still counted for the log.
+pub struct NullWriter {
+    channel_maps: Vec<Vec<usize>>,
+    events: usize,
+}
+
+impl RunWriter for NullWriter {
+    fn create(
+        _run_file: PathBuf,
+        channel_maps: Vec<Vec<usize>>,
+        _n_samples: usize,
+        _max_events_per_board: usize,
+        _n_threads: u8,
+        _params: DatasetParams,
+        _layout: StorageLayout,
+    ) -> Result<Self> {
+        Ok(Self {
+            channel_maps,
+            events: 0,
+        })
+    }
+
+    fn set_rollover_policy(&mut self, _policy: RolloverPolicy) {}
+
+    fn set_operator(&mut self, _operator: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn append_event(&mut self, _board: usize, _event: &EventRecord) -> Result<()> {
+        self.events += 1;
+        Ok(())
+    }
+
+    fn append_hit(&mut self, _board: usize, _timestamp: u64, _hit: &DppPsdHit) -> Result<()> {
+        self.events += 1;
+        Ok(())
+    }
+
+    fn append_endpoint_record(&mut self, _board: usize, _record: &EndpointRecord) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_timestamp_offsets(&mut self, _offsets: Vec<i64>) -> Result<()> {
+        Ok(())
+    }
+
+    fn channel_map(&self, board: usize) -> &[usize] {
+        &self.channel_maps[board]
+    }
+
+    fn subrun(&self) -> usize {
+        0
+    }
+
+    fn rollover(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_averages(&mut self, _averages: &[WaveformAverage]) -> Result<()> {
+        Ok(())
+    }
+
+    fn finalize(&mut self, status: &str) -> Result<()> {
+        info!(
+            "Discarded {} board events, run ended {}",
+            self.events, status
+        );
+        Ok(())
+    }
+}
+
+impl RunWriter for HDF5Writer {
+    fn create(
+        run_file: PathBuf,
+        channel_maps: Vec<Vec<usize>>,
+        n_samples: usize,
+        max_events_per_board: usize,
+        n_threads: u8,
+        params: DatasetParams,
+        layout: StorageLayout,
+    ) -> Result<Self> {
+        HDF5Writer::new(
+            run_file,
+            channel_maps,
+            n_samples,
+            max_events_per_board,
+            n_threads,
+            params,
+            layout,
+        )
+    }
+
+    fn set_rollover_policy(&mut self, policy: RolloverPolicy) {
+        HDF5Writer::set_rollover_policy(self, policy)
+    }
+
+    fn set_operator(&mut self, operator: &str) -> Result<()> {
+        HDF5Writer::set_operator(self, operator)
+    }
+
+    fn append_event(&mut self, board: usize, event: &EventRecord) -> Result<()> {
+        HDF5Writer::append_event(self, board, event)
+    }
+
+    fn append_hit(&mut self, board: usize, timestamp: u64, hit: &DppPsdHit) -> Result<()> {
+        HDF5Writer::append_hit(self, board, timestamp, hit)
+    }
+
+    fn append_endpoint_record(&mut self, board: usize, record: &EndpointRecord) -> Result<()> {
+        HDF5Writer::append_endpoint_record(self, board, record)
+    }
+
+    fn set_timestamp_offsets(&mut self, offsets: Vec<i64>) -> Result<()> {
+        HDF5Writer::set_timestamp_offsets(self, offsets)
+    }
+
+    fn channel_map(&self, board: usize) -> &[usize] {
+        HDF5Writer::channel_map(self, board)
+    }
+
+    fn subrun(&self) -> usize {
+        HDF5Writer::subrun(self)
+    }
+
+    fn rollover(&mut self) -> Result<()> {
+        HDF5Writer::rollover(self)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.flush_all()
+    }
+
+    fn write_averages(&mut self, averages: &[WaveformAverage]) -> Result<()> {
+        HDF5Writer::write_averages(self, averages)
+    }
+
+    fn finalize(&mut self, status: &str) -> Result<()> {
+        HDF5Writer::finalize(self, status)
+    }
+}
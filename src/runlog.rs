@@ -0,0 +1,134 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::Path,
+};
+
+/// One finished run as kept in the run log, so the logbook always agrees
+/// with the files on disk.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunRecord {
+    pub stream: String,
+    pub campaign: usize,
+    pub run: usize,
+    pub run_type: String,
+    /// `run_status` the run's files were closed with
+    pub status: String,
+    pub operator: Option<String>,
+    pub comment: Option<String>,
+    /// The campaign's `[campaign]` description, detector and tags
+    pub description: Option<String>,
+    pub detector: Option<String>,
+    pub tags: Vec<String>,
+    /// Start and end as RFC 3339 local time
+    pub start: String,
+    pub end: String,
+    pub duration_s: f64,
+    pub events: usize,
+    pub bytes: usize,
+    pub misaligned_events: usize,
+    pub dropped_events: usize,
+    /// The run's config copy, data files and stats CSV
+    pub files: Vec<String>,
+    /// SHA-256 of each file in `files`, with `file_checksums`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<Vec<String>>,
+    /// Each board's counters at the end of the run
+    pub board_stats: Vec<BoardRunStats>,
+    /// Samples per channel of the run's records
+    pub record_len: usize,
+    /// The previous run's record length, only where it differs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_record_len: Option<usize>,
+    /// Which run of `--schedule` this was, as `step/runs label`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scheduled_run: Option<String>,
+}
+
+/// A board's own counters read when the run stopped, in the board's units,
+/// and the range of its hottest ADC's temperature during the run. Counters
+/// the board couldn't report are left out.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct BoardRunStats {
+    pub board: usize,
+    pub trigger_cnt: Option<u64>,
+    pub lost_trigger_cnt: Option<u64>,
+    pub realtime_monitor: Option<u64>,
+    pub deadtime_monitor: Option<u64>,
+    pub livetime_monitor: Option<u64>,
+    /// Share of the run's real time the board was dead, from its monitors
+    pub dead_time_fraction: Option<f64>,
+    /// Lowest and highest hottest-ADC temperature seen, in °C
+    pub temp_min: Option<f64>,
+    pub temp_max: Option<f64>,
+}
+
+impl BoardRunStats {
+    /// The counters as `(name, value)` pairs, skipping those not read.
+    pub fn counters(&self) -> Vec<(&'static str, u64)> {
+        [
+            ("trigger_cnt", self.trigger_cnt),
+            ("lost_trigger_cnt", self.lost_trigger_cnt),
+            ("realtime_monitor", self.realtime_monitor),
+            ("deadtime_monitor", self.deadtime_monitor),
+            ("livetime_monitor", self.livetime_monitor),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value?)))
+        .collect()
+    }
+}
+
+/// Step of a run's lifecycle, recorded in the run journal as it is reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunPhase {
+    /// Resetting and configuring the boards
+    Configuring,
+    /// Boards armed, waiting for the start
+    Armed,
+    /// Acquisition started and the run numbered
+    Running,
+    /// Boards being disarmed and the pipeline drained
+    Stopping,
+    /// Files closed and the run logged
+    Closed,
+}
+
+/// One line of the run journal. Runs are only numbered once they start, so
+/// the earlier phases have no run number.
+#[derive(Debug, Clone, Serialize)]
+pub struct JournalEntry {
+    /// RFC 3339 local time
+    pub time: String,
+    pub stream: String,
+    pub campaign: usize,
+    pub run: Option<usize>,
+    pub phase: RunPhase,
+}
+
+/// Append a run to a JSON Lines run log, one record per line, creating the
+/// log and its directory if needed.
+pub fn append_run_record(path: &Path, record: &RunRecord) -> Result<()> {
+    append_line(path, &serde_json::to_string(record)?, false)
+}
+
+/// Append a lifecycle step to the run journal. The entry is synced to disk
+/// before returning, so after a crash the journal shows how far the run got.
+pub fn append_journal_entry(path: &Path, entry: &JournalEntry) -> Result<()> {
+    append_line(path, &serde_json::to_string(entry)?, true)
+}
+
+fn append_line(path: &Path, line: &str, sync: bool) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut log = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(log, "{}", line)?;
+    if sync {
+        log.sync_data()?;
+    }
+    Ok(())
+}
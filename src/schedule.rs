@@ -0,0 +1,129 @@
+use crate::Conf;
+use anyhow::{anyhow, Result};
+use confique::{Config, Partial};
+use serde::Deserialize;
+use std::{collections::BTreeMap, fs, path::Path};
+
+/// Settings every run of a stream shares, changing them needs a restart.
+const FIXED_SETTINGS: [&str; 4] = [
+    "run_settings.boards",
+    "run_settings.campaign_num",
+    "run_settings.output_dir",
+    "run_settings.dir_layout",
+];
+
+/// One run of a schedule and the settings it changes from the config file.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduledRun {
+    /// Label for the TUI and the log, e.g. `threshold 20`
+    pub name: Option<String>,
+    /// Length of the run in seconds, `run_duration` if unset
+    pub duration: Option<u64>,
+    /// Settings of the run, laid out as in the config file
+    #[serde(default)]
+    pub set: toml::Table,
+}
+
+impl ScheduledRun {
+    /// The changed settings as `setting = value` lines, board entries
+    /// numbered as in `diff_config`.
+    pub fn settings(&self) -> Vec<String> {
+        let mut settings = BTreeMap::new();
+        crate::flatten_toml("", &toml::Value::Table(self.set.clone()), &mut settings);
+        settings
+            .into_iter()
+            .map(|(key, value)| format!("{} = {}", key, value))
+            .collect()
+    }
+
+    /// The run's name, or its changed settings if it has none.
+    pub fn label(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => self.settings().join(", "),
+        }
+    }
+
+    /// Merge the run's settings into the config file's text. Returns the
+    /// merged text, for the run's config copy, and the config it gives.
+    pub fn config(&self, config_text: &str) -> Result<(String, Conf)> {
+        if let Some(key) = FIXED_SETTINGS.iter().find(|key| contains(&self.set, key)) {
+            return Err(anyhow!("{} can't change between runs", key));
+        }
+        let mut table: toml::Table = toml::from_str(config_text)?;
+        merge(&mut table, &self.set);
+        let text = toml::to_string(&table)?;
+        let partial: <Conf as Config>::Partial = toml::from_str(&text)?;
+        let config = Conf::from_partial(partial.with_fallback(Partial::default_values()))?;
+        Ok((text, config))
+    }
+}
+
+/// Runs taken one after the other, each with its own length and settings,
+/// e.g. the points of a threshold scan. Read from a TOML file of `[[runs]]`
+/// tables.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Schedule {
+    pub runs: Vec<ScheduledRun>,
+}
+
+impl Schedule {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| anyhow!("failed to read {}: {}", path.display(), e))?;
+        let schedule: Self =
+            toml::from_str(&text).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        if schedule.runs.is_empty() {
+            return Err(anyhow!("{} schedules no runs", path.display()));
+        }
+        Ok(schedule)
+    }
+
+    /// Check that every run's settings give a valid config with the config
+    /// file in `config_text`, before any run is taken.
+    pub fn check(&self, config_text: &str) -> Result<()> {
+        for (i, run) in self.runs.iter().enumerate() {
+            run.config(config_text)
+                .map_err(|e| anyhow!("scheduled run {} ({}): {}", i + 1, run.label(), e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Merge `overrides` into `table`: tables key by key, arrays of tables such
+/// as `board_settings.boards` entry by entry, other values replaced.
+fn merge(table: &mut toml::Table, overrides: &toml::Table) {
+    for (key, value) in overrides {
+        match (table.get_mut(key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(value)) => merge(base, value),
+            (Some(toml::Value::Array(base)), toml::Value::Array(entries))
+                if base.iter().chain(entries).all(toml::Value::is_table) =>
+            {
+                for (i, entry) in entries.iter().enumerate() {
+                    match (base.get_mut(i), entry) {
+                        (Some(toml::Value::Table(base)), toml::Value::Table(entry)) => {
+                            merge(base, entry)
+                        }
+                        _ => base.push(entry.clone()),
+                    }
+                }
+            }
+            _ => {
+                table.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// Whether `table` sets the dotted setting `key`.
+fn contains(table: &toml::Table, key: &str) -> bool {
+    match key.split_once('.') {
+        Some((head, rest)) => table
+            .get(head)
+            .and_then(toml::Value::as_table)
+            .is_some_and(|table| contains(table, rest)),
+        None => table.contains_key(key),
+    }
+}
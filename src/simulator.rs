@@ -0,0 +1,564 @@
+use crate::{Backend, EndpointStats, EventWrapper, FELibReturn, RawBlock, SimulationSettings};
+use log::info;
+use rand::Rng;
+use std::{
+    sync::{Mutex, MutexGuard, OnceLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Simulated digitizers standing in for FELib in simulation and training
+/// mode. Once enabled every `felib` call is answered here, so no hardware is
+/// touched.
+static SIMULATOR: OnceLock<Mutex<Simulator>> = OnceLock::new();
+
+/// Mean time between injected faults
+const MEAN_FAULT_INTERVAL: Duration = Duration::from_secs(90);
+/// How long a simulated full disk lasts
+const DISK_FULL_DURATION: Duration = Duration::from_secs(30);
+/// How long a simulated overheating ADC lasts
+const OVERHEAT_DURATION: Duration = Duration::from_secs(60);
+/// Channels reported by a simulated board
+const SIM_NUM_CH: usize = 64;
+/// Board handles are spaced so endpoint handles can be derived from them
+const HANDLE_STRIDE: u64 = 1 << 16;
+/// Time between two reads of a simulated stats endpoint
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+/// `MaxRawDataSize` of a simulated board
+const SIM_MAX_RAW_DATA_SIZE: usize = 1 << 16;
+
+#[derive(Debug, Default)]
+struct SimBoard {
+    url: String,
+    armed: bool,
+    running: bool,
+    /// Disarmed after running, readout returns Stop
+    stopped: bool,
+    trigger_id: u32,
+    t_start: Option<Instant>,
+    next_event: Option<Instant>,
+    /// Board has dropped out and only returns communication errors
+    dropped_out: bool,
+    /// Skip a trigger ID on the next event
+    skip_trigger: bool,
+    /// When the stats endpoint has its next counters ready
+    next_stats: Option<Instant>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Fault {
+    BoardDropout,
+    Misalignment,
+    DiskFull,
+    Overheat,
+}
+
+#[derive(Debug)]
+struct Simulator {
+    settings: SimulationSettings,
+    /// Training mode, where faults are always injected
+    training: bool,
+    boards: Vec<SimBoard>,
+    next_fault: Instant,
+    disk_full_until: Option<Instant>,
+    overheat_until: Option<(usize, Instant)>,
+}
+
+impl Simulator {
+    fn new(settings: SimulationSettings, training: bool) -> Self {
+        Self {
+            settings,
+            training,
+            boards: Vec::new(),
+            next_fault: Instant::now() + random_fault_interval(),
+            disk_full_until: None,
+            overheat_until: None,
+        }
+    }
+
+    fn board(&mut self, handle: u64) -> Result<&mut SimBoard, FELibReturn> {
+        let index = (handle / HANDLE_STRIDE) as usize;
+        index
+            .checked_sub(1)
+            .and_then(|i| self.boards.get_mut(i))
+            .ok_or(FELibReturn::InvalidHandle)
+    }
+
+    /// Inject a random fault once the next fault is due and a run is going.
+    fn maybe_inject_fault(&mut self) {
+        let now = Instant::now();
+        if !(self.training || self.settings.faults)
+            || now < self.next_fault
+            || !self.boards.iter().any(|b| b.running)
+        {
+            return;
+        }
+        self.next_fault = now + random_fault_interval();
+
+        let mut rng = rand::rng();
+        let board = rng.random_range(0..self.boards.len());
+        let fault = match rng.random_range(0..4) {
+            0 => Fault::BoardDropout,
+            1 => Fault::Misalignment,
+            2 => Fault::DiskFull,
+            _ => Fault::Overheat,
+        };
+        info!("Training: injecting {:?} on board {}", fault, board);
+        match fault {
+            Fault::BoardDropout => self.boards[board].dropped_out = true,
+            Fault::Misalignment => self.boards[board].skip_trigger = true,
+            Fault::DiskFull => self.disk_full_until = Some(now + DISK_FULL_DURATION),
+            Fault::Overheat => self.overheat_until = Some((board, now + OVERHEAT_DURATION)),
+        }
+    }
+}
+
+fn random_fault_interval() -> Duration {
+    MEAN_FAULT_INTERVAL.mul_f64(rand::rng().random_range(0.5..1.5))
+}
+
+fn sim() -> MutexGuard<'static, Simulator> {
+    SIMULATOR
+        .get()
+        .expect("simulator not enabled")
+        .lock()
+        .unwrap()
+}
+
+/// Time between simulated triggers on every board.
+fn event_period(settings: &SimulationSettings) -> Duration {
+    Duration::from_secs_f64(1.0 / settings.event_rate)
+}
+
+/// Answers every `felib` call with the simulated boards.
+#[derive(Debug, Clone, Copy)]
+pub struct SimBackend;
+
+/// Switch every FELib call over to simulated boards producing the pulses of
+/// `settings`, for development without hardware or, with `training`, for
+/// training shifters with random faults.
+pub fn enable_simulation(settings: SimulationSettings, training: bool) {
+    SIMULATOR.get_or_init(|| Mutex::new(Simulator::new(settings, training)));
+    crate::set_backend(Box::new(SimBackend));
+}
+
+pub fn training_mode() -> bool {
+    SIMULATOR
+        .get()
+        .is_some_and(|sim| sim.lock().unwrap().training)
+}
+
+/// Disk usage reported while a simulated full disk is active.
+pub fn simulated_disk_space() -> Option<(u64, u64)> {
+    if !training_mode() {
+        return None;
+    }
+    let mut sim = sim();
+    match sim.disk_full_until {
+        Some(until) if Instant::now() < until => Some((1, 1000)),
+        Some(_) => {
+            sim.disk_full_until = None;
+            None
+        }
+        None => None,
+    }
+}
+
+fn sim_open(url: &str) -> Result<u64, FELibReturn> {
+    let mut sim = sim();
+    sim.boards.push(SimBoard {
+        url: url.to_string(),
+        ..Default::default()
+    });
+    Ok(sim.boards.len() as u64 * HANDLE_STRIDE)
+}
+
+/// Any call that only needs a valid handle, like setting a parameter.
+fn sim_check_handle(handle: u64) -> Result<(), FELibReturn> {
+    sim().board(handle).map(|_| ())
+}
+
+fn sim_gethandle(handle: u64) -> Result<u64, FELibReturn> {
+    sim().board(handle)?;
+    // endpoint handles live just above their board's handle
+    Ok(handle - handle % HANDLE_STRIDE + 1)
+}
+
+fn sim_getparenthandle(handle: u64) -> Result<u64, FELibReturn> {
+    sim().board(handle)?;
+    Ok(handle - handle % HANDLE_STRIDE)
+}
+
+fn sim_getvalue(handle: u64, path: &str) -> Result<String, FELibReturn> {
+    let mut sim = sim();
+    let overheating = match sim.overheat_until {
+        Some((board, until)) if Instant::now() < until => {
+            board + 1 == (handle / HANDLE_STRIDE) as usize
+        }
+        Some(_) => {
+            sim.overheat_until = None;
+            false
+        }
+        None => false,
+    };
+    let board = sim.board(handle)?;
+    let value = match path {
+        "/par/NumCh" => SIM_NUM_CH.to_string(),
+        "/par/ModelName" => String::from("VX2740 (simulated)"),
+        "/par/SerialNum" => format!("sim-{}", board.url),
+        "/par/TriggerCnt" => board.trigger_id.to_string(),
+        "/par/LostTriggerCnt" => String::from("0"),
+        "/par/RealtimeMonitor" => board
+            .t_start
+            .map(|t| t.elapsed().as_nanos() / 8)
+            .unwrap_or_default()
+            .to_string(),
+        "/par/DeadtimeMonitor" => String::from("0"),
+        // bit 0 armed, bit 1 running, bit 2 run signal
+        "/par/AcquisitionStatus" => {
+            (board.armed as u32 | (board.running as u32) << 1 | (board.running as u32) << 2)
+                .to_string()
+        }
+        "/par/MaxRawDataSize" => SIM_MAX_RAW_DATA_SIZE.to_string(),
+        "/par/TempSensAirIn" => String::from("28"),
+        "/par/TempSensAirOut" => String::from("36"),
+        "/par/TempSensHottestADC" if overheating => String::from("86"),
+        "/par/TempSensHottestADC" => String::from("54"),
+        "/par/SpeedSensFan1" | "/par/SpeedSensFan2" => String::from("3200"),
+        "/par/ADC_SamplRate" => String::from("125"),
+        // 2 Vpp over 16 bits
+        p if p.ends_with("/par/ADCToVolts") => String::from("3.0517578125e-05"),
+        _ => String::from("0"),
+    };
+    Ok(value)
+}
+
+fn sim_sendcommand(handle: u64, path: &str) -> Result<(), FELibReturn> {
+    let mut sim = sim();
+    match path {
+        "/cmd/reset" => {
+            let board = sim.board(handle)?;
+            let url = std::mem::take(&mut board.url);
+            *board = SimBoard {
+                url,
+                ..Default::default()
+            };
+        }
+        "/cmd/armacquisition" => sim.board(handle)?.armed = true,
+        // boards are daisy-chained, starting the first starts them all
+        "/cmd/swstartacquisition" => {
+            let now = Instant::now();
+            let period = event_period(&sim.settings);
+            for board in sim.boards.iter_mut().filter(|b| b.armed) {
+                board.running = true;
+                board.t_start = Some(now);
+                board.next_event = Some(now + period);
+            }
+        }
+        "/cmd/disarmacquisition" => {
+            let board = sim.board(handle)?;
+            board.armed = false;
+            board.running = false;
+            board.stopped = true;
+        }
+        _ => {
+            sim.board(handle)?;
+        }
+    }
+    Ok(())
+}
+
+fn sim_readdata(handle: u64, timeout: Duration, data: &mut EventWrapper) -> FELibReturn {
+    let mut sim = sim();
+    sim.maybe_inject_fault();
+    let settings = sim.settings.clone();
+    let board = match sim.board(handle) {
+        Ok(board) => board,
+        Err(e) => return e,
+    };
+
+    if board.dropped_out {
+        drop(sim);
+        thread::sleep(timeout);
+        return FELibReturn::Comm;
+    }
+    if board.stopped {
+        return FELibReturn::Stop;
+    }
+    let (Some(t_start), Some(next_event)) = (board.t_start, board.next_event) else {
+        drop(sim);
+        thread::sleep(timeout);
+        return FELibReturn::Timeout;
+    };
+    let now = Instant::now();
+    if now < next_event {
+        drop(sim);
+        thread::sleep((next_event - now).min(timeout));
+        return FELibReturn::Timeout;
+    }
+
+    board.next_event = Some(next_event + event_period(&settings));
+    if board.skip_trigger {
+        board.trigger_id += 1;
+        board.skip_trigger = false;
+    }
+    let trigger_id = board.trigger_id;
+    board.trigger_id += 1;
+    drop(sim);
+
+    fill_event(data, &settings, trigger_id, next_event - t_start);
+    FELibReturn::Success
+}
+
+/// Wait for the board's next event without taking it.
+fn sim_hasdata(handle: u64, timeout: Duration) -> Result<(), FELibReturn> {
+    let mut sim = sim();
+    let board = sim.board(handle)?;
+    if board.dropped_out {
+        drop(sim);
+        thread::sleep(timeout);
+        return Err(FELibReturn::Comm);
+    }
+    // the read that follows reports the stop
+    if board.stopped {
+        return Ok(());
+    }
+    let Some(next_event) = board.next_event else {
+        drop(sim);
+        thread::sleep(timeout);
+        return Err(FELibReturn::Timeout);
+    };
+    drop(sim);
+    let now = Instant::now();
+    if now + timeout < next_event {
+        thread::sleep(timeout);
+        return Err(FELibReturn::Timeout);
+    }
+    thread::sleep(next_event.saturating_duration_since(now));
+    Ok(())
+}
+
+/// Hits come at the same pace and with the same faults as scope events.
+fn sim_read_dpp_psd(handle: u64, timeout: Duration, data: &mut EventWrapper) -> FELibReturn {
+    let res = sim_readdata(handle, timeout, data);
+    if let (FELibReturn::Success, Some(hit)) = (res, data.dpp_psd.as_mut()) {
+        let mut rng = rand::rng();
+        hit.channel = rng.random_range(0..SIM_NUM_CH as u8);
+        hit.fine_timestamp = rng.random_range(0..1024);
+        hit.energy = rng.random_range(100..4000);
+        hit.energy_short = hit.energy / rng.random_range(2..5);
+        data.c_event.event_size = 16;
+    }
+    res
+}
+
+/// Simulated boards send no raw data, their raw endpoint only times out
+/// until the run stops.
+fn sim_read_raw(handle: u64, timeout: Duration) -> FELibReturn {
+    let mut sim = sim();
+    let board = match sim.board(handle) {
+        Ok(board) => board,
+        Err(e) => return e,
+    };
+    if board.stopped {
+        return FELibReturn::Stop;
+    }
+    drop(sim);
+    thread::sleep(timeout);
+    FELibReturn::Timeout
+}
+
+/// Counters every `STATS_INTERVAL` while the board runs, with every trigger
+/// saved and no dead time.
+fn sim_read_stats(handle: u64, timeout: Duration, stats: &mut EndpointStats) -> FELibReturn {
+    let mut sim = sim();
+    let board = match sim.board(handle) {
+        Ok(board) => board,
+        Err(e) => return e,
+    };
+    if board.dropped_out {
+        drop(sim);
+        thread::sleep(timeout);
+        return FELibReturn::Comm;
+    }
+    if board.stopped {
+        return FELibReturn::Stop;
+    }
+    let Some(t_start) = board.t_start else {
+        drop(sim);
+        thread::sleep(timeout);
+        return FELibReturn::Timeout;
+    };
+    let due = board.next_stats.unwrap_or(t_start + STATS_INTERVAL);
+    let now = Instant::now();
+    if now < due {
+        drop(sim);
+        thread::sleep((due - now).min(timeout));
+        return FELibReturn::Timeout;
+    }
+    board.next_stats = Some(due + STATS_INTERVAL);
+    let real_time = (now - t_start).as_nanos() as u64;
+    let triggers = board.trigger_id;
+    stats.real_time.fill(real_time);
+    stats.dead_time.fill(0);
+    stats.live_time.fill(real_time);
+    stats.trigger_cnt.fill(triggers);
+    stats.saved_event_cnt.fill(triggers);
+    FELibReturn::Success
+}
+
+/// Shape of a simulated pulse with unit height, a rise and an exponential
+/// decay with the configured time constants in samples.
+fn pulse_shape(settings: &SimulationSettings) -> Vec<f64> {
+    let len = (settings.decay_time * 7.0).ceil().max(1.0) as usize;
+    let shape: Vec<f64> = (0..len)
+        .map(|i| {
+            let t = i as f64;
+            let decay = (-t / settings.decay_time).exp();
+            if settings.rise_time > 0.0 {
+                decay - (-t / settings.rise_time).exp()
+            } else {
+                decay
+            }
+        })
+        .collect();
+    let peak = shape.iter().copied().fold(f64::MIN, f64::max);
+    shape.into_iter().map(|x| x / peak).collect()
+}
+
+/// Gaussian noise with the given RMS, by the Box-Muller transform.
+fn gaussian(rng: &mut impl Rng, rms: f64) -> f64 {
+    let u1: f64 = rng.random_range(f64::EPSILON..1.0);
+    let u2: f64 = rng.random_range(0.0..1.0);
+    rms * (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+/// Baseline with noise and `pulses_per_event` pulses in random channels.
+fn fill_event(
+    data: &mut EventWrapper,
+    settings: &SimulationSettings,
+    trigger_id: u32,
+    since_start: Duration,
+) {
+    let mut rng = rand::rng();
+    let (n_channels, n_samples) = data.waveform_data.dim();
+    let baseline = settings.baseline as f64;
+    if settings.noise_rms > 0.0 {
+        data.waveform_data.mapv_inplace(|_| {
+            (baseline + gaussian(&mut rng, settings.noise_rms)).clamp(0.0, u16::MAX as f64) as u16
+        });
+    } else {
+        data.waveform_data.fill(settings.baseline);
+    }
+    if n_channels > 0 && n_samples > 0 {
+        let shape = pulse_shape(settings);
+        for _ in 0..settings.pulses_per_event {
+            let channel = rng.random_range(0..n_channels);
+            let start = rng.random_range(0..n_samples);
+            let amplitude = rng.random_range(settings.amplitude_min..=settings.amplitude_max);
+            for (sample, height) in data
+                .waveform_data
+                .row_mut(channel)
+                .iter_mut()
+                .skip(start)
+                .zip(&shape)
+            {
+                *sample = (*sample as f64 + amplitude * height).min(u16::MAX as f64) as u16;
+            }
+        }
+    }
+    data.c_event.trigger_id = trigger_id;
+    data.c_event.timestamp = since_start.as_nanos() as u64;
+    data.c_event.timestamp_us = since_start.as_secs_f64() * 1e6;
+    data.c_event.flags = 0;
+    data.c_event.board_fail = false;
+    data.c_event.event_size = n_channels * n_samples * std::mem::size_of::<u16>();
+}
+
+impl Backend for SimBackend {
+    fn name(&self) -> &'static str {
+        "simulator"
+    }
+
+    fn lib_info(&self) -> Result<String, FELibReturn> {
+        Ok(String::from("simulator"))
+    }
+
+    fn lib_version(&self) -> Result<String, FELibReturn> {
+        Ok(String::from("simulator"))
+    }
+
+    fn last_error(&self) -> Result<String, FELibReturn> {
+        Err(FELibReturn::NotImplemented)
+    }
+
+    fn devices_discovery(&self) -> Result<String, FELibReturn> {
+        Ok(String::from("[]"))
+    }
+
+    fn open(&self, url: &str) -> Result<u64, FELibReturn> {
+        sim_open(url)
+    }
+
+    fn close(&self, handle: u64) -> Result<(), FELibReturn> {
+        sim_check_handle(handle)
+    }
+
+    fn impl_lib_version(&self, _handle: u64) -> Result<String, FELibReturn> {
+        Ok(String::from("simulator"))
+    }
+
+    fn device_tree(&self, _handle: u64) -> Result<String, FELibReturn> {
+        Ok(String::from("{}"))
+    }
+
+    fn get_value(&self, handle: u64, path: &str) -> Result<String, FELibReturn> {
+        sim_getvalue(handle, path)
+    }
+
+    fn set_value(&self, handle: u64, _path: &str, _value: &str) -> Result<(), FELibReturn> {
+        sim_check_handle(handle)
+    }
+
+    fn send_command(&self, handle: u64, path: &str) -> Result<(), FELibReturn> {
+        sim_sendcommand(handle, path)
+    }
+
+    fn set_read_data_format(&self, handle: u64, _format: &str) -> Result<(), FELibReturn> {
+        sim_check_handle(handle)
+    }
+
+    fn read_data(
+        &self,
+        handle: u64,
+        timeout_ms: i32,
+        data: &mut EventWrapper,
+        _fine_timestamp: bool,
+    ) -> FELibReturn {
+        sim_readdata(handle, Duration::from_millis(timeout_ms as u64), data)
+    }
+
+    fn read_dpp_psd(&self, handle: u64, timeout_ms: i32, data: &mut EventWrapper) -> FELibReturn {
+        sim_read_dpp_psd(handle, Duration::from_millis(timeout_ms as u64), data)
+    }
+
+    fn read_raw(&self, handle: u64, timeout_ms: i32, _block: &mut RawBlock) -> FELibReturn {
+        sim_read_raw(handle, Duration::from_millis(timeout_ms as u64))
+    }
+
+    fn read_stats(&self, handle: u64, timeout_ms: i32, stats: &mut EndpointStats) -> FELibReturn {
+        sim_read_stats(handle, Duration::from_millis(timeout_ms as u64), stats)
+    }
+
+    fn has_data(&self, handle: u64, timeout_ms: i32) -> Result<(), FELibReturn> {
+        sim_hasdata(handle, Duration::from_millis(timeout_ms as u64))
+    }
+
+    fn get_handle(&self, handle: u64, _path: &str) -> Result<u64, FELibReturn> {
+        sim_gethandle(handle)
+    }
+
+    fn get_parent_handle(&self, handle: u64, _path: &str) -> Result<u64, FELibReturn> {
+        sim_getparenthandle(handle)
+    }
+}
@@ -0,0 +1,128 @@
+use serde::Serialize;
+use std::{
+    fs,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+/// Shortest time between two samples of the process counters
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+static MONITOR: OnceLock<Mutex<ProcessMonitor>> = OnceLock::new();
+
+/// Resource use of the DAQ process itself, to tell a slow host apart from
+/// slow boards.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ProcessStats {
+    /// CPU time over wall time, 100% per busy core
+    pub cpu_percent: f64,
+    /// Resident memory in bytes
+    pub rss: u64,
+    /// Bytes sent to storage per second, in MB/s
+    pub write_rate: f64,
+}
+
+/// Cumulative counters read from procfs.
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    at: Instant,
+    /// User plus system CPU time in seconds
+    cpu_time: f64,
+    rss: u64,
+    write_bytes: u64,
+}
+
+impl Sample {
+    fn read() -> Option<Self> {
+        let at = Instant::now();
+        // Fields after the command name, which may contain spaces, start
+        // with the state; utime and stime are the 14th and 15th fields
+        let stat = fs::read_to_string("/proc/self/stat").ok()?;
+        let (_, fields) = stat.rsplit_once(')')?;
+        let mut fields = fields.split_whitespace().skip(11);
+        let utime: u64 = fields.next()?.parse().ok()?;
+        let stime: u64 = fields.next()?.parse().ok()?;
+        let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+        if ticks <= 0 {
+            return None;
+        }
+
+        let status = fs::read_to_string("/proc/self/status").ok()?;
+        let rss_kb: u64 = proc_field(&status, "VmRSS")?
+            .trim_end_matches("kB")
+            .trim()
+            .parse()
+            .ok()?;
+        let io = fs::read_to_string("/proc/self/io").ok()?;
+        let write_bytes = proc_field(&io, "write_bytes")?.parse().ok()?;
+
+        Some(Self {
+            at,
+            cpu_time: (utime + stime) as f64 / ticks as f64,
+            rss: rss_kb * 1024,
+            write_bytes,
+        })
+    }
+}
+
+/// The value of a `name: value` line of a procfs file.
+fn proc_field<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+    text.lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+        .map(str::trim)
+}
+
+#[derive(Debug, Default)]
+struct ProcessMonitor {
+    last: Option<Sample>,
+    stats: Option<ProcessStats>,
+}
+
+impl ProcessMonitor {
+    fn update(&mut self) {
+        if self
+            .last
+            .is_some_and(|last| last.at.elapsed() < SAMPLE_INTERVAL)
+        {
+            return;
+        }
+        let Some(sample) = Sample::read() else {
+            return;
+        };
+        if let Some(last) = self.last {
+            let elapsed = (sample.at - last.at).as_secs_f64();
+            self.stats = Some(ProcessStats {
+                cpu_percent: (sample.cpu_time - last.cpu_time) / elapsed * 100.0,
+                rss: sample.rss,
+                write_rate: sample.write_bytes.saturating_sub(last.write_bytes) as f64
+                    / elapsed
+                    / (1024.0 * 1024.0),
+            });
+        }
+        self.last = Some(sample);
+    }
+}
+
+/// The DAQ process's CPU, memory and write rate over the last second or so.
+/// `None` until two samples have been taken, or where procfs isn't
+/// available.
+pub fn process_stats() -> Option<ProcessStats> {
+    let mut monitor = MONITOR
+        .get_or_init(|| Mutex::new(ProcessMonitor::default()))
+        .lock()
+        .unwrap();
+    monitor.update();
+    monitor.stats
+}
+
+impl std::fmt::Display for ProcessStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "CPU {:.0}%, RSS {:.0} MB, writes {:.2} MB/s",
+            self.cpu_percent,
+            self.rss as f64 / (1024.0 * 1024.0),
+            self.write_rate
+        )
+    }
+}
@@ -0,0 +1,78 @@
+use crate::{Severity, ThemeName};
+use ratatui::style::Color;
+
+/// Colors and symbols used by every TUI widget.
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    /// Live values such as rates, counts and board readings
+    pub value: Color,
+    /// Key hints like `<Q>`
+    pub key: Color,
+    /// Selected tab
+    pub highlight: Color,
+    /// Attention-grabbing text such as a held run or a tab with critical alarms
+    pub alert: Color,
+    /// Status bit that is set, and the symbol drawn for it
+    pub bit_set: (Color, &'static str),
+    /// Status bit that is clear, and the symbol drawn for it
+    pub bit_clear: (Color, &'static str),
+    pub info: Color,
+    pub warning: Color,
+    pub critical: Color,
+}
+
+impl Theme {
+    pub fn new(name: ThemeName) -> Self {
+        match name {
+            ThemeName::Default => Self {
+                value: Color::Yellow,
+                key: Color::Blue,
+                highlight: Color::Yellow,
+                alert: Color::Red,
+                bit_set: (Color::Red, "●"),
+                bit_clear: (Color::White, "●"),
+                info: Color::Cyan,
+                warning: Color::Yellow,
+                critical: Color::Red,
+            },
+            ThemeName::HighContrast => Self {
+                value: Color::White,
+                key: Color::LightCyan,
+                highlight: Color::LightYellow,
+                alert: Color::LightRed,
+                bit_set: (Color::White, "●"),
+                bit_clear: (Color::DarkGray, "○"),
+                info: Color::White,
+                warning: Color::LightYellow,
+                critical: Color::LightRed,
+            },
+            // Okabe-Ito palette, distinguishable with the common forms of
+            // color blindness. Status bits also differ in shape.
+            ThemeName::ColorBlind => Self {
+                value: Color::Rgb(240, 228, 66),
+                key: Color::Rgb(86, 180, 233),
+                highlight: Color::Rgb(230, 159, 0),
+                alert: Color::Rgb(213, 94, 0),
+                bit_set: (Color::Rgb(230, 159, 0), "●"),
+                bit_clear: (Color::Rgb(0, 114, 178), "○"),
+                info: Color::Rgb(86, 180, 233),
+                warning: Color::Rgb(230, 159, 0),
+                critical: Color::Rgb(213, 94, 0),
+            },
+        }
+    }
+
+    pub fn severity(&self, severity: Severity) -> Color {
+        match severity {
+            Severity::Info => self.info,
+            Severity::Warning => self.warning,
+            Severity::Critical => self.critical,
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::new(ThemeName::Default)
+    }
+}
@@ -1,34 +1,50 @@
 use crate::{
-    digitizer_params, BoardEvent, Conf, Counter, EventWrapper, FELibReturn, HDF5Writer,
-    ZeroSuppressionEdge,
+    felib, AlarmCenter, AlarmKind, BoardEvent, BoardRunStats, Calibration, CalibrationRunSettings,
+    Conf, Controller, Counter, DashboardWidget, DatasetParams, DirLayout, DiscoveredDevice,
+    DiskEstimate, DstParams, DstWriter, EndpointRecord, EndpointStats, EndpointType, EventBuilding,
+    EventRecord, EventWrapper, ExistingFiles, ExtraEndpoint, FELibReturn, HDF5Writer, JournalEntry,
+    Messages, Mirror, NullWriter, ParamChange, PreflightReport, Pulse, RawBlock, ReadStrategy,
+    ReadTimeout, RoiSettings, RolloverPolicy, RunPhase, RunRecord, RunSettings, RunType, RunWriter,
+    Schedule, Severity, StartMode, Storage, StorageLayout, StreamStatus, Theme, UiState, Uploader,
+    WaveformAverage, ZeroSuppressionEdge, ZsMode, ZsParallelism, RUN_STATUS_ABORTED,
+    RUN_STATUS_GOOD,
 };
 use anyhow::{anyhow, Result};
-use crossbeam_channel::{tick, unbounded, Receiver, RecvError, Sender};
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
-use log::info;
+use confique::Config;
+use crossbeam_channel::{unbounded, Receiver, RecvError, Sender};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent,
+    MouseEventKind,
+};
+use log::{info, warn};
 use ndarray::{parallel::prelude::*, s};
-use ndarray::{ArrayViewMut1, Axis};
+use ndarray::{Array2, ArrayView1, ArrayViewMut1, Axis};
 use rand::Rng;
 use ratatui::{
-    layout::{Constraint, Direction, Flex, Layout},
-    style::{Color, Style, Stylize},
+    layout::{Constraint, Direction, Flex, Layout, Position, Rect},
+    style::{Modifier, Style, Stylize},
     symbols::border,
     text::{Line, Span, Text},
-    widgets::{Block, Clear, Paragraph},
+    widgets::{Block, Clear, Paragraph, Sparkline, Tabs, Wrap},
     DefaultTerminal, Frame,
 };
+use scopeguard::ScopeGuard;
 use std::fs;
 use std::{
+    borrow::Cow,
     collections::VecDeque,
-    fs::DirEntry,
-    path::PathBuf,
+    path::{Path, PathBuf},
     time::{Duration, Instant},
 };
 use std::{sync::atomic::Ordering, thread::JoinHandle};
 use std::{
-    sync::{atomic::AtomicBool, Arc, Condvar, Mutex},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize},
+        Arc, Condvar, Mutex,
+    },
     thread,
 };
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
 
 #[derive(Debug)]
 #[allow(dead_code)]
@@ -36,440 +52,2962 @@ enum DaqError {
     MisalignedEvents,
     DroppedEvents,
     DataTakingTransit,
-    EventProcessingTransit,
-    FELib(FELibReturn),
+    /// No event within this many consecutive read timeouts
+    BoardStalled(usize),
+    /// The acquisition start signal didn't come within `ACQ_START_TIMEOUT`
+    StartTimeout,
+    /// A FELib call failed, with the library's details of the error if
+    /// it had any
+    FELib(FELibReturn, Option<String>),
 }
 
 impl From<FELibReturn> for DaqError {
+    /// Picks up the library's details, which are kept per thread, so the
+    /// conversion has to happen on the thread whose call failed.
     fn from(value: FELibReturn) -> Self {
-        Self::FELib(value)
+        let details = felib::last_error()
+            .ok()
+            .map(|details| details.trim().to_string())
+            .filter(|details| !details.is_empty());
+        Self::FELib(value, details)
+    }
+}
+
+impl DaqError {
+    /// Classify the error for the alarm center.
+    fn alarm(&self, msg: &Messages) -> (AlarmKind, Severity, String) {
+        match self {
+            DaqError::MisalignedEvents => (
+                AlarmKind::DataQuality,
+                Severity::Critical,
+                String::from(msg.misaligned_run_stopped),
+            ),
+            DaqError::DroppedEvents => (
+                AlarmKind::DataQuality,
+                Severity::Critical,
+                String::from(msg.dropped_run_stopped),
+            ),
+            DaqError::DataTakingTransit => (
+                AlarmKind::Hardware,
+                Severity::Critical,
+                String::from(msg.data_taking_error),
+            ),
+            DaqError::BoardStalled(timeouts) => (
+                AlarmKind::Hardware,
+                Severity::Critical,
+                (msg.board_stalled)(*timeouts),
+            ),
+            DaqError::StartTimeout => (
+                AlarmKind::Hardware,
+                Severity::Critical,
+                String::from(msg.start_timeout),
+            ),
+            DaqError::FELib(val, details) => (
+                AlarmKind::Hardware,
+                Severity::Critical,
+                match details {
+                    Some(details) => format!("{}: {} ({})", msg.felib_error, val, details),
+                    None => format!("{}: {}", msg.felib_error, val),
+                },
+            ),
+        }
     }
 }
 
+/// Event-channel backlog above which a rate alarm is raised
+const MAX_BUFFER_BACKLOG: usize = 1000;
+/// Free fraction of the output disk below which disk alarms are raised
+const DISK_WARNING_FRACTION: f64 = 0.05;
+const DISK_CRITICAL_FRACTION: f64 = 0.01;
+/// How long to wait for terminal input before stepping the streams again
+const INPUT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How long a board may take to report armed
+const ARM_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long a board thread waits for the other boards to be armed and the
+/// run to start
+const ACQ_START_TIMEOUT: Duration = Duration::from_secs(30);
+/// Read timeout of the extra endpoints, which also bounds how long an abort
+/// waits for their threads
+const EXTRA_ENDPOINT_TIMEOUT_MS: i32 = 100;
+/// Pause between attempts at setting up a board's endpoint
+const ENDPOINT_RETRY_DELAY: Duration = Duration::from_millis(200);
+/// AcquisitionStatus bit set while the board is armed
+const ACQ_STATUS_ARMED: u32 = 1;
+/// Board panel readings that older firmware may not have
+const OPTIONAL_MONITORS: [&str; 2] = ["/par/RealtimeMonitor", "/par/DeadtimeMonitor"];
+/// Number of finished runs whose compression ratio feeds the disk estimate
+const COMPRESSION_HISTORY_LEN: usize = 10;
+/// Rows of the event rate history at the bottom of the run stats panel
+const RATE_HISTORY_HEIGHT: u16 = 5;
+/// Height of the run stats panel from which it shows the rate history
+const MIN_RATE_HISTORY_PANEL_HEIGHT: u16 = 14;
+/// How often rate and disk alarms are re-evaluated during a run
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// Longest sleep of the software trigger generator between checks for the
+/// end of the run
+const SW_TRIGGER_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The event builder's counters at one point in the run.
 #[derive(Default, Clone)]
 struct RunInfo {
-    pub event_sizes: Vec<usize>,
+    pub events: usize,
+    pub bytes: usize,
     pub event_channel_buf: usize,
     pub misaligned_events: usize,
     pub dropped_events: usize,
+    pub subrun: usize,
+}
+
+/// Counters the event builder keeps up to date as it goes, read by the
+/// stream on every step instead of handing a `RunInfo` over for every built
+/// event.
+#[derive(Debug, Default)]
+struct RunStats {
+    events: AtomicUsize,
+    bytes: AtomicUsize,
+    event_channel_buf: AtomicUsize,
+    misaligned_events: AtomicUsize,
+    dropped_events: AtomicUsize,
+    subrun: AtomicUsize,
 }
 
-impl RunInfo {
-    fn event_size(&self) -> usize {
-        self.event_sizes.iter().sum()
+impl RunStats {
+    /// Count a built board event of `size` bytes.
+    fn add_event(&self, size: usize) {
+        self.events.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(size, Ordering::Relaxed);
     }
+
+    /// Publish the event builder's other counters.
+    fn update(&self, buffer_len: usize, misaligned: usize, dropped: usize, subrun: usize) {
+        self.event_channel_buf.store(buffer_len, Ordering::Relaxed);
+        self.misaligned_events.store(misaligned, Ordering::Relaxed);
+        self.dropped_events.store(dropped, Ordering::Relaxed);
+        self.subrun.store(subrun, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> RunInfo {
+        RunInfo {
+            events: self.events.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            event_channel_buf: self.event_channel_buf.load(Ordering::Relaxed),
+            misaligned_events: self.misaligned_events.load(Ordering::Relaxed),
+            dropped_events: self.dropped_events.load(Ordering::Relaxed),
+            subrun: self.subrun.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Run statistics at one point in the run, kept for the end-of-run CSV.
+#[derive(Debug, Clone, Default)]
+struct StatsSample {
+    /// Seconds since the run began
+    elapsed: f64,
+    events: usize,
+    bytes: usize,
+    /// Events per second since the previous sample
+    event_rate: f64,
+    /// MB/s since the previous sample
+    data_rate: f64,
+    buffer_len: usize,
+    misaligned_events: usize,
+    dropped_events: usize,
 }
 
+/// One independent acquisition stream: a set of boards with its own
+/// configuration, event builder, run numbering and output files.
 #[derive(Debug)]
-pub struct Tui {
+pub struct Stream {
+    pub name: String,
     pub counter: Counter,
     pub t_begin: Instant,
+    /// Wall-clock start of the current run, for the run log
+    pub started: OffsetDateTime,
     pub run_duration: Duration,
+    /// Physics, or the calibration run inserted at the scheduled time
+    pub run_type: RunType,
     pub run_num: usize,
     pub camp_num: usize,
     pub curr_run: usize,
     pub buffer_len: usize,
     pub misaligned_events: usize,
     pub dropped_events: usize,
+    /// Subrun file the current run is writing, from 0
+    pub subrun: usize,
+    /// Where the campaign's runs are written
+    pub dirs: DirLayout,
+    /// Directory of the current run's files
+    pub run_dir: PathBuf,
     pub config: Conf,
-    pub boards: Vec<(usize, u64)>,
+    pub boards: Vec<(usize, felib::DeviceHandle)>,
+    pub board_channels: Vec<usize>,
+    /// Enabled channels of each board, the only ones read out and saved
+    pub channel_maps: Vec<Vec<usize>>,
+    /// Optional monitor parameters each board's firmware lacks, found at startup
+    pub unsupported_monitors: Vec<Vec<&'static str>>,
     pub max_runs: Option<usize>,
-    pub show_popup: Option<String>,
+    pub alarms: AlarmCenter,
+    pub board_failures: VecDeque<BoardFailure>,
+    pub active_boards: Arc<Vec<AtomicBool>>,
+    /// Current read timeout of each board's data-taking thread in ms
+    pub read_timeouts: Arc<Vec<AtomicU64>>,
     pub exit: Option<StatusExit>,
+    pub next_run: Option<NextRun>,
     pub config_file: String,
+    /// Contents of the config file as last loaded, to tell when it changed
+    config_text: String,
+    /// Settings the last reload of the config file changed
+    config_changes: Vec<String>,
+    /// Board panel currently expanded by the operator
+    pub expanded_board: Option<usize>,
+    pub theme: Theme,
+    pub msg: &'static Messages,
+    state: StreamState,
+    last_health_check: Instant,
+    /// When the next calibration run is due, none if not scheduled
+    next_calibration: Option<OffsetDateTime>,
+    /// Statistics sampled during the current run
+    stats_history: Vec<StatsSample>,
+    /// Set while a retention pass is running in the background
+    retention_running: Arc<AtomicBool>,
+    /// Board add/replace dialog, if open
+    device_picker: Option<DevicePicker>,
+    /// Pre-flight report of the latest run start
+    preflight: Option<PreflightReport>,
+    /// Whether the pre-flight report is shown over the panels
+    show_preflight: bool,
+    /// Parameter snapshot of the latest run start
+    last_params: Vec<Vec<(String, String)>>,
+    /// Settings changed between the last two run starts, none before the
+    /// second run
+    param_changes: Option<Vec<ParamChange>>,
+    /// Samples per channel of the current run's records
+    record_len: usize,
+    /// The previous run's record length where the current run's differs
+    previous_record_len: Option<usize>,
+    /// Whether the parameter changes are shown over the panels
+    show_param_changes: bool,
+    /// Second copy of the subrun files, if configured
+    mirror: Option<Mirror>,
+    /// Upload of the subrun files to an object store, if configured
+    uploader: Option<Uploader>,
+    /// Operator's comment on the current run, saved when it ends
+    pub comment: Option<String>,
+    /// Comment being typed in the comment popup
+    comment_input: Option<String>,
+    /// Bytes on disk per byte read out, of the latest finished runs
+    compression_ratios: VecDeque<f64>,
+    /// Hold the next run once the current one ends, as run control asked
+    hold_next: bool,
+    /// Lowest and highest hottest-ADC temperature of each board this run
+    temp_ranges: Vec<Option<(f64, f64)>>,
+    /// Board health problems waiting for the operator to dismiss their popup
+    health_alerts: VecDeque<String>,
+    /// Boards whose health problem has already popped up, until it clears
+    health_tripped: Vec<bool>,
+    /// Each board's counters at the end of the last run
+    board_stats: Vec<BoardRunStats>,
+    /// Runs to take one after the other with their own settings, from
+    /// `--schedule`
+    schedule: Option<Schedule>,
+    /// Index of the scheduled run in progress or taken next
+    schedule_step: usize,
+    /// Config file with the scheduled run's settings merged in, saved as
+    /// the run's config copy
+    run_config_text: Option<String>,
+}
+
+/// Dialog for pointing a board slot of the config file at a digitizer found
+/// by FELib device discovery.
+#[derive(Debug, Default)]
+struct DevicePicker {
+    /// Discovery in progress, it can take several seconds
+    discovery: Option<JoinHandle<Result<Vec<DiscoveredDevice>>>>,
+    devices: Vec<DiscoveredDevice>,
+    error: Option<String>,
+    selected: usize,
+    /// Board to replace, one past the last board adds a new one
+    slot: usize,
+}
+
+impl Drop for Stream {
+    /// Never leave boards armed behind, e.g. when another stream's error
+    /// ends the program mid-run.
+    fn drop(&mut self) {
+        if let StreamState::Running(threads) = &self.state {
+            threads.shutdown.store(true, Ordering::SeqCst);
+            let devices: Vec<_> = self.boards.iter().map(|(_, dev)| dev.clone()).collect();
+            if let Err(e) = disarm_boards(&devices) {
+                warn!("[{}] Failed to disarm boards on exit: {:?}", self.name, e);
+            }
+        }
+    }
+}
+
+/// Screen areas of a stream's widgets, shared by drawing and mouse handling.
+/// Areas of the panels, zero-sized for panels the dashboard doesn't show.
+#[derive(Default)]
+struct StreamLayout {
+    run_stats: Rect,
+    /// Board index and panel area of each board panel shown
+    boards: Vec<(usize, Rect)>,
+    alarms: Rect,
+    system: Rect,
+}
+
+/// Where a stream is in its run cycle.
+#[derive(Debug)]
+enum StreamState {
+    /// Ready to configure the boards and start the next run
+    Idle,
+    Running(RunThreads),
+    /// Next run held until critical alarms have been acknowledged
+    AwaitingAck,
+    /// Counting down the inter-run gap
+    Gap,
+    /// Out of runs or quit, boards closed
+    Finished,
+}
+
+/// Pipeline threads and channels of the run in progress.
+#[derive(Debug)]
+struct RunThreads {
+    shutdown: Arc<AtomicBool>,
+    stats: Arc<RunStats>,
+    tx_events: Sender<BoardEvent>,
+    ev_handle: JoinHandle<Result<(), DaqError>>,
+    board_handles: Vec<Option<JoinHandle<Result<(), DaqError>>>>,
+    /// Readout threads of the extra endpoints, with their board
+    endpoint_handles: Vec<(usize, JoinHandle<Result<(), DaqError>>)>,
+    /// Periodic software trigger generator and the flag stopping it
+    sw_trigger: Option<(Arc<AtomicBool>, JoinHandle<()>)>,
+}
+
+/// What a board's readout thread needs of the run, besides the config.
+struct ReadoutContext {
+    endpoint: felib::EndpointHandle,
+    /// Channels of the board, the board fills one waveform for each
+    num_ch: usize,
+    /// Enabled channels, the only ones given room in the events
+    channel_map: Vec<usize>,
+    /// Set and notified once every board is armed
+    acq_start: Arc<(Mutex<bool>, Condvar)>,
+    shutdown: Arc<AtomicBool>,
+    /// Current read timeout of each board in ms, for the board panels
+    read_timeouts: Arc<Vec<AtomicU64>>,
+}
+
+/// What the event processing thread needs of the run, besides the config.
+struct ProcessingContext {
+    /// Path of the run's first subrun file
+    run_file: PathBuf,
+    run_type: RunType,
+    /// Enabled channels of each board
+    channel_maps: Vec<Vec<usize>>,
+    calibration: Option<Calibration>,
+    /// Every parameter of each board as read back at the start of the run
+    board_params: Vec<Vec<(String, String)>>,
+    /// Boards still taking data, a failed board's events are dropped
+    active_boards: Arc<Vec<AtomicBool>>,
+    shutdown: Arc<AtomicBool>,
+}
+
+/// State of the countdown between two runs.
+#[derive(Debug, Clone, Copy)]
+pub enum NextRun {
+    /// Next run starts at this instant
+    At(Instant),
+    /// Countdown paused by the operator with this much time remaining
+    Held(Duration),
 }
 
 #[derive(Debug, Clone, Copy)]
 pub enum StatusExit {
+    /// Gracefully stop the run and exit the DAQ
     Quit,
     Timeout,
+    /// Run reached `max_subruns` or `max_run_events`, the next one starts
+    /// without waiting for the inter-run gap
+    Cycle,
+    /// Run ended early by the operator
+    Stop,
+    /// Run ended immediately without draining buffered events
+    Abort,
+}
+
+/// A board whose data-taking thread failed mid-run and is awaiting an
+/// operator decision.
+#[derive(Debug, Clone)]
+pub struct BoardFailure {
+    pub board_id: usize,
+    pub message: String,
+}
+
+/// Terminal front end driving every stream, one tab per stream.
+#[derive(Debug)]
+pub struct Tui {
+    pub controller: Controller,
+    pub selected: usize,
+    /// Layout last written to the UI state file
+    ui_state: UiState,
 }
 
 impl Tui {
+    pub fn new(streams: Vec<Stream>) -> Self {
+        let mut tui = Self {
+            controller: Controller::new(streams),
+            selected: 0,
+            ui_state: UiState::load(Path::new(crate::UI_STATE_FILE)),
+        };
+        tui.restore_ui_state();
+        tui
+    }
+
+    /// Reopen the tab and panels the shifter had open last time, skipping
+    /// anything that no longer matches the configured streams and boards.
+    fn restore_ui_state(&mut self) {
+        if let Some(name) = &self.ui_state.selected_stream {
+            if let Some(i) = self.controller.streams.iter().position(|s| &s.name == name) {
+                self.selected = i;
+            }
+        }
+        for stream in self.controller.streams.iter_mut() {
+            stream.expanded_board = self
+                .ui_state
+                .expanded_boards
+                .get(&stream.name)
+                .copied()
+                .filter(|&board| board < stream.boards.len());
+        }
+    }
+
+    /// Write the current layout to the UI state file if it changed.
+    fn save_ui_state(&mut self) {
+        let ui_state = UiState {
+            selected_stream: Some(self.controller.streams[self.selected].name.clone()),
+            expanded_boards: self
+                .controller
+                .streams
+                .iter()
+                .filter_map(|s| s.expanded_board.map(|board| (s.name.clone(), board)))
+                .collect(),
+        };
+        if ui_state != self.ui_state {
+            if let Err(e) = ui_state.save(Path::new(crate::UI_STATE_FILE)) {
+                warn!("Failed to save UI state: {}", e);
+            }
+            self.ui_state = ui_state;
+        }
+    }
+
     pub fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
-        let ticker = tick(Duration::from_secs(1));
-        let max_runs = self.max_runs.unwrap_or(0);
+        let refresh_interval = self.controller.refresh_interval();
+        let mut last_draw = Instant::now();
+        let mut redraw = true;
 
         loop {
-            // draw terminal here before resetting everything
-            terminal.draw(|f| self.draw(f))?;
-
-            // Reset the boards and reconfigure everything for next run
-            for &(_, dev_handle) in &self.boards {
-                crate::felib_sendcommand(dev_handle, "/cmd/reset")?;
+            redraw |= self.controller.check_quit_signal();
+            redraw |= self.controller.step()?;
+            if self.controller.is_finished() {
+                return Ok(());
             }
-            for &(i, dev_handle) in &self.boards {
-                crate::configure_board(i, dev_handle, &self.config)?;
+
+            // Redraw as soon as something changed, otherwise often enough to
+            // keep clocks and countdowns moving
+            if redraw || last_draw.elapsed() >= refresh_interval {
+                terminal.draw(|f| self.draw(f))?;
+                last_draw = Instant::now();
             }
-            for &(i, dev_handle) in &self.boards {
-                crate::configure_sync(dev_handle, i, self.boards.len(), &self.config)?;
+            redraw = self.handle_events(INPUT_POLL_INTERVAL)?;
+            if redraw {
+                self.save_ui_state();
             }
-            info!("Reset and configured digitizer(s)");
-
-            let shutdown = Arc::new(AtomicBool::new(false));
-            let (tx_stats, rx_stats) = unbounded();
-            let (tx_events, ev_handle, board_handles) =
-                self.begin_run(Arc::clone(&shutdown), tx_stats)?;
-            info!("Beginning run {}", self.run_num);
-            digitizer_params::log_all(&self.boards);
+        }
+    }
 
-            self.t_begin = Instant::now();
-            self.exit = None;
-            self.counter.reset();
-            self.buffer_len = 0;
-            while self.exit.is_none() && !shutdown.load(Ordering::SeqCst) {
-                let _ = ticker.recv();
+    /// Split the screen into the tab bar (only shown with several streams)
+    /// and the selected stream's area.
+    fn layout(&self, area: Rect) -> (Option<Rect>, Rect) {
+        if self.controller.streams.len() > 1 {
+            let [tabs_area, area] =
+                Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(area);
+            (Some(tabs_area), area)
+        } else {
+            (None, area)
+        }
+    }
 
-                // Drain stats channel
-                while let Ok(run_info) = rx_stats.try_recv() {
-                    self.counter.increment(run_info.event_size());
-                    self.buffer_len = run_info.event_channel_buf;
-                    self.misaligned_events = run_info.misaligned_events;
-                    self.dropped_events = run_info.dropped_events;
+    fn tab_titles(&self) -> Vec<Line<'_>> {
+        self.controller
+            .streams
+            .iter()
+            .map(|s| {
+                let mut title = vec![Span::raw(s.name.as_str())];
+                if s.alarms.has_unacknowledged_critical() {
+                    title.push(" ●".fg(s.theme.alert));
                 }
+                Line::from(title)
+            })
+            .collect()
+    }
 
-                self.handle_events()?;
-
-                if self.t_begin.elapsed() >= self.run_duration {
-                    self.exit = Some(StatusExit::Timeout);
-                }
+    fn draw(&self, frame: &mut Frame) {
+        let (tabs_area, area) = self.layout(frame.area());
+        if let Some(tabs_area) = tabs_area {
+            let theme = self.controller.streams[self.selected].theme;
+            let tabs = Tabs::new(self.tab_titles())
+                .select(self.selected)
+                .highlight_style(Style::default().fg(theme.highlight).bold());
+            frame.render_widget(tabs, tabs_area);
+        }
 
-                terminal.draw(|f| self.draw(f))?;
-            }
+        self.controller.streams[self.selected].draw(frame, area);
+    }
 
-            // If user quit, record that so outer loop can break
-            if let Some(StatusExit::Quit) = self.exit {
-                shutdown.store(true, Ordering::SeqCst);
-            }
+    /// Handle every pending terminal event, waiting up to `timeout` for the
+    /// first one. Returns whether any event arrived.
+    fn handle_events(&mut self, mut timeout: Duration) -> Result<bool> {
+        let mut handled = false;
+        while event::poll(timeout)? {
+            match event::read()? {
+                Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
+                    self.handle_key_event(key_event)
+                }
+                Event::Mouse(mouse_event) => self.handle_mouse_event(mouse_event)?,
+                _ => {}
+            };
+            handled = true;
+            timeout = Duration::ZERO;
+        }
+        Ok(handled)
+    }
 
-            // disarm boards
-            for &(_, dev) in &self.boards {
-                crate::felib_sendcommand(dev, "/cmd/disarmacquisition")?;
+    fn handle_key_event(&mut self, key_event: KeyEvent) {
+        let typing = self.controller.streams[self.selected].is_typing();
+        match key_event.code {
+            // Raw mode delivers Ctrl-C as a key instead of SIGINT
+            KeyCode::Char('c') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                info!("User exited DAQ with Ctrl-C");
+                self.controller.quit()
             }
-            // join board threads
-            for h in board_handles {
-                match h.join() {
-                    Err(_) => return Err(anyhow!("Data taking panic")),
-                    Ok(inner) => {
-                        if let Err(daq_err) = inner {
-                            match daq_err {
-                                DaqError::MisalignedEvents => {
-                                    self.show_popup =
-                                        Some(String::from("Misaligned events. Quitting DAQ.\n<q> to exit."));
-                                }
-                                DaqError::DroppedEvents => {
-                                    self.show_popup =
-                                        Some(String::from("Events dropped. Quitting DAQ.\n<q> to exit."))
-                                }
-                                DaqError::FELib(val) => self.show_popup = Some(val.to_string()),
-                                DaqError::DataTakingTransit => {
-                                    self.show_popup = Some(String::from(
-                                        "Data taking pipeline error. Quitting DAQ.\n<q> to exit.",
-                                    ))
-                                }
-                                DaqError::EventProcessingTransit => {
-                                    self.show_popup = Some(String::from(
-                                        "Event processing stats pipeline error. Quitting DAQ.\n<q> to exit.",
-                                    ))
-                                }
-                            }
-                            terminal.draw(|f| self.draw(f))?;
-                            self.handle_error_event()?;
-                        }
-                    }
-                }
+            // Text typed into the comment popup is the stream's
+            _ if typing => self.controller.streams[self.selected].handle_key_event(key_event),
+            KeyCode::Char('q') => {
+                info!("User exited DAQ");
+                self.controller.quit()
             }
-            // drop tx_events so event thread will exit
-            drop(tx_events);
-            // wait for event‐processing to finish
-            match ev_handle.join() {
-                Err(_) => return Err(anyhow!("Event processing panic")),
-                Ok(inner) => {
-                    if let Err(daq_err) = inner {
-                        match daq_err {
-                            DaqError::MisalignedEvents => {
-                                self.show_popup = Some(String::from(
-                                    "Misaligned events. Quitting DAQ.\n<q> to exit.",
-                                ));
-                            }
-                            DaqError::DroppedEvents => {
-                                self.show_popup = Some(String::from(
-                                    "Events dropped. Quitting DAQ.\n<q> to exit.",
-                                ));
-                            }
-                            _ => {}
-                        }
-                        terminal.draw(|f| self.draw(f))?;
-                        self.handle_error_event()?;
-                    }
-                }
+            KeyCode::Tab => self.selected = (self.selected + 1) % self.controller.streams.len(),
+            KeyCode::BackTab => {
+                self.selected = (self.selected + self.controller.streams.len() - 1)
+                    % self.controller.streams.len()
             }
+            _ => self.controller.streams[self.selected].handle_key_event(key_event),
+        }
+    }
 
-            // if user quit, break out of the outer loop
-            if let Some(StatusExit::Quit) = self.exit {
-                // Close all boards
-                for &(_, dev_handle) in &self.boards {
-                    crate::felib_close(dev_handle)?;
-                }
-                return Ok(());
-            }
-            self.curr_run += 1;
-            if self.curr_run == max_runs && max_runs != 0 {
-                // Close all boards
-                for &(_, dev_handle) in &self.boards {
-                    crate::felib_close(dev_handle)?;
+    fn handle_mouse_event(&mut self, mouse_event: MouseEvent) -> Result<()> {
+        let (cols, rows) = crossterm::terminal::size()?;
+        let (tabs_area, area) = self.layout(Rect::new(0, 0, cols, rows));
+        let position = Position::new(mouse_event.column, mouse_event.row);
+
+        if let Some(tabs_area) = tabs_area.filter(|a| a.contains(position)) {
+            if let MouseEventKind::Down(MouseButton::Left) = mouse_event.kind {
+                // Tabs are drawn as " title " separated by a one column divider
+                let mut x = tabs_area.x;
+                for (i, title) in self.tab_titles().iter().enumerate() {
+                    let width = title.width() as u16 + 2;
+                    if position.x < x + width {
+                        self.selected = i;
+                        break;
+                    }
+                    x += width + 1;
                 }
-                return Ok(());
             }
+        } else {
+            self.controller.streams[self.selected].handle_mouse_event(mouse_event, area);
         }
+        Ok(())
     }
+}
 
+impl Stream {
     pub fn new(
         config: Conf,
-        boards: Vec<(usize, u64)>,
+        boards: Vec<(usize, felib::DeviceHandle)>,
         max_runs: Option<usize>,
         config_file: String,
+        schedule: Option<Schedule>,
     ) -> Self {
         let run_duration = Duration::from_secs(config.run_settings.run_duration);
+        let next_calibration = next_calibration_after(&config.calibration_run, local_now());
         let camp_num = config.run_settings.campaign_num;
+        let theme = Theme::new(config.ui.theme);
+        let msg = Messages::new(config.ui.language);
+        let name = config.run_settings.stream_name(&config_file);
+        let dirs = DirLayout::new(&config.run_settings, &name, camp_num);
+        let mirror = Mirror::new(&config.mirror, &config.run_settings.output_dir);
+        let uploader = Uploader::new(&config.upload, &config.run_settings.output_dir);
+        let unsupported_monitors = boards
+            .iter()
+            .map(|&(i, ref handle)| {
+                let unsupported: Vec<_> = OPTIONAL_MONITORS
+                    .into_iter()
+                    .filter(|path| !crate::parameter_supported(handle.raw(), path))
+                    .collect();
+                if !unsupported.is_empty() {
+                    warn!(
+                        "[{}] Board {} firmware lacks {}, hiding it",
+                        name,
+                        i,
+                        unsupported.join(", ")
+                    );
+                }
+                unsupported
+            })
+            .collect();
         Self {
-            counter: Counter::default(),
+            name,
+            counter: Counter::with_rate_history(config.ui.rate_history_minutes * 60),
             t_begin: Instant::now(),
+            started: local_now(),
             run_num: 0,
             curr_run: 0,
-            show_popup: None,
+            alarms: AlarmCenter::new(config.notifications.clone()),
+            board_failures: VecDeque::new(),
+            active_boards: Arc::new(Vec::new()),
+            read_timeouts: Arc::new(Vec::new()),
             exit: None,
+            next_run: None,
             buffer_len: 0,
             camp_num,
             config,
             boards,
+            board_channels: Vec::new(),
+            channel_maps: Vec::new(),
+            unsupported_monitors,
             max_runs,
             run_duration,
+            run_type: RunType::Physics,
             misaligned_events: 0,
             dropped_events: 0,
+            subrun: 0,
+            dirs,
+            run_dir: PathBuf::new(),
+            config_text: fs::read_to_string(&config_file).unwrap_or_default(),
+            config_changes: Vec::new(),
             config_file,
+            expanded_board: None,
+            theme,
+            msg,
+            state: StreamState::Idle,
+            last_health_check: Instant::now(),
+            next_calibration,
+            stats_history: Vec::new(),
+            retention_running: Arc::new(AtomicBool::new(false)),
+            device_picker: None,
+            preflight: None,
+            show_preflight: false,
+            last_params: Vec::new(),
+            param_changes: None,
+            record_len: 0,
+            previous_record_len: None,
+            show_param_changes: false,
+            mirror,
+            uploader,
+            comment: None,
+            comment_input: None,
+            compression_ratios: VecDeque::new(),
+            hold_next: false,
+            temp_ranges: Vec::new(),
+            health_alerts: VecDeque::new(),
+            health_tripped: Vec::new(),
+            board_stats: Vec::new(),
+            schedule,
+            schedule_step: 0,
+            run_config_text: None,
         }
     }
 
-    fn draw(&self, frame: &mut Frame) {
-        let outer_layout = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints(vec![Constraint::Percentage(50), Constraint::Percentage(50)])
-            .split(frame.area());
+    /// Whether the comment popup is taking every key.
+    pub fn is_typing(&self) -> bool {
+        self.comment_input.is_some()
+    }
 
-        let inner_layout = Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints(vec![Constraint::Fill(1); self.boards.len()])
-            .split(outer_layout[1]);
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, StreamState::Finished)
+    }
 
-        let run_stats = self.run_stats_paragraph();
-        frame.render_widget(run_stats, outer_layout[0]);
+    /// Gracefully stop the run in progress and finish.
+    pub fn quit(&mut self) {
+        self.exit = Some(StatusExit::Quit);
+    }
 
-        for &(i, _) in &self.boards {
-            let board_status = self.board_status_paragraph(i);
-            frame.render_widget(board_status, inner_layout[i]);
+    /// Make the decisions the operator would in headless mode: a board
+    /// failure restarts the run, and alarms holding the next run are
+    /// acknowledged since they are already logged.
+    pub fn handle_unattended(&mut self) {
+        if let Some(failure) = self.board_failures.front() {
+            info!(
+                "[{}] Restarting run after board {} failure",
+                self.name, failure.board_id
+            );
+            self.board_failures.clear();
+            self.exit = Some(StatusExit::Stop);
         }
+        // already logged with their alarms
+        self.health_alerts.clear();
+        if matches!(self.state, StreamState::AwaitingAck) {
+            info!(
+                "[{}] Acknowledging alarms to continue unattended",
+                self.name
+            );
+            self.alarms.acknowledge_all();
+        }
+    }
 
-        if let Some(err) = &self.show_popup {
-            let block = Block::bordered().title("DAQ Error").bold();
-            let daq_error = Paragraph::new(Text::from(err.as_str()))
-                .centered()
-                .block(block);
-            let vertical = Layout::vertical([Constraint::Percentage(20)]).flex(Flex::Center);
-            let horizontal = Layout::horizontal([Constraint::Percentage(60)]).flex(Flex::Center);
-            let [area] = vertical.areas(frame.area());
-            let [area] = horizontal.areas(area);
-            frame.render_widget(Clear, area); //this clears out the background
-            frame.render_widget(daq_error, area);
+    fn state_name(&self) -> &'static str {
+        match self.state {
+            StreamState::Idle => "starting",
+            StreamState::Running(_) => "running",
+            StreamState::AwaitingAck => "awaiting acknowledgement",
+            StreamState::Gap => "between runs",
+            StreamState::Finished => "finished",
         }
     }
 
-    fn handle_events(&mut self) -> Result<()> {
-        if event::poll(Duration::ZERO)? {
-            match event::read()? {
-                Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
-                    self.handle_key_event(key_event)
-                }
-                _ => {}
-            };
+    /// One line summary of the stream's state and run stats, for headless
+    /// mode.
+    pub fn status_line(&self) -> String {
+        let line = format!(
+            "[{}] run {} {}: {} events, {:.1} MB, {:.2} MB/s, {} misaligned, {} dropped, {} unacknowledged alarms",
+            self.name,
+            self.run_num,
+            self.state_name(),
+            self.counter.n_events,
+            self.counter.total_size as f64 / (1024.0 * 1024.0),
+            self.counter.average_rate(),
+            self.misaligned_events,
+            self.dropped_events,
+            self.alarms.unacknowledged()
+        );
+        match self.schedule_progress() {
+            Some(progress) => format!("{}, scheduled run {}", line, progress),
+            None => line,
+        }
+    }
+
+    /// The scheduled run in progress or next as `step/runs label`, none
+    /// without a schedule.
+    fn schedule_progress(&self) -> Option<String> {
+        let schedule = self.schedule.as_ref()?;
+        let step = self.schedule_step.min(schedule.runs.len() - 1);
+        Some(format!(
+            "{}/{} {}",
+            step + 1,
+            schedule.runs.len(),
+            schedule.runs[step].label()
+        ))
+    }
+
+    /// The stream's state and run stats for run control.
+    pub fn status(&self) -> StreamStatus {
+        StreamStatus {
+            stream: self.name.clone(),
+            state: self.state_name().to_string(),
+            campaign: self.camp_num,
+            run: self.run_num,
+            run_type: self.run_type.name().to_string(),
+            events: self.counter.n_events,
+            bytes: self.counter.total_size,
+            data_rate: self.counter.average_rate(),
+            misaligned_events: self.misaligned_events,
+            dropped_events: self.dropped_events,
+            unacknowledged_alarms: self.alarms.unacknowledged(),
+            held: self.hold_next || matches!(self.next_run, Some(NextRun::Held(_))),
+            schedule: self.schedule_progress(),
+            process: crate::process_stats(),
+        }
+    }
+
+    /// Start the next run now, skipping what is left of the gap or a hold,
+    /// as run control asked.
+    pub fn start_next_run(&mut self) -> Result<(), String> {
+        match self.state {
+            StreamState::Idle => {}
+            StreamState::Gap => {
+                info!("[{}] Run control started the next run", self.name);
+                self.next_run = None;
+            }
+            StreamState::Running(_) => return Err(String::from("a run is in progress")),
+            StreamState::AwaitingAck => {
+                return Err(String::from(
+                    "critical alarms must be acknowledged before the next run",
+                ))
+            }
+            StreamState::Finished => return Err(String::from("the stream has finished")),
         }
+        self.hold_next = false;
         Ok(())
     }
 
-    fn handle_error_event(&mut self) -> Result<()> {
-        match event::read()? {
-            Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
-                self.handle_key_event(key_event)
+    /// End the run in progress and hold the next one until run control
+    /// starts it. Between runs, the next run is held.
+    pub fn stop_run(&mut self) -> Result<(), String> {
+        match self.state {
+            StreamState::Running(_) if self.exit.is_none() => {
+                info!("[{}] Run control stopped run {}", self.name, self.run_num);
+                self.exit = Some(StatusExit::Stop);
             }
+            StreamState::Gap => {
+                if let Some(NextRun::At(at)) = self.next_run {
+                    info!("[{}] Run control held the next run", self.name);
+                    self.next_run =
+                        Some(NextRun::Held(at.saturating_duration_since(Instant::now())));
+                }
+            }
+            StreamState::Finished => return Err(String::from("the stream has finished")),
             _ => {}
         }
+        self.hold_next = true;
+        Ok(())
+    }
 
+    /// Read the config file again for the runs to come, as run control
+    /// asked. The boards and where the campaign is written can't change
+    /// without restarting cliq.
+    pub fn reload_config(&mut self) -> Result<(), String> {
+        let text = fs::read_to_string(&self.config_file).map_err(|e| e.to_string())?;
+        let mut config = Conf::from_file(&self.config_file).map_err(|e| e.to_string())?;
+        let (old, new) = (&self.config.run_settings, &config.run_settings);
+        if new.boards != old.boards
+            || new.campaign_num != old.campaign_num
+            || new.output_dir != old.output_dir
+            || new.dir_layout != old.dir_layout
+        {
+            return Err(String::from(
+                "boards, campaign_num, output_dir and dir_layout need a restart to change",
+            ));
+        }
+        // an operator given on the command line stays
+        if config.run_settings.operator.is_none() {
+            config.run_settings.operator = self.config.run_settings.operator.clone();
+        }
+        let changes = crate::diff_config(&self.config_text, &text).unwrap_or_default();
+        info!(
+            "[{}] Reloaded {}, used from the next run:\n{}",
+            self.name,
+            self.config_file,
+            changes.join("\n")
+        );
+        self.raise_alarm(
+            AlarmKind::Hardware,
+            Severity::Info,
+            (self.msg.config_reloaded)(changes.len()),
+        );
+        self.config_text = text;
+        self.config_changes = changes;
+        self.run_duration = Duration::from_secs(config.run_settings.run_duration);
+        self.next_calibration = next_calibration_after(&config.calibration_run, local_now());
+        self.config = config;
         Ok(())
     }
 
-    fn handle_key_event(&mut self, key_event: KeyEvent) {
-        match key_event.code {
-            KeyCode::Char('q') => {
-                info!("User exited DAQ");
-                self.exit()
+    /// Advance the stream's run cycle without blocking on the run. Returns
+    /// whether anything shown on screen changed.
+    pub fn step(&mut self) -> Result<bool> {
+        let quit = matches!(self.exit, Some(StatusExit::Quit));
+        let prev_state = std::mem::discriminant(&self.state);
+        let mut changed = self.poll_device_picker();
+        changed |= self.poll_transfers();
+        match std::mem::replace(&mut self.state, StreamState::Idle) {
+            StreamState::Idle if quit => self.finish()?,
+            StreamState::Idle => match self.start_run()? {
+                Some(threads) => {
+                    if self.config.run_settings.comment_prompt {
+                        self.open_comment_input();
+                    }
+                    self.state = StreamState::Running(threads)
+                }
+                // The run couldn't be set up, wait for the operator to see why
+                None => self.state = StreamState::AwaitingAck,
+            },
+            StreamState::Running(mut threads) => {
+                changed |= self.monitor_run(&mut threads)?;
+                if self.exit.is_some() || threads.shutdown.load(Ordering::SeqCst) {
+                    self.end_run(threads)?;
+                    self.after_run()?;
+                } else {
+                    self.state = StreamState::Running(threads);
+                }
             }
-            _ => {}
+            StreamState::AwaitingAck if quit => self.finish()?,
+            StreamState::AwaitingAck => {
+                // Keep the UI live but hold off the next run until critical
+                // alarms have been acknowledged
+                if self.alarms.has_unacknowledged_critical() {
+                    self.state = StreamState::AwaitingAck;
+                } else {
+                    self.start_gap();
+                }
+            }
+            StreamState::Gap if quit => self.finish()?,
+            StreamState::Gap => match self.next_run {
+                Some(NextRun::At(at)) if Instant::now() >= at => self.next_run = None,
+                Some(_) => self.state = StreamState::Gap,
+                None => {}
+            },
+            StreamState::Finished => self.state = StreamState::Finished,
         }
+        Ok(changed || std::mem::discriminant(&self.state) != prev_state)
     }
 
-    fn exit(&mut self) {
-        self.exit = Some(StatusExit::Quit);
+    /// Reset and configure the boards and spawn the run's pipeline threads.
+    /// Returns `None` if a board's endpoint couldn't be set up; an alarm says
+    /// which board and step failed.
+    fn start_run(&mut self) -> Result<Option<RunThreads>> {
+        // The run after the scheduled time is a calibration run
+        let now = local_now();
+        let calibration_due = self.next_calibration.is_some_and(|at| now >= at);
+        self.run_type = if calibration_due {
+            self.config.calibration_run.run_type
+        } else {
+            RunType::Physics
+        };
+        if self.run_type == RunType::Physics {
+            if let Err(e) = self.apply_schedule() {
+                let message = format!("{}: {}", self.msg.schedule_failed, e);
+                self.raise_alarm(AlarmKind::Hardware, Severity::Critical, message);
+                return Ok(None);
+            }
+        }
+        let run_config = self.run_config();
+        self.journal(RunPhase::Configuring, None);
+
+        // Reset the boards and reconfigure everything for next run
+        for (_, dev) in &self.boards {
+            crate::felib::send_command(dev.raw(), "/cmd/reset")?;
+        }
+        self.board_channels = self
+            .boards
+            .iter()
+            .map(|(_, dev)| crate::get_num_channels(dev.raw()))
+            .collect::<Result<_, _>>()?;
+        self.channel_maps = self
+            .boards
+            .iter()
+            .map(|&(i, _)| {
+                self.config.board_settings.boards[i]
+                    .en_chans
+                    .enabled(self.board_channels[i])
+            })
+            .collect();
+        for &(i, ref dev) in &self.boards {
+            crate::configure_board(i, dev.raw(), self.board_channels[i], &run_config)?;
+        }
+        for &(i, ref dev) in &self.boards {
+            crate::configure_sync(dev.raw(), i, self.boards.len(), &self.config)?;
+            crate::invalidate_cached_values(dev.raw());
+        }
+        info!("[{}] Reset and configured digitizer(s)", self.name);
+        self.preflight_check();
+        let calibration = self.load_calibration()?;
+        // the configured state of every board, kept with the data
+        let board_params: Vec<_> = self
+            .boards
+            .iter()
+            .map(|&(i, ref dev)| {
+                let params = crate::read_parameters(dev.raw());
+                crate::log_parameters(i, &params);
+                params
+            })
+            .collect();
+        if !self.last_params.is_empty() {
+            let changes = crate::diff_params(&self.last_params, &board_params);
+            if !changes.is_empty() {
+                let lines: Vec<String> = changes.iter().map(|c| c.to_string()).collect();
+                info!(
+                    "[{}] Parameters changed since the last run:\n{}",
+                    self.name,
+                    lines.join("\n")
+                );
+            }
+            self.param_changes = Some(changes);
+        }
+        self.last_params = board_params.clone();
+
+        let attempts = self.config.run_settings.endpoint_retries + 1;
+        let mut endpoints = Vec::with_capacity(self.boards.len());
+        for (board_id, dev) in &self.boards {
+            match setup_endpoint(dev, &self.config.run_settings, attempts) {
+                Ok(endpoint) => endpoints.push(endpoint),
+                Err(e) => {
+                    let message = format!("{}: {}", self.msg.endpoint_setup_failed, e);
+                    let message = (self.msg.board_alarm)(*board_id, &message);
+                    self.raise_alarm(AlarmKind::Hardware, Severity::Critical, message);
+                    return Ok(None);
+                }
+            }
+        }
+        let mut extra_endpoints = Vec::new();
+        for &(board_id, ref dev) in &self.boards {
+            for &kind in &self.config.run_settings.extra_endpoints {
+                let num_ch = self.board_channels[board_id];
+                match setup_extra_endpoint(dev, kind, num_ch, attempts) {
+                    Ok((endpoint, record)) => {
+                        extra_endpoints.push((board_id, kind, endpoint, record))
+                    }
+                    Err(e) => {
+                        let message = format!("{}: {}", self.msg.endpoint_setup_failed, e);
+                        let message = (self.msg.board_alarm)(board_id, &message);
+                        self.raise_alarm(AlarmKind::Hardware, Severity::Critical, message);
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+
+        let shutdown = Arc::new(AtomicBool::new(false));
+        self.active_boards = Arc::new(self.boards.iter().map(|_| AtomicBool::new(true)).collect());
+        self.read_timeouts = Arc::new(self.boards.iter().map(|_| AtomicU64::new(0)).collect());
+        self.board_failures.clear();
+        let stats = Arc::new(RunStats::default());
+        let Some((tx_events, ev_handle, board_handles)) = self.begin_run(
+            endpoints,
+            calibration,
+            board_params,
+            Arc::clone(&shutdown),
+            Arc::clone(&stats),
+        )?
+        else {
+            return Ok(None);
+        };
+        let endpoint_handles = extra_endpoints
+            .into_iter()
+            .map(|(board_id, kind, endpoint, record)| {
+                let tx = tx_events.clone();
+                let shutdown = Arc::clone(&shutdown);
+                let handle = thread::spawn(move || {
+                    endpoint_thread(board_id, kind, endpoint, record, tx, shutdown)
+                });
+                (board_id, handle)
+            })
+            .collect();
+        match &self.config.run_settings.operator {
+            Some(operator) => info!(
+                "[{}] Beginning {} run {} (operator {})",
+                self.name,
+                self.run_type.name(),
+                self.run_num,
+                operator
+            ),
+            None => info!(
+                "[{}] Beginning {} run {}",
+                self.name,
+                self.run_type.name(),
+                self.run_num
+            ),
+        }
+        if calibration_due {
+            self.next_calibration = next_calibration_after(&self.config.calibration_run, now);
+        }
+
+        self.t_begin = Instant::now();
+        self.started = local_now();
+        self.exit = None;
+        self.counter.reset();
+        self.subrun = 0;
+        self.buffer_len = 0;
+        self.stats_history.clear();
+        self.temp_ranges.clear();
+        self.board_stats.clear();
+
+        let sw_trigger = self.config.run_settings.sw_trigger_rate.map(|rate| {
+            let stop = Arc::new(AtomicBool::new(false));
+            let devices: Vec<_> = self.boards.iter().map(|(_, dev)| dev.clone()).collect();
+            let stop_clone = Arc::clone(&stop);
+            let handle = thread::spawn(move || sw_trigger_thread(devices, rate, stop_clone));
+            (stop, handle)
+        });
+
+        Ok(Some(RunThreads {
+            shutdown,
+            stats,
+            tx_events,
+            ev_handle,
+            board_handles: board_handles.into_iter().map(Some).collect(),
+            endpoint_handles,
+            sw_trigger,
+        }))
     }
 
-    fn run_stats_paragraph(&'_ self) -> Paragraph<'_> {
-        let title =
-            Line::from(format!(" Campaign {} Run {} Status ", self.camp_num, self.run_num).bold());
-        let instructrions = Line::from(vec![" Quit ".into(), "<Q> ".blue().bold()]);
-        let block = Block::bordered()
-            .title(title.centered())
-            .title_bottom(instructrions.centered())
-            .border_set(border::THICK);
+    /// Collect stats, raise alarms and check whether the run is over.
+    /// Returns whether new stats or board failures came in.
+    fn monitor_run(&mut self, threads: &mut RunThreads) -> Result<bool> {
+        let (prev_misaligned, prev_dropped) = (self.misaligned_events, self.dropped_events);
+        let mut changed = self.take_stats(&threads.stats);
+        self.counter.sample_rate();
+        if self.misaligned_events > prev_misaligned {
+            self.raise_alarm(
+                AlarmKind::DataQuality,
+                Severity::Warning,
+                String::from(self.msg.misaligned_discarded),
+            );
+        }
+        if self.dropped_events > prev_dropped {
+            self.raise_alarm(
+                AlarmKind::DataQuality,
+                Severity::Warning,
+                String::from(self.msg.trigger_ids_skipped),
+            );
+        }
+        if self.last_health_check.elapsed() >= HEALTH_CHECK_INTERVAL {
+            self.check_rate_and_disk();
+            self.check_board_health();
+            self.sample_stats();
+            self.last_health_check = Instant::now();
+        }
 
-        let status_text = Text::from(vec![
-            Line::from(vec![
-                "Elapsed time: ".into(),
-                self.counter
-                    .t_begin
-                    .elapsed()
-                    .as_secs()
-                    .to_string()
-                    .yellow(),
-                " s".into(),
-                " Events: ".into(),
-                self.counter.n_events.to_string().yellow(),
-                " Data rate: ".into(),
-                format!("{:.2}", self.counter.average_rate()).yellow(),
-                " MB/s ".into(),
-                " Buffer length: ".into(),
-                self.buffer_len.to_string().yellow(),
-            ]),
-            Line::from(vec![
-                "Misaligned events: ".into(),
-                self.misaligned_events.to_string().yellow(),
-                " Dropped events: ".into(),
-                self.dropped_events.to_string().yellow(),
-            ]),
-        ]);
+        // Surface board threads that died mid-run so the operator can
+        // decide what to do while the other boards keep running
+        for (board_id, slot) in threads.board_handles.iter_mut().enumerate() {
+            if slot.as_ref().is_some_and(|h| h.is_finished()) {
+                let h = slot.take().unwrap();
+                match h.join() {
+                    Err(_) => return Err(anyhow!("Data taking panic")),
+                    Ok(Err(daq_err)) => {
+                        let (kind, severity, message) = daq_err.alarm(self.msg);
+                        let message = (self.msg.board_alarm)(board_id, &message);
+                        self.raise_alarm(kind, severity, message.clone());
+                        self.board_failures
+                            .push_back(BoardFailure { board_id, message });
+                        changed = true;
+                    }
+                    Ok(Ok(())) => {}
+                }
+            }
+        }
 
-        Paragraph::new(status_text).centered().block(block)
+        if self.exit.is_none() && self.t_begin.elapsed() >= self.current_run_duration() {
+            self.exit = Some(StatusExit::Timeout);
+        }
+        if self.exit.is_none() && self.run_limit_reached() {
+            info!(
+                "[{}] Run {} reached its size limit, cycling to the next run",
+                self.name, self.run_num
+            );
+            self.exit = Some(StatusExit::Cycle);
+        }
+        Ok(changed)
     }
 
-    fn board_status_paragraph(&'_ self, board: usize) -> Paragraph<'_> {
-        let title = Line::from(format!(" Board {} Status ", self.boards[board].0).bold());
-        let block = Block::bordered()
-            .title(title.centered())
-            .border_set(border::THICK);
-        let handle = self.boards[board].1;
-        let mut status_text = vec![];
-        match crate::felib_getvalue(handle, "/par/RealtimeMonitor") {
-            Ok(s) => status_text.push(Line::from(format!("Realtime Monitor: {}", s).yellow())),
-            Err(_) => status_text.push(Line::from("Realtime monitor: err in read".yellow())),
-        };
-        match crate::felib_getvalue(handle, "/par/DeadtimeMonitor") {
-            Ok(s) => status_text.push(Line::from(format!("Deadtime Monitor: {}", s).yellow())),
-            Err(_) => status_text.push(Line::from("Deadtime monitor: err in read".yellow())),
-        };
-        match crate::felib_getvalue(handle, "/par/TriggerCnt") {
-            Ok(s) => status_text.push(Line::from(format!("Trigger count: {}", s).yellow())),
-            Err(_) => status_text.push(Line::from("Trigger counts: err in read".yellow())),
-        };
-        match crate::felib_getvalue(handle, "/par/LostTriggerCnt") {
-            Ok(s) => status_text.push(Line::from(format!("Lost trigger count: {}", s).yellow())),
-            Err(_) => status_text.push(Line::from("Lost trigger count: err in read".yellow())),
+    /// Read the event builder's current counters. Returns whether any of
+    /// them moved since the last call.
+    fn take_stats(&mut self, stats: &RunStats) -> bool {
+        let run_info = stats.snapshot();
+        let changed = run_info.events != self.counter.n_events
+            || run_info.event_channel_buf != self.buffer_len
+            || run_info.misaligned_events != self.misaligned_events
+            || run_info.dropped_events != self.dropped_events
+            || run_info.subrun != self.subrun;
+        self.counter.n_events = run_info.events;
+        self.counter.total_size = run_info.bytes;
+        self.buffer_len = run_info.event_channel_buf;
+        self.misaligned_events = run_info.misaligned_events;
+        self.dropped_events = run_info.dropped_events;
+        // the writer closes a subrun file when it moves on to the next
+        for closed in self.subrun..run_info.subrun {
+            self.subrun_closed(closed);
+        }
+        self.subrun = run_info.subrun;
+        changed
+    }
+
+    /// Configured length of the run in progress.
+    fn current_run_duration(&self) -> Duration {
+        match self.run_type {
+            RunType::Physics => self.run_duration,
+            _ => Duration::from_secs(self.config.calibration_run.duration),
+        }
+    }
+
+    /// Configuration the boards are set up with for the next run. Calibration
+    /// runs trigger every board from `calibration_run.trig_source`.
+    fn run_config(&self) -> Conf {
+        let mut config = self.config.clone();
+        if self.run_type != RunType::Physics {
+            for board in &mut config.board_settings.boards {
+                board.trig_source = self.config.calibration_run.trig_source.clone();
+            }
+        }
+        config
+    }
+
+    /// Whether the run has filled `max_subruns` subrun files or built
+    /// `max_run_events` events.
+    fn run_limit_reached(&self) -> bool {
+        let settings = &self.config.run_settings;
+        settings.max_subruns.is_some_and(|max| self.subrun >= max)
+            || settings
+                .max_run_events
+                .is_some_and(|max| self.counter.n_events >= max)
+    }
+
+    /// Disarm the boards and join the pipeline threads.
+    fn end_run(&mut self, threads: RunThreads) -> Result<()> {
+        self.journal(RunPhase::Stopping, Some(self.run_num));
+        if let Some((stop, handle)) = threads.sw_trigger {
+            stop.store(true, Ordering::SeqCst);
+            let _ = handle.join();
+        }
+        // An abort skips draining: the pipeline threads stop at once and
+        // whatever is still queued is discarded. Every other exit lets the
+        // boards stop on disarm and the event builder drain its channel.
+        if let Some(StatusExit::Abort) = self.exit {
+            threads.shutdown.store(true, Ordering::SeqCst);
+        }
+
+        let devices: Vec<_> = self.boards.iter().map(|(_, dev)| dev.clone()).collect();
+        disarm_boards(&devices)?;
+        // the counters are final once the boards are stopped
+        self.board_stats = self.read_board_stats();
+        // join board threads
+        self.board_failures.clear();
+        for (board_id, h) in threads.board_handles.into_iter().enumerate() {
+            let Some(h) = h else { continue };
+            match h.join() {
+                Err(_) => return Err(anyhow!("Data taking panic")),
+                Ok(inner) => {
+                    if let Err(daq_err) = inner {
+                        let (kind, severity, message) = daq_err.alarm(self.msg);
+                        let message = (self.msg.board_alarm)(board_id, &message);
+                        self.raise_alarm(kind, severity, message);
+                    }
+                }
+            }
+        }
+        for (board_id, h) in threads.endpoint_handles {
+            match h.join() {
+                Err(_) => return Err(anyhow!("Endpoint readout panic")),
+                Ok(Err(daq_err)) => {
+                    let (kind, severity, message) = daq_err.alarm(self.msg);
+                    let message = (self.msg.board_alarm)(board_id, &message);
+                    self.raise_alarm(kind, severity, message);
+                }
+                Ok(Ok(())) => {}
+            }
+        }
+        // drop tx_events so event thread will exit
+        drop(threads.tx_events);
+        // wait for event‐processing to finish
+        match threads.ev_handle.join() {
+            Err(_) => return Err(anyhow!("Event processing panic")),
+            Ok(inner) => {
+                if let Err(daq_err) = inner {
+                    let (kind, severity, message) = daq_err.alarm(self.msg);
+                    self.raise_alarm(kind, severity, message);
+                }
+            }
+        }
+        // the event builder's final counters
+        self.take_stats(&threads.stats);
+        self.log_run_summary();
+        self.record_compression_ratio();
+        self.sample_stats();
+        if let Err(e) = self.write_stats_csv() {
+            self.raise_alarm(
+                AlarmKind::Disk,
+                Severity::Warning,
+                format!("{}: {}", self.msg.stats_csv_failed, e),
+            );
+        }
+        if let Err(e) = self.save_comment() {
+            self.raise_alarm(
+                AlarmKind::Disk,
+                Severity::Warning,
+                format!("{}: {}", self.msg.comment_failed, e),
+            );
+        }
+        if let Err(e) = self.save_board_stats() {
+            self.raise_alarm(
+                AlarmKind::Disk,
+                Severity::Warning,
+                format!("{}: {}", self.msg.board_stats_failed, e),
+            );
+        }
+        // the run's last subrun files are closed now the threads are done
+        let mut subrun = self.subrun;
+        while self.subrun_path(subrun).exists() {
+            self.subrun_closed(subrun);
+            subrun += 1;
+        }
+        if let Err(e) = self.append_run_log() {
+            self.raise_alarm(
+                AlarmKind::Disk,
+                Severity::Warning,
+                format!("{}: {}", self.msg.run_log_failed, e),
+            );
+        }
+        self.journal(RunPhase::Closed, Some(self.run_num));
+        // the next comment is for the next run
+        self.comment = None;
+        self.start_retention();
+        Ok(())
+    }
+
+    /// Clean up old, transferred subrun files in the background so hashing
+    /// them doesn't hold up the next run.
+    fn start_retention(&self) {
+        let settings = self.config.retention.clone();
+        if settings.max_age_days.is_none() || self.retention_running.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let dirs = self.dirs.clone();
+        let running = Arc::clone(&self.retention_running);
+        let name = self.name.clone();
+        thread::spawn(move || {
+            if let Err(e) = crate::apply_retention(&dirs, &settings) {
+                warn!("[{}] Retention pass failed: {}", name, e);
+            }
+            running.store(false, Ordering::SeqCst);
+        });
+    }
+
+    /// Decide what follows a finished run.
+    fn after_run(&mut self) -> Result<()> {
+        // if user quit, stop the stream
+        if let Some(StatusExit::Quit) = self.exit {
+            return self.finish();
+        }
+        self.curr_run += 1;
+        let max_runs = self.max_runs.unwrap_or(0);
+        if self.curr_run == max_runs && max_runs != 0 {
+            return self.finish();
+        }
+        // a run cycled at its size limit is followed by another of the
+        // same scheduled run
+        let cycled = matches!(self.exit, Some(StatusExit::Cycle));
+        if self.run_type == RunType::Physics && !cycled && self.advance_schedule() {
+            return self.finish();
+        }
+        // changes of an earlier reload have had their run
+        self.config_changes.clear();
+        self.reload_if_changed();
+
+        if self.alarms.has_unacknowledged_critical() {
+            self.state = StreamState::AwaitingAck;
+        } else if let Some(StatusExit::Cycle) = self.exit {
+            // Only splitting a long run, carry on without a gap
+            self.state = StreamState::Idle;
+        } else {
+            self.start_gap();
+        }
+        Ok(())
+    }
+
+    /// Set up the config of the scheduled run that is next, if following a
+    /// schedule: the config file with the run's settings merged in.
+    fn apply_schedule(&mut self) -> Result<()> {
+        let Some(schedule) = &self.schedule else {
+            return Ok(());
+        };
+        let run = &schedule.runs[self.schedule_step];
+        let (text, mut config) = run.config(&self.config_text)?;
+        // an operator given on the command line stays
+        config.run_settings.operator = self.config.run_settings.operator.clone();
+        info!(
+            "[{}] Scheduled run {} of {} ({}):\n{}",
+            self.name,
+            self.schedule_step + 1,
+            schedule.runs.len(),
+            run.label(),
+            run.settings().join("\n")
+        );
+        self.run_duration =
+            Duration::from_secs(run.duration.unwrap_or(config.run_settings.run_duration));
+        self.config = config;
+        self.run_config_text = Some(text);
+        Ok(())
+    }
+
+    /// Move on to the next scheduled run once one ends. Returns whether the
+    /// schedule is done.
+    fn advance_schedule(&mut self) -> bool {
+        let Some(schedule) = &self.schedule else {
+            return false;
+        };
+        self.schedule_step += 1;
+        if self.schedule_step < schedule.runs.len() {
+            return false;
+        }
+        let runs = schedule.runs.len();
+        info!("[{}] Schedule of {} runs finished", self.name, runs);
+        self.raise_alarm(
+            AlarmKind::Hardware,
+            Severity::Info,
+            (self.msg.schedule_finished)(runs),
+        );
+        true
+    }
+
+    /// Pick up edits to the config file made during the run, so changed
+    /// thresholds, offsets or zero suppression apply from the next run. A
+    /// file that can't be used raises an alarm once and the current config
+    /// stays.
+    fn reload_if_changed(&mut self) {
+        let Ok(text) = fs::read_to_string(&self.config_file) else {
+            return;
+        };
+        if text == self.config_text {
+            return;
+        }
+        if let Err(e) = self.reload_config() {
+            warn!("[{}] Not reloading {}: {}", self.name, self.config_file, e);
+            let message = format!("{}: {}", self.msg.config_reload_failed, e);
+            self.raise_alarm(AlarmKind::Hardware, Severity::Warning, message);
+            self.config_text = text;
+        }
+    }
+
+    /// Start counting down the configured gap between runs. The operator can
+    /// start the next run early or hold it indefinitely.
+    fn start_gap(&mut self) {
+        let gap = Duration::from_secs(self.config.run_settings.inter_run_gap);
+        if self.hold_next {
+            self.hold_next = false;
+            self.next_run = Some(NextRun::Held(gap));
+            self.state = StreamState::Gap;
+        } else if gap.is_zero() {
+            self.state = StreamState::Idle;
+        } else {
+            self.next_run = Some(NextRun::At(Instant::now() + gap));
+            self.state = StreamState::Gap;
+        }
+    }
+
+    /// Stop starting runs. The boards stay open for the panels until the
+    /// stream is dropped.
+    fn finish(&mut self) -> Result<()> {
+        self.state = StreamState::Finished;
+        self.next_run = None;
+        info!("[{}] Stream finished", self.name);
+        Ok(())
+    }
+
+    fn layout(&self, area: Rect) -> StreamLayout {
+        // An expanded board panel takes the whole width and more height
+        if let Some(board) = self.expanded_board {
+            let outer_layout = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([
+                    Constraint::Percentage(25),
+                    Constraint::Percentage(55),
+                    Constraint::Percentage(20),
+                ])
+                .split(area);
+            return StreamLayout {
+                run_stats: outer_layout[0],
+                boards: vec![(board, outer_layout[1])],
+                alarms: outer_layout[2],
+                ..Default::default()
+            };
+        }
+
+        // Otherwise the panels are arranged as the dashboard config says
+        let dashboard = &self.config.ui.dashboard;
+        let widgets = dashboard.widgets();
+        let constraints: Vec<Constraint> = if dashboard.heights.is_empty() {
+            vec![Constraint::Fill(1); widgets.len()]
+        } else {
+            dashboard
+                .heights
+                .iter()
+                .map(|&height| Constraint::Percentage(height))
+                .collect()
+        };
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(constraints)
+            .split(area);
+
+        let mut layout = StreamLayout::default();
+        for (row, &row_area) in widgets.iter().zip(rows.iter()) {
+            let cells = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints(vec![Constraint::Fill(1); row.len()])
+                .split(row_area);
+            for (&widget, &cell) in row.iter().zip(cells.iter()) {
+                match widget {
+                    DashboardWidget::RunStats => layout.run_stats = cell,
+                    DashboardWidget::Alarms => layout.alarms = cell,
+                    DashboardWidget::System => layout.system = cell,
+                    DashboardWidget::Board(board) => layout.boards.push((board, cell)),
+                    DashboardWidget::Boards => {
+                        let inner_layout = Layout::default()
+                            .direction(Direction::Horizontal)
+                            .constraints(vec![Constraint::Fill(1); self.boards.len()])
+                            .split(cell);
+                        layout.boards.extend(
+                            self.boards
+                                .iter()
+                                .zip(inner_layout.iter())
+                                .map(|(&(i, _), &board_area)| (i, board_area)),
+                        );
+                    }
+                }
+            }
+        }
+        layout
+    }
+
+    fn draw(&self, frame: &mut Frame, area: Rect) {
+        let layout = self.layout(area);
+
+        // The rate history takes the bottom of the run stats panel if the
+        // panel has room for both
+        let mut stats_area = layout.run_stats;
+        if stats_area.height >= MIN_RATE_HISTORY_PANEL_HEIGHT {
+            let [top, bottom] =
+                Layout::vertical([Constraint::Fill(1), Constraint::Length(RATE_HISTORY_HEIGHT)])
+                    .areas(stats_area);
+            stats_area = top;
+            frame.render_widget(self.rate_history_sparkline(bottom.width), bottom);
+        }
+        let run_stats = self.run_stats_paragraph();
+        frame.render_widget(run_stats, stats_area);
+
+        for &(i, board_area) in &layout.boards {
+            let board_status = self.board_status_paragraph(i);
+            frame.render_widget(board_status, board_area);
+        }
+
+        let alarm_list = self.alarm_paragraph(layout.alarms.height);
+        frame.render_widget(alarm_list, layout.alarms);
+
+        frame.render_widget(self.system_paragraph(), layout.system);
+
+        if let Some(failure) = self.board_failures.front() {
+            let block = Block::bordered().title(self.msg.board_failure).bold();
+            let text = Text::from(vec![
+                Line::from(failure.message.as_str()),
+                Line::from(""),
+                Line::from(vec![
+                    "<R> ".fg(self.theme.key).bold(),
+                    self.msg.restart_run.into(),
+                ]),
+                Line::from(vec![
+                    "<C> ".fg(self.theme.key).bold(),
+                    (self.msg.continue_without_board)(failure.board_id).into(),
+                ]),
+                Line::from(vec![
+                    "<S> ".fg(self.theme.key).bold(),
+                    self.msg.stop_run.into(),
+                ]),
+            ]);
+            let dialog = Paragraph::new(text).centered().block(block);
+            let vertical = Layout::vertical([Constraint::Length(7)]).flex(Flex::Center);
+            let horizontal = Layout::horizontal([Constraint::Percentage(60)]).flex(Flex::Center);
+            let [area] = vertical.areas(area);
+            let [area] = horizontal.areas(area);
+            frame.render_widget(Clear, area); //this clears out the background
+            frame.render_widget(dialog, area);
+        } else if let Some(alert) = self.health_alerts.front() {
+            self.draw_health_alert(frame, area, alert);
+        } else if let Some(picker) = &self.device_picker {
+            self.draw_device_picker(frame, area, picker);
+        } else if let Some(text) = &self.comment_input {
+            self.draw_comment_input(frame, area, text);
+        } else if let Some(report) = self.preflight.as_ref().filter(|_| self.show_preflight) {
+            self.draw_preflight(frame, area, report);
+        } else if self.show_param_changes {
+            self.draw_param_changes(frame, area, self.param_changes.as_deref());
+        }
+    }
+
+    fn draw_health_alert(&self, frame: &mut Frame, area: Rect, alert: &str) {
+        let instructions = Line::from(vec![
+            format!(" {} ", self.msg.close).into(),
+            "<Esc> ".fg(self.theme.key).bold(),
+        ]);
+        let block = Block::bordered()
+            .title(self.msg.board_health_title.bold())
+            .title_bottom(instructions.centered())
+            .border_style(Style::default().fg(self.theme.critical));
+        let lines: Vec<Line> = alert
+            .lines()
+            .map(|line| Line::from(line.fg(self.theme.critical).bold()))
+            .collect();
+        let height = lines.len() as u16 + 2;
+        let dialog = Paragraph::new(lines).centered().block(block);
+        let vertical = Layout::vertical([Constraint::Length(height)]).flex(Flex::Center);
+        let horizontal = Layout::horizontal([Constraint::Percentage(60)]).flex(Flex::Center);
+        let [area] = vertical.areas(area);
+        let [area] = horizontal.areas(area);
+        frame.render_widget(Clear, area);
+        frame.render_widget(dialog, area);
+    }
+
+    fn draw_device_picker(&self, frame: &mut Frame, area: Rect, picker: &DevicePicker) {
+        let msg = self.msg;
+        let target = if picker.slot < self.config.run_settings.boards.len() {
+            (msg.replace_board)(picker.slot)
+        } else {
+            String::from(msg.add_board)
+        };
+        let instructions = Line::from(vec![
+            format!(" {} ", msg.select).into(),
+            "<↑/↓> ".fg(self.theme.key).bold(),
+            format!(" {} ", msg.target_board).into(),
+            "<←/→> ".fg(self.theme.key).bold(),
+            format!(" {} ", msg.save).into(),
+            "<Enter> ".fg(self.theme.key).bold(),
+            format!(" {} ", msg.close).into(),
+            "<Esc> ".fg(self.theme.key).bold(),
+        ]);
+        let block = Block::bordered()
+            .title(msg.device_picker_title.bold())
+            .title_bottom(instructions.centered());
+
+        let mut lines = vec![
+            Line::from(vec![
+                "◀ ".fg(self.theme.key),
+                target.bold(),
+                " ▶".fg(self.theme.key),
+            ])
+            .centered(),
+            Line::from(""),
+        ];
+        if let Some(error) = &picker.error {
+            lines.push(Line::from(error.as_str().fg(self.theme.alert)));
+        } else if picker.discovery.is_some() {
+            lines.push(Line::from(msg.discovering));
+        } else if picker.devices.is_empty() {
+            lines.push(Line::from(msg.no_devices_found));
+        }
+        for (i, device) in picker.devices.iter().enumerate() {
+            let mut style = Style::default().fg(self.theme.value);
+            if i == picker.selected {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            lines.push(Line::styled(
+                format!(
+                    "{:<16} {:<10} {:<16} {}",
+                    device.model,
+                    device.serial,
+                    device.ip,
+                    device.url()
+                ),
+                style,
+            ));
+        }
+
+        let dialog = Paragraph::new(lines).block(block);
+        let height = picker.devices.len().max(1) as u16 + 4;
+        let vertical = Layout::vertical([Constraint::Length(height)]).flex(Flex::Center);
+        let horizontal = Layout::horizontal([Constraint::Percentage(80)]).flex(Flex::Center);
+        let [area] = vertical.areas(area);
+        let [area] = horizontal.areas(area);
+        frame.render_widget(Clear, area);
+        frame.render_widget(dialog, area);
+    }
+
+    fn draw_comment_input(&self, frame: &mut Frame, area: Rect, text: &str) {
+        let msg = self.msg;
+        let instructions = Line::from(vec![
+            format!(" {} ", msg.save).into(),
+            "<Enter> ".fg(self.theme.key).bold(),
+            format!(" {} ", msg.close).into(),
+            "<Esc> ".fg(self.theme.key).bold(),
+        ]);
+        let block = Block::bordered()
+            .title(msg.comment_title.bold())
+            .title_bottom(instructions.centered());
+        let line = Line::from(vec![text.fg(self.theme.value), "█".fg(self.theme.key)]);
+        let dialog = Paragraph::new(line).wrap(Wrap { trim: false }).block(block);
+        let vertical = Layout::vertical([Constraint::Length(5)]).flex(Flex::Center);
+        let horizontal = Layout::horizontal([Constraint::Percentage(60)]).flex(Flex::Center);
+        let [area] = vertical.areas(area);
+        let [area] = horizontal.areas(area);
+        frame.render_widget(Clear, area);
+        frame.render_widget(dialog, area);
+    }
+
+    fn draw_param_changes(&self, frame: &mut Frame, area: Rect, changes: Option<&[ParamChange]>) {
+        let instructions = Line::from(vec![
+            format!(" {} ", self.msg.close).into(),
+            "<Esc> ".fg(self.theme.key).bold(),
+        ]);
+        let block = Block::bordered()
+            .title(self.msg.param_changes_title.bold())
+            .title_bottom(instructions.centered());
+        let mut lines: Vec<Line> = Vec::new();
+        if !self.config_changes.is_empty() {
+            lines.push(Line::from(self.msg.config_changes.bold()));
+            lines.extend(
+                self.config_changes
+                    .iter()
+                    .map(|change| Line::from(change.as_str().fg(self.theme.value))),
+            );
+        }
+        if !lines.is_empty() && changes.is_some() {
+            lines.push(Line::from(""));
+        }
+        match changes {
+            Some([]) => lines.push(Line::from(self.msg.no_param_changes)),
+            Some(changes) => {
+                lines.extend(
+                    changes
+                        .iter()
+                        .map(|change| Line::from(change.to_string().fg(self.theme.value))),
+                );
+            }
+            None => {}
+        }
+        // long lists are cut off at the bottom of the screen
+        let height = (lines.len() as u16 + 2).min(area.height);
+        let dialog = Paragraph::new(lines).block(block);
+        let vertical = Layout::vertical([Constraint::Length(height)]).flex(Flex::Center);
+        let horizontal = Layout::horizontal([Constraint::Percentage(70)]).flex(Flex::Center);
+        let [area] = vertical.areas(area);
+        let [area] = horizontal.areas(area);
+        frame.render_widget(Clear, area);
+        frame.render_widget(dialog, area);
+    }
+
+    fn draw_preflight(&self, frame: &mut Frame, area: Rect, report: &PreflightReport) {
+        let msg = self.msg;
+        let instructions = Line::from(vec![
+            format!(" {} ", msg.close).into(),
+            "<Esc> ".fg(self.theme.key).bold(),
+        ]);
+        let block = Block::bordered()
+            .title(msg.preflight_title.bold())
+            .title_bottom(instructions.centered());
+        let value = |v: String| v.fg(self.theme.value);
+        let field =
+            |label: &str, v: String| Line::from(vec![format!("{}: ", label).into(), value(v)]);
+
+        let mut lines = Vec::new();
+        for board in &report.boards {
+            match &board.identity {
+                Ok((model, serial)) => {
+                    lines.push(Line::from(
+                        (msg.board_identity)(board.board, model, serial).bold(),
+                    ));
+                    lines.push(field(
+                        msg.firmware,
+                        board.firmware.clone().unwrap_or_else(|| msg.unknown.into()),
+                    ));
+                    lines.push(field(
+                        msg.channels_enabled,
+                        format!("{}/{}", board.channels_enabled, board.num_ch),
+                    ));
+                }
+                Err(_) => lines.push(Line::from(
+                    (msg.board_unreachable)(board.board)
+                        .fg(self.theme.alert)
+                        .bold(),
+                )),
+            }
+        }
+        lines.push(Line::from(""));
+        lines.push(match report.free_disk {
+            Some((avail, total)) => field(
+                msg.free_disk,
+                format!("{:.1} / {:.1} GB", avail as f64 / 1e9, total as f64 / 1e9),
+            ),
+            None => Line::from(format!("{}: {}", msg.free_disk, msg.unknown).fg(self.theme.alert)),
+        });
+        lines.push(field(
+            msg.config_hash,
+            report
+                .config_hash
+                .clone()
+                .unwrap_or_else(|| msg.unknown.into()),
+        ));
+        lines.push(field(msg.event_size, format!("{} B", report.event_size())));
+        lines.push(field(
+            msg.estimated_data_rate,
+            report
+                .data_rate()
+                .map(|rate| format!("{:.2} MB/s", rate))
+                .unwrap_or_else(|| msg.unknown.into()),
+        ));
+
+        let height = lines.len() as u16 + 2;
+        let dialog = Paragraph::new(lines).block(block);
+        let vertical = Layout::vertical([Constraint::Length(height)]).flex(Flex::Center);
+        let horizontal = Layout::horizontal([Constraint::Percentage(60)]).flex(Flex::Center);
+        let [area] = vertical.areas(area);
+        let [area] = horizontal.areas(area);
+        frame.render_widget(Clear, area);
+        frame.render_widget(dialog, area);
+    }
+
+    fn handle_key_event(&mut self, key_event: KeyEvent) {
+        if !self.board_failures.is_empty() && self.handle_failure_key(key_event.code) {
+            return;
+        }
+        if !self.health_alerts.is_empty() {
+            if matches!(key_event.code, KeyCode::Esc | KeyCode::Enter) {
+                self.health_alerts.pop_front();
+            }
+            return;
+        }
+        if self.device_picker.is_some() {
+            self.handle_picker_key(key_event.code);
+            return;
+        }
+        if self.comment_input.is_some() {
+            self.handle_comment_key(key_event.code);
+            return;
+        }
+        if self.show_preflight && matches!(key_event.code, KeyCode::Esc | KeyCode::Enter) {
+            self.show_preflight = false;
+            return;
+        }
+        if self.show_param_changes && matches!(key_event.code, KeyCode::Esc | KeyCode::Enter) {
+            self.show_param_changes = false;
+            return;
+        }
+        match key_event.code {
+            KeyCode::Char('s') if self.exit.is_none() => {
+                info!("User stopped run {}", self.run_num);
+                self.exit = Some(StatusExit::Stop);
+            }
+            KeyCode::Char('x') if self.exit.is_none() => {
+                info!("User aborted run {}", self.run_num);
+                self.exit = Some(StatusExit::Abort);
+                // Hold the next run until the operator acknowledges the abort
+                self.raise_alarm(
+                    AlarmKind::DataQuality,
+                    Severity::Critical,
+                    (self.msg.run_aborted)(self.run_num),
+                );
+            }
+            KeyCode::Char('t') if matches!(self.state, StreamState::Running(_)) => {
+                info!("User sent a software trigger");
+                let devices: Vec<_> = self.boards.iter().map(|(_, dev)| dev.clone()).collect();
+                if let Err(e) = send_sw_trigger(&devices) {
+                    self.raise_alarm(
+                        AlarmKind::Hardware,
+                        Severity::Warning,
+                        format!("{}: {}", self.msg.sw_trigger_failed, e),
+                    );
+                }
+            }
+            KeyCode::Char('n') if self.next_run.is_some() => {
+                info!("User started next run early");
+                self.next_run = None;
+            }
+            KeyCode::Char('h') => match self.next_run {
+                Some(NextRun::At(at)) => {
+                    info!("User held next run");
+                    self.next_run =
+                        Some(NextRun::Held(at.saturating_duration_since(Instant::now())));
+                }
+                Some(NextRun::Held(remaining)) => {
+                    info!("User released hold on next run");
+                    self.next_run = Some(NextRun::At(Instant::now() + remaining));
+                }
+                None => {}
+            },
+            KeyCode::Char('d') => self.open_device_picker(),
+            KeyCode::Char('c') => self.open_comment_input(),
+            KeyCode::Char('p') if self.preflight.is_some() => {
+                self.show_preflight = !self.show_preflight
+            }
+            KeyCode::Char('v')
+                if self.param_changes.is_some() || !self.config_changes.is_empty() =>
+            {
+                self.show_param_changes = !self.show_param_changes
+            }
+            KeyCode::Char('a') => self.alarms.acknowledge_selected(),
+            KeyCode::Char('A') => self.alarms.acknowledge_all(),
+            KeyCode::Up => self.alarms.select_previous(),
+            KeyCode::Down => self.alarms.select_next(),
+            KeyCode::Esc => self.expanded_board = None,
+            _ => {}
+        }
+    }
+
+    /// Clicking a board panel expands it (or collapses it again), clicking an
+    /// alarm selects it and the scroll wheel moves through the alarm list.
+    fn handle_mouse_event(&mut self, mouse_event: MouseEvent, area: Rect) {
+        // The failure dialog, health popup, device picker and comment popup
+        // are modal and only take keys
+        if !self.board_failures.is_empty()
+            || !self.health_alerts.is_empty()
+            || self.device_picker.is_some()
+            || self.comment_input.is_some()
+        {
+            return;
+        }
+        let layout = self.layout(area);
+        let position = Position::new(mouse_event.column, mouse_event.row);
+
+        // TODO: select channels by clicking them once there is a waveform
+        // view (bojohnson5/cliq#synth-1719-followup)
+        match mouse_event.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                if let Some(&(board, _)) = layout.boards.iter().find(|(_, a)| a.contains(position))
+                {
+                    self.expanded_board = match self.expanded_board {
+                        Some(_) => None,
+                        None => Some(board),
+                    };
+                } else if layout.alarms.contains(position) {
+                    // Same scrolling as alarm_paragraph, inside the border
+                    let visible = layout.alarms.height.saturating_sub(2) as usize;
+                    let scroll = (self.alarms.selected() + 1).saturating_sub(visible);
+                    let row = position.y.saturating_sub(layout.alarms.y + 1) as usize;
+                    self.alarms.select(scroll + row);
+                }
+            }
+            MouseEventKind::ScrollUp if layout.alarms.contains(position) => {
+                self.alarms.select_previous()
+            }
+            MouseEventKind::ScrollDown if layout.alarms.contains(position) => {
+                self.alarms.select_next()
+            }
+            _ => {}
+        }
+    }
+
+    /// Apply the operator's choice for the board failure currently shown.
+    /// Returns false if the key isn't one of the dialog's choices.
+    fn handle_failure_key(&mut self, code: KeyCode) -> bool {
+        let Some(failure) = self.board_failures.front().cloned() else {
+            return false;
+        };
+        match code {
+            KeyCode::Char('r') => {
+                info!("Restarting run after board {} failure", failure.board_id);
+                self.board_failures.clear();
+                self.exit = Some(StatusExit::Stop);
+            }
+            KeyCode::Char('c') => {
+                info!("Continuing run without board {}", failure.board_id);
+                if let Some(active) = self.active_boards.get(failure.board_id) {
+                    active.store(false, Ordering::SeqCst);
+                }
+                self.board_failures.pop_front();
+            }
+            KeyCode::Char('s') => {
+                info!("Stopping run after board {} failure", failure.board_id);
+                self.board_failures.clear();
+                self.exit = Some(StatusExit::Stop);
+                // Hold the next run until the operator acknowledges the stop
+                self.raise_alarm(
+                    AlarmKind::Hardware,
+                    Severity::Critical,
+                    (self.msg.run_stopped_after_failure)(self.run_num, failure.board_id),
+                );
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Open the comment popup with the current comment to edit.
+    fn open_comment_input(&mut self) {
+        self.comment_input = Some(self.comment.clone().unwrap_or_default());
+    }
+
+    fn handle_comment_key(&mut self, code: KeyCode) {
+        let Some(text) = &mut self.comment_input else {
+            return;
+        };
+        match code {
+            KeyCode::Char(c) => text.push(c),
+            KeyCode::Backspace => {
+                text.pop();
+            }
+            KeyCode::Esc => self.comment_input = None,
+            KeyCode::Enter => {
+                let text = text.trim().to_string();
+                info!("[{}] Run {} comment: {}", self.name, self.run_num, text);
+                self.comment = Some(text).filter(|t| !t.is_empty());
+                self.comment_input = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Record the operator's comment in the `comment` attribute of the
+    /// finished run's files.
+    fn save_comment(&self) -> Result<()> {
+        let Some(comment) = &self.comment else {
+            return Ok(());
+        };
+        for path in self.run_files() {
+            if path.extension().is_some_and(|ext| ext == "h5") {
+                crate::write_file_comment(&path, comment)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read each board's trigger and time counters, skipping the monitors
+    /// its firmware lacks, and take a last temperature reading.
+    fn read_board_stats(&mut self) -> Vec<BoardRunStats> {
+        self.update_temp_ranges();
+        self.boards
+            .iter()
+            .enumerate()
+            .map(|(i, &(board, ref dev))| {
+                let counter = |path: &str| {
+                    if self.unsupported_monitors[i].contains(&path) {
+                        return None;
+                    }
+                    crate::felib::get_value(dev.raw(), path)
+                        .ok()
+                        .and_then(|v| v.trim().parse::<u64>().ok())
+                };
+                let temps = self.temp_ranges.get(i).copied().flatten();
+                let realtime = counter("/par/RealtimeMonitor");
+                let deadtime = counter("/par/DeadtimeMonitor");
+                BoardRunStats {
+                    board,
+                    trigger_cnt: counter("/par/TriggerCnt"),
+                    lost_trigger_cnt: counter("/par/LostTriggerCnt"),
+                    realtime_monitor: realtime,
+                    deadtime_monitor: deadtime,
+                    livetime_monitor: counter("/par/LivetimeMonitor"),
+                    dead_time_fraction: dead_time_fraction(realtime, deadtime),
+                    temp_min: temps.map(|(min, _)| min),
+                    temp_max: temps.map(|(_, max)| max),
+                }
+            })
+            .collect()
+    }
+
+    /// Store the boards' end-of-run counters in the run's files.
+    fn save_board_stats(&self) -> Result<()> {
+        if self.board_stats.is_empty() {
+            return Ok(());
+        }
+        for path in self.run_files() {
+            if path.extension().is_some_and(|ext| ext == "h5") {
+                crate::write_board_run_stats(&path, &self.board_stats)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Open the board add/replace dialog and start device discovery.
+    fn open_device_picker(&mut self) {
+        info!("[{}] Discovering devices", self.name);
+        self.device_picker = Some(DevicePicker {
+            discovery: Some(thread::spawn(crate::discover_devices)),
+            ..Default::default()
+        });
+    }
+
+    /// Pick up the discovery results once they are in. Returns whether the
+    /// dialog changed.
+    fn poll_device_picker(&mut self) -> bool {
+        let Some(picker) = &mut self.device_picker else {
+            return false;
+        };
+        if !picker.discovery.as_ref().is_some_and(|h| h.is_finished()) {
+            return false;
+        }
+        match picker.discovery.take().unwrap().join() {
+            Ok(Ok(devices)) => picker.devices = devices,
+            Ok(Err(e)) => picker.error = Some(format!("{}: {}", self.msg.discovery_failed, e)),
+            Err(_) => picker.error = Some(String::from(self.msg.discovery_failed)),
+        }
+        true
+    }
+
+    fn handle_picker_key(&mut self, code: KeyCode) {
+        let num_boards = self.config.run_settings.boards.len();
+        let Some(picker) = &mut self.device_picker else {
+            return;
+        };
+        match code {
+            KeyCode::Up => picker.selected = picker.selected.saturating_sub(1),
+            KeyCode::Down if picker.selected + 1 < picker.devices.len() => picker.selected += 1,
+            KeyCode::Left => picker.slot = picker.slot.saturating_sub(1),
+            KeyCode::Right if picker.slot < num_boards => picker.slot += 1,
+            KeyCode::Esc => self.device_picker = None,
+            KeyCode::Enter => {
+                let Some(device) = picker.devices.get(picker.selected) else {
+                    return;
+                };
+                let slot = picker.slot;
+                // Boards bound by serial number stay bound by serial number
+                let by_serial = self
+                    .config
+                    .run_settings
+                    .boards
+                    .get(slot)
+                    .is_some_and(|b| b.starts_with(crate::SERIAL_PREFIX));
+                let url = if by_serial && !device.serial.is_empty() {
+                    format!("{}{}", crate::SERIAL_PREFIX, device.serial)
+                } else {
+                    device.url()
+                };
+                match crate::set_board_url(&self.config_file, slot, &url) {
+                    Ok(()) => {
+                        info!(
+                            "[{}] Set board {} to {} in {}",
+                            self.name, slot, url, self.config_file
+                        );
+                        self.device_picker = None;
+                        self.raise_alarm(
+                            AlarmKind::Hardware,
+                            Severity::Info,
+                            (self.msg.board_url_saved)(slot, &url),
+                        );
+                    }
+                    Err(e) => picker.error = Some(format!("{}", e)),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Read the configured calibration file, if any, so a missing or broken
+    /// file stops the run before the boards are armed.
+    fn load_calibration(&self) -> Result<Option<Calibration>> {
+        let Some(file) = &self.config.calibration.file else {
+            return Ok(None);
+        };
+        let calibration = Calibration::load(Path::new(file))
+            .map_err(|e| anyhow!("failed to load calibration {}: {}", file, e))?;
+        info!(
+            "[{}] Calibration {} (sha256 {})",
+            self.name, calibration.file, calibration.sha256
+        );
+        Ok(Some(calibration))
+    }
+
+    /// Summarize the configured boards before arming them. The report is
+    /// logged every run and shown on the first run, or whenever it found a
+    /// problem, so the operator can catch a wrong setup before data is taken.
+    fn preflight_check(&mut self) {
+        let report = PreflightReport::collect(
+            &self.boards,
+            &self.board_channels,
+            &self.config,
+            &self.config_file,
+        );
+        info!(
+            "[{}] Pre-flight report:\n{}",
+            self.name,
+            report.text(self.msg)
+        );
+        if report.has_problems() {
+            self.raise_alarm(
+                AlarmKind::Hardware,
+                Severity::Warning,
+                String::from(self.msg.preflight_problems),
+            );
+        }
+        self.show_preflight |= self.preflight.is_none() || report.has_problems();
+        self.preflight = Some(report);
+        self.check_campaign_volume();
+    }
+
+    /// Mean compression ratio of the latest runs, no compression until a run
+    /// has finished.
+    fn compression_ratio(&self) -> f64 {
+        if self.compression_ratios.is_empty() {
+            return 1.0;
+        }
+        self.compression_ratios.iter().sum::<f64>() / self.compression_ratios.len() as f64
+    }
+
+    /// Expected disk usage from the latest pre-flight report, if its data
+    /// rate is known.
+    fn disk_estimate(&self) -> Option<DiskEstimate> {
+        let report = self.preflight.as_ref()?;
+        let (available, _) = crate::disk_space(Path::new(&self.config.run_settings.output_dir))?;
+        DiskEstimate::new(report, self.compression_ratio(), available)
+    }
+
+    /// Warn if the runs left in the campaign won't fit on the output disk.
+    /// Without a run limit only the next run is counted.
+    fn check_campaign_volume(&mut self) {
+        let Some(estimate) = self.disk_estimate() else {
+            return;
+        };
+        let runs_left = self
+            .max_runs
+            .map_or(1, |max_runs| max_runs.saturating_sub(self.curr_run));
+        let needed = estimate.volume(self.run_duration * runs_left as u32);
+        if needed > estimate.available {
+            self.raise_alarm(
+                AlarmKind::Disk,
+                Severity::Warning,
+                (self.msg.campaign_exceeds_disk)(
+                    needed as f64 / 1e9,
+                    estimate.available as f64 / 1e9,
+                ),
+            );
+        }
+    }
+
+    /// Compare the size of the finished run's files with the bytes read out
+    /// to learn how well the data compresses.
+    fn record_compression_ratio(&mut self) {
+        if self.counter.total_size == 0 {
+            return;
+        }
+        let on_disk: u64 = self
+            .run_files()
+            .iter()
+            .filter(|path| path.extension().is_some_and(|ext| ext == "h5"))
+            .filter_map(|path| fs::metadata(path).ok())
+            .map(|metadata| metadata.len())
+            .sum();
+        let ratio = on_disk as f64 / self.counter.total_size as f64;
+        info!(
+            "[{}] Run {} compression ratio {:.3}",
+            self.name, self.run_num, ratio
+        );
+        if self.compression_ratios.len() == COMPRESSION_HISTORY_LEN {
+            self.compression_ratios.pop_front();
+        }
+        self.compression_ratios.push_back(ratio);
+    }
+
+    fn raise_alarm(&mut self, kind: AlarmKind, severity: Severity, message: String) {
+        self.alarms.raise(kind, severity, message, self.run_num);
+    }
+
+    /// Raise rate and disk alarms from the current run state.
+    fn check_rate_and_disk(&mut self) {
+        if self.buffer_len > MAX_BUFFER_BACKLOG {
+            self.raise_alarm(
+                AlarmKind::Rate,
+                Severity::Warning,
+                (self.msg.buffer_backlog)(MAX_BUFFER_BACKLOG),
+            );
+        }
+
+        let output_dir = PathBuf::from(&self.config.run_settings.output_dir);
+        if let Some((avail, total)) = crate::disk_space(&output_dir) {
+            let free = avail as f64 / total as f64;
+            if free < DISK_CRITICAL_FRACTION {
+                self.raise_alarm(
+                    AlarmKind::Disk,
+                    Severity::Critical,
+                    (self.msg.disk_low)(DISK_CRITICAL_FRACTION * 100.0),
+                );
+            } else if free < DISK_WARNING_FRACTION {
+                self.raise_alarm(
+                    AlarmKind::Disk,
+                    Severity::Warning,
+                    (self.msg.disk_low)(DISK_WARNING_FRACTION * 100.0),
+                );
+            }
+        }
+    }
+
+    /// Each board's hottest ADC temperature, `None` where it can't be read.
+    fn hottest_adc_temps(&self) -> Vec<Option<f64>> {
+        self.boards
+            .iter()
+            .map(|(_, dev)| {
+                crate::get_value_cached(dev.raw(), "/par/TempSensHottestADC")
+                    .ok()
+                    .and_then(|t| t.trim().parse::<f64>().ok())
+            })
+            .collect()
+    }
+
+    /// Widen each board's temperature range of the run by a new reading.
+    fn update_temp_ranges(&mut self) {
+        let temps = self.hottest_adc_temps();
+        self.temp_ranges.resize(temps.len(), None);
+        for (range, temp) in self.temp_ranges.iter_mut().zip(temps) {
+            let Some(temp) = temp else { continue };
+            *range = Some(range.map_or((temp, temp), |(min, max)| (min.min(temp), max.max(temp))));
+        }
+    }
+
+    /// Raise a hardware alarm for any board whose ADCs run hot. A board
+    /// past `health.temp_limit` or with a watched error flag set raises a
+    /// critical alarm and stops the operator with a popup, once for each
+    /// time the condition starts.
+    fn check_board_health(&mut self) {
+        self.update_temp_ranges();
+        let health = self.config.health.clone();
+        self.health_tripped.resize(self.boards.len(), false);
+        for (i, temp) in self.hottest_adc_temps().into_iter().enumerate() {
+            let (board, dev) = (self.boards[i].0, self.boards[i].1.raw());
+            let mut problems = Vec::new();
+            match temp {
+                Some(temp) if temp > health.temp_limit => {
+                    problems.push((self.msg.board_overheating)(board, health.temp_limit))
+                }
+                Some(temp) if temp > health.temp_warning => self.raise_alarm(
+                    AlarmKind::Hardware,
+                    Severity::Warning,
+                    (self.msg.board_overheating)(board, health.temp_warning),
+                ),
+                _ => {}
+            }
+            let flags = error_flags(dev).unwrap_or(0) & health.watched_flags();
+            if flags != 0 {
+                problems.push((self.msg.board_error_flags)(board, flags));
+            }
+
+            if problems.is_empty() {
+                self.health_tripped[i] = false;
+                continue;
+            }
+            for problem in &problems {
+                self.raise_alarm(AlarmKind::Hardware, Severity::Critical, problem.clone());
+            }
+            if !self.health_tripped[i] {
+                self.health_tripped[i] = true;
+                self.health_alerts.push_back(problems.join("\n"));
+            }
+        }
+    }
+
+    /// The current run's files: its config copy, subrun files and stats CSV.
+    fn run_files(&self) -> Vec<PathBuf> {
+        let names = [
+            format!("run{:0>6}_", self.run_num),
+            format!("config_run{:0>6}.", self.run_num),
+            format!("stats_run{:0>6}.", self.run_num),
+            format!("dst_run{:0>6}.", self.run_num),
+        ];
+        let Ok(entries) = fs::read_dir(&self.run_dir) else {
+            return Vec::new();
+        };
+        let mut files: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| names.iter().any(|prefix| name.starts_with(prefix)))
+            })
+            .collect();
+        files.sort();
+        files
+    }
+
+    /// Path of one of the current run's subrun files.
+    fn subrun_path(&self, subrun: usize) -> PathBuf {
+        self.run_dir
+            .join(crate::subrun_file_name(self.run_num, subrun))
+    }
+
+    /// Hand a subrun file the writer has closed to the mirror and uploader.
+    fn subrun_closed(&self, subrun: usize) {
+        let path = self.subrun_path(subrun);
+        if let Some(mirror) = &self.mirror {
+            mirror.file_closed(&path);
+        }
+        if let Some(uploader) = &self.uploader {
+            let manifest = self
+                .dirs
+                .campaign_root()
+                .join(&self.config.retention.manifest);
+            uploader.file_closed(&path, &manifest);
+        }
+    }
+
+    /// Raise an alarm for every mirror copy or upload that failed. Returns
+    /// whether any did.
+    fn poll_transfers(&mut self) -> bool {
+        let mut failures = Vec::new();
+        if let Some(mirror) = &self.mirror {
+            failures.extend(
+                mirror
+                    .failures()
+                    .into_iter()
+                    .map(|f| format!("{}: {}", self.msg.mirror_failed, f)),
+            );
+        }
+        if let Some(uploader) = &self.uploader {
+            failures.extend(
+                uploader
+                    .failures()
+                    .into_iter()
+                    .map(|f| format!("{}: {}", self.msg.upload_failed, f)),
+            );
+        }
+        let changed = !failures.is_empty();
+        for failure in failures {
+            self.raise_alarm(AlarmKind::Disk, Severity::Warning, failure);
+        }
+        changed
+    }
+
+    /// Append the finished run to the run log, see `run_settings.run_log`.
+    fn append_run_log(&self) -> Result<()> {
+        let settings = &self.config.run_settings;
+        if settings.run_log.is_empty() {
+            return Ok(());
+        }
+        let status = match self.exit {
+            Some(StatusExit::Abort) => RUN_STATUS_ABORTED,
+            _ => RUN_STATUS_GOOD,
+        };
+        let timestamp = |t: OffsetDateTime| t.format(&Rfc3339).unwrap_or_default();
+        let files = self.run_files();
+        // Checksums of the files as closed, to verify copies on tape or
+        // offsite against
+        let sha256 = settings
+            .file_checksums
+            .then(|| {
+                files
+                    .iter()
+                    .map(|path| crate::sha256_file(path))
+                    .collect::<Result<Vec<_>>>()
+            })
+            .transpose()?;
+        let record = RunRecord {
+            stream: self.name.clone(),
+            campaign: self.camp_num,
+            run: self.run_num,
+            run_type: self.run_type.name().to_string(),
+            status: status.to_string(),
+            operator: settings.operator.clone(),
+            comment: self.comment.clone(),
+            description: self.config.campaign.description.clone(),
+            detector: self.config.campaign.detector.clone(),
+            tags: self.config.campaign.tags.clone(),
+            start: timestamp(self.started),
+            end: timestamp(local_now()),
+            duration_s: self.t_begin.elapsed().as_secs_f64(),
+            events: self.counter.n_events,
+            bytes: self.counter.total_size,
+            misaligned_events: self.misaligned_events,
+            dropped_events: self.dropped_events,
+            files: files
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect(),
+            sha256,
+            board_stats: self.board_stats.clone(),
+            record_len: self.record_len,
+            previous_record_len: self.previous_record_len,
+            scheduled_run: self
+                .schedule_progress()
+                .filter(|_| self.run_type == RunType::Physics),
+        };
+        // a relative run log sits in the campaign root with the runs
+        let path = self.dirs.campaign_root().join(&settings.run_log);
+        crate::append_run_record(&path, &record)
+    }
+
+    /// Append a lifecycle step to the run journal, see
+    /// `run_settings.run_journal`. A failed write is only logged, as the run
+    /// itself is fine.
+    fn journal(&self, phase: RunPhase, run: Option<usize>) {
+        let settings = &self.config.run_settings;
+        if settings.run_journal.is_empty() {
+            return;
+        }
+        let entry = JournalEntry {
+            time: local_now().format(&Rfc3339).unwrap_or_default(),
+            stream: self.name.clone(),
+            campaign: self.camp_num,
+            run,
+            phase,
+        };
+        let path = self.dirs.campaign_root().join(&settings.run_journal);
+        if let Err(e) = crate::append_journal_entry(&path, &entry) {
+            warn!("[{}] Failed to write the run journal: {}", self.name, e);
+        }
+    }
+
+    /// Record the current run statistics in the stats history.
+    fn sample_stats(&mut self) {
+        let elapsed = self.counter.t_begin.elapsed().as_secs_f64();
+        let prev = self.stats_history.last().cloned().unwrap_or_default();
+        let dt = elapsed - prev.elapsed;
+        let (event_rate, data_rate) = if dt > 0.0 {
+            (
+                (self.counter.n_events - prev.events) as f64 / dt,
+                (self.counter.total_size - prev.bytes) as f64 / dt / (1024.0 * 1024.0),
+            )
+        } else {
+            (0.0, 0.0)
+        };
+        self.stats_history.push(StatsSample {
+            elapsed,
+            events: self.counter.n_events,
+            bytes: self.counter.total_size,
+            event_rate,
+            data_rate,
+            buffer_len: self.buffer_len,
+            misaligned_events: self.misaligned_events,
+            dropped_events: self.dropped_events,
+        });
+    }
+
+    /// Write the run's statistics time series next to its data files for
+    /// shift reports.
+    fn write_stats_csv(&self) -> Result<()> {
+        let path = self
+            .run_dir
+            .join(format!("stats_run{:0>6}.csv", self.run_num));
+        let mut csv = String::from(
+            "elapsed_s,events,bytes,event_rate_hz,data_rate_mb_s,buffer_len,misaligned_events,dropped_events\n",
+        );
+        for sample in &self.stats_history {
+            csv.push_str(&format!(
+                "{:.3},{},{},{:.3},{:.3},{},{},{}\n",
+                sample.elapsed,
+                sample.events,
+                sample.bytes,
+                sample.event_rate,
+                sample.data_rate,
+                sample.buffer_len,
+                sample.misaligned_events,
+                sample.dropped_events
+            ));
+        }
+        fs::write(&path, csv)?;
+        info!("[{}] Wrote run statistics to {}", self.name, path.display());
+        Ok(())
+    }
+
+    fn log_run_summary(&self) {
+        let alarms: Vec<String> = self
+            .alarms
+            .for_run(self.run_num)
+            .map(|a| a.summary())
+            .collect();
+        let alarms = if alarms.is_empty() {
+            String::from("none")
+        } else {
+            alarms.join("\n")
+        };
+        let boards: Vec<String> = self
+            .board_stats
+            .iter()
+            .map(|stats| {
+                let mut line: Vec<String> = stats
+                    .counters()
+                    .into_iter()
+                    .map(|(name, value)| format!("{} {}", name, value))
+                    .collect();
+                if let Some(fraction) = stats.dead_time_fraction {
+                    line.push(format!("dead time {:.2}%", fraction * 100.0));
+                }
+                if let (Some(min), Some(max)) = (stats.temp_min, stats.temp_max) {
+                    line.push(format!("hottest ADC {:.0}-{:.0} °C", min, max));
+                }
+                format!("Board {}: {}\n", stats.board, line.join(", "))
+            })
+            .collect();
+        info!(
+            "[{}] Run {} summary: {} events, {} misaligned, {} dropped\n{}Alarms:\n{}",
+            self.name,
+            self.run_num,
+            self.counter.n_events,
+            self.misaligned_events,
+            self.dropped_events,
+            boards.concat(),
+            alarms
+        );
+    }
+
+    fn run_stats_paragraph(&'_ self) -> Paragraph<'_> {
+        let msg = self.msg;
+        let mut title = vec![(msg.run_status_title)(self.camp_num, self.run_num).bold()];
+        if crate::training_mode() {
+            title.push(format!(" {} ", msg.training).fg(self.theme.alert).bold());
+        }
+        let title = Line::from(title);
+        let instructrions = Line::from(vec![
+            format!(" {} ", msg.quit).into(),
+            "<Q> ".fg(self.theme.key).bold(),
+            format!(" {} ", msg.stop_run).into(),
+            "<S> ".fg(self.theme.key).bold(),
+            format!(" {} ", msg.abort_run).into(),
+            "<X> ".fg(self.theme.key).bold(),
+            format!(" {} ", msg.boards).into(),
+            "<D> ".fg(self.theme.key).bold(),
+            format!(" {} ", msg.preflight).into(),
+            "<P> ".fg(self.theme.key).bold(),
+            format!(" {} ", msg.param_changes).into(),
+            "<V> ".fg(self.theme.key).bold(),
+            format!(" {} ", msg.sw_trigger).into(),
+            "<T> ".fg(self.theme.key).bold(),
+            format!(" {} ", msg.comment).into(),
+            "<C> ".fg(self.theme.key).bold(),
+        ]);
+        let block = Block::bordered()
+            .title(title.centered())
+            .title_bottom(instructrions.centered())
+            .border_set(border::THICK);
+
+        let mut counts = vec![
+            format!("{}: ", msg.misaligned_events).into(),
+            self.misaligned_events.to_string().fg(self.theme.value),
+            format!(" {}: ", msg.dropped_events).into(),
+            self.dropped_events.to_string().fg(self.theme.value),
+        ];
+        if let Some(operator) = &self.config.run_settings.operator {
+            counts.push(format!(" {}: ", msg.operator).into());
+            counts.push(operator.as_str().fg(self.theme.value));
+        }
+        if let Some(progress) = self.schedule_progress() {
+            counts.push(format!(" {}: ", msg.schedule).into());
+            counts.push(progress.fg(self.theme.value));
+        }
+        let mut status_text = Text::from(vec![
+            Line::from(vec![
+                format!("{}: ", msg.elapsed_time).into(),
+                self.counter
+                    .t_begin
+                    .elapsed()
+                    .as_secs()
+                    .to_string()
+                    .fg(self.theme.value),
+                " s".into(),
+                format!(" {}: ", msg.events).into(),
+                self.counter.n_events.to_string().fg(self.theme.value),
+                format!(" {}: ", msg.data_rate).into(),
+                format!("{:.2}", self.counter.average_rate()).fg(self.theme.value),
+                " MB/s ".into(),
+                format!(" {}: ", msg.buffer_length).into(),
+                self.buffer_len.to_string().fg(self.theme.value),
+            ]),
+            Line::from(counts),
+        ]);
+
+        let (disk_rate, disk_full_in) = match self.disk_estimate() {
+            Some(estimate) => (
+                format!("{:.2} MB/s", estimate.rate),
+                estimate
+                    .time_to_full()
+                    .map_or_else(|| String::from("-"), format_duration),
+            ),
+            None => (String::from(msg.unknown), String::from(msg.unknown)),
+        };
+        status_text.push_line(Line::from(vec![
+            format!("{}: ", msg.disk_rate).into(),
+            disk_rate.fg(self.theme.value),
+            format!(" {}: ", msg.disk_full_in).into(),
+            disk_full_in.fg(self.theme.value),
+        ]));
+
+        // Dead time since the run started, from each board's own monitors
+        let mut dead_times = Vec::new();
+        for (i, &(board, ref dev)) in self.boards.iter().enumerate() {
+            let monitor = |path: &str| {
+                if self.unsupported_monitors[i].contains(&path) {
+                    return None;
+                }
+                crate::get_value_cached(dev.raw(), path)
+                    .ok()
+                    .and_then(|v| v.trim().parse::<u64>().ok())
+            };
+            let fraction = dead_time_fraction(
+                monitor("/par/RealtimeMonitor"),
+                monitor("/par/DeadtimeMonitor"),
+            );
+            if let Some(fraction) = fraction {
+                dead_times.push(format!(" {} ", (msg.board_name)(board)).into());
+                dead_times.push(format!("{:.1}%", fraction * 100.0).fg(self.theme.value));
+            }
+        }
+        if !dead_times.is_empty() {
+            dead_times.insert(0, format!("{}:", msg.dead_time).into());
+            status_text.push_line(Line::from(dead_times));
+        }
+
+        match self.next_run {
+            Some(NextRun::At(at)) => status_text.push_line(Line::from(vec![
+                format!("{} ", msg.next_run_in).into(),
+                (at.saturating_duration_since(Instant::now()).as_secs() + 1)
+                    .to_string()
+                    .fg(self.theme.value),
+                " s ".into(),
+                format!(" {} ", msg.start_now).into(),
+                "<N> ".fg(self.theme.key).bold(),
+                format!(" {} ", msg.hold).into(),
+                "<H> ".fg(self.theme.key).bold(),
+            ])),
+            Some(NextRun::Held(_)) => status_text.push_line(Line::from(vec![
+                format!("{} ", msg.next_run_on_hold)
+                    .fg(self.theme.alert)
+                    .bold(),
+                format!(" {} ", msg.resume_countdown).into(),
+                "<H> ".fg(self.theme.key).bold(),
+                format!(" {} ", msg.start_now).into(),
+                "<N> ".fg(self.theme.key).bold(),
+            ])),
+            None => {}
+        }
+
+        Paragraph::new(status_text).centered().block(block)
+    }
+
+    /// The event rate history, averaged down to one bar per column.
+    fn rate_history_sparkline(&self, width: u16) -> Sparkline<'_> {
+        let history = &self.counter.rate_history;
+        let columns = (width as usize).saturating_sub(2).max(1);
+        let per_bar = history.len().div_ceil(columns).max(1);
+        let bars: Vec<u64> = history
+            .iter()
+            .copied()
+            .collect::<Vec<_>>()
+            .chunks(per_bar)
+            .map(|chunk| chunk.iter().sum::<u64>() / chunk.len() as u64)
+            .collect();
+        let max = bars.iter().copied().max().unwrap_or(0);
+        let title = (self.msg.rate_history_title)(self.config.ui.rate_history_minutes, max);
+        Sparkline::default()
+            .block(Block::bordered().title(title))
+            .data(&bars)
+            .style(Style::default().fg(self.theme.value))
+    }
+
+    fn board_status_paragraph(&'_ self, board: usize) -> Paragraph<'_> {
+        let msg = self.msg;
+        let title = Line::from((msg.board_status_title)(self.boards[board].0).bold());
+        let block = Block::bordered()
+            .title(title.centered())
+            .border_set(border::THICK);
+        let handle = self.boards[board].1.raw();
+        let reading = |label: &str, path: &str| {
+            let value = crate::get_value_cached(handle, path)
+                .unwrap_or_else(|_| String::from(msg.read_error));
+            Line::from(format!("{}: {}", label, value).fg(self.theme.value))
         };
-        match crate::felib_getvalue(handle, "/par/AcquisitionStatus") {
+
+        let mut status_text: Vec<Line> = [
+            (msg.realtime_monitor, "/par/RealtimeMonitor"),
+            (msg.deadtime_monitor, "/par/DeadtimeMonitor"),
+        ]
+        .into_iter()
+        .filter(|(_, path)| !self.unsupported_monitors[board].contains(path))
+        .map(|(label, path)| reading(label, path))
+        .collect();
+        status_text.push(reading(msg.trigger_count, "/par/TriggerCnt"));
+        status_text.push(reading(msg.lost_trigger_count, "/par/LostTriggerCnt"));
+        match crate::get_value_cached(handle, "/par/AcquisitionStatus") {
             Ok(s) => {
                 // parse the status code as a number, then format as binary string
                 let bin = format!("{:b}", s.parse::<u32>().unwrap());
 
                 // build a Spans line: first the label, then one Span per bit
                 let mut spans = Vec::with_capacity(1 + bin.len());
-                spans.push(Span::raw("Acquisition status: ").yellow());
+                spans.push(format!("{}: ", msg.acquisition_status).fg(self.theme.value));
                 spans.extend(bin.chars().map(|c| {
                     let (color, label) = match c {
-                        '1' => (Color::Red, "●"),
-                        '0' => (Color::White, "●"),
-                        _ => (Color::White, "?"),
+                        '1' => self.theme.bit_set,
+                        '0' => self.theme.bit_clear,
+                        _ => (self.theme.bit_clear.0, "?"),
                     };
                     Span::styled(label, Style::default().fg(color))
                 }));
 
                 status_text.push(Line::from(spans));
             }
-            Err(_) => status_text.push(Line::from("Acquisition status: err in read".yellow())),
-        };
-        match crate::felib_getvalue(handle, "/par/TempSensAirIn") {
-            Ok(s) => status_text.push(Line::from(
-                format!("Incoming air temp (°C): {}", s).yellow(),
-            )),
-            Err(_) => status_text.push(Line::from("Incoming air temp: err in read".yellow())),
+            Err(_) => status_text.push(reading(msg.acquisition_status, "/par/AcquisitionStatus")),
         };
-        match crate::felib_getvalue(handle, "/par/TempSensAirOut") {
-            Ok(s) => status_text.push(Line::from(
-                format!("Outgoing air temp (°C): {}", s).yellow(),
-            )),
-            Err(_) => status_text.push(Line::from("Outgoing air temp: err in read".yellow())),
+        status_text.push(reading(msg.air_in_temp, "/par/TempSensAirIn"));
+        status_text.push(reading(msg.air_out_temp, "/par/TempSensAirOut"));
+        let health = &self.config.health;
+        let hottest = crate::get_value_cached(handle, "/par/TempSensHottestADC");
+        let temp_color = match hottest
+            .as_ref()
+            .ok()
+            .and_then(|t| t.trim().parse::<f64>().ok())
+        {
+            Some(temp) if temp > health.temp_limit => self.theme.critical,
+            Some(temp) if temp > health.temp_warning => self.theme.warning,
+            _ => self.theme.value,
         };
-        match crate::felib_getvalue(handle, "/par/TempSensHottestADC") {
-            Ok(s) => status_text.push(Line::from(format!("Hottest ADC temp (°C): {}", s).yellow())),
-            Err(_) => status_text.push(Line::from("Hottest ADC temp: err in read".yellow())),
+        status_text.push(Line::from(
+            format!(
+                "{}: {}",
+                msg.hottest_adc_temp,
+                hottest.unwrap_or_else(|_| String::from(msg.read_error))
+            )
+            .fg(temp_color),
+        ));
+        let fans: Vec<String> = ["/par/SpeedSensFan1", "/par/SpeedSensFan2"]
+            .into_iter()
+            .map(|path| {
+                crate::get_value_cached(handle, path)
+                    .unwrap_or_else(|_| String::from(msg.read_error))
+            })
+            .collect();
+        // a stopped fan is the first sign of a board about to cook
+        let fan_color = if fans.iter().any(|speed| speed.trim() == "0") {
+            self.theme.critical
+        } else {
+            self.theme.value
         };
+        status_text.push(Line::from(
+            format!("{}: {}", msg.fan_speeds, fans.join(" / ")).fg(fan_color),
+        ));
+        status_text.push(match error_flags(handle) {
+            Some(flags) => Line::from(format!("{}: {:#010x}", msg.error_flags, flags).fg(
+                if flags & health.watched_flags() != 0 {
+                    self.theme.critical
+                } else {
+                    self.theme.value
+                },
+            )),
+            None => reading(msg.error_flags, "/par/ErrorFlags"),
+        });
+        if let Some(timeout) = self
+            .read_timeouts
+            .get(board)
+            .map(|t| t.load(Ordering::Relaxed))
+            .filter(|&t| t > 0)
+        {
+            status_text.push(Line::from(
+                format!("{}: {}", msg.read_timeout, timeout).fg(self.theme.value),
+            ));
+        }
 
         Paragraph::new(status_text).centered().block(block)
     }
 
+    fn alarm_paragraph(&'_ self, height: u16) -> Paragraph<'_> {
+        let msg = self.msg;
+        let title = Line::from((msg.alarms_title)(self.alarms.unacknowledged()).bold());
+        let instructions = Line::from(vec![
+            format!(" {} ", msg.acknowledge).into(),
+            "<A> ".fg(self.theme.key).bold(),
+            format!(" {} ", msg.acknowledge_all).into(),
+            "<Shift-A> ".fg(self.theme.key).bold(),
+            format!(" {} ", msg.select).into(),
+            "<↑/↓> ".fg(self.theme.key).bold(),
+        ]);
+        let block = Block::bordered()
+            .title(title.centered())
+            .title_bottom(instructions.centered())
+            .border_set(border::THICK);
+
+        let lines: Vec<Line> = self
+            .alarms
+            .alarms()
+            .iter()
+            .enumerate()
+            .map(|(i, alarm)| {
+                let mut style = Style::default().fg(self.theme.severity(alarm.severity));
+                if alarm.acknowledged {
+                    style = style.add_modifier(Modifier::DIM);
+                }
+                if i == self.alarms.selected() {
+                    style = style.add_modifier(Modifier::REVERSED);
+                }
+                Line::styled(alarm.summary(), style)
+            })
+            .collect();
+
+        // Scroll so the selected alarm stays visible
+        let visible = height.saturating_sub(2) as usize;
+        let scroll = (self.alarms.selected() + 1).saturating_sub(visible);
+        Paragraph::new(lines)
+            .block(block)
+            .scroll((scroll as u16, 0))
+    }
+
+    /// The DAQ process's own load, to tell a slow host from slow boards.
+    fn system_paragraph(&'_ self) -> Paragraph<'_> {
+        let msg = self.msg;
+        let block = Block::bordered()
+            .title(Line::from(msg.system_title.bold()).centered())
+            .border_set(border::THICK);
+        let (cpu, memory, write_rate) = match crate::process_stats() {
+            Some(stats) => (
+                format!("{:.0}%", stats.cpu_percent),
+                format!("{:.0} MB", stats.rss as f64 / (1024.0 * 1024.0)),
+                format!("{:.2} MB/s", stats.write_rate),
+            ),
+            None => (
+                String::from(msg.unknown),
+                String::from(msg.unknown),
+                String::from(msg.unknown),
+            ),
+        };
+        let text = Text::from(vec![
+            Line::from(vec![
+                format!("{}: ", msg.cpu).into(),
+                cpu.fg(self.theme.value),
+            ]),
+            Line::from(vec![
+                format!("{}: ", msg.memory).into(),
+                memory.fg(self.theme.value),
+            ]),
+            Line::from(vec![
+                format!("{}: ", msg.write_rate).into(),
+                write_rate.fg(self.theme.value),
+            ]),
+        ]);
+        Paragraph::new(text).block(block)
+    }
+
     fn begin_run(
         &mut self,
+        endpoints: Vec<felib::EndpointHandle>,
+        calibration: Option<Calibration>,
+        board_params: Vec<Vec<(String, String)>>,
         shutdown: Arc<AtomicBool>,
-        tx_stats: Sender<RunInfo>,
-    ) -> Result<(
-        Sender<BoardEvent>,
-        JoinHandle<Result<(), DaqError>>,
-        Vec<JoinHandle<Result<(), DaqError>>>,
-    )> {
+        stats: Arc<RunStats>,
+    ) -> Result<
+        Option<(
+            Sender<BoardEvent>,
+            JoinHandle<Result<(), DaqError>>,
+            Vec<JoinHandle<Result<(), DaqError>>>,
+        )>,
+    > {
         // Shared signal for acquisition start.
         let acq_start = Arc::new((Mutex::new(false), Condvar::new()));
-        // Shared counter for endpoint configuration.
-        let endpoint_configured = Arc::new((Mutex::new(0u32), Condvar::new()));
 
         // Channel to receive events from board threads.
         let (tx_events, rx_events) = unbounded();
 
+        // Until the run is handed over, bailing out stops the board threads
+        // (releasing any still waiting for the start) and disarms the boards
+        let devices: Vec<_> = self.boards.iter().map(|(_, dev)| dev.clone()).collect();
+        let guard = scopeguard::guard((), |_| {
+            shutdown.store(true, Ordering::SeqCst);
+            let (lock, cvar) = &*acq_start;
+            *lock.lock().unwrap() = true;
+            cvar.notify_all();
+            if let Err(e) = disarm_boards(&devices) {
+                warn!("Failed to disarm boards after a failed run start: {:?}", e);
+            }
+        });
+
         // Spawn a data-taking thread for each board.
         let mut board_thread_handles = Vec::new();
-        for &(board_id, dev_handle) in &self.boards {
+        for (&(board_id, _), endpoint) in self.boards.iter().zip(endpoints) {
             let config_clone = self.config.clone();
-            let acq_start_clone = Arc::clone(&acq_start);
-            let endpoint_configured_clone = Arc::clone(&endpoint_configured);
             let tx_clone = tx_events.clone();
-            let shutdown_clone = Arc::clone(&shutdown);
+            let context = ReadoutContext {
+                endpoint,
+                num_ch: self.board_channels[board_id],
+                channel_map: self.channel_maps[board_id].clone(),
+                acq_start: Arc::clone(&acq_start),
+                shutdown: Arc::clone(&shutdown),
+                read_timeouts: Arc::clone(&self.read_timeouts),
+            };
             let handle = thread::spawn(move || {
-                data_taking_thread(
-                    board_id,
-                    dev_handle,
-                    config_clone,
-                    tx_clone,
-                    acq_start_clone,
-                    endpoint_configured_clone,
-                    shutdown_clone,
-                )
+                data_taking_thread(board_id, context, config_clone, tx_clone)
             });
             board_thread_handles.push(handle);
         }
 
-        // Wait until all boards have configured their endpoints.
-        {
-            let (lock, cond) = &*endpoint_configured;
-            let mut count = lock.lock().unwrap();
-            while *count < self.boards.len() as u32 {
-                count = cond.wait(count).unwrap();
-            }
+        // A board that doesn't arm fails only this stream's run start, the
+        // guard stops the board threads and disarms the others
+        if let Err((board, e)) = self.arm_boards() {
+            let message = format!("{}: {}", self.msg.arm_failed, e);
+            let message = (self.msg.board_alarm)(board, &message);
+            self.raise_alarm(AlarmKind::Hardware, Severity::Critical, message);
+            return Ok(None);
         }
+        self.journal(RunPhase::Armed, None);
 
         // Signal acquisition start.
         {
@@ -480,96 +3018,203 @@ impl Tui {
         }
 
         // Begin run acquisition.
-        crate::felib_sendcommand(self.boards[0].1, "/cmd/swstartacquisition")?;
+        match self.config.sync_settings.start_mode {
+            StartMode::SwFirstBoard => {
+                felib::send_command(self.boards[0].1.raw(), "/cmd/swstartacquisition")?;
+            }
+            StartMode::SwEachBoard => {
+                for (_, dev) in &self.boards {
+                    felib::send_command(dev.raw(), "/cmd/swstartacquisition")?;
+                }
+            }
+            mode @ (StartMode::Sin | StartMode::Lvds) => {
+                info!("[{}] Armed, waiting for {:?} start signal", self.name, mode);
+            }
+        }
 
         // Create the appropriate directory for file-writing
-        let run_file = self.create_run_file().unwrap();
+        let run_file = self.create_run_file()?;
+        ScopeGuard::into_inner(guard);
+        self.journal(RunPhase::Running, Some(self.run_num));
 
         // Spawn a dedicated thread to process incoming events and print global stats.
         let config_clone = self.config.clone();
-        let shutdown_clone = Arc::clone(&shutdown);
+        let context = ProcessingContext {
+            run_file,
+            run_type: self.run_type,
+            channel_maps: self.channel_maps.clone(),
+            calibration,
+            board_params,
+            active_boards: Arc::clone(&self.active_boards),
+            shutdown: Arc::clone(&shutdown),
+        };
+        let process = match self.config.run_settings.storage {
+            Storage::Hdf5 => event_processing::<HDF5Writer>,
+            Storage::Discard => {
+                warn!(
+                    "[{}] Discarding run {}'s events, nothing is written",
+                    self.name, self.run_num
+                );
+                event_processing::<NullWriter>
+            }
+        };
         let event_processing_handle = thread::spawn(move || -> Result<(), DaqError> {
-            event_processing(rx_events, tx_stats, run_file, config_clone, shutdown_clone)
+            process(rx_events, &stats, config_clone, context)
         });
 
-        Ok((tx_events, event_processing_handle, board_thread_handles))
+        Ok(Some((
+            tx_events,
+            event_processing_handle,
+            board_thread_handles,
+        )))
     }
 
+    /// Arm the boards one at a time in the configured order, making sure each
+    /// reports armed before moving on to the next. Fails with the board that
+    /// couldn't be armed.
+    fn arm_boards(&self) -> Result<(), (usize, anyhow::Error)> {
+        for board in self.config.sync_settings.arm_order() {
+            let dev = self.boards[board].1.raw();
+            felib::send_command(dev, "/cmd/armacquisition")
+                .map_err(|e| (board, anyhow!("{:?}", e)))?;
+            let t_arm = Instant::now();
+            loop {
+                let status = felib::get_value(dev, "/par/AcquisitionStatus")
+                    .ok()
+                    .and_then(|s| s.trim().parse::<u32>().ok());
+                if status.is_some_and(|s| s & ACQ_STATUS_ARMED != 0) {
+                    break;
+                }
+                if t_arm.elapsed() >= ARM_TIMEOUT {
+                    return Err((board, anyhow!("not armed within {:?}", ARM_TIMEOUT)));
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            crate::invalidate_cached_values(dev);
+        }
+        info!("[{}] Armed digitizer(s)", self.name);
+        Ok(())
+    }
+
+    /// Number the next run after the campaign's last one and prepare its
+    /// directory with a copy of the config.
     fn create_run_file(&mut self) -> Result<PathBuf> {
-        let camp_dir = self.create_camp_dir().unwrap();
-        let runs: Vec<DirEntry> = std::fs::read_dir(&camp_dir)
-            .unwrap()
-            .filter_map(|e| e.ok())
-            .collect();
-        let max_run = runs
+        let files = self.dirs.campaign_files()?;
+        let max_run = files
             .iter()
             .filter_map(|path| {
-                path.file_name()
-                    .to_str() // Get file name (OsStr)
-                    .and_then(|filename| {
-                        // Ensure the filename starts with "run"
-                        if let Some(stripped) = filename.strip_prefix("run") {
-                            // Split at '_' and take the first part
-                            let parts: Vec<&str> = stripped.split('_').collect();
-                            parts.first()?.parse::<usize>().ok()
-                        } else {
-                            None
-                        }
-                    })
+                // data files are named run<N>_<subrun>.h5
+                let name = path.file_name()?.to_str()?;
+                name.strip_prefix("run")?
+                    .split('_')
+                    .next()?
+                    .parse::<usize>()
+                    .ok()
             })
             .max();
+        // Other hosts writing to the same campaign may have claimed numbers
+        // whose files don't exist yet
+        let last_run = max_run.max(self.dirs.last_reserved_run()?);
+        self.run_num = self
+            .dirs
+            .reserve_run(last_run.map_or(0, |last| last + 1), &self.name)?;
 
-        if let Some(max) = max_run {
-            let run_filename = format!("run{:0>6}_00.h5", max + 1);
-            let run_path = camp_dir.join(&run_filename);
-            self.run_num = max + 1;
-
-            let config_name = format!("config_run{:0>6}.toml", self.run_num);
-            let config_dest = camp_dir.join(&config_name);
-            fs::create_dir_all(&camp_dir)?;
-            fs::copy(&self.config_file, &config_dest)
-                .map_err(|e| anyhow::anyhow!("failed to copy config: {}", e))?;
-
-            Ok(run_path)
-        } else {
-            fs::create_dir_all(&camp_dir)?;
-            let config_dest = camp_dir.join("config_run000000.toml");
-            fs::copy(&self.config_file, &config_dest)
-                .map_err(|e| anyhow::anyhow!("failed to copy config: {}", e))?;
-            let run_path = camp_dir.join("run000000_00.h5");
-            Ok(run_path)
+        // A record length change can't go into the previous run's datasets,
+        // note it so the run log shows where the campaign's records change
+        self.record_len = self.config.board_settings.common.record_len;
+        self.previous_record_len = max_run
+            .and_then(|max| {
+                let name = crate::subrun_file_name(max, 0);
+                files
+                    .iter()
+                    .find(|path| path.file_name().is_some_and(|n| n == name.as_str()))
+            })
+            .and_then(|path| crate::read_record_len(path).ok().flatten())
+            .filter(|&previous| previous != self.record_len);
+        if let (Some(previous), Some(run)) = (self.previous_record_len, max_run) {
+            info!(
+                "[{}] Record length changed from {} to {} samples since run {}",
+                self.name, previous, self.record_len, run
+            );
         }
-    }
 
-    fn create_camp_dir(&self) -> Result<PathBuf> {
-        let camp_dir = format!(
-            "{}/camp{}",
-            self.config.run_settings.output_dir, self.config.run_settings.campaign_num
-        );
-        let path = PathBuf::from(camp_dir);
-        if !std::fs::exists(&path).unwrap() {
-            match std::fs::create_dir_all(&path) {
-                Ok(_) => {
-                    println!("Create campaign directory");
+        let started = local_now();
+        let mut run_dir = self.dirs.create_run_dir(self.run_num, started)?;
+        // The numbering only sees the campaign's own directories, a file can
+        // still be in the way, e.g. one restored from a backup
+        loop {
+            let run_path = run_dir.join(crate::subrun_file_name(self.run_num, 0));
+            if !run_path.exists() {
+                break;
+            }
+            match self.config.run_settings.existing_files {
+                ExistingFiles::Error => {
+                    return Err(anyhow::anyhow!(
+                        "{} already exists, not overwriting it",
+                        run_path.display()
+                    ))
                 }
-                Err(e) => {
-                    eprintln!("Error creating dir: {:?}", e)
+                ExistingFiles::Overwrite => {
+                    warn!("[{}] Overwriting {}", self.name, run_path.display());
+                    break;
+                }
+                ExistingFiles::Increment => {
+                    warn!(
+                        "[{}] {} already exists, numbering the run {}",
+                        self.name,
+                        run_path.display(),
+                        self.run_num + 1
+                    );
+                    self.run_num = self.dirs.reserve_run(self.run_num + 1, &self.name)?;
+                    run_dir = self.dirs.create_run_dir(self.run_num, started)?;
                 }
             }
         }
-
-        Ok(path)
+        let config_dest = run_dir.join(format!("config_run{:0>6}.toml", self.run_num));
+        match &self.run_config_text {
+            Some(text) => fs::write(&config_dest, text),
+            None => fs::copy(&self.config_file, &config_dest).map(|_| ()),
+        }
+        .map_err(|e| anyhow::anyhow!("failed to copy config: {}", e))?;
+        let run_path = run_dir.join(crate::subrun_file_name(self.run_num, 0));
+        self.run_dir = run_dir;
+        Ok(run_path)
     }
 }
 
-fn event_processing(
+fn local_now() -> OffsetDateTime {
+    OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc())
+}
+
+/// First time after `now` the scheduled calibration run is due.
+fn next_calibration_after(
+    settings: &CalibrationRunSettings,
+    now: OffsetDateTime,
+) -> Option<OffsetDateTime> {
+    let at = now.replace_time(settings.time_of_day()?);
+    Some(if at > now {
+        at
+    } else {
+        at + time::Duration::DAY
+    })
+}
+
+fn event_processing<W: RunWriter>(
     rx: Receiver<BoardEvent>,
-    tx_stats: Sender<RunInfo>,
-    run_file: PathBuf,
+    stats: &RunStats,
     config: Conf,
-    shutdown: Arc<AtomicBool>,
+    context: ProcessingContext,
 ) -> Result<(), DaqError> {
     info!("Started event processing thread");
+    let ProcessingContext {
+        run_file,
+        run_type,
+        channel_maps,
+        calibration,
+        board_params,
+        active_boards,
+        shutdown,
+    } = context;
     // new counters
     let mut misaligned_count = 0;
     let mut dropped_count = 0;
@@ -578,17 +3223,109 @@ fn event_processing(
     let num_boards = config.run_settings.boards.len();
     let mut events = Vec::with_capacity(num_boards);
 
-    let mut writer = HDF5Writer::new(
+    let roi = config
+        .roi_settings
+        .enabled
+        .then(|| config.roi_settings.clone());
+    let multires = &config.multires_settings;
+    let apply_calibration = calibration.clone().filter(|_| config.calibration.apply);
+    // DPP-PSD hits have no waveforms to take baselines of
+    let baseline_samples = (config.baseline.enabled
+        && config.run_settings.endpoint.has_waveforms())
+    .then(|| config.baseline.samples(&config.board_settings.common));
+    let baseline_pedestal = baseline_samples
+        .filter(|_| config.baseline.subtract)
+        .map(|_| config.baseline.pedestal);
+    let layout = StorageLayout {
+        run_type,
+        endpoint: config.run_settings.endpoint,
+        roi: roi.clone(),
+        decimation: multires.enabled.then_some(multires.decimation),
+        delta_encoding: config.run_settings.delta_encoding,
+        waveform_checksums: config.run_settings.waveform_checksums,
+        calibration_applied: apply_calibration.is_some(),
+        calibration,
+        correct_timestamps: config.sync_settings.correct_timestamps,
+        timestamp_offsets: None,
+        multi_window: config.board_settings.common.multi_window,
+        fine_timestamp: config.run_settings.fine_timestamp,
+        event_header: config.run_settings.event_header,
+        baseline_samples,
+        baseline_pedestal,
+        board_suppression: config.zs_settings.board_suppression(),
+        zs_audit: (config.zs_settings.zs_audit_prescale > 0)
+            .then_some(config.zs_settings.zs_audit_prescale),
+        board_params,
+        volt_waveforms: config.run_settings.volt_waveforms,
+        sparse_waveforms: config.run_settings.sparse_waveforms,
+        record_len: config.board_settings.common.record_len,
+        existing_files: config.run_settings.existing_files,
+        campaign: config.campaign.clone(),
+    };
+    // DPP-PSD hits have no waveforms to average
+    let mut averages: Vec<WaveformAverage> = channel_maps
+        .iter()
+        .filter(|_| config.run_settings.endpoint.has_waveforms())
+        .map(|channels| {
+            WaveformAverage::new(channels.len(), config.board_settings.common.record_len)
+        })
+        .collect();
+    // Pulses for the reduced file, found on the waveforms before any cuts
+    let dst_params = DstParams {
+        threshold: config
+            .dst
+            .threshold
+            .unwrap_or(config.zs_settings.zs_threshold),
+        pre_samples: config.dst.pre_samples,
+        integration_samples: config.dst.integration_samples,
+        baseline_samples: config.zs_settings.zs_samples.max(1) as usize,
+    };
+    let mut dst_writer = None;
+    if config.dst.enabled
+        && config.run_settings.endpoint.has_waveforms()
+        && config.run_settings.storage == Storage::Hdf5
+    {
+        let edge = format!("{:?}", config.zs_settings.zs_edge);
+        match DstWriter::create(
+            &run_file,
+            &DatasetParams::new(&config.run_settings),
+            dst_params,
+            &edge,
+        ) {
+            Ok(dst) => dst_writer = Some(dst),
+            Err(e) => warn!(
+                "Failed to create the reduced file, no pulses are saved: {}",
+                e
+            ),
+        }
+    }
+    // Threshold of each waveform row of each board, none for rows kept whole
+    let zs_thresholds: Vec<Vec<Option<f64>>> = channel_maps
+        .iter()
+        .map(|channels| {
+            channels
+                .iter()
+                .map(|&ch| config.zs_settings.channel_threshold(ch))
+                .collect()
+        })
+        .collect();
+    let mut writer = W::create(
         run_file,
-        64,
-        config.board_settings.common.record_len,
-        config.run_settings.boards.len(),
+        channel_maps,
+        roi.as_ref()
+            .map_or(config.board_settings.common.record_len, |roi| {
+                roi.window_len()
+            }),
         config.run_settings.max_events_per_board,
-        50,
         config.run_settings.blosc_threads,
-        config.run_settings.compression_level,
+        DatasetParams::new(&config.run_settings),
+        layout,
     )
     .unwrap();
+    writer.set_rollover_policy(RolloverPolicy::new(&config.run_settings));
+    if let Some(operator) = &config.run_settings.operator {
+        writer.set_operator(operator).unwrap();
+    }
 
     let mut queues = Vec::with_capacity(num_boards);
     for _ in 0..num_boards {
@@ -596,171 +3333,621 @@ fn event_processing(
     }
     let mut rng = rand::rng();
     let zs_level = config.zs_settings.zs_level;
-    let zs_threshold = config.zs_settings.zs_threshold;
     let zs_edge = config.zs_settings.zs_edge;
     let zs_samples = config.zs_settings.zs_samples;
     let zs_window_size = config.zs_settings.zs_window_size;
+    let zs_margins = (
+        config.zs_settings.zs_pre_samples,
+        config.zs_settings.zs_post_samples,
+    );
+    let board_zs = config.zs_settings.zs_mode == ZsMode::Board;
+    let zs_audit_prescale = config.zs_settings.zs_audit_prescale;
+    let zs_parallelism = config.zs_settings.zs_parallelism;
+    let zs_batch_size = match zs_parallelism {
+        ZsParallelism::Channel => 1,
+        ZsParallelism::Event => config.zs_settings.zs_batch_size,
+    };
+    // Time spent in software zero suppression, to compare the parallelism
+    // options on the actual records
+    let mut zs_time = Duration::ZERO;
+    let mut zs_events = 0;
+    // Built events held back until the boards' timestamp offsets are known,
+    // so every written event can get a corrected timestamp
+    let offset_events = config.sync_settings.offset_events;
+    let mut offset_pending = (offset_events > 0).then(Vec::new);
+    let event_building = config.sync_settings.event_building;
+    let independent = event_building == EventBuilding::Independent;
+    // How far each board's part of an event may be from the earliest with
+    // timestamp building; a start that went down the chain with the wrong
+    // RunDelay leaves the clocks apart by up to the difference to board 0's
+    let tolerances: Vec<u64> = (0..num_boards)
+        .map(|board| {
+            let delay = crate::get_run_delay(board, num_boards) as i64;
+            let skew = (delay - crate::get_run_delay(0, num_boards) as i64).unsigned_abs();
+            config.sync_settings.coincidence_window_ns + skew
+        })
+        .collect();
+    // Next expected trigger ID of each board in independent mode
+    let mut next_trig_ids = vec![0u32; num_boards];
 
     loop {
-        match rx.recv() {
-            Ok(mut board_event) => {
-                let r: f64 = rng.random();
-                if r > zs_level {
-                    zero_suppress(
-                        &mut board_event,
-                        zs_threshold,
-                        zs_edge,
-                        zs_samples,
-                        zs_window_size,
-                    );
-                    board_event.zero_suppressed = true;
-                    queues[board_event.board_id].push_back(board_event);
-                } else {
-                    board_event.zero_suppressed = false;
-                    let mut suppressed_event = board_event.clone();
-                    zero_suppress(
-                        &mut suppressed_event,
-                        zs_threshold,
-                        zs_edge,
-                        zs_samples,
-                        zs_window_size,
+        let first = match rx.recv() {
+            Ok(board_event) => board_event,
+            Err(RecvError) => {
+                // All boards stopped and every queued event was received
+                if let Some(pending) = offset_pending.take() {
+                    write_with_offsets(&mut writer, pending, num_boards, &roi, &apply_calibration);
+                }
+                writer.write_averages(&averages).unwrap();
+                writer.finalize(RUN_STATUS_GOOD).unwrap();
+                break;
+            }
+        };
+        // Events already waiting are suppressed together with event
+        // parallelism
+        let batch = std::iter::once(first).chain(rx.try_iter().take(zs_batch_size - 1));
+        // Each event with whether it still needs software zero suppression
+        let mut processed: Vec<(BoardEvent, bool)> = Vec::new();
+        for mut board_event in batch {
+            // Extra endpoint records aren't events, they're written as they
+            // come
+            if let Some(record) = board_event.endpoint_record.take() {
+                writer
+                    .append_endpoint_record(board_event.board_id, &record)
+                    .unwrap();
+                continue;
+            }
+            // DPP-PSD hits have no waveforms to process
+            if board_event.event.dpp_psd.is_some() {
+                processed.push((board_event, false));
+                continue;
+            }
+            averages[board_event.board_id].add(&board_event.event.waveform_data);
+            board_event.pile_up = pile_up_flags(&board_event.event.waveform_data, &config);
+            if let Some(dst) = &mut dst_writer {
+                let channels = writer.channel_map(board_event.board_id);
+                let pulses = dst_pulses(&board_event, channels, &dst_params, &config);
+                if let Err(e) = dst.append(pulses) {
+                    warn!(
+                        "Failed to write the reduced file, no more pulses are saved: {}",
+                        e
                     );
-                    suppressed_event.zero_suppressed = true;
-                    queues[board_event.board_id].push_back(board_event);
-                    queues[suppressed_event.board_id].push_back(suppressed_event);
+                    dst_writer = None;
                 }
             }
-            Err(RecvError) => {
-                writer.flush_all().unwrap();
-                break;
+            // Windows are placed on the raw waveforms so a zero suppressed
+            // copy keeps the same offsets
+            if let Some(roi) = &roi {
+                board_event.roi_offsets =
+                    Some(roi_offsets(&board_event.event.waveform_data, roi, &config));
             }
+            if multires.enabled {
+                board_event.full_res = keep_full_res(&board_event, &config);
+            }
+            if let Some(samples) = baseline_samples {
+                let waveforms = &mut board_event.event.waveform_data;
+                let baselines = channel_baselines(waveforms, samples);
+                if let Some(pedestal) = baseline_pedestal {
+                    subtract_baselines(waveforms, &baselines, pedestal);
+                }
+                board_event.baselines = Some(baselines);
+            }
+            if board_zs {
+                // the firmware already dropped the quiet channels
+                board_event.zero_suppressed = true;
+                processed.push((board_event, false));
+            } else if rng.random::<f64>() > zs_level {
+                let trigger_id = board_event.event.c_event.trigger_id as usize;
+                if zs_audit_prescale > 0 && trigger_id.is_multiple_of(zs_audit_prescale) {
+                    board_event.unsuppressed = Some(board_event.event.waveform_data.clone());
+                }
+                board_event.zero_suppressed = true;
+                processed.push((board_event, true));
+            } else {
+                board_event.zero_suppressed = false;
+                let mut suppressed_event = board_event.clone();
+                suppressed_event.zero_suppressed = true;
+                processed.push((board_event, false));
+                processed.push((suppressed_event, true));
+            }
+        }
+        let zs_start = Instant::now();
+        let suppress = |(board_event, suppress): &mut (BoardEvent, bool), parallel_channels| {
+            if *suppress {
+                zero_suppress(
+                    board_event,
+                    &zs_thresholds[board_event.board_id],
+                    zs_edge,
+                    zs_samples,
+                    zs_window_size,
+                    zs_margins,
+                    parallel_channels,
+                );
+            }
+        };
+        match zs_parallelism {
+            ZsParallelism::Channel => processed.iter_mut().for_each(|e| suppress(e, true)),
+            ZsParallelism::Event => processed.par_iter_mut().for_each(|e| suppress(e, false)),
+        }
+        zs_time += zs_start.elapsed();
+        zs_events += processed.iter().filter(|(_, suppress)| *suppress).count();
+        for (board_event, _) in processed {
+            queues[board_event.board_id].push_back(board_event);
         }
 
-        if queues.iter().all(|q| q.front().is_some()) {
-            // if queue0.front().is_some() && queue1.front().is_some() {
-            crate::align_queues(&mut queues, &mut misaligned_count);
+        // Boards the operator chose to continue without no longer take part
+        // in event building
+        let mut active_queues: Vec<&mut VecDeque<BoardEvent>> = Vec::with_capacity(num_boards);
+        for (board_id, queue) in queues.iter_mut().enumerate() {
+            if active_boards[board_id].load(Ordering::SeqCst) {
+                active_queues.push(queue);
+            } else {
+                queue.clear();
+            }
+        }
 
-            if queues.iter().all(|q| q.front().is_some()) {
-                // if let (Some(e0), Some(e1)) = (queue0.front(), queue1.front()) {
-                let trgid = queues[0].front().unwrap().event.c_event.trigger_id;
-                // let _trgid1 = e1.event.c_event.trigger_id;
+        if independent {
+            for queue in active_queues.iter_mut() {
+                while let Some(event) = queue.pop_front() {
+                    let trgid = event.event.c_event.trigger_id;
+                    let next = &mut next_trig_ids[event.board_id];
+                    if trgid > *next {
+                        dropped_count += (trgid - *next) as usize;
+                    }
+                    *next = (*next).max(trgid + 1);
 
-                if trgid != curr_trig_id {
-                    dropped_count += (trgid as isize - curr_trig_id as isize).abs() as usize;
+                    stats.add_event(event.event.c_event.event_size);
+                    write_event(&mut writer, &event, &roi, &apply_calibration);
+                }
+            }
+        } else {
+            // A batch can complete several events
+            while !active_queues.is_empty() && active_queues.iter().all(|q| q.front().is_some()) {
+                // if queue0.front().is_some() && queue1.front().is_some() {
+                if event_building == EventBuilding::Timestamp {
+                    crate::align_queues_by_time(
+                        &mut active_queues,
+                        &tolerances,
+                        &mut misaligned_count,
+                    );
+                } else {
+                    crate::align_queues(&mut active_queues, &mut misaligned_count);
                 }
 
-                curr_trig_id = trgid + 1;
+                if active_queues.iter().all(|q| q.front().is_some()) {
+                    // if let (Some(e0), Some(e1)) = (queue0.front(), queue1.front()) {
+                    let front = active_queues[0].front().unwrap();
+                    let trgid = front.event.c_event.trigger_id;
+                    // let _trgid1 = e1.event.c_event.trigger_id;
 
-                for queue in queues.iter_mut() {
-                    events.push(queue.pop_front().unwrap());
-                }
+                    // Later windows of a trigger repeat its ID, and with
+                    // timestamp building the IDs aren't trusted
+                    if front.window == 0
+                        && trgid != curr_trig_id
+                        && event_building == EventBuilding::Aligned
+                    {
+                        dropped_count += (trgid as isize - curr_trig_id as isize).abs() as usize;
+                    }
 
-                let run_info = RunInfo {
-                    event_sizes: events.iter().map(|e| e.event.c_event.event_size).collect(),
-                    event_channel_buf: rx.len(),
-                    misaligned_events: misaligned_count,
-                    dropped_events: dropped_count,
-                };
+                    curr_trig_id = trgid + 1;
 
-                if tx_stats.send(run_info).is_err() {
-                    shutdown.store(true, Ordering::SeqCst);
-                    return Err(DaqError::EventProcessingTransit);
-                }
+                    for queue in active_queues.iter_mut() {
+                        events.push(queue.pop_front().unwrap());
+                    }
+
+                    for event in &events {
+                        stats.add_event(event.event.c_event.event_size);
+                    }
 
-                for event in &events {
-                    writer
-                        .append_event(
-                            event.board_id,
-                            event.event.c_event.timestamp,
-                            &event.event.waveform_data,
-                            event.event.c_event.trigger_id,
-                            event.event.c_event.flags,
-                            event.event.c_event.board_fail,
-                            event.zero_suppressed,
-                        )
-                        .unwrap();
+                    if let Some(pending) = &mut offset_pending {
+                        pending.push(std::mem::take(&mut events));
+                        if pending.len() >= offset_events {
+                            let pending = offset_pending.take().unwrap();
+                            write_with_offsets(
+                                &mut writer,
+                                pending,
+                                num_boards,
+                                &roi,
+                                &apply_calibration,
+                            );
+                        }
+                    } else {
+                        for event in &events {
+                            write_event(&mut writer, event, &roi, &apply_calibration);
+                        }
+                        events.clear();
+                    }
                 }
-                events.clear();
             }
         }
 
+        stats.update(rx.len(), misaligned_count, dropped_count, writer.subrun());
+
         if shutdown.load(Ordering::SeqCst) {
-            writer.flush_all().unwrap();
+            if let Some(pending) = offset_pending.take() {
+                write_with_offsets(&mut writer, pending, num_boards, &roi, &apply_calibration);
+            }
+            writer.write_averages(&averages).unwrap();
+            writer.finalize(RUN_STATUS_ABORTED).unwrap();
             break;
         }
     }
 
+    stats.update(rx.len(), misaligned_count, dropped_count, writer.subrun());
+    if let Some(Err(e)) = dst_writer.map(DstWriter::finish) {
+        warn!("Failed to close the reduced file: {}", e);
+    }
+    if zs_events > 0 {
+        info!(
+            "Zero suppressed {} events in {:.1} µs each with {} parallelism",
+            zs_events,
+            zs_time.as_secs_f64() * 1e6 / zs_events as f64,
+            zs_parallelism.name()
+        );
+    }
     info!("Ending event processing thread");
-    drop(tx_stats);
     Ok(())
 }
 
+/// Hand one board's event to the writer, cut to its ROI windows and
+/// calibrated if enabled.
+fn write_event(
+    writer: &mut impl RunWriter,
+    event: &BoardEvent,
+    roi: &Option<RoiSettings>,
+    calibration: &Option<Calibration>,
+) {
+    if let Some(hit) = &event.event.dpp_psd {
+        writer
+            .append_hit(event.board_id, event.event.c_event.timestamp, hit)
+            .unwrap();
+        return;
+    }
+    let mut waveforms = match (roi, &event.roi_offsets) {
+        (Some(roi), Some(offsets)) => Cow::Owned(roi_waveforms(
+            &event.event.waveform_data,
+            offsets,
+            roi.window_len(),
+        )),
+        _ => Cow::Borrowed(&event.event.waveform_data),
+    };
+    // Audited waveforms get the same cuts and corrections, so they compare
+    // sample by sample with the suppressed ones
+    let mut unsuppressed = event
+        .unsuppressed
+        .as_ref()
+        .map(|raw| match (roi, &event.roi_offsets) {
+            (Some(roi), Some(offsets)) => roi_waveforms(raw, offsets, roi.window_len()),
+            _ => raw.clone(),
+        });
+    if let Some(calibration) = calibration {
+        let channels = writer.channel_map(event.board_id);
+        calibration.apply(event.board_id, channels, waveforms.to_mut());
+        if let Some(unsuppressed) = &mut unsuppressed {
+            calibration.apply(event.board_id, channels, unsuppressed);
+        }
+    }
+    let record = EventRecord {
+        timestamp: event.event.c_event.timestamp,
+        timestamp_us: event.event.c_event.timestamp_us,
+        waveforms: &waveforms,
+        trigger_id: event.event.c_event.trigger_id,
+        flags: event.event.c_event.flags,
+        fail: event.event.c_event.board_fail,
+        size: event.event.c_event.event_size,
+        zero_suppressed: event.zero_suppressed,
+        roi_offsets: event.roi_offsets.as_deref(),
+        pile_up: &event.pile_up,
+        baselines: event.baselines.as_deref(),
+        window: event.window,
+        full_res: event.full_res,
+        unsuppressed: unsuppressed.as_ref(),
+    };
+    writer.append_event(event.board_id, &record).unwrap();
+}
+
+/// Measure the boards' timestamp offsets from the events built at the start
+/// of the run, store them and write the held back events.
+fn write_with_offsets(
+    writer: &mut impl RunWriter,
+    pending: Vec<Vec<BoardEvent>>,
+    num_boards: usize,
+    roi: &Option<RoiSettings>,
+    calibration: &Option<Calibration>,
+) {
+    let offsets = crate::timestamp_offsets(&pending, num_boards);
+    info!(
+        "Timestamp offsets to board 0 from {} events: {:?} ns",
+        pending.len(),
+        offsets
+    );
+    writer.set_timestamp_offsets(offsets).unwrap();
+    for event in pending.iter().flatten() {
+        write_event(writer, event, roi, calibration);
+    }
+}
+
+/// Short human-readable duration, e.g. `3d 4h` or `12m 5s`.
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (days, hours, mins) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, mins)
+    } else {
+        format!("{}m {}s", mins, secs % 60)
+    }
+}
+
+/// Dead time over real time, with both monitors in the board's units.
+fn dead_time_fraction(realtime: Option<u64>, deadtime: Option<u64>) -> Option<f64> {
+    match (realtime, deadtime) {
+        (Some(realtime), Some(deadtime)) if realtime > 0 => Some(deadtime as f64 / realtime as f64),
+        _ => None,
+    }
+}
+
+/// A board's `ErrorFlags`, `None` if they can't be read.
+fn error_flags(handle: u64) -> Option<u32> {
+    crate::get_value_cached(handle, "/par/ErrorFlags")
+        .ok()
+        .and_then(|flags| flags.trim().parse().ok())
+}
+
+/// Send one software trigger to every board.
+fn send_sw_trigger(devices: &[felib::DeviceHandle]) -> Result<(), FELibReturn> {
+    for dev in devices {
+        felib::send_command(dev.raw(), "/cmd/sendswtrigger")?;
+    }
+    Ok(())
+}
+
+/// Send software triggers to every board at `rate` Hz until `stop` is set,
+/// for pedestal runs and finding dead channels without a pulser.
+fn sw_trigger_thread(devices: Vec<felib::DeviceHandle>, rate: f64, stop: Arc<AtomicBool>) {
+    let period = Duration::from_secs_f64(1.0 / rate);
+    let mut next = Instant::now();
+    let mut failed = false;
+    while !stop.load(Ordering::SeqCst) {
+        let now = Instant::now();
+        if now < next {
+            // Short sleeps so a slow generator still stops promptly
+            thread::sleep((next - now).min(SW_TRIGGER_POLL_INTERVAL));
+            continue;
+        }
+        // Only the first failure is logged, a board in trouble shows up as
+        // alarms from its data-taking thread anyway
+        if let Err(e) = send_sw_trigger(&devices) {
+            if !failed {
+                warn!("Failed to send software trigger: {:?}", e);
+                failed = true;
+            }
+        }
+        next += period;
+    }
+}
+
+/// Disarm every board, even if some fail, and return the first error.
+fn disarm_boards(devices: &[felib::DeviceHandle]) -> Result<(), FELibReturn> {
+    let mut result = Ok(());
+    for dev in devices {
+        let disarmed = felib::send_command(dev.raw(), "/cmd/disarmacquisition");
+        crate::invalidate_cached_values(dev.raw());
+        result = result.and(disarmed);
+    }
+    result
+}
+
+/// Get, activate and set the data format of a board's readout endpoint,
+/// trying each step up to `attempts` times. The error names the step that
+/// kept failing.
+fn setup_endpoint(
+    device: &felib::DeviceHandle,
+    run_settings: &RunSettings,
+    attempts: usize,
+) -> Result<felib::EndpointHandle> {
+    let endpoint_type = run_settings.endpoint;
+    let endpoint = retry(
+        attempts,
+        &format!("getting the {} endpoint", endpoint_type.name()),
+        || device.endpoint(&endpoint_type.path()),
+    )?;
+    retry(attempts, "activating the endpoint", || {
+        endpoint.activate(endpoint_type.name())
+    })?;
+    retry(attempts, "setting the read data format", || {
+        felib::set_read_data_format(
+            endpoint.raw(),
+            endpoint_type.read_format(run_settings.fine_timestamp),
+        )
+    })?;
+    Ok(endpoint)
+}
+
+/// Get one of a board's extra endpoints and set its data format, without
+/// activating it, trying each step up to `attempts` times. Returns the
+/// endpoint with an empty record to read into.
+fn setup_extra_endpoint(
+    device: &felib::DeviceHandle,
+    kind: ExtraEndpoint,
+    num_ch: usize,
+    attempts: usize,
+) -> Result<(felib::EndpointHandle, EndpointRecord)> {
+    let endpoint = retry(
+        attempts,
+        &format!("getting the {} endpoint", kind.name()),
+        || device.endpoint(&kind.path()),
+    )?;
+    retry(attempts, "setting the read data format", || {
+        felib::set_read_data_format(endpoint.raw(), kind.read_format())
+    })?;
+    let record = match kind {
+        ExtraEndpoint::Raw => {
+            let size = retry(attempts, "reading MaxRawDataSize", || {
+                felib::get_value(device.raw(), "/par/MaxRawDataSize")
+            })?;
+            let size = size
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("invalid MaxRawDataSize {}", size))?;
+            EndpointRecord::Raw(RawBlock::new(size))
+        }
+        ExtraEndpoint::Stats => EndpointRecord::Stats(EndpointStats::new(num_ch)),
+    };
+    Ok((endpoint, record))
+}
+
+fn retry<T>(
+    attempts: usize,
+    step: &str,
+    mut f: impl FnMut() -> Result<T, FELibReturn>,
+) -> Result<T> {
+    let mut last_err = FELibReturn::Generic;
+    for attempt in 1..=attempts.max(1) {
+        match f() {
+            Ok(value) => return Ok(value),
+            Err(e) => {
+                warn!(
+                    "{} failed (attempt {}/{}): {:?}",
+                    step, attempt, attempts, e
+                );
+                last_err = e;
+                if attempt < attempts {
+                    thread::sleep(ENDPOINT_RETRY_DELAY);
+                }
+            }
+        }
+    }
+    Err(anyhow!(
+        "{} failed after {} attempts: {:?}",
+        step,
+        attempts,
+        last_err
+    ))
+}
+
 /// Data-taking thread function for one board.
-/// It configures the endpoint, signals that configuration is complete,
-/// waits for the shared acquisition start signal, then continuously reads events and sends them.
+/// It waits for the shared acquisition start signal on its configured
+/// endpoint, then continuously reads events and sends them.
 fn data_taking_thread(
     board_id: usize,
-    dev_handle: u64,
+    context: ReadoutContext,
     config: Conf,
     tx: Sender<BoardEvent>,
-    acq_start: Arc<(Mutex<bool>, Condvar)>,
-    endpoint_configured: Arc<(Mutex<u32>, Condvar)>,
-    shutdown: Arc<AtomicBool>,
 ) -> Result<(), DaqError> {
     info!("Started data taking thread for board {board_id}");
-    // Set up endpoint.
-    let mut ep_handle = 0;
-    let mut ep_folder_handle = 0;
-    crate::felib_gethandle(dev_handle, "/endpoint/scope", &mut ep_handle)?;
-    crate::felib_getparenthandle(ep_handle, "", &mut ep_folder_handle)?;
-    crate::felib_setvalue(ep_folder_handle, "/par/activeendpoint", "scope")?;
-    crate::felib_setreaddataformat(ep_handle, crate::EVENT_FORMAT)?;
-    crate::felib_sendcommand(dev_handle, "/cmd/armacquisition")?;
-
-    // Signal that this board's endpoint is configured.
-    {
-        let (lock, cond) = &*endpoint_configured;
-        let mut count = lock.lock().unwrap();
-        *count += 1;
-        cond.notify_all();
-    }
+    let ReadoutContext {
+        endpoint,
+        num_ch,
+        channel_map,
+        acq_start,
+        shutdown,
+        read_timeouts,
+    } = context;
 
-    // Wait for the acquisition start signal.
+    // Wait for the acquisition start signal, but never forever
     {
         let (lock, cvar) = &*acq_start;
-        let mut started = lock.lock().unwrap();
-        while !*started {
-            started = cvar.wait(started).unwrap();
+        let started = lock.lock().unwrap();
+        let (started, _) = cvar
+            .wait_timeout_while(started, ACQ_START_TIMEOUT, |started| !*started)
+            .unwrap();
+        if !*started {
+            warn!("Board {board_id} never got the acquisition start signal");
+            return Err(DaqError::StartTimeout);
         }
     }
 
     // Data-taking loop.
-    // num_ch has to be 64 due to the way CAEN reads data from the board
-    let num_ch = 64;
+    // num_ch must match the board's NumCh since CAEN fills one waveform per
+    // channel, but only the enabled channels in channel_map get room
     let waveform_len = config.board_settings.common.record_len;
-    let mut event = EventWrapper::new(num_ch, waveform_len);
+    let endpoint_type = config.run_settings.endpoint;
+    let fine_timestamp = config.run_settings.fine_timestamp;
+    let new_event = || match endpoint_type {
+        EndpointType::DppPsd => EventWrapper::new_dpp_psd(),
+        _ => EventWrapper::with_channels(num_ch, waveform_len, &channel_map),
+    };
+    let mut event = new_event();
+    // Windows of one trigger come one after another with the same trigger ID
+    let mut last_trigger_id = None;
+    let mut window = 0;
+    let policy = config.read_policy(board_id);
+    let mut read_timeout = ReadTimeout::new(
+        Duration::from_millis(policy.timeout_min),
+        Duration::from_millis(policy.timeout_max),
+    );
+    let mut consecutive_timeouts = 0;
+    let read = |event: &mut EventWrapper, timeout_ms| match endpoint_type {
+        EndpointType::DppPsd => felib::read_dpp_psd(endpoint.raw(), timeout_ms, event),
+        _ => felib::read_data(endpoint.raw(), timeout_ms, event, fine_timestamp),
+    };
     loop {
         if shutdown.load(Ordering::SeqCst) {
             break;
         }
-        match crate::felib_readdata(ep_handle, &mut event) {
+        let timeout = read_timeout.current();
+        if let Some(shown) = read_timeouts.get(board_id) {
+            shown.store(timeout.as_millis() as u64, Ordering::Relaxed);
+        }
+        let timeout_ms = timeout.as_millis() as i32;
+        let res = match policy.strategy {
+            ReadStrategy::ReadData => read(&mut event, timeout_ms),
+            ReadStrategy::HasData => match felib::has_data(endpoint.raw(), timeout_ms) {
+                Ok(()) => read(&mut event, 0),
+                Err(e) => e,
+            },
+        };
+        match res {
             FELibReturn::Success => {
+                read_timeout.event();
+                consecutive_timeouts = 0;
+                let trigger_id = event.c_event.trigger_id;
+                window = match last_trigger_id {
+                    Some(id) if id == trigger_id && config.board_settings.common.multi_window => {
+                        window + 1
+                    }
+                    _ => 0,
+                };
+                last_trigger_id = Some(trigger_id);
                 // Instead of allocating a new EventWrapper,
                 // swap out the current one using std::mem::replace.
                 let board_event = BoardEvent {
                     board_id,
-                    event: std::mem::replace(&mut event, EventWrapper::new(num_ch, waveform_len)),
+                    event: std::mem::replace(&mut event, new_event()),
                     zero_suppressed: false,
+                    roi_offsets: None,
+                    full_res: true,
+                    pile_up: Vec::new(),
+                    baselines: None,
+                    window,
+                    unsuppressed: None,
+                    endpoint_record: None,
                 };
                 if tx.send(board_event).is_err() {
                     shutdown.store(true, Ordering::SeqCst);
                     return Err(DaqError::DataTakingTransit);
                 }
             }
-            FELibReturn::Timeout => continue,
+            FELibReturn::Timeout => {
+                read_timeout.timed_out();
+                consecutive_timeouts += 1;
+                if policy
+                    .stall_timeouts
+                    .is_some_and(|limit| consecutive_timeouts >= limit)
+                {
+                    warn!("Board {board_id} stalled after {consecutive_timeouts} empty reads");
+                    return Err(DaqError::BoardStalled(consecutive_timeouts));
+                }
+            }
             FELibReturn::Stop => {
                 break;
             }
+            // The board is gone, hand it to the operator instead of spinning
+            err @ (FELibReturn::Comm | FELibReturn::DevNotFound | FELibReturn::InvalidHandle) => {
+                return Err(err.into());
+            }
             _ => (),
         }
     }
@@ -770,41 +3957,259 @@ fn data_taking_thread(
     Ok(())
 }
 
+/// Read one of a board's extra endpoints until the board stops, sending each
+/// record to the event processing thread along with the board's events.
+fn endpoint_thread(
+    board_id: usize,
+    kind: ExtraEndpoint,
+    endpoint: felib::EndpointHandle,
+    mut record: EndpointRecord,
+    tx: Sender<BoardEvent>,
+    shutdown: Arc<AtomicBool>,
+) -> Result<(), DaqError> {
+    info!(
+        "Started {} endpoint thread for board {board_id}",
+        kind.name()
+    );
+    while !shutdown.load(Ordering::SeqCst) {
+        let res = match &mut record {
+            EndpointRecord::Raw(block) => {
+                felib::read_raw(endpoint.raw(), EXTRA_ENDPOINT_TIMEOUT_MS, block)
+            }
+            EndpointRecord::Stats(stats) => {
+                felib::read_stats(endpoint.raw(), EXTRA_ENDPOINT_TIMEOUT_MS, stats)
+            }
+        };
+        match res {
+            FELibReturn::Success => {
+                let board_event = BoardEvent {
+                    board_id,
+                    event: EventWrapper::new(0, 0),
+                    zero_suppressed: false,
+                    roi_offsets: None,
+                    full_res: false,
+                    pile_up: Vec::new(),
+                    baselines: None,
+                    window: 0,
+                    unsuppressed: None,
+                    endpoint_record: Some(record.clone()),
+                };
+                if tx.send(board_event).is_err() {
+                    return Err(DaqError::DataTakingTransit);
+                }
+            }
+            FELibReturn::Timeout => {}
+            FELibReturn::Stop => break,
+            e => return Err(e.into()),
+        }
+    }
+    info!(
+        "Ending {} endpoint thread for board {board_id}",
+        kind.name()
+    );
+    Ok(())
+}
+
 /// suppress adc samples from digitizer based on user-defined threshold
 /// relative to baseline and whether or not the pulses are rising or
-/// falling
+/// falling. Each channel has its own threshold, channels without one are
+/// left whole. The channels run in parallel if `parallel_channels` is set.
 fn zero_suppress(
     board_data: &mut BoardEvent,
-    threshold: f64,
+    thresholds: &[Option<f64>],
     edge: ZeroSuppressionEdge,
     bl_samples: isize,
     window_size: usize,
+    margins: (usize, usize),
+    parallel_channels: bool,
 ) {
-    board_data
+    let suppress = |(channel, threshold): (ArrayViewMut1<u16>, &Option<f64>)| {
+        let Some(threshold) = *threshold else {
+            return;
+        };
+        let baseline = baseline(channel.view(), bl_samples);
+        zs_algo(channel, baseline, threshold, window_size, edge, margins);
+    };
+    let channels = board_data.event.waveform_data.axis_iter_mut(Axis(0));
+    if parallel_channels {
+        channels
+            .into_par_iter()
+            .zip(thresholds.par_iter())
+            .for_each(suppress);
+    } else {
+        channels.zip(thresholds).for_each(suppress);
+    }
+}
+
+/// Mean of the first `bl_samples` samples of a channel.
+fn baseline(channel: ArrayView1<u16>, bl_samples: isize) -> f64 {
+    let mut sum = 0.0;
+    for val in channel.slice(s![0..bl_samples]) {
+        sum += *val as f64;
+    }
+    sum / bl_samples as f64
+}
+
+/// Each channel's baseline, the mean of its first `samples` samples.
+fn channel_baselines(waveforms: &Array2<u16>, samples: usize) -> Vec<f32> {
+    waveforms
+        .axis_iter(Axis(0))
+        .map(|channel| baseline(channel, samples as isize) as f32)
+        .collect()
+}
+
+/// Move each channel's baseline to `pedestal`, clamping to the sample range.
+fn subtract_baselines(waveforms: &mut Array2<u16>, baselines: &[f32], pedestal: u16) {
+    for (mut channel, &baseline) in waveforms.axis_iter_mut(Axis(0)).zip(baselines) {
+        let shift = pedestal as f64 - baseline as f64;
+        channel.mapv_inplace(|x| (x as f64 + shift).round().clamp(0.0, u16::MAX as f64) as u16);
+    }
+}
+
+/// Start of each channel's ROI window: `pre_samples` before its first pulse,
+/// or around the trigger position if the channel has no pulse. Windows are
+/// kept inside the record.
+fn roi_offsets(waveforms: &Array2<u16>, roi: &RoiSettings, config: &Conf) -> Vec<usize> {
+    let zs = &config.zs_settings;
+    let trigger = config.board_settings.common.pre_trig_len;
+    waveforms
+        .axis_iter(Axis(0))
+        .map(|channel| {
+            let last_start = channel.len().saturating_sub(roi.window_len());
+            let baseline = baseline(channel, zs.zs_samples);
+            let pulse_start = find_pulses(
+                channel,
+                baseline,
+                zs.zs_threshold,
+                zs.zs_window_size,
+                zs.zs_edge,
+            )
+            .first()
+            .map_or(trigger, |&(start, _)| start);
+            pulse_start.saturating_sub(roi.pre_samples).min(last_start)
+        })
+        .collect()
+}
+
+/// Pulses of every channel of an event for the reduced file: where the
+/// signal crosses the threshold, its amplitude and the integral of the
+/// window starting `pre_samples` before the crossing.
+fn dst_pulses(
+    board_event: &BoardEvent,
+    channels: &[usize],
+    dst: &DstParams,
+    config: &Conf,
+) -> Vec<Pulse> {
+    let zs = &config.zs_settings;
+    let sign = match zs.zs_edge {
+        ZeroSuppressionEdge::Rise => 1.0,
+        ZeroSuppressionEdge::Fall => -1.0,
+    };
+    let c_event = &board_event.event.c_event;
+    let mut pulses = Vec::new();
+    for (channel, &ch) in board_event
         .event
         .waveform_data
-        .axis_iter_mut(Axis(0))
-        .into_par_iter()
-        .for_each(|channel| {
-            let mut sum = 0.0;
-            for val in channel.slice(s![0..bl_samples]) {
-                sum += *val as f64;
-            }
-            let baseline = sum / bl_samples as f64;
-            zs_algo(channel, baseline, threshold, window_size, edge);
-        });
+        .axis_iter(Axis(0))
+        .zip(channels)
+    {
+        let baseline = baseline(channel, dst.baseline_samples as isize);
+        let height = |x: &u16| sign * (*x as f64 - baseline);
+        for (start, end) in find_pulses(
+            channel,
+            baseline,
+            dst.threshold,
+            zs.zs_window_size,
+            zs.zs_edge,
+        ) {
+            let amplitude = channel
+                .slice(s![start..end])
+                .iter()
+                .map(height)
+                .fold(0.0, f64::max);
+            let window_end = (start + dst.integration_samples).min(channel.len());
+            let integral: f64 = channel
+                .slice(s![start.saturating_sub(dst.pre_samples)..window_end])
+                .iter()
+                .map(height)
+                .sum();
+            pulses.push(Pulse {
+                board: board_event.board_id as u32,
+                channel: ch as u32,
+                trigger_id: c_event.trigger_id,
+                timestamp: c_event.timestamp,
+                start: start as u32,
+                width: (end - start) as u32,
+                amplitude: amplitude as f32,
+                integral: integral as f32,
+                baseline: baseline as f32,
+            });
+        }
+    }
+    pulses
 }
 
-/// the actual zero suppression algorithm which uses a sliding window to find
-/// the beginning and end of the pulse and then zero suppresses anything
-/// that isn't a pulse
-fn zs_algo(
-    mut channel: ArrayViewMut1<u16>,
+/// Flag the channels with more than one pulse in the record, i.e. the signal
+/// crossed the threshold again after dropping back below it. Pulses are
+/// found with the zero suppression settings.
+fn pile_up_flags(waveforms: &Array2<u16>, config: &Conf) -> Vec<bool> {
+    let zs = &config.zs_settings;
+    waveforms
+        .axis_iter(Axis(0))
+        .map(|channel| {
+            let baseline = baseline(channel, zs.zs_samples);
+            find_pulses(
+                channel,
+                baseline,
+                zs.zs_threshold,
+                zs.zs_window_size,
+                zs.zs_edge,
+            )
+            .len()
+                > 1
+        })
+        .collect()
+}
+
+/// Multi-resolution filter: keep full resolution for events with a channel
+/// far enough from its baseline, and for every `prescale`th trigger.
+fn keep_full_res(board_event: &BoardEvent, config: &Conf) -> bool {
+    let multires = &config.multires_settings;
+    let trigger_id = board_event.event.c_event.trigger_id as usize;
+    if multires.prescale > 0 && trigger_id.is_multiple_of(multires.prescale) {
+        return true;
+    }
+    board_event
+        .event
+        .waveform_data
+        .axis_iter(Axis(0))
+        .any(|channel| {
+            let baseline = baseline(channel, config.zs_settings.zs_samples);
+            channel
+                .iter()
+                .any(|&x| (x as f64 - baseline).abs() >= multires.min_amplitude)
+        })
+}
+
+/// Cut each channel's ROI window out of the full record.
+fn roi_waveforms(waveforms: &Array2<u16>, offsets: &[usize], len: usize) -> Array2<u16> {
+    let mut roi = Array2::zeros((waveforms.nrows(), len));
+    for (ch, (mut row, &offset)) in roi.axis_iter_mut(Axis(0)).zip(offsets).enumerate() {
+        row.assign(&waveforms.slice(s![ch, offset..offset + len]));
+    }
+    roi
+}
+
+/// the pulse finding used by zero suppression and ROI saving: a sliding
+/// window average compared to the baseline gives the start and end of each
+/// pulse
+fn find_pulses(
+    channel: ArrayView1<u16>,
     baseline: f64,
     threshold: f64,
     window_size: usize,
     edge: ZeroSuppressionEdge,
-) {
+) -> Vec<(usize, usize)> {
     let mut win_sum: f64 = channel
         .slice(s![0..window_size])
         .iter()
@@ -852,6 +4257,21 @@ fn zs_algo(
     if in_pulse {
         intervals.push((pulse_start, n));
     }
+    intervals
+}
+
+/// the actual zero suppression algorithm which finds the beginning and end
+/// of each pulse and then zero suppresses anything that isn't a pulse
+fn zs_algo(
+    mut channel: ArrayViewMut1<u16>,
+    baseline: f64,
+    threshold: f64,
+    window_size: usize,
+    edge: ZeroSuppressionEdge,
+    (pre_samples, post_samples): (usize, usize),
+) {
+    let intervals = find_pulses(channel.view(), baseline, threshold, window_size, edge);
+    let n = channel.len();
 
     if intervals.is_empty() {
         channel.fill(0);
@@ -861,6 +4281,10 @@ fn zs_algo(
     let data: &mut [u16] = channel.as_slice_mut().unwrap();
     let mut cursor = 0;
     for &(start, end) in &intervals {
+        // widen the pulse so its leading edge and tail survive, overlapping
+        // the previous one if they come close
+        let start = start.saturating_sub(pre_samples).max(cursor);
+        let end = (end + post_samples).min(n).max(start);
         // zero from cursor up to start
         for idx in cursor..start {
             data[idx] = 0;
@@ -0,0 +1,41 @@
+use anyhow::Result;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, path::Path};
+
+/// File in the working directory where the TUI layout is remembered
+pub const UI_STATE_FILE: &str = "cliq_ui_state.toml";
+
+/// What the shifter was looking at, restored when the TUI is reopened.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct UiState {
+    /// Name of the stream whose tab was selected
+    pub selected_stream: Option<String>,
+    /// Expanded board panel, keyed by stream name
+    pub expanded_boards: HashMap<String, usize>,
+    // TODO: the selected channel, once the waveform view exists
+    // (bojohnson5/cliq#synth-1722-followup)
+}
+
+impl UiState {
+    /// Load the saved state, falling back to the default layout if the file
+    /// is missing or unreadable.
+    pub fn load(path: &Path) -> Self {
+        let Ok(contents) = fs::read_to_string(path) else {
+            return Self::default();
+        };
+        toml::from_str(&contents).unwrap_or_else(|e| {
+            warn!(
+                "Ignoring unreadable UI state file {}: {}",
+                path.display(),
+                e
+            );
+            Self::default()
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+}
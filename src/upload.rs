@@ -0,0 +1,382 @@
+use crate::UploadSettings;
+use anyhow::{anyhow, Result};
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use hmac::{Hmac, Mac};
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+use time::{macros::format_description, OffsetDateTime};
+
+/// Wait before the first retry of a failed request, doubled for every
+/// further attempt
+const RETRY_BACKOFF: Duration = Duration::from_secs(2);
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent-encode everything but the unreserved characters, as SigV4 wants.
+fn uri_encode(s: &str, keep_slash: bool) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            b'/' if keep_slash => String::from("/"),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Minimal S3 client for path-style multipart uploads with AWS Signature
+/// Version 4, which MinIO and other S3 compatible stores accept too.
+#[derive(Debug, Clone)]
+struct S3Client {
+    endpoint: String,
+    /// `host[:port]` of the endpoint, as signed
+    host: String,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    retries: usize,
+}
+
+impl S3Client {
+    fn new(settings: &UploadSettings, endpoint: &str) -> Self {
+        let endpoint = endpoint.trim_end_matches('/').to_string();
+        let host = endpoint
+            .split_once("://")
+            .map_or(endpoint.as_str(), |(_, rest)| rest)
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        // both are checked to be set when the config is loaded
+        let (access_key, secret_key) = settings.credentials().unwrap_or_default();
+        Self {
+            host,
+            bucket: settings.bucket.clone(),
+            region: settings.region.clone(),
+            access_key,
+            secret_key,
+            retries: settings.retries,
+            endpoint,
+        }
+    }
+
+    /// Send one signed request, retrying failures with a growing backoff.
+    fn send(
+        &self,
+        method: &str,
+        key: &str,
+        query: &[(&str, String)],
+        body: &[u8],
+    ) -> Result<ureq::Response> {
+        let mut attempt = 0;
+        loop {
+            match self.send_once(method, key, query, body) {
+                Ok(response) => return Ok(response),
+                Err(e) if attempt < self.retries => {
+                    warn!("{} {} failed, retrying: {}", method, key, e);
+                    thread::sleep(RETRY_BACKOFF * 2u32.pow(attempt as u32));
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn send_once(
+        &self,
+        method: &str,
+        key: &str,
+        query: &[(&str, String)],
+        body: &[u8],
+    ) -> Result<ureq::Response> {
+        let now = OffsetDateTime::now_utc();
+        let amz_date = now.format(format_description!(
+            "[year][month][day]T[hour][minute][second]Z"
+        ))?;
+        let date = &amz_date[..8];
+        let payload_hash = sha256_hex(body);
+
+        let uri = format!(
+            "/{}/{}",
+            uri_encode(&self.bucket, false),
+            uri_encode(key, true)
+        );
+        let mut params: Vec<(String, String)> = query
+            .iter()
+            .map(|(k, v)| (uri_encode(k, false), uri_encode(v, false)))
+            .collect();
+        params.sort();
+        let query = params
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method, uri, query, self.host, payload_hash, amz_date, signed_headers, payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            sha256_hex(canonical_request.as_bytes())
+        );
+        let mut signing_key = format!("AWS4{}", self.secret_key).into_bytes();
+        for part in [date, &self.region, "s3", "aws4_request"] {
+            signing_key = hmac_sha256(&signing_key, part.as_bytes());
+        }
+        let signature = hex(&hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key, scope, signed_headers, signature
+        );
+
+        let mut url = format!("{}{}", self.endpoint, uri);
+        if !query.is_empty() {
+            url = format!("{}?{}", url, query);
+        }
+        ureq::request(method, &url)
+            .set("Host", &self.host)
+            .set("x-amz-date", &amz_date)
+            .set("x-amz-content-sha256", &payload_hash)
+            .set("Authorization", &authorization)
+            .send_bytes(body)
+            .map_err(|e| anyhow!("{}", e))
+    }
+
+    /// Upload a file in parts of `part_size` bytes. Every part is sent with
+    /// its SHA-256, so the store rejects anything corrupted on the way.
+    /// Returns the SHA-256 of the whole file as read for the upload.
+    fn upload(&self, path: &Path, key: &str, part_size: usize) -> Result<String> {
+        let response = self.send("POST", key, &[("uploads", String::new())], &[])?;
+        let body = response.into_string()?;
+        let upload_id = body
+            .split_once("<UploadId>")
+            .and_then(|(_, rest)| rest.split_once("</UploadId>"))
+            .map(|(id, _)| id.to_string())
+            .ok_or_else(|| anyhow!("no UploadId in the response to starting the upload"))?;
+
+        let result = self.upload_parts(path, key, &upload_id, part_size);
+        if result.is_err() {
+            // don't leave the parts behind to be billed for
+            let _ = self.send("DELETE", key, &[("uploadId", upload_id)], &[]);
+        }
+        result
+    }
+
+    fn upload_parts(
+        &self,
+        path: &Path,
+        key: &str,
+        upload_id: &str,
+        part_size: usize,
+    ) -> Result<String> {
+        let mut file = File::open(path)?;
+        let mut hasher = Sha256::new();
+        let mut parts = Vec::new();
+        let mut buf = vec![0u8; part_size];
+        loop {
+            let mut len = 0;
+            while len < part_size {
+                let n = file.read(&mut buf[len..])?;
+                if n == 0 {
+                    break;
+                }
+                len += n;
+            }
+            // an empty file still needs its one part
+            if len == 0 && !parts.is_empty() {
+                break;
+            }
+            let part_number = parts.len() + 1;
+            let response = self.send(
+                "PUT",
+                key,
+                &[
+                    ("partNumber", part_number.to_string()),
+                    ("uploadId", upload_id.to_string()),
+                ],
+                &buf[..len],
+            )?;
+            hasher.update(&buf[..len]);
+            let etag = response
+                .header("ETag")
+                .ok_or_else(|| anyhow!("no ETag for part {}", part_number))?
+                .to_string();
+            parts.push(etag);
+            if len < part_size {
+                break;
+            }
+        }
+
+        let mut complete = String::from("<CompleteMultipartUpload>");
+        for (i, etag) in parts.iter().enumerate() {
+            complete.push_str(&format!(
+                "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                i + 1,
+                etag
+            ));
+        }
+        complete.push_str("</CompleteMultipartUpload>");
+        let response = self.send(
+            "POST",
+            key,
+            &[("uploadId", upload_id.to_string())],
+            complete.as_bytes(),
+        )?;
+        // S3 can report a failed completion inside a 200 response
+        let body = response.into_string()?;
+        if body.contains("<Error>") {
+            return Err(anyhow!("completing the upload failed: {}", body));
+        }
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+}
+
+/// The part of an uploader shared with its thread.
+#[derive(Debug, Clone)]
+struct UploadJob {
+    client: S3Client,
+    output_dir: PathBuf,
+    prefix: String,
+    part_size: usize,
+    failures: Sender<String>,
+}
+
+impl UploadJob {
+    /// Upload a closed file and add it to the campaign's transfer manifest.
+    fn upload(&self, path: &Path, manifest: &Path) -> Result<()> {
+        let relative = path.strip_prefix(&self.output_dir).unwrap_or(path);
+        let key = format!(
+            "{}{}",
+            self.prefix,
+            relative.to_string_lossy().trim_start_matches('/')
+        );
+        let checksum = self.client.upload(path, &key, self.part_size)?;
+        info!(
+            "Uploaded {} to s3://{}/{}",
+            path.display(),
+            self.client.bucket,
+            key
+        );
+
+        let name = path
+            .file_name()
+            .ok_or_else(|| anyhow!("{} has no file name", path.display()))?;
+        if let Some(dir) = manifest.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut manifest = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(manifest)?;
+        writeln!(manifest, "{}  {}", checksum, name.to_string_lossy())?;
+        Ok(())
+    }
+}
+
+/// Streams closed subrun files to an S3 compatible object store from a
+/// background thread. Each uploaded file is added to the transfer manifest,
+/// which lets retention remove it from the DAQ host later. Dropping the
+/// uploader waits for the files already queued.
+#[derive(Debug)]
+pub struct Uploader {
+    /// `None` once closed
+    queue: Option<Sender<(PathBuf, PathBuf)>>,
+    failures: Receiver<String>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Uploader {
+    /// The configured uploader, `None` if `upload.endpoint` is unset.
+    pub fn new(settings: &UploadSettings, output_dir: &str) -> Option<Self> {
+        let endpoint = settings.endpoint.as_ref()?;
+        let client = S3Client::new(settings, endpoint);
+        let (failure_tx, failures) = unbounded();
+        let job = UploadJob {
+            client,
+            output_dir: PathBuf::from(output_dir),
+            prefix: settings.prefix.clone(),
+            part_size: (settings.part_size_mb * 1024 * 1024) as usize,
+            failures: failure_tx,
+        };
+        let (queue, rx) = unbounded::<(PathBuf, PathBuf)>();
+        // ends once the queue is closed
+        let thread = thread::spawn(move || {
+            for (path, manifest) in rx {
+                if let Err(e) = job.upload(&path, &manifest) {
+                    warn!("Failed to upload {}: {}", path.display(), e);
+                    let _ = job.failures.send(format!("{}: {}", path.display(), e));
+                }
+            }
+        });
+        Some(Self {
+            queue: Some(queue),
+            failures,
+            thread: Some(thread),
+        })
+    }
+
+    /// Queue a subrun file the writer has closed, to be listed in
+    /// `manifest` once uploaded.
+    pub fn file_closed(&self, path: &Path, manifest: &Path) {
+        if let Some(queue) = &self.queue {
+            let _ = queue.send((path.to_path_buf(), manifest.to_path_buf()));
+        }
+    }
+
+    /// Uploads that failed since the last call.
+    pub fn failures(&self) -> Vec<String> {
+        self.failures.try_iter().collect()
+    }
+
+    /// Close the queue and wait for the files still in it to be uploaded,
+    /// logging any that failed.
+    fn finish(&mut self) {
+        let Some(queue) = self.queue.take() else {
+            return;
+        };
+        if !queue.is_empty() {
+            info!("Waiting for {} queued uploads to finish", queue.len());
+        }
+        drop(queue);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                warn!("The upload thread panicked, queued files may not be uploaded");
+            }
+        }
+        for failure in self.failures() {
+            warn!("Not uploaded: {}", failure);
+        }
+    }
+}
+
+impl Drop for Uploader {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
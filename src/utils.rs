@@ -1,8 +1,14 @@
 use crate::{
-    ChannelConfig, Conf, DCOffsetConfig, EventWrapper, FELibReturn, ITLConnect, SamplesOverThr,
-    TriggerEdge, TriggerThr, TriggerThrMode,
+    ChannelConfig, Conf, DCOffsetConfig, EndpointRecord, EventWrapper, FELibReturn, ITLConnect,
+    SamplesOverThr, TriggerEdge, TriggerThr, TriggerThrMode,
+};
+use ndarray::Array2;
+use std::{
+    collections::VecDeque,
+    ffi::CString,
+    path::Path,
+    time::{Duration, Instant},
 };
-use std::{collections::VecDeque, time::Instant};
 
 /// Structure representing an event coming from a board.
 #[derive(Debug, Clone)]
@@ -11,8 +17,29 @@ pub struct BoardEvent {
     pub board_id: usize,
     pub event: EventWrapper,
     pub zero_suppressed: bool,
+    /// First sample of each channel's ROI window, in ROI mode
+    pub roi_offsets: Option<Vec<usize>>,
+    /// Whether the event keeps full resolution in multi-resolution mode
+    pub full_res: bool,
+    /// Channels with more than one pulse in the record
+    pub pile_up: Vec<bool>,
+    /// Baseline of each channel from the start of the record, if computed
+    pub baselines: Option<Vec<f32>>,
+    /// Index of the window within its trigger in multi-window mode, 0
+    /// otherwise
+    pub window: u32,
+    /// Waveforms before zero suppression, kept for events picked by the
+    /// zero suppression audit
+    pub unsuppressed: Option<Array2<u16>>,
+    /// A record of one of the board's extra endpoints instead of an event
+    pub endpoint_record: Option<EndpointRecord>,
 }
 
+/// Shortest time between two samples of the event rate history
+const RATE_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+/// Samples the event rate history keeps unless told otherwise, 10 minutes
+const DEFAULT_RATE_HISTORY_LEN: usize = 600;
+
 /// A helper structure to track statistics, with both
 /// *all-time* counters and a *sliding 1 s window* rate.
 #[derive(Debug)]
@@ -23,6 +50,13 @@ pub struct Counter {
     pub n_events: usize,
     /// Time when this counter was created or last reset
     pub t_begin: Instant,
+    /// Event rate in Hz of each second sampled, oldest first. It carries
+    /// over resets, so drifts across short runs show too
+    pub rate_history: VecDeque<u64>,
+    /// Samples `rate_history` keeps
+    history_len: usize,
+    /// When and at which event count the rate was last sampled
+    last_sample: Option<(Instant, usize)>,
 }
 
 impl Default for Counter {
@@ -31,6 +65,9 @@ impl Default for Counter {
             total_size: 0,
             n_events: 0,
             t_begin: Instant::now(),
+            rate_history: VecDeque::new(),
+            history_len: DEFAULT_RATE_HISTORY_LEN,
+            last_sample: None,
         }
     }
 }
@@ -41,15 +78,44 @@ impl Counter {
         Default::default()
     }
 
+    /// A counter whose rate history covers the last `seconds`.
+    pub fn with_rate_history(seconds: usize) -> Self {
+        Counter {
+            history_len: seconds,
+            ..Default::default()
+        }
+    }
+
     /// Copy constructor
     pub fn from(other: &Self) -> Self {
         Counter {
             total_size: other.total_size,
             n_events: other.n_events,
             t_begin: other.t_begin,
+            rate_history: other.rate_history.clone(),
+            history_len: other.history_len,
+            last_sample: other.last_sample,
         }
     }
 
+    /// Add the event rate since the last sample to the rate history, at
+    /// most once a second.
+    pub fn sample_rate(&mut self) {
+        let now = Instant::now();
+        if let Some((at, events)) = self.last_sample {
+            let elapsed = now - at;
+            if elapsed < RATE_SAMPLE_INTERVAL {
+                return;
+            }
+            let rate = self.n_events.saturating_sub(events) as f64 / elapsed.as_secs_f64();
+            self.rate_history.push_back(rate.round() as u64);
+            while self.rate_history.len() > self.history_len {
+                self.rate_history.pop_front();
+            }
+        }
+        self.last_sample = Some((now, self.n_events));
+    }
+
     /// Long-term average rate since t_begin, in MB/s
     pub fn average_rate(&self) -> f64 {
         let secs = self.t_begin.elapsed().as_secs_f64();
@@ -63,81 +129,232 @@ impl Counter {
         self.n_events += 1;
     }
 
-    /// Reset both all-time counters and the sliding window.
+    /// Reset both all-time counters and the sliding window. The rate
+    /// history is kept and sampled afresh from the new run on.
     pub fn reset(&mut self) {
         let now = Instant::now();
         self.total_size = 0;
         self.n_events = 0;
         self.t_begin = now;
+        self.last_sample = None;
+    }
+}
+
+/// Expected time between events the read timeout covers
+const READ_TIMEOUT_INTERVALS: f64 = 4.0;
+/// Weight of the newest interval in the mean time between events
+const READ_INTERVAL_WEIGHT: f64 = 0.1;
+
+/// `ReadData` timeout following a board's trigger rate: a few mean event
+/// intervals at high rates, growing after every timeout at low rates, and
+/// always within `[min, max]`.
+#[derive(Debug, Clone)]
+pub struct ReadTimeout {
+    min: Duration,
+    max: Duration,
+    current: Duration,
+    /// Smoothed time between events in seconds
+    mean_interval: Option<f64>,
+    last_event: Option<Instant>,
+}
+
+impl ReadTimeout {
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self {
+            min,
+            max,
+            current: Duration::from_millis(100).clamp(min, max),
+            mean_interval: None,
+            last_event: None,
+        }
+    }
+
+    /// Current timeout
+    pub fn current(&self) -> Duration {
+        self.current
+    }
+
+    /// An event was read.
+    pub fn event(&mut self) {
+        let now = Instant::now();
+        if let Some(last) = self.last_event {
+            let interval = (now - last).as_secs_f64();
+            let mean = self.mean_interval.map_or(interval, |mean| {
+                mean + READ_INTERVAL_WEIGHT * (interval - mean)
+            });
+            self.mean_interval = Some(mean);
+            self.current =
+                Duration::from_secs_f64(mean * READ_TIMEOUT_INTERVALS).clamp(self.min, self.max);
+        }
+        self.last_event = Some(now);
+    }
+
+    /// A read timed out without an event.
+    pub fn timed_out(&mut self) {
+        self.current = (self.current * 2).min(self.max);
+    }
+}
+
+/// Per-channel average of a board's waveforms over a run. Records all start
+/// the same number of samples before the trigger, so they average aligned on
+/// the trigger.
+#[derive(Debug, Clone)]
+pub struct WaveformAverage {
+    sum: Array2<f64>,
+    count: usize,
+}
+
+impl WaveformAverage {
+    pub fn new(n_channels: usize, n_samples: usize) -> Self {
+        WaveformAverage {
+            sum: Array2::zeros((n_channels, n_samples)),
+            count: 0,
+        }
+    }
+
+    /// Add an event's waveforms, one row per channel.
+    pub fn add(&mut self, waveforms: &Array2<u16>) {
+        if waveforms.dim() != self.sum.dim() {
+            return;
+        }
+        self.sum.zip_mut_with(waveforms, |sum, &x| *sum += x as f64);
+        self.count += 1;
     }
+
+    /// Number of events averaged
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn average(&self) -> Array2<f64> {
+        &self.sum / self.count.max(1) as f64
+    }
+}
+
+/// Available and total bytes on the filesystem holding `path`.
+pub fn disk_space(path: &Path) -> Option<(u64, u64)> {
+    if let Some(space) = crate::simulated_disk_space() {
+        return Some(space);
+    }
+    let c_path = CString::new(path.to_str()?).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    let res = unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) };
+    if res != 0 {
+        return None;
+    }
+    let avail = stat.f_bavail as u64 * stat.f_frsize as u64;
+    let total = stat.f_blocks as u64 * stat.f_frsize as u64;
+    Some((avail, total))
+}
+
+/// Read the number of channels the board reports via `/par/NumCh`.
+pub fn get_num_channels(handle: u64) -> Result<usize, FELibReturn> {
+    crate::felib::get_value(handle, "/par/NumCh")?
+        .trim()
+        .parse()
+        .map_err(|_| FELibReturn::Generic)
 }
 
-pub fn configure_board(board_id: usize, handle: u64, config: &Conf) -> Result<(), FELibReturn> {
+/// Whether the board's firmware knows the parameter at `path`. Read errors
+/// other than an unknown parameter count as supported.
+pub fn parameter_supported(handle: u64, path: &str) -> bool {
+    !matches!(
+        crate::felib::get_value(handle, path),
+        Err(FELibReturn::InvalidParam | FELibReturn::NotImplemented)
+    )
+}
+
+/// Build the parameter path addressing every channel of a board with `num_ch` channels.
+fn all_channels(num_ch: usize, param: &str) -> String {
+    format!("/ch/0..{}/par/{}", num_ch - 1, param)
+}
+
+pub fn configure_board(
+    board_id: usize,
+    handle: u64,
+    num_ch: usize,
+    config: &Conf,
+) -> Result<(), FELibReturn> {
+    // Every channel range below needs at least one channel
+    if num_ch == 0 {
+        log::warn!("Board {} reports no channels, not configuring it", board_id);
+        return Err(FELibReturn::InvalidParam);
+    }
     match config.board_settings.boards[board_id].en_chans {
         ChannelConfig::All(_) => {
-            crate::felib_setvalue(handle, "/ch/0..63/par/ChEnable", "true")?;
+            crate::felib::set_value(handle, &all_channels(num_ch, "ChEnable"), "true")?;
         }
         ChannelConfig::List(ref channels) => {
+            // Channels are enabled after a reset, the board must only read
+            // out those in its channel map
+            crate::felib::set_value(handle, &all_channels(num_ch, "ChEnable"), "false")?;
             for channel in channels {
                 let path = format!("/ch/{}/par/ChEnable", channel);
-                crate::felib_setvalue(handle, &path, "true")?;
+                crate::felib::set_value(handle, &path, "true")?;
             }
         }
     }
     match config.board_settings.boards[board_id].dc_offset {
         DCOffsetConfig::Global(offset) => {
-            crate::felib_setvalue(handle, "/ch/0..63/par/DCOffset", &offset.to_string())?;
+            crate::felib::set_value(
+                handle,
+                &all_channels(num_ch, "DCOffset"),
+                &offset.to_string(),
+            )?;
         }
         DCOffsetConfig::PerChannel(ref map) => {
             for (chan, offset) in map {
                 let path = format!("/ch/{}/par/DCOffset", chan);
 
-                crate::felib_setvalue(handle, &path, &offset.to_string())?;
+                crate::felib::set_value(handle, &path, &offset.to_string())?;
             }
         }
     }
-    crate::felib_setvalue(
+    crate::felib::set_value(
         handle,
         "/par/RecordLengthS",
         &config.board_settings.common.record_len.to_string(),
     )?;
-    crate::felib_setvalue(
+    crate::felib::set_value(
         handle,
         "/par/PreTriggerS",
         &config.board_settings.common.pre_trig_len.to_string(),
     )?;
-    crate::felib_setvalue(
+    if config.board_settings.common.multi_window {
+        crate::felib::set_value(handle, "/par/EnMultiWindowRun", "True")?;
+    }
+    crate::felib::set_value(
         handle,
         "/par/AcqTriggerSource",
         &config.board_settings.boards[board_id].trig_source,
     )?;
-    crate::felib_setvalue(
+    crate::felib::set_value(
         handle,
         "/par/IOlevel",
         &config.board_settings.boards[board_id].io_level,
     )?;
-    crate::felib_setvalue(
+    crate::felib::set_value(
         handle,
         "/par/TestPulsePeriod",
         &config.board_settings.boards[board_id]
             .test_pulse_period
             .to_string(),
     )?;
-    crate::felib_setvalue(
+    crate::felib::set_value(
         handle,
         "/par/TestPulseWidth",
         &config.board_settings.boards[board_id]
             .test_pulse_width
             .to_string(),
     )?;
-    crate::felib_setvalue(
+    crate::felib::set_value(
         handle,
         "/par/TestPulseLowLevel",
         &config.board_settings.boards[board_id]
             .test_pulse_low
             .to_string(),
     )?;
-    crate::felib_setvalue(
+    crate::felib::set_value(
         handle,
         "/par/TestPulseHighLevel",
         &config.board_settings.boards[board_id]
@@ -146,41 +363,45 @@ pub fn configure_board(board_id: usize, handle: u64, config: &Conf) -> Result<()
     )?;
     match config.board_settings.boards[board_id].trig_thr {
         TriggerThr::Global(thr) => {
-            crate::felib_setvalue(handle, "/ch/0..63/par/TriggerThr", &thr.to_string())?;
+            crate::felib::set_value(
+                handle,
+                &all_channels(num_ch, "TriggerThr"),
+                &thr.to_string(),
+            )?;
         }
         TriggerThr::PerChannel(ref map) => {
             for (chan, thr) in map {
                 let path = format!("/ch/{}/par/TriggerThr", chan);
 
-                crate::felib_setvalue(handle, &path, &thr.to_string())?;
+                crate::felib::set_value(handle, &path, &thr.to_string())?;
             }
         }
     }
     match config.board_settings.boards[board_id].trig_thr_mode {
         TriggerThrMode::Global(ref mode) => {
-            crate::felib_setvalue(handle, "/ch/0..63/par/TriggerThrMode", mode)?;
+            crate::felib::set_value(handle, &all_channels(num_ch, "TriggerThrMode"), mode)?;
         }
         TriggerThrMode::PerChannel(ref map) => {
             for (chan, mode) in map {
                 let path = format!("/ch/{}/par/TriggerThrMode", chan);
 
-                crate::felib_setvalue(handle, &path, mode)?;
+                crate::felib::set_value(handle, &path, mode)?;
             }
         }
     }
     match config.board_settings.boards[board_id].trig_edge {
         TriggerEdge::Fall => {
-            crate::felib_setvalue(handle, "/ch/0..63/par/SelfTriggerEdge", "Fall")?;
+            crate::felib::set_value(handle, &all_channels(num_ch, "SelfTriggerEdge"), "Fall")?;
         }
         TriggerEdge::Rise => {
-            crate::felib_setvalue(handle, "/ch/0..63/par/SelfTriggerEdge", "Rise")?;
+            crate::felib::set_value(handle, &all_channels(num_ch, "SelfTriggerEdge"), "Rise")?;
         }
     }
     match config.board_settings.boards[board_id].samples_over_thr {
         SamplesOverThr::Global(samples) => {
-            crate::felib_setvalue(
+            crate::felib::set_value(
                 handle,
-                "/ch/0..63/par/SamplesOverThreshold",
+                &all_channels(num_ch, "SamplesOverThreshold"),
                 &samples.to_string(),
             )?;
         }
@@ -188,53 +409,66 @@ pub fn configure_board(board_id: usize, handle: u64, config: &Conf) -> Result<()
             for (chan, samples) in map {
                 let path = format!("/ch/{}/par/SamplesOverThreshold", chan);
 
-                crate::felib_setvalue(handle, &path, &samples.to_string())?;
+                crate::felib::set_value(handle, &path, &samples.to_string())?;
             }
         }
     }
-    crate::felib_setvalue(
+    if let Some((threshold, samples)) = config.zs_settings.board_suppression() {
+        crate::felib::set_value(handle, "/par/EnChSuppr", "True")?;
+        crate::felib::set_value(
+            handle,
+            &all_channels(num_ch, "ChSupprThr"),
+            &threshold.to_string(),
+        )?;
+        crate::felib::set_value(
+            handle,
+            &all_channels(num_ch, "ChSupprSamplesOverThreshold"),
+            &samples.to_string(),
+        )?;
+    }
+    crate::felib::set_value(
         handle,
         "/par/ITLAMainLogic",
         &config.board_settings.boards[board_id].itl_logic,
     )?;
-    crate::felib_setvalue(
+    crate::felib::set_value(
         handle,
         "/par/ITLAMajorityLev",
         &config.board_settings.boards[board_id]
             .itl_majority_level
             .to_string(),
     )?;
-    crate::felib_setvalue(
+    crate::felib::set_value(
         handle,
         "/par/ITLAPairLogic",
         &config.board_settings.boards[board_id].itl_pair_logic,
     )?;
-    crate::felib_setvalue(
+    crate::felib::set_value(
         handle,
         "/par/ITLAPolarity",
         &config.board_settings.boards[board_id].itl_polarity,
     )?;
-    crate::felib_setvalue(
+    crate::felib::set_value(
         handle,
         "/par/ITLAGateWidth",
         &config.board_settings.boards[board_id]
             .itl_gatewidth
             .to_string(),
     )?;
-    crate::felib_setvalue(
+    crate::felib::set_value(
         handle,
         "/par/ITLAEnRetrigger",
         &config.board_settings.boards[board_id].itl_retrig,
     )?;
     match config.board_settings.boards[board_id].itl_connect {
         ITLConnect::Global(ref connect) => {
-            crate::felib_setvalue(handle, "/ch/0..63/par/ITLConnect", connect)?;
+            crate::felib::set_value(handle, &all_channels(num_ch, "ITLConnect"), connect)?;
         }
         ITLConnect::PerChannel(ref map) => {
             for (chan, connect) in map {
                 let path = format!("/ch/{}/par/ITLConnect", chan);
 
-                crate::felib_setvalue(handle, &path, connect)?;
+                crate::felib::set_value(handle, &path, connect)?;
             }
         }
     }
@@ -248,32 +482,32 @@ pub fn configure_sync(
     num_boards: usize,
     config: &Conf,
 ) -> Result<(), FELibReturn> {
-    crate::felib_setvalue(
+    crate::felib::set_value(
         handle,
         "/par/ClockSource",
         &config.sync_settings.boards[board_id].clock_src,
     )?;
-    crate::felib_setvalue(
+    crate::felib::set_value(
         handle,
         "/par/SyncOutMode",
         &config.sync_settings.boards[board_id].sync_out,
     )?;
-    crate::felib_setvalue(
+    crate::felib::set_value(
         handle,
         "/par/StartSource",
         &config.sync_settings.boards[board_id].start_source,
     )?;
-    crate::felib_setvalue(
+    crate::felib::set_value(
         handle,
         "/par/EnClockOutFP",
         &config.sync_settings.boards[board_id].clock_out_fp,
     )?;
-    crate::felib_setvalue(
+    crate::felib::set_value(
         handle,
         "/par/EnAutoDisarmAcq",
         &config.sync_settings.boards[board_id].auto_disarm,
     )?;
-    crate::felib_setvalue(
+    crate::felib::set_value(
         handle,
         "/par/TrgOutMode",
         &config.sync_settings.boards[board_id].trig_out,
@@ -281,8 +515,8 @@ pub fn configure_sync(
 
     let run_delay = get_run_delay(board_id, num_boards);
     let clock_out_delay = get_clock_out_delay(board_id, num_boards);
-    crate::felib_setvalue(handle, "/par/RunDelay", &run_delay.to_string())?;
-    crate::felib_setvalue(
+    crate::felib::set_value(handle, "/par/RunDelay", &run_delay.to_string())?;
+    crate::felib::set_value(
         handle,
         "/par/VolatileClockOutDelay",
         &clock_out_delay.to_string(),
@@ -304,7 +538,7 @@ fn get_clock_out_delay(board_id: usize, num_boards: usize) -> isize {
     }
 }
 
-fn get_run_delay(board_id: usize, num_boards: usize) -> usize {
+pub(crate) fn get_run_delay(board_id: usize, num_boards: usize) -> usize {
     let first_board = board_id == 0;
     let board_id_from_last = num_boards - board_id - 1;
 
@@ -317,20 +551,82 @@ fn get_run_delay(board_id: usize, num_boards: usize) -> usize {
     run_delay_clk * 8
 }
 
+/// Timestamp offset in ns of each board to board 0, the median difference
+/// over built events with the same trigger. Boards without coincident events
+/// get 0.
+pub fn timestamp_offsets(built: &[Vec<BoardEvent>], num_boards: usize) -> Vec<i64> {
+    let mut diffs = vec![Vec::new(); num_boards];
+    for events in built {
+        let Some(reference) = events.iter().find(|e| e.board_id == 0) else {
+            continue;
+        };
+        let t0 = reference.event.c_event.timestamp as i64;
+        for event in events {
+            diffs[event.board_id].push(event.event.c_event.timestamp as i64 - t0);
+        }
+    }
+    diffs
+        .into_iter()
+        .map(|mut d| {
+            if d.is_empty() {
+                return 0;
+            }
+            d.sort_unstable();
+            d[d.len() / 2]
+        })
+        .collect()
+}
+
+/// Drops events from the queue fronts until the fronts lie within each
+/// board's tolerance in `tolerances` (ns, by board ID) of the earliest one,
+/// or a queue becomes empty. An earliest event another queue's front is
+/// already past can't find a partner there any more, so it's dropped and
+/// counted in `misaligned_count`.
+pub fn align_queues_by_time(
+    queues: &mut [&mut VecDeque<BoardEvent>],
+    tolerances: &[u64],
+    misaligned_count: &mut usize,
+) {
+    while let Some(fronts) = queues
+        .iter()
+        .map(|q| q.front().map(|e| (e.event.c_event.timestamp, e.board_id)))
+        .collect::<Option<Vec<_>>>()
+    {
+        let Some((first, &(earliest, _))) = fronts
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, &(timestamp, _))| timestamp)
+        else {
+            break;
+        };
+        let coincident = fronts
+            .iter()
+            .all(|&(timestamp, board)| timestamp - earliest <= tolerances[board]);
+        if coincident {
+            break;
+        }
+        queues[first].pop_front();
+        *misaligned_count += 1;
+    }
+}
+
 /// Repeatedly drops “stale” events from each queue until all
-/// non‑empty queue fronts share the same trigger ID (or until
+/// non‑empty queue fronts share the same trigger ID and window (or until
 /// one queue becomes empty), counting each drop in `misaligned_count`.
-pub fn align_queues(queues: &mut [VecDeque<BoardEvent>], misaligned_count: &mut usize) {
+pub fn align_queues(queues: &mut [&mut VecDeque<BoardEvent>], misaligned_count: &mut usize) {
     loop {
         // If any queue is empty, we can’t fully align
         if queues.iter().any(|q| q.front().is_none()) {
             break;
         }
 
-        // Gather all front trigger IDs
+        // Gather all front trigger IDs and windows
         let ids = queues
             .iter()
-            .map(|q| q.front().unwrap().event.c_event.trigger_id)
+            .map(|q| {
+                let e = q.front().unwrap();
+                (e.event.c_event.trigger_id, e.window)
+            })
             .collect::<Vec<_>>();
 
         // If they’re already all equal, we’re done
@@ -342,7 +638,7 @@ pub fn align_queues(queues: &mut [VecDeque<BoardEvent>], misaligned_count: &mut
         let max_id = *ids.iter().max().unwrap();
         for q in queues.iter_mut() {
             while let Some(e) = q.front() {
-                let tid = e.event.c_event.trigger_id;
+                let tid = (e.event.c_event.trigger_id, e.window);
                 if tid < max_id {
                     q.pop_front();
                     *misaligned_count += 1;
@@ -1,140 +1,1008 @@
+use crate::{
+    BoardRunStats, Calibration, CampaignSettings, Compressor, DppPsdHit, EndpointRecord,
+    EndpointStats, EndpointType, ExistingFiles, FloatType, RawBlock, RoiSettings, RunSettings,
+    RunType, WaveformAverage, ZsMode,
+};
 use anyhow::{anyhow, Result};
-use hdf5::{filters::blosc_set_nthreads, Dataset, File, Group};
-use ndarray::{s, Array2, Array3};
-use std::path::PathBuf;
+use hdf5::{
+    filters::blosc_set_nthreads, types::VarLenUnicode, Dataset, DatasetBuilderEmptyShape, File,
+    Group, H5Type, Location,
+};
+use ndarray::{s, Array1, Array2, Array3, ArrayViewMut2, Axis};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Shortest time between two checks of a file's age and size against the
+/// rollover policy
+const ROLLOVER_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// `run_status` of a file still being written
+pub const RUN_STATUS_RUNNING: &str = "running";
+/// `run_status` of a file that was completely written and flushed
+pub const RUN_STATUS_GOOD: &str = "good";
+/// `run_status` of a file whose run was aborted without draining buffered events
+pub const RUN_STATUS_ABORTED: &str = "aborted";
+
+/// Units and description of the datasets of a board group, attached to
+/// each as the `units` and `description` attributes so the files explain
+/// themselves. Datasets without a unit get an empty `units`.
+const DATASET_METADATA: &[(&str, &str, &str)] = &[
+    ("timestamps", "ns", "Trigger time of each event (or hit)"),
+    (
+        "timestamps_corrected",
+        "ns",
+        "Trigger time minus the board's timestamp_offset to board 0",
+    ),
+    (
+        "timestamps_us",
+        "us",
+        "Trigger time including the fine timestamp",
+    ),
+    (
+        "event_header",
+        "",
+        "Per-event header: timestamp (ns), trigger_id, flags, board_fail, event_size (bytes)",
+    ),
+    (
+        "waveforms",
+        "ADC counts",
+        "Waveform of each event, one row per channel_map entry",
+    ),
+    (
+        "waveforms_decimated",
+        "ADC counts",
+        "Decimated waveform of each event, one row per channel_map entry",
+    ),
+    (
+        "waveforms_full",
+        "ADC counts",
+        "Full resolution waveforms of the events passing the multi-resolution filter",
+    ),
+    (
+        "waveforms_full_events",
+        "",
+        "Row in the event datasets of each waveforms_full entry",
+    ),
+    (
+        "waveforms_unsuppressed",
+        "ADC counts",
+        "Waveforms of the zero suppression audit's events before suppression",
+    ),
+    (
+        "waveforms_unsuppressed_events",
+        "",
+        "Row in the event datasets of each waveforms_unsuppressed entry",
+    ),
+    (
+        "waveforms_volts",
+        "V",
+        "Calibrated waveform of each event in volts, one row per channel_map entry",
+    ),
+    (
+        "waveform_crc32",
+        "",
+        "CRC32 (IEEE) of each event's stored waveforms as little-endian u16",
+    ),
+    ("channel_map", "", "Board channel of each waveform row"),
+    (
+        "calibration",
+        "",
+        "Gain (column 0) and offset in ADC counts (column 1) of each waveform row",
+    ),
+    (
+        "waveforms_sparse",
+        "ADC counts",
+        "Waveform rows of the channels zero suppression didn't empty, event after event",
+    ),
+    (
+        "waveforms_sparse_index",
+        "",
+        "Row in waveforms_sparse where each event's waveforms start",
+    ),
+    (
+        "suppressed_channels",
+        "",
+        "Bit i set where zero suppression emptied waveform row i of the event",
+    ),
+    ("triggerids", "", "Trigger ID of each event"),
+    ("flags", "", "Event flags reported by the board"),
+    (
+        "boardfail",
+        "",
+        "Whether the board was in a fail state when the event was read",
+    ),
+    (
+        "zero_suppressed",
+        "",
+        "Whether the event's waveforms were zero suppressed",
+    ),
+    (
+        "pile_up",
+        "",
+        "Whether a channel's record holds more than one pulse",
+    ),
+    (
+        "roi_offsets",
+        "samples",
+        "Record sample each channel's ROI window starts at",
+    ),
+    ("baselines", "ADC counts", "Baseline of each channel"),
+    (
+        "window",
+        "",
+        "Index of the event's window within its trigger",
+    ),
+    ("channel", "", "Board channel of each hit"),
+    ("fine_timestamps", "", "Fine timestamp of each hit"),
+    ("energy", "ADC counts", "Long gate charge of each hit"),
+    (
+        "energy_short",
+        "ADC counts",
+        "Short gate charge of each hit",
+    ),
+    ("flags_low_priority", "", "Low priority flags of each hit"),
+    ("flags_high_priority", "", "High priority flags of each hit"),
+];
+
+/// A numeric parameter from a board's parameter snapshot.
+fn board_param(board_params: &[(String, String)], path: &str) -> Option<f64> {
+    board_params
+        .iter()
+        .find(|(p, _)| p == path)
+        .and_then(|(_, value)| value.parse().ok())
+}
+
+/// Each waveform row's volts per ADC count as the board reports it, `None`
+/// unless the board reported it for every row.
+fn adc_to_volts(board_params: &[(String, String)], channels: &[usize]) -> Option<Array1<f64>> {
+    channels
+        .iter()
+        .map(|ch| board_param(board_params, &format!("/ch/{}/par/ADCToVolts", ch)))
+        .collect()
+}
+
+/// Attach the `DATASET_METADATA` attributes to the datasets of a board
+/// group. Waveform datasets also get `sampling_period_ns`, and
+/// `adc_to_volts` with each row's volts per ADC count, from the board's
+/// parameter snapshot where it has them.
+fn describe_datasets(
+    group: &Group,
+    channels: &[usize],
+    board_params: &[(String, String)],
+    decimation: Option<usize>,
+) -> Result<()> {
+    // ADC_SamplRate is in MS/s
+    let sampling_period = board_param(board_params, "/par/ADC_SamplRate")
+        .filter(|&rate| rate > 0.0)
+        .map(|rate| 1000.0 / rate);
+    let adc_to_volts = adc_to_volts(board_params, channels);
+
+    let names = group.member_names()?;
+    for &(name, units, description) in DATASET_METADATA {
+        if !names.iter().any(|n| n == name) {
+            continue;
+        }
+        let dataset = group.dataset(name)?;
+        write_str_attr(&dataset, "units", units)?;
+        write_str_attr(&dataset, "description", description)?;
+        if !matches!(units, "ADC counts" | "V") || !name.starts_with("waveforms") {
+            continue;
+        }
+        let factor = match name {
+            "waveforms_full" => 1,
+            _ => decimation.unwrap_or(1),
+        };
+        if let Some(period) = sampling_period {
+            write_scalar_attr(&dataset, "sampling_period_ns", &(period * factor as f64))?;
+        }
+        if let Some(adc_to_volts) = adc_to_volts.as_ref().filter(|a| !a.is_empty()) {
+            dataset
+                .new_attr::<f64>()
+                .shape(adc_to_volts.len())
+                .create("adc_to_volts")?
+                .write(adc_to_volts)?;
+        }
+    }
+    Ok(())
+}
+
+/// Volts per ADC count and offset in volts of each waveform row of a board:
+/// the board's `ADCToVolts`, times the calibration's gain and offset unless
+/// the waveforms are calibrated already.
+fn volt_conversion(
+    board: usize,
+    channels: &[usize],
+    board_params: &[(String, String)],
+    layout: &StorageLayout,
+) -> Result<Vec<(f64, f64)>> {
+    let adc_to_volts = adc_to_volts(board_params, channels)
+        .ok_or_else(|| anyhow!("board {} reported no ADCToVolts for its channels", board))?;
+    Ok(channels
+        .iter()
+        .zip(&adc_to_volts)
+        .map(|(&ch, &volts)| match &layout.calibration {
+            Some(calibration) if !layout.calibration_applied => {
+                let (gain, offset) = calibration.constants(board, ch);
+                (volts * gain, volts * offset)
+            }
+            _ => (volts, 0.0),
+        })
+        .collect())
+}
+
+/// Create or overwrite a scalar string attribute.
+pub fn write_str_attr(loc: &Location, name: &str, value: &str) -> Result<()> {
+    let value: VarLenUnicode = value.parse().map_err(|e| anyhow!("{:?}", e))?;
+    let attr = if loc.attr_names()?.iter().any(|n| n == name) {
+        loc.attr(name)?
+    } else {
+        loc.new_attr::<VarLenUnicode>().shape(()).create(name)?
+    };
+    attr.write_scalar(&value)?;
+    Ok(())
+}
+
+/// Record the operator's comment in the `comment` attribute of a closed run
+/// file.
+pub fn write_file_comment(path: &Path, comment: &str) -> Result<()> {
+    let file = File::open_rw(path)?;
+    write_str_attr(&file, "comment", comment)
+}
+
+/// Create or overwrite a scalar numeric attribute.
+pub(crate) fn write_scalar_attr<T: H5Type>(loc: &Location, name: &str, value: &T) -> Result<()> {
+    let attr = if loc.attr_names()?.iter().any(|n| n == name) {
+        loc.attr(name)?
+    } else {
+        loc.new_attr::<T>().shape(()).create(name)?
+    };
+    attr.write_scalar(value)?;
+    Ok(())
+}
+
+/// Record the boards' end-of-run counters as attributes of the board groups
+/// of a closed run file, `stats` in the order of the groups.
+pub fn write_board_run_stats(path: &Path, stats: &[BoardRunStats]) -> Result<()> {
+    let file = File::open_rw(path)?;
+    for (i, board) in stats.iter().enumerate() {
+        let Ok(group) = file.group(&format!("board{}", i)) else {
+            continue;
+        };
+        for (name, value) in board.counters() {
+            write_scalar_attr(&group, name, &value)?;
+        }
+        if let Some(fraction) = board.dead_time_fraction {
+            write_scalar_attr(&group, "dead_time_fraction", &fraction)?;
+        }
+        if let (Some(min), Some(max)) = (board.temp_min, board.temp_max) {
+            write_scalar_attr(&group, "temp_min", &min)?;
+            write_scalar_attr(&group, "temp_max", &max)?;
+        }
+    }
+    Ok(())
+}
+
+/// Create a data file, only replacing an existing one if `existing_files`
+/// allows it.
+fn create_file(path: &Path, existing_files: ExistingFiles) -> Result<File> {
+    match existing_files {
+        ExistingFiles::Overwrite => Ok(File::create(path)?),
+        ExistingFiles::Error | ExistingFiles::Increment => File::create_excl(path).map_err(|e| {
+            if path.exists() {
+                anyhow!("{} already exists, not overwriting it", path.display())
+            } else {
+                e.into()
+            }
+        }),
+    }
+}
+
+/// The `record_len` a data file was written with, `None` for files from
+/// before it was recorded.
+pub fn read_record_len(path: &Path) -> Result<Option<usize>> {
+    let file = File::open(path)?;
+    if !file.attr_names()?.iter().any(|name| name == "record_len") {
+        return Ok(None);
+    }
+    Ok(Some(file.attr("record_len")?.read_scalar()?))
+}
+
+/// Name of a run's subrun file, e.g. `run000012_01.h5`.
+pub fn subrun_file_name(run: usize, subrun: usize) -> String {
+    format!("run{:0>6}_{:0>2}.h5", run, subrun)
+}
+
+/// Read a scalar string attribute.
+pub fn read_str_attr(loc: &Location, name: &str) -> Result<String> {
+    let value: VarLenUnicode = loc.attr(name)?.read_scalar()?;
+    Ok(value.as_str().to_string())
+}
+
+/// How the waveforms of a run are laid out in the files.
+#[derive(Debug, Clone, Default)]
+pub struct StorageLayout {
+    pub run_type: RunType,
+    /// Endpoint the events come from, DPP-PSD hits are stored as hit tables
+    /// instead of waveforms
+    pub endpoint: EndpointType,
+    /// Window saved around each channel's pulse, full records if `None`
+    pub roi: Option<RoiSettings>,
+    /// Decimation factor of the waveforms saved for every event in
+    /// multi-resolution mode, where full resolution is kept only for
+    /// selected events
+    pub decimation: Option<usize>,
+    /// Store the first differences of the waveforms, see `delta_decode`
+    pub delta_encoding: bool,
+    /// Save a CRC32 of each event's stored waveforms in `waveform_crc32`
+    pub waveform_checksums: bool,
+    /// Per-channel gain and offset corrections of the run
+    pub calibration: Option<Calibration>,
+    /// Whether the saved waveforms and averages are corrected, otherwise
+    /// the constants are only stored
+    pub calibration_applied: bool,
+    /// Save `timestamps_corrected` with each board's offset to board 0
+    /// subtracted
+    pub correct_timestamps: bool,
+    /// Measured timestamp offset of each board to board 0, in ns
+    pub timestamp_offsets: Option<Vec<i64>>,
+    /// Save each event's window index, for multi-window acquisition
+    pub multi_window: bool,
+    /// Save each event's timestamp in µs with its fine part
+    pub fine_timestamp: bool,
+    /// Also save the per-event scalars as one `event_header` table
+    pub event_header: bool,
+    /// Save each channel's baseline, averaged over this many samples at the
+    /// start of the record
+    pub baseline_samples: Option<usize>,
+    /// Level the baseline was moved to in the saved waveforms, if subtracted
+    pub baseline_pedestal: Option<u16>,
+    /// `ChSupprThr` and `ChSupprSamplesOverThreshold` if the boards'
+    /// firmware did the zero suppression instead of cliq
+    pub board_suppression: Option<(u32, u32)>,
+    /// Prescale of the zero suppression audit, which saves every Nth
+    /// suppressed event's waveforms unsuppressed too
+    pub zs_audit: Option<usize>,
+    /// Every parameter of each board as read back at the start of the run,
+    /// as FELib path and value
+    pub board_params: Vec<Vec<(String, String)>>,
+    /// Also store the waveforms in volts as `waveforms_volts`
+    pub volt_waveforms: Option<FloatType>,
+    /// Store the waveforms without the channels zero suppression emptied,
+    /// see `SparseWaveforms`
+    pub sparse_waveforms: bool,
+    /// Samples per channel read from the boards, before ROI cuts or
+    /// decimation. Fixed for a run, so a change always starts a new run
+    pub record_len: usize,
+    /// Whether a file that already exists may be replaced
+    pub existing_files: ExistingFiles,
+    /// Description, detector and tags of the campaign
+    pub campaign: CampaignSettings,
+}
+
+impl StorageLayout {
+    /// Record the layout in file attributes: the ROI window, so the pulse
+    /// start is `roi_offsets + roi_pre_samples`, the decimation factor and
+    /// the `waveform_encoding`, `raw` or `delta`, which calibration file was
+    /// used, the `endpoint` read and where `zero_suppression` happened,
+    /// along with the campaign's description, detector and tags.
+    fn write_attrs(&self, file: &File) -> Result<()> {
+        write_str_attr(file, "run_type", self.run_type.name())?;
+        if let Some(description) = &self.campaign.description {
+            write_str_attr(file, "campaign_description", description)?;
+        }
+        if let Some(detector) = &self.campaign.detector {
+            write_str_attr(file, "detector", detector)?;
+        }
+        if !self.campaign.tags.is_empty() {
+            write_str_attr(file, "tags", &self.campaign.tags.join(","))?;
+        }
+        write_str_attr(file, "endpoint", self.endpoint.name())?;
+        let zs_mode = match self.board_suppression {
+            Some(_) => ZsMode::Board,
+            None => ZsMode::Software,
+        };
+        write_str_attr(file, "zero_suppression", zs_mode.name())?;
+        let encoding = if self.delta_encoding { "delta" } else { "raw" };
+        write_str_attr(file, "waveform_encoding", encoding)?;
+        if self.waveform_checksums {
+            write_str_attr(file, "waveform_checksum", "crc32")?;
+        }
+        if let Some(calibration) = &self.calibration {
+            write_str_attr(file, "calibration_file", &calibration.file)?;
+            write_str_attr(file, "calibration_sha256", &calibration.sha256)?;
+            let applied = if self.calibration_applied {
+                "waveforms"
+            } else {
+                "none"
+            };
+            write_str_attr(file, "calibration_applied", applied)?;
+        }
+        let mut attrs = vec![("record_len", self.record_len)];
+        if let Some(roi) = &self.roi {
+            attrs.push(("roi_pre_samples", roi.pre_samples));
+            attrs.push(("roi_post_samples", roi.post_samples));
+        }
+        if let Some(decimation) = self.decimation {
+            attrs.push(("decimation", decimation));
+        }
+        if let Some(samples) = self.baseline_samples {
+            attrs.push(("baseline_samples", samples));
+        }
+        if let Some(pedestal) = self.baseline_pedestal {
+            attrs.push(("baseline_pedestal", pedestal as usize));
+        }
+        if let Some((threshold, samples)) = self.board_suppression {
+            attrs.push(("board_zs_threshold", threshold as usize));
+            attrs.push(("board_zs_samples", samples as usize));
+        }
+        if let Some(prescale) = self.zs_audit {
+            attrs.push(("zs_audit_prescale", prescale));
+        }
+        for (name, value) in attrs {
+            file.new_attr::<usize>().shape(()).create(name)?;
+            file.attr(name)?.write_scalar(&value)?;
+        }
+        Ok(())
+    }
+}
+
+/// One row of the `event_header` table, a board's per-event scalars packed
+/// together so they can be scanned with a single read.
+#[derive(H5Type, Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct EventHeader {
+    pub timestamp: u64,
+    pub trigger_id: u32,
+    pub flags: u16,
+    pub board_fail: bool,
+    /// Bytes read out for the event
+    pub event_size: u64,
+}
+
+/// One board's event as handed to the writer.
+#[derive(Debug, Clone, Copy)]
+pub struct EventRecord<'a> {
+    pub timestamp: u64,
+    /// Timestamp in µs including the fine timestamp, if read
+    pub timestamp_us: f64,
+    pub waveforms: &'a Array2<u16>,
+    pub trigger_id: u32,
+    pub flags: u16,
+    pub fail: bool,
+    /// Bytes read out for the event
+    pub size: usize,
+    pub zero_suppressed: bool,
+    /// First sample of each channel's window in ROI mode
+    pub roi_offsets: Option<&'a [usize]>,
+    /// Channels with more than one pulse in the record
+    pub pile_up: &'a [bool],
+    /// Baseline of each channel, if saved
+    pub baselines: Option<&'a [f32]>,
+    /// Index of the window within its trigger
+    pub window: u32,
+    /// Keep the full resolution waveforms in multi-resolution mode
+    pub full_res: bool,
+    /// Waveforms before zero suppression, for events picked by the audit
+    pub unsuppressed: Option<&'a Array2<u16>>,
+}
+
+/// Replace each channel's samples after the first with the difference to the
+/// previous sample, wrapping around. Slow signals then become runs of small
+/// numbers that compress much better.
+pub fn delta_encode(mut waveforms: ArrayViewMut2<u16>) {
+    for mut channel in waveforms.axis_iter_mut(Axis(0)) {
+        for i in (1..channel.len()).rev() {
+            channel[i] = channel[i].wrapping_sub(channel[i - 1]);
+        }
+    }
+}
+
+/// Undo `delta_encode`: a running sum along each channel, wrapping around.
+/// Apply to the last axis of waveforms read from a file whose
+/// `waveform_encoding` attribute is `delta`.
+pub fn delta_decode(mut waveforms: ArrayViewMut2<u16>) {
+    for mut channel in waveforms.axis_iter_mut(Axis(0)) {
+        for i in 1..channel.len() {
+            channel[i] = channel[i].wrapping_add(channel[i - 1]);
+        }
+    }
+}
+
+/// Average every `factor` samples of each channel into one sample.
+fn decimate(waveforms: &Array2<u16>, factor: usize) -> Array2<u16> {
+    let (channels, samples) = waveforms.dim();
+    let mut decimated = Array2::zeros((channels, samples.div_ceil(factor)));
+    for (mut row, wf) in decimated
+        .axis_iter_mut(Axis(0))
+        .zip(waveforms.axis_iter(Axis(0)))
+    {
+        for (j, value) in row.iter_mut().enumerate() {
+            let bin = wf.slice(s![j * factor..((j + 1) * factor).min(samples)]);
+            *value = (bin.iter().map(|&x| x as u64).sum::<u64>() / bin.len() as u64) as u16;
+        }
+    }
+    decimated
+}
+
+/// Buffering, chunking and compression of the per-event datasets.
+#[derive(Debug, Clone, Copy)]
+pub struct DatasetParams {
+    /// Events buffered per board before they're written
+    pub buffer_capacity: usize,
+    /// Events per chunk
+    pub chunk_events: usize,
+    /// Channels and samples per waveform chunk, all if unset
+    pub chunk_channels: Option<usize>,
+    pub chunk_samples: Option<usize>,
+    pub compressor: Compressor,
+    pub compression_level: u8,
+}
+
+/// When a subrun file is closed and the next one started, besides when a
+/// board's datasets are full.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RolloverPolicy {
+    /// Time a file is written for
+    pub interval: Option<Duration>,
+    /// Size on disk a file grows to, in bytes
+    pub size: Option<u64>,
+}
+
+impl RolloverPolicy {
+    pub fn new(settings: &RunSettings) -> Self {
+        Self {
+            interval: settings
+                .rollover_minutes
+                .map(|minutes| Duration::from_secs(minutes * 60)),
+            size: settings.rollover_size_mb.map(|mb| mb * 1024 * 1024),
+        }
+    }
+}
+
+impl DatasetParams {
+    pub fn new(settings: &RunSettings) -> Self {
+        Self {
+            buffer_capacity: settings.buffer_capacity,
+            chunk_events: settings.chunk_events.unwrap_or(settings.buffer_capacity),
+            chunk_channels: settings.chunk_channels,
+            chunk_samples: settings.chunk_samples,
+            compressor: settings.compressor,
+            compression_level: settings.compression_level,
+        }
+    }
+
+    /// Chunk shape of a waveform dataset, which can't be larger than the
+    /// dataset itself.
+    pub(crate) fn waveform_chunk(
+        &self,
+        n_channels: usize,
+        n_samples: usize,
+    ) -> (usize, usize, usize) {
+        (
+            self.chunk_events,
+            self.chunk_channels
+                .map_or(n_channels, |c| c.min(n_channels)),
+            self.chunk_samples.map_or(n_samples, |s| s.min(n_samples)),
+        )
+    }
+}
+
+/// Applies the configured compressor to a dataset being built.
+pub(crate) trait Compressed {
+    fn compressed(self, params: &DatasetParams) -> Self;
+}
+
+impl Compressed for DatasetBuilderEmptyShape {
+    fn compressed(self, params: &DatasetParams) -> Self {
+        match params.compressor {
+            Compressor::Zstd => self.blosc_zstd(params.compression_level, true),
+            Compressor::Lz4 => self.blosc_lz4(params.compression_level, true),
+            Compressor::None => self,
+        }
+    }
+}
 
 /// HDF5Writer creates two groups (one per board) and routes events accordingly.
 pub struct HDF5Writer {
     pub file: File,
     pub boards: Vec<BoardData>,
-    n_channels: usize,
+    /// Per-board hits, for the DPP-PSD endpoint where `boards` is empty
+    pub hits: Vec<HitData>,
+    /// Per-board raw endpoint blocks, created with the first block of each
+    /// file
+    raw: Vec<Option<RawData>>,
+    /// Per-board stats endpoint counters, created with the first read of
+    /// each file
+    endpoint_stats: Vec<Option<StatsData>>,
+    /// Channels saved of each board, in the order of the waveform rows
+    channel_maps: Vec<Vec<usize>>,
     n_samples: usize,
     max_events_per_board: usize,
-    buffer_capacity: usize,
+    params: DatasetParams,
     subrun: usize,
-    file_template: String,
-    compression_level: u8,
+    /// Directory and number of the run, the subrun files are named from
+    run_dir: PathBuf,
+    run_num: usize,
     pub saved_events: usize,
+    /// Operator recorded in every file of the run
+    operator: Option<String>,
+    layout: StorageLayout,
+    rollover_policy: RolloverPolicy,
+    /// When the current file was created
+    file_opened: Instant,
+    /// When the current file was last checked against `rollover_policy`
+    rollover_checked: Instant,
 }
 
 impl HDF5Writer {
+    /// In ROI mode `n_samples` is the ROI window length and every event comes
+    /// with the offset of each channel's window. Each board's waveforms hold
+    /// one row per channel of its entry in `channel_maps`.
     pub fn new(
         filename: PathBuf,
-        n_channels: usize,
+        channel_maps: Vec<Vec<usize>>,
         n_samples: usize,
-        n_boards: usize,
         max_events_per_board: usize,
-        buffer_capacity: usize,
         n_threads: u8,
-        compression_level: u8,
+        params: DatasetParams,
+        layout: StorageLayout,
     ) -> Result<Self> {
-        let file_template = filename.to_str().unwrap().replace("_00", "_{}");
-        let file = File::create(filename)?;
+        let run_dir = filename.parent().map(Path::to_path_buf).unwrap_or_default();
+        let run_num = filename
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix("run"))
+            .and_then(|name| name.split('_').next())
+            .and_then(|num| num.parse().ok())
+            .ok_or_else(|| anyhow!("{} isn't a run file name", filename.display()))?;
+        let file = create_file(&filename, layout.existing_files)?;
         // Create a scalar attribute "saved_events" and initialize to 0
         file.new_attr::<usize>().shape(()).create("saved_events")?;
+        write_str_attr(&file, "run_status", RUN_STATUS_RUNNING)?;
+        layout.write_attrs(&file)?;
         blosc_set_nthreads(n_threads);
 
         // Create BoardData for each board.
         let boards = Self::create_boards(
             &file,
-            n_channels,
+            &channel_maps,
             n_samples,
-            n_boards,
             max_events_per_board,
-            buffer_capacity,
-            compression_level,
+            &params,
+            &layout,
+        )?;
+        let hits = Self::create_hits(
+            &file,
+            channel_maps.len(),
+            max_events_per_board,
+            &params,
+            &layout,
         )?;
 
+        let n_boards = channel_maps.len();
         Ok(Self {
             file,
             boards,
-            n_channels,
+            hits,
+            raw: (0..n_boards).map(|_| None).collect(),
+            endpoint_stats: (0..n_boards).map(|_| None).collect(),
+            channel_maps,
             n_samples,
             max_events_per_board,
-            buffer_capacity,
+            params,
             subrun: 0,
-            file_template,
-            compression_level,
+            run_dir,
+            run_num,
             saved_events: 0,
+            operator: None,
+            layout,
+            rollover_policy: RolloverPolicy::default(),
+            file_opened: Instant::now(),
+            rollover_checked: Instant::now(),
         })
     }
 
+    /// Create one group per board, each sized for that board's saved channels
+    /// and with their numbers in `channel_map`.
     fn create_boards(
         file: &File,
-        n_channels: usize,
+        channel_maps: &[Vec<usize>],
         n_samples: usize,
-        n_boards: usize,
         max_events: usize,
-        buffer_capacity: usize,
-        compression_level: u8,
+        params: &DatasetParams,
+        layout: &StorageLayout,
     ) -> Result<Vec<BoardData>> {
-        let groups: Vec<Group> = (0..n_boards)
+        let groups: Vec<Group> = (0..channel_maps.len())
             .map(|board| file.create_group(&format!("board{}", board)))
             .collect::<Result<_, _>>()?;
-        let boards: Vec<BoardData> = groups
+        for (group, params) in groups.iter().zip(&layout.board_params) {
+            let params_group = group.create_group("board_params")?;
+            for (path, value) in params {
+                write_str_attr(&params_group, path, value)?;
+            }
+        }
+        if !layout.endpoint.has_waveforms() {
+            return Ok(Vec::new());
+        }
+        for (group, channels) in groups.iter().zip(channel_maps) {
+            let channels: Array1<u32> = channels.iter().map(|&ch| ch as u32).collect();
+            group
+                .new_dataset_builder()
+                .with_data(&channels)
+                .create("channel_map")?;
+        }
+        if let Some(calibration) = &layout.calibration {
+            for (board, (group, channels)) in groups.iter().zip(channel_maps).enumerate() {
+                group
+                    .new_dataset_builder()
+                    .with_data(&calibration.board_constants(board, channels))
+                    .create("calibration")?;
+            }
+        }
+        let mut boards: Vec<BoardData> = groups
             .iter()
-            .map(|group| {
-                BoardData::new(
-                    group,
-                    n_channels,
-                    n_samples,
-                    max_events,
-                    buffer_capacity,
-                    compression_level,
-                )
+            .zip(channel_maps)
+            .map(|(group, channels)| {
+                BoardData::new(group, channels.len(), n_samples, max_events, params, layout)
             })
             .collect::<Result<_, _>>()?;
+        if let Some(offsets) = &layout.timestamp_offsets {
+            for ((group, board), &offset) in groups.iter().zip(boards.iter_mut()).zip(offsets) {
+                board.set_timestamp_offset(group, offset)?;
+            }
+        }
+        for (board, (group, channels)) in groups.iter().zip(channel_maps).enumerate() {
+            let board_params = layout
+                .board_params
+                .get(board)
+                .map_or(&[][..], Vec::as_slice);
+            if layout.volt_waveforms.is_some() {
+                boards[board].set_volt_conversion(
+                    group,
+                    volt_conversion(board, channels, board_params, layout)?,
+                )?;
+            }
+            describe_datasets(group, channels, board_params, layout.decimation)?;
+        }
         Ok(boards)
     }
 
+    /// Hit tables of each board's group, only for the DPP-PSD endpoint.
+    fn create_hits(
+        file: &File,
+        n_boards: usize,
+        max_events: usize,
+        params: &DatasetParams,
+        layout: &StorageLayout,
+    ) -> Result<Vec<HitData>> {
+        if layout.endpoint != EndpointType::DppPsd {
+            return Ok(Vec::new());
+        }
+        (0..n_boards)
+            .map(|board| {
+                let group = file.group(&format!("board{}", board))?;
+                let hits = HitData::new(&group, max_events, params)?;
+                describe_datasets(&group, &[], &[], None)?;
+                Ok(hits)
+            })
+            .collect()
+    }
+
+    /// Append a DPP-PSD hit for the specified board, rolling over to the next
+    /// subrun file when the board's hit table is full.
+    pub fn append_hit(&mut self, board: usize, timestamp: u64, hit: &DppPsdHit) -> Result<()> {
+        if self.hits[board].is_full() || self.rollover_due() {
+            self.rollover()?;
+        }
+        let saved = self.hits[board].current_event;
+        self.hits[board].append(timestamp, hit)?;
+        if self.hits[board].current_event != saved {
+            self.update_saved_events()?;
+        }
+        Ok(())
+    }
+
+    /// Append a record of one of a board's extra endpoints to the board's
+    /// `raw` or `stats` group, rolling over to the next subrun file when the
+    /// group is full.
+    pub fn append_endpoint_record(&mut self, board: usize, record: &EndpointRecord) -> Result<()> {
+        let full = match record {
+            EndpointRecord::Raw(_) => self.raw[board].as_ref().is_some_and(RawData::is_full),
+            EndpointRecord::Stats(_) => self.endpoint_stats[board]
+                .as_ref()
+                .is_some_and(StatsData::is_full),
+        };
+        if full {
+            self.rollover()?;
+        }
+        let group = self.file.group(&format!("board{}", board))?;
+        let max = self.max_events_per_board;
+        match record {
+            EndpointRecord::Raw(block) => {
+                if self.raw[board].is_none() {
+                    self.raw[board] =
+                        Some(RawData::new(&group, max, block.data.len(), &self.params)?);
+                }
+                self.raw[board].as_mut().unwrap().append(block)
+            }
+            EndpointRecord::Stats(stats) => {
+                if self.endpoint_stats[board].is_none() {
+                    self.endpoint_stats[board] = Some(StatsData::new(
+                        &group,
+                        max,
+                        stats.real_time.len(),
+                        &self.params,
+                    )?);
+                }
+                self.endpoint_stats[board].as_mut().unwrap().append(stats)
+            }
+        }
+    }
+
     /// Append an event for the specified board (0 or 1) along with its timestamp.
-    pub fn append_event(
-        &mut self,
-        board: usize,
-        timestamp: u64,
-        waveforms: &Array2<u16>,
-        trigger_id: u32,
-        flag: u16,
-        fail: bool,
-        zs_flag: bool,
-    ) -> Result<()> {
-        let result =
-            self.boards[board].append_event(timestamp, waveforms, trigger_id, flag, fail, zs_flag);
+    /// Also rolls over once the current file is as old or as large as the
+    /// rollover policy allows.
+    pub fn append_event(&mut self, board: usize, event: &EventRecord) -> Result<()> {
+        if self.rollover_due() {
+            self.rollover()?;
+        }
+        let saved = self.boards[board].current_event;
+        let result = self.boards[board].append_event(event);
 
         if let Err(e) = result {
             if e.to_string().contains("Maximum number of events reached") {
                 self.rollover()?;
-                return self.boards[board]
-                    .append_event(timestamp, waveforms, trigger_id, flag, fail, zs_flag);
+                return self.boards[board].append_event(event);
             } else {
                 return Err(e);
             }
         }
+        // A full buffer was just written out
+        if self.boards[board].current_event != saved {
+            self.update_saved_events()?;
+        }
 
         Ok(())
     }
 
+    /// Record the events written so far in the `saved_events` attributes of
+    /// the file and of each board group, and flush the file so monitoring
+    /// reading it follows the subrun as it fills.
+    fn update_saved_events(&mut self) -> Result<()> {
+        let mut total = 0;
+        for board in 0..self.channel_maps.len() {
+            let saved = self.boards.get(board).map_or(0, |b| b.current_event)
+                + self.hits.get(board).map_or(0, |h| h.current_event);
+            let group = self.file.group(&format!("board{}", board))?;
+            write_scalar_attr(&group, "saved_events", &saved)?;
+            total += saved;
+        }
+        self.saved_events = total;
+        self.file
+            .attr("saved_events")?
+            .write_scalar(&self.saved_events)?;
+        self.file.flush()?;
+        Ok(())
+    }
+
     /// Flush any remaining buffered events for both boards.
     pub fn flush_all(&mut self) -> Result<()> {
         for board in self.boards.iter_mut() {
             board.flush()?;
         }
-        // Update total saved_events after flushing
-        self.saved_events = self.boards.iter().map(|b| b.current_event).sum();
-        self.file
-            .attr("saved_events")?
-            .write_scalar(&self.saved_events)?;
+        for hits in self.hits.iter_mut() {
+            hits.flush()?;
+        }
+        self.update_saved_events()
+    }
+
+    /// Flush everything and record how the run ended in the `run_status` attribute.
+    pub fn finalize(&mut self, status: &str) -> Result<()> {
+        self.flush_all()?;
+        write_str_attr(&self.file, "run_status", status)
+    }
+
+    /// Store each board's run-average waveform in the current file as
+    /// `average_waveform`, with the number of events averaged in its `events`
+    /// attribute.
+    pub fn write_averages(&mut self, averages: &[WaveformAverage]) -> Result<()> {
+        for (board, average) in averages.iter().enumerate() {
+            let group = self.file.group(&format!("board{}", board))?;
+            let mut values = average.average();
+            if let (Some(calibration), true) =
+                (&self.layout.calibration, self.layout.calibration_applied)
+            {
+                calibration.apply_f64(board, &self.channel_maps[board], &mut values);
+            }
+            let dataset = group
+                .new_dataset_builder()
+                .with_data(&values)
+                .create("average_waveform")?;
+            dataset
+                .new_attr::<usize>()
+                .shape(())
+                .create("events")?
+                .write_scalar(&average.count())?;
+        }
+        Ok(())
+    }
+
+    /// Record each board's measured timestamp offset to board 0 in its
+    /// `timestamp_offset` attribute, in this and every later subrun file.
+    /// Events written from now on get corrected timestamps if enabled.
+    pub fn set_timestamp_offsets(&mut self, offsets: Vec<i64>) -> Result<()> {
+        for (i, (board, &offset)) in self.boards.iter_mut().zip(&offsets).enumerate() {
+            let group = self.file.group(&format!("board{}", i))?;
+            board.set_timestamp_offset(&group, offset)?;
+        }
+        self.layout.timestamp_offsets = Some(offsets);
+        Ok(())
+    }
+
+    /// Channels saved of a board, in the order of its waveform rows.
+    pub fn channel_map(&self, board: usize) -> &[usize] {
+        &self.channel_maps[board]
+    }
+
+    /// Index of the subrun file being written, from 0.
+    pub fn subrun(&self) -> usize {
+        self.subrun
+    }
+
+    /// Also start a new subrun file after `policy`'s time or file size.
+    pub fn set_rollover_policy(&mut self, policy: RolloverPolicy) {
+        self.rollover_policy = policy;
+    }
+
+    /// Path of the subrun file being written.
+    pub fn current_path(&self) -> PathBuf {
+        self.run_dir
+            .join(subrun_file_name(self.run_num, self.subrun))
+    }
+
+    /// Whether the current file has reached the rollover policy's time or
+    /// size. The size on disk lags the buffered events, so a file ends up
+    /// a buffer or so larger than the limit.
+    fn rollover_due(&mut self) -> bool {
+        let policy = self.rollover_policy;
+        if (policy.interval.is_none() && policy.size.is_none())
+            || self.rollover_checked.elapsed() < ROLLOVER_CHECK_INTERVAL
+        {
+            return false;
+        }
+        self.rollover_checked = Instant::now();
+        policy
+            .interval
+            .is_some_and(|interval| self.file_opened.elapsed() >= interval)
+            || policy.size.is_some_and(|size| {
+                fs::metadata(self.current_path()).is_ok_and(|meta| meta.len() >= size)
+            })
+    }
+
+    /// Record the operator in the `operator` attribute of this and every
+    /// later subrun file.
+    pub fn set_operator(&mut self, operator: &str) -> Result<()> {
+        write_str_attr(&self.file, "operator", operator)?;
+        self.operator = Some(operator.to_string());
         Ok(())
     }
 
     /// Rollover the current file:
     pub fn rollover(&mut self) -> Result<()> {
         // Retrieve the buffered events from each board (but do not flush them to disk in the current file).
-        let vals: Vec<(Array2<u64>, Array3<u16>, usize)> = self
+        let vals: Vec<BufferedEvents> = self
             .boards
             .iter_mut()
             .map(|board| board.take_buffer())
             .collect();
+        // Buffered hits always fit in the current file
+        for hits in self.hits.iter_mut() {
+            hits.flush()?;
+        }
+        self.update_saved_events()?;
 
         // Flush any fully accumulated events in the buffers (if needed) so that we start fresh.
         // (You might decide to handle partially full buffers as shown below.)
@@ -143,44 +1011,469 @@ impl HDF5Writer {
         self.subrun += 1;
         // Build new filename using the base name and new subrun.
         // For example: run1_1.h5
-        let new_filename = self
-            .file_template
-            .replace("_{}", &format!("_{:0>2}", self.subrun));
-        let new_path = PathBuf::from(new_filename);
+        let new_path = self.current_path();
         // Create new file.
-        let new_file = File::create(&new_path)?;
+        let new_file = create_file(&new_path, self.layout.existing_files)?;
         new_file
             .new_attr::<usize>()
             .shape(())
             .create("saved_events")?;
         new_file.attr("saved_events")?.write_scalar(&0)?;
+        write_str_attr(&new_file, "run_status", RUN_STATUS_RUNNING)?;
+        if let Some(operator) = &self.operator {
+            write_str_attr(&new_file, "operator", operator)?;
+        }
+        self.layout.write_attrs(&new_file)?;
         // Create new groups and board data.
         let new_boards = Self::create_boards(
             &new_file,
-            self.n_channels,
+            &self.channel_maps,
             self.n_samples,
-            self.boards.len(),
             self.max_events_per_board,
-            self.buffer_capacity,
-            self.compression_level,
+            &self.params,
+            &self.layout,
         )?;
+        let new_hits = Self::create_hits(
+            &new_file,
+            self.channel_maps.len(),
+            self.max_events_per_board,
+            &self.params,
+            &self.layout,
+        )?;
+
+        // The file being closed holds a complete subrun
+        write_str_attr(&self.file, "run_status", RUN_STATUS_GOOD)?;
 
         // Replace the current file and boards.
         self.file = new_file;
         self.boards = new_boards;
+        self.hits = new_hits;
+        self.raw.fill_with(|| None);
+        self.endpoint_stats.fill_with(|| None);
+        self.file_opened = Instant::now();
 
         // Write the buffered events into the new file.
-        for (i, (ts, wf, count)) in vals.into_iter().enumerate() {
-            if count > 0 {
-                self.boards[i].append_buffer(ts, wf, count)?;
+        for (i, buffered) in vals.into_iter().enumerate() {
+            if buffered.count > 0 {
+                self.boards[i].append_buffer(buffered)?;
             }
         }
         // Reset and update saved_events after rollover
-        self.saved_events = self.boards.iter().map(|b| b.current_event).sum();
-        self.file
-            .attr("saved_events")?
-            .write_scalar(&self.saved_events)?;
+        self.update_saved_events()
+    }
+}
+
+/// Events taken out of a board's buffers to be written to the next file.
+pub struct BufferedEvents {
+    pub timestamps: Array2<u64>,
+    pub waveforms: Array3<u16>,
+    pub trigger_ids: Array2<u32>,
+    pub flags: Array2<u16>,
+    pub fails: Array2<bool>,
+    pub zero_suppressed: Array2<bool>,
+    pub roi_offsets: Option<Array2<u32>>,
+    pub pile_up: Array2<bool>,
+    pub baselines: Option<Array2<f32>>,
+    pub waveform_crc32: Option<Array2<u32>>,
+    pub windows: Option<Array2<u32>>,
+    pub timestamps_us: Option<Array2<f64>>,
+    pub headers: Option<Array1<EventHeader>>,
+    /// Full resolution waveforms and their row among these events
+    pub full_res: Option<(Array3<u16>, Vec<usize>)>,
+    /// Unsuppressed waveforms of audited events and their row among these
+    /// events
+    pub unsuppressed: Option<(Array3<u16>, Vec<usize>)>,
+    pub volts: Option<Array3<f32>>,
+    pub suppressed_channels: Array2<u64>,
+    pub count: usize,
+}
 
+/// Extra waveforms of selected events, stored with the event row of each:
+/// the full resolution waveforms in multi-resolution mode and the
+/// unsuppressed waveforms of the zero suppression audit.
+pub struct WaveformCopies {
+    pub waveforms: Dataset,
+    /// Event row of each stored waveform
+    pub events: Dataset,
+    pub current: usize,
+    pub wf_buffer: Array3<u16>,
+    /// Row in the board's event buffer of each buffered waveform
+    pub rows: Vec<usize>,
+}
+
+impl WaveformCopies {
+    /// Create the datasets `name` and `{name}_events`.
+    fn new(
+        group: &Group,
+        name: &str,
+        shape: (usize, usize, usize),
+        params: &DatasetParams,
+    ) -> Result<Self> {
+        let (max_events, n_channels, n_samples) = shape;
+        let buffer_capacity = params.buffer_capacity;
+        let waveforms = group
+            .new_dataset::<u16>()
+            .shape(shape)
+            .compressed(params)
+            .chunk(params.waveform_chunk(n_channels, n_samples))
+            .create(name)?;
+        let events = group
+            .new_dataset::<u64>()
+            .shape((max_events, 1))
+            .compressed(params)
+            .chunk((params.chunk_events, 1))
+            .create(format!("{}_events", name).as_str())?;
+        Ok(Self {
+            waveforms,
+            events,
+            current: 0,
+            wf_buffer: Array3::zeros((buffer_capacity, n_channels, n_samples)),
+            rows: Vec::with_capacity(buffer_capacity),
+        })
+    }
+
+    /// Buffer the waveforms of the event in row `row` of the board's buffer.
+    fn push(&mut self, row: usize, waveforms: &Array2<u16>, delta_encoding: bool) {
+        let mut copy = self.wf_buffer.slice_mut(s![self.rows.len(), .., ..]);
+        copy.assign(waveforms);
+        if delta_encoding {
+            delta_encode(copy);
+        }
+        self.rows.push(row);
+    }
+
+    /// Take the buffered waveforms and their rows without writing them.
+    fn take(&mut self) -> (Array3<u16>, Vec<usize>) {
+        let rows = std::mem::take(&mut self.rows);
+        let wf = self.wf_buffer.slice(s![0..rows.len(), .., ..]).to_owned();
+        (wf, rows)
+    }
+
+    /// Write waveforms whose rows count from event `first_event`.
+    fn write(&self, wf: &Array3<u16>, rows: &[usize], first_event: usize) -> Result<()> {
+        let count = rows.len();
+        if count == 0 {
+            return Ok(());
+        }
+        self.waveforms.write_slice(
+            &wf.slice(s![0..count, .., ..]).to_owned(),
+            (self.current..self.current + count, .., ..),
+        )?;
+        let events = Array2::from_shape_fn((count, 1), |(i, _)| (first_event + rows[i]) as u64);
+        self.events
+            .write_slice(&events, (self.current..self.current + count, ..))?;
+        Ok(())
+    }
+
+    /// Write the buffered waveforms, the board's buffer starts at event `first_event`.
+    fn flush(&mut self, first_event: usize) -> Result<()> {
+        self.write(&self.wf_buffer, &self.rows, first_event)?;
+        self.current += self.rows.len();
+        self.rows.clear();
+        Ok(())
+    }
+}
+
+/// Whether bit `row` of a `suppressed_channels` mask is set. Only the first
+/// 64 rows can be marked.
+fn row_suppressed(mask: u64, row: usize) -> bool {
+    row < u64::BITS as usize && mask >> row & 1 == 1
+}
+
+/// Waveforms without the channel rows zero suppression emptied: the kept
+/// rows of every event one after another, where each event's rows start,
+/// and which rows they are from the event's `suppressed_channels` mask.
+pub struct SparseWaveforms {
+    pub rows: Dataset,
+    /// First row of each event in `rows`
+    pub index: Dataset,
+    /// Next row of `rows` to write
+    pub current: usize,
+}
+
+impl SparseWaveforms {
+    fn new(
+        group: &Group,
+        max_events: usize,
+        n_channels: usize,
+        n_samples: usize,
+        params: &DatasetParams,
+    ) -> Result<Self> {
+        // Room for every row of every event, unwritten chunks take no space
+        let (chunk_events, chunk_channels, chunk_samples) =
+            params.waveform_chunk(n_channels, n_samples);
+        let rows = group
+            .new_dataset::<u16>()
+            .shape((max_events * n_channels, n_samples))
+            .compressed(params)
+            .chunk((chunk_events * chunk_channels, chunk_samples))
+            .create("waveforms_sparse")?;
+        let index = group
+            .new_dataset::<u64>()
+            .shape((max_events, 1))
+            .compressed(params)
+            .chunk((params.chunk_events, 1))
+            .create("waveforms_sparse_index")?;
+        Ok(Self {
+            rows,
+            index,
+            current: 0,
+        })
+    }
+
+    /// Write the kept rows of `waveforms`, events from `first_event` on.
+    fn write(
+        &mut self,
+        waveforms: &Array3<u16>,
+        masks: &Array2<u64>,
+        first_event: usize,
+    ) -> Result<()> {
+        let (count, n_channels, _) = waveforms.dim();
+        let mut index = Array2::<u64>::zeros((count, 1));
+        let mut kept = Vec::new();
+        for (i, &mask) in masks.column(0).iter().enumerate().take(count) {
+            index[[i, 0]] = (self.current + kept.len()) as u64;
+            kept.extend(
+                (0..n_channels)
+                    .filter(|&row| !row_suppressed(mask, row))
+                    .map(|row| waveforms.slice(s![i, row, ..])),
+            );
+        }
+        if !kept.is_empty() {
+            let rows = ndarray::stack(Axis(0), &kept)?;
+            self.rows
+                .write_slice(&rows, (self.current..self.current + kept.len(), ..))?;
+            self.current += kept.len();
+        }
+        self.index
+            .write_slice(&index, (first_event..first_event + count, ..))?;
+        Ok(())
+    }
+}
+
+/// Hit table of one board read from the DPP-PSD endpoint, one row per hit.
+pub struct HitData {
+    pub current_event: usize,
+    pub max_events: usize,
+    pub buffer_capacity: usize,
+    pub channels: Dataset,
+    pub timestamps: Dataset,
+    pub fine_timestamps: Dataset,
+    pub energy: Dataset,
+    pub energy_short: Dataset,
+    pub flags_low_priority: Dataset,
+    pub flags_high_priority: Dataset,
+    pub buffer: Vec<(u64, DppPsdHit)>,
+}
+
+impl HitData {
+    fn new(group: &Group, max_events: usize, params: &DatasetParams) -> Result<Self> {
+        let buffer_capacity = params.buffer_capacity;
+        let u16_dataset = |name: &str| {
+            group
+                .new_dataset::<u16>()
+                .shape(max_events)
+                .compressed(params)
+                .chunk(params.chunk_events)
+                .create(name)
+        };
+        Ok(Self {
+            current_event: 0,
+            max_events,
+            buffer_capacity,
+            channels: group
+                .new_dataset::<u8>()
+                .shape(max_events)
+                .compressed(params)
+                .chunk(params.chunk_events)
+                .create("channel")?,
+            timestamps: group
+                .new_dataset::<u64>()
+                .shape(max_events)
+                .compressed(params)
+                .chunk(params.chunk_events)
+                .create("timestamps")?,
+            fine_timestamps: u16_dataset("fine_timestamps")?,
+            energy: u16_dataset("energy")?,
+            energy_short: u16_dataset("energy_short")?,
+            flags_low_priority: u16_dataset("flags_low_priority")?,
+            flags_high_priority: u16_dataset("flags_high_priority")?,
+            buffer: Vec::with_capacity(buffer_capacity),
+        })
+    }
+
+    /// Whether the table has no room for another hit, counting buffered hits.
+    fn is_full(&self) -> bool {
+        self.current_event + self.buffer.len() >= self.max_events
+    }
+
+    fn append(&mut self, timestamp: u64, hit: &DppPsdHit) -> Result<()> {
+        if self.is_full() {
+            return Err(anyhow!("Maximum number of events reached"));
+        }
+        self.buffer.push((timestamp, *hit));
+        if self.buffer.len() >= self.buffer_capacity {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let count = self.buffer.len();
+        if count == 0 {
+            return Ok(());
+        }
+        let rows = self.current_event..self.current_event + count;
+        let column = |f: fn(&DppPsdHit) -> u16| -> Array1<u16> {
+            self.buffer.iter().map(|(_, hit)| f(hit)).collect()
+        };
+        let channels: Array1<u8> = self.buffer.iter().map(|(_, hit)| hit.channel).collect();
+        let timestamps: Array1<u64> = self.buffer.iter().map(|&(ts, _)| ts).collect();
+        self.channels.write_slice(&channels, rows.clone())?;
+        self.timestamps.write_slice(&timestamps, rows.clone())?;
+        self.fine_timestamps
+            .write_slice(&column(|h| h.fine_timestamp), rows.clone())?;
+        self.energy
+            .write_slice(&column(|h| h.energy), rows.clone())?;
+        self.energy_short
+            .write_slice(&column(|h| h.energy_short), rows.clone())?;
+        self.flags_low_priority
+            .write_slice(&column(|h| h.flags_low_priority), rows.clone())?;
+        self.flags_high_priority
+            .write_slice(&column(|h| h.flags_high_priority), rows)?;
+        self.current_event += count;
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+/// Blocks of a board's raw endpoint in its `raw` group, one row per block
+/// padded to the largest block the board sends. Blocks are large, so each
+/// is written as it comes.
+pub struct RawData {
+    current: usize,
+    max_blocks: usize,
+    data: Dataset,
+    size: Dataset,
+    n_events: Dataset,
+}
+
+impl RawData {
+    fn new(
+        group: &Group,
+        max_blocks: usize,
+        block_len: usize,
+        params: &DatasetParams,
+    ) -> Result<Self> {
+        let group = group.create_group("raw")?;
+        Ok(Self {
+            current: 0,
+            max_blocks,
+            data: group
+                .new_dataset::<u8>()
+                .shape((max_blocks, block_len))
+                .compressed(params)
+                .chunk((1, block_len))
+                .create("data")?,
+            size: group
+                .new_dataset::<u64>()
+                .shape(max_blocks)
+                .compressed(params)
+                .chunk(params.chunk_events)
+                .create("size")?,
+            n_events: group
+                .new_dataset::<u32>()
+                .shape(max_blocks)
+                .compressed(params)
+                .chunk(params.chunk_events)
+                .create("n_events")?,
+        })
+    }
+
+    fn is_full(&self) -> bool {
+        self.current >= self.max_blocks
+    }
+
+    fn append(&mut self, block: &RawBlock) -> Result<()> {
+        let row = self.current..self.current + 1;
+        let data = Array2::from_shape_vec((1, block.data.len()), block.data.clone())?;
+        self.data.write_slice(&data, (row.clone(), ..))?;
+        self.size
+            .write_slice(&Array1::from_elem(1, block.size as u64), row.clone())?;
+        self.n_events
+            .write_slice(&Array1::from_elem(1, block.n_events), row)?;
+        self.current += 1;
+        Ok(())
+    }
+}
+
+/// Counters of a board's stats endpoint in its `stats` group, one row per
+/// read and one column per board channel.
+pub struct StatsData {
+    current: usize,
+    max_rows: usize,
+    real_time: Dataset,
+    dead_time: Dataset,
+    live_time: Dataset,
+    trigger_cnt: Dataset,
+    saved_event_cnt: Dataset,
+}
+
+impl StatsData {
+    fn new(
+        group: &Group,
+        max_rows: usize,
+        n_channels: usize,
+        params: &DatasetParams,
+    ) -> Result<Self> {
+        let group = group.create_group("stats")?;
+        let u64_dataset = |name: &str| {
+            group
+                .new_dataset::<u64>()
+                .shape((max_rows, n_channels))
+                .compressed(params)
+                .chunk((params.chunk_events, n_channels))
+                .create(name)
+        };
+        let u32_dataset = |name: &str| {
+            group
+                .new_dataset::<u32>()
+                .shape((max_rows, n_channels))
+                .compressed(params)
+                .chunk((params.chunk_events, n_channels))
+                .create(name)
+        };
+        Ok(Self {
+            current: 0,
+            max_rows,
+            real_time: u64_dataset("real_time")?,
+            dead_time: u64_dataset("dead_time")?,
+            live_time: u64_dataset("live_time")?,
+            trigger_cnt: u32_dataset("trigger_cnt")?,
+            saved_event_cnt: u32_dataset("saved_event_cnt")?,
+        })
+    }
+
+    fn is_full(&self) -> bool {
+        self.current >= self.max_rows
+    }
+
+    fn append(&mut self, stats: &EndpointStats) -> Result<()> {
+        let row = (self.current..self.current + 1, ..);
+        let n_channels = stats.real_time.len();
+        let as_row = |values: &[u64]| Array2::from_shape_vec((1, n_channels), values.to_vec());
+        let as_row_u32 = |values: &[u32]| Array2::from_shape_vec((1, n_channels), values.to_vec());
+        self.real_time
+            .write_slice(&as_row(&stats.real_time)?, row.clone())?;
+        self.dead_time
+            .write_slice(&as_row(&stats.dead_time)?, row.clone())?;
+        self.live_time
+            .write_slice(&as_row(&stats.live_time)?, row.clone())?;
+        self.trigger_cnt
+            .write_slice(&as_row_u32(&stats.trigger_cnt)?, row.clone())?;
+        self.saved_event_cnt
+            .write_slice(&as_row_u32(&stats.saved_event_cnt)?, row)?;
+        self.current += 1;
         Ok(())
     }
 }
@@ -190,11 +1483,36 @@ pub struct BoardData {
     pub current_event: usize,
     pub max_events: usize,
     pub timestamps: Dataset,
-    pub waveforms: Dataset,
+    /// Every channel row of each event, unless they're stored sparse
+    pub waveforms: Option<Dataset>,
+    pub sparse: Option<SparseWaveforms>,
+    /// Rows emptied by zero suppression of each event, as a bitmask
+    pub suppressed_channels: Dataset,
+    pub mask_buffer: Array2<u64>,
     pub trigids: Dataset,
     pub flags: Dataset,
     pub fails: Dataset,
     pub zero_suppressed: Dataset,
+    /// First sample of each channel's window, in ROI mode
+    pub roi_offsets: Option<Dataset>,
+    /// Per-channel pile-up flags
+    pub pile_up: Dataset,
+    /// Per-channel baselines, if saved
+    pub baselines: Option<Dataset>,
+    pub baseline_buffer: Option<Array2<f32>>,
+    /// Timestamps with the board's offset to board 0 subtracted
+    pub timestamps_corrected: Option<Dataset>,
+    /// Offset to board 0 in ns
+    pub timestamp_offset: i64,
+    /// Window index of each event within its trigger, in multi-window mode
+    pub windows: Option<Dataset>,
+    pub window_buffer: Option<Array2<u32>>,
+    /// Timestamps in µs with the fine timestamp, if read
+    pub timestamps_us: Option<Dataset>,
+    pub ts_us_buffer: Option<Array2<f64>>,
+    /// Per-event scalars as one table
+    pub event_header: Option<Dataset>,
+    pub header_buffer: Option<Array1<EventHeader>>,
     pub buffer_capacity: usize,
     pub buffer_count: usize,
     pub ts_buffer: Array2<u64>,
@@ -203,7 +1521,28 @@ pub struct BoardData {
     pub flag_buffer: Array2<u16>,
     pub fail_buffer: Array2<bool>,
     pub zs_buffer: Array2<bool>,
+    pub roi_buffer: Option<Array2<u32>>,
+    /// CRC32 of each event's waveforms as stored, with `waveform_checksums`
+    pub waveform_crc32: Option<Dataset>,
+    pub crc_buffer: Option<Array2<u32>>,
+    pub pile_up_buffer: Array2<bool>,
+    /// Decimation factor of `waveforms` in multi-resolution mode
+    pub decimation: Option<usize>,
+    /// Full resolution copies of selected events, in multi-resolution mode
+    /// where `waveforms` holds the decimated waveforms
+    pub full_res: Option<WaveformCopies>,
+    /// Unsuppressed copies of the events picked by the zero suppression
+    /// audit, stored like `waveforms`
+    pub unsuppressed: Option<WaveformCopies>,
+    /// Calibrated waveforms in volts, stored as `volt_type`
+    pub volts: Option<Dataset>,
+    pub volt_type: Option<FloatType>,
+    pub volt_buffer: Option<Array3<f32>>,
+    /// Volts per ADC count and offset in volts of each waveform row
+    pub volt_conversion: Vec<(f64, f64)>,
+    pub delta_encoding: bool,
     pub n_channels: usize,
+    /// Samples per channel of incoming events, before decimation
     pub n_samples: usize,
 }
 
@@ -213,77 +1552,254 @@ impl BoardData {
         n_channels: usize,
         n_samples: usize,
         max_events: usize,
-        buffer_capacity: usize,
-        compression_level: u8,
+        params: &DatasetParams,
+        layout: &StorageLayout,
     ) -> Result<Self> {
+        let roi = layout.roi.is_some();
+        let buffer_capacity = params.buffer_capacity;
+        let stored_samples = layout
+            .decimation
+            .map_or(n_samples, |factor| n_samples.div_ceil(factor));
         // Create datasets
         // For timestamps we use shape (max_events, 1) to allow writing a 1D slice later.
         let ts_shape = (max_events, 1);
         let timestamps = group
             .new_dataset::<u64>()
             .shape(ts_shape)
-            .blosc_zstd(compression_level, true)
-            .chunk((buffer_capacity, 1))
+            .compressed(params)
+            .chunk((params.chunk_events, 1))
             .create("timestamps")?;
 
-        let wf_shape = (max_events, n_channels, n_samples);
-        let waveforms = group
-            .new_dataset::<u16>()
-            .shape(wf_shape)
-            // Set chunking and compression if desired.
-            .blosc_zstd(compression_level, true)
-            .chunk((buffer_capacity, n_channels, n_samples))
-            .create("waveforms")?;
+        let timestamps_corrected = layout
+            .correct_timestamps
+            .then(|| {
+                group
+                    .new_dataset::<u64>()
+                    .shape(ts_shape)
+                    .compressed(params)
+                    .chunk((params.chunk_events, 1))
+                    .create("timestamps_corrected")
+            })
+            .transpose()?;
+
+        let wf_shape = (max_events, n_channels, stored_samples);
+        let (waveforms, sparse) = if layout.sparse_waveforms {
+            let sparse =
+                SparseWaveforms::new(group, max_events, n_channels, stored_samples, params)?;
+            (None, Some(sparse))
+        } else {
+            let waveforms = group
+                .new_dataset::<u16>()
+                .shape(wf_shape)
+                // Set chunking and compression if desired.
+                .compressed(params)
+                .chunk(params.waveform_chunk(n_channels, stored_samples))
+                .create(match layout.decimation {
+                    Some(_) => "waveforms_decimated",
+                    None => "waveforms",
+                })?;
+            (Some(waveforms), None)
+        };
+        let suppressed_channels = group
+            .new_dataset::<u64>()
+            .shape((max_events, 1))
+            .compressed(params)
+            .chunk((params.chunk_events, 1))
+            .create("suppressed_channels")?;
+        let full_res = layout
+            .decimation
+            .map(|_| {
+                WaveformCopies::new(
+                    group,
+                    "waveforms_full",
+                    (max_events, n_channels, n_samples),
+                    params,
+                )
+            })
+            .transpose()?;
+        let unsuppressed = layout
+            .zs_audit
+            .map(|_| WaveformCopies::new(group, "waveforms_unsuppressed", wf_shape, params))
+            .transpose()?;
+        let volts = layout
+            .volt_waveforms
+            .map(|float| {
+                match float {
+                    FloatType::F16 => group.new_dataset::<half::f16>(),
+                    FloatType::F32 => group.new_dataset::<f32>(),
+                }
+                .shape(wf_shape)
+                .compressed(params)
+                .chunk(params.waveform_chunk(n_channels, stored_samples))
+                .create("waveforms_volts")
+            })
+            .transpose()?;
 
         let trigid_shape = (max_events, 1);
         let trigids = group
             .new_dataset::<u32>()
             .shape(trigid_shape)
-            .blosc_zstd(compression_level, true)
-            .chunk((buffer_capacity, 1))
+            .compressed(params)
+            .chunk((params.chunk_events, 1))
             .create("triggerids")?;
 
         let flags_shape = (max_events, 1);
         let flags = group
             .new_dataset::<u16>()
             .shape(flags_shape)
-            .blosc_zstd(compression_level, true)
-            .chunk((buffer_capacity, 1))
+            .compressed(params)
+            .chunk((params.chunk_events, 1))
             .create("flags")?;
 
         let fail_shape = (max_events, 1);
         let fails = group
             .new_dataset::<bool>()
             .shape(fail_shape)
-            .blosc_zstd(compression_level, true)
-            .chunk((buffer_capacity, 1))
+            .compressed(params)
+            .chunk((params.chunk_events, 1))
             .create("boardfail")?;
 
         let zs_shape = (max_events, 1);
         let zero_suppressed = group
             .new_dataset::<bool>()
             .shape(zs_shape)
-            .blosc_zstd(compression_level, true)
-            .chunk((buffer_capacity, 1))
+            .compressed(params)
+            .chunk((params.chunk_events, 1))
             .create("zero_suppressed")?;
 
+        let pile_up = group
+            .new_dataset::<bool>()
+            .shape((max_events, n_channels))
+            .compressed(params)
+            .chunk((params.chunk_events, n_channels))
+            .create("pile_up")?;
+
+        let roi_offsets = if roi {
+            Some(
+                group
+                    .new_dataset::<u32>()
+                    .shape((max_events, n_channels))
+                    .compressed(params)
+                    .chunk((params.chunk_events, n_channels))
+                    .create("roi_offsets")?,
+            )
+        } else {
+            None
+        };
+
+        let waveform_crc32 = layout
+            .waveform_checksums
+            .then(|| {
+                group
+                    .new_dataset::<u32>()
+                    .shape((max_events, 1))
+                    .compressed(params)
+                    .chunk((params.chunk_events, 1))
+                    .create("waveform_crc32")
+            })
+            .transpose()?;
+
+        let baselines = layout
+            .baseline_samples
+            .map(|_| {
+                group
+                    .new_dataset::<f32>()
+                    .shape((max_events, n_channels))
+                    .compressed(params)
+                    .chunk((params.chunk_events, n_channels))
+                    .create("baselines")
+            })
+            .transpose()?;
+
+        let windows = layout
+            .multi_window
+            .then(|| {
+                group
+                    .new_dataset::<u32>()
+                    .shape((max_events, 1))
+                    .compressed(params)
+                    .chunk((params.chunk_events, 1))
+                    .create("window")
+            })
+            .transpose()?;
+
+        let timestamps_us = layout
+            .fine_timestamp
+            .then(|| {
+                group
+                    .new_dataset::<f64>()
+                    .shape(ts_shape)
+                    .compressed(params)
+                    .chunk((params.chunk_events, 1))
+                    .create("timestamps_us")
+            })
+            .transpose()?;
+
+        let event_header = layout
+            .event_header
+            .then(|| {
+                group
+                    .new_dataset::<EventHeader>()
+                    .shape(max_events)
+                    .compressed(params)
+                    .chunk(params.chunk_events)
+                    .create("event_header")
+            })
+            .transpose()?;
+
         // Create the in-memory buffers.
         let ts_buffer = Array2::<u64>::zeros((buffer_capacity, 1));
-        let wf_buffer = Array3::<u16>::zeros((buffer_capacity, n_channels, n_samples));
+        let header_buffer = layout
+            .event_header
+            .then(|| Array1::from_elem(buffer_capacity, EventHeader::default()));
+        let ts_us_buffer = layout
+            .fine_timestamp
+            .then(|| Array2::<f64>::zeros((buffer_capacity, 1)));
+        let window_buffer = layout
+            .multi_window
+            .then(|| Array2::<u32>::zeros((buffer_capacity, 1)));
+        let wf_buffer = Array3::<u16>::zeros((buffer_capacity, n_channels, stored_samples));
         let trigid_buffer = Array2::<u32>::zeros((buffer_capacity, 1));
         let flag_buffer = Array2::<u16>::zeros((buffer_capacity, 1));
         let fail_buffer = Array2::<bool>::default((buffer_capacity, 1));
         let zs_buffer = Array2::<bool>::default((buffer_capacity, 1));
+        let pile_up_buffer = Array2::<bool>::default((buffer_capacity, n_channels));
+        let roi_buffer = roi.then(|| Array2::<u32>::zeros((buffer_capacity, n_channels)));
+        let crc_buffer = layout
+            .waveform_checksums
+            .then(|| Array2::<u32>::zeros((buffer_capacity, 1)));
+        let baseline_buffer = layout
+            .baseline_samples
+            .map(|_| Array2::<f32>::zeros((buffer_capacity, n_channels)));
+        let volt_buffer = layout
+            .volt_waveforms
+            .map(|_| Array3::<f32>::zeros((buffer_capacity, n_channels, stored_samples)));
+        let mask_buffer = Array2::<u64>::zeros((buffer_capacity, 1));
 
         Ok(Self {
             current_event: 0,
             max_events,
             timestamps,
             waveforms,
+            sparse,
+            suppressed_channels,
+            mask_buffer,
             trigids,
             flags,
             fails,
             zero_suppressed,
+            roi_offsets,
+            pile_up,
+            baselines,
+            baseline_buffer,
+            timestamps_corrected,
+            timestamp_offset: 0,
+            windows,
+            window_buffer,
+            timestamps_us,
+            ts_us_buffer,
+            event_header,
+            header_buffer,
             buffer_capacity,
             buffer_count: 0,
             ts_buffer,
@@ -292,40 +1808,225 @@ impl BoardData {
             flag_buffer,
             fail_buffer,
             zs_buffer,
+            roi_buffer,
+            waveform_crc32,
+            crc_buffer,
+            pile_up_buffer,
+            decimation: layout.decimation,
+            full_res,
+            unsuppressed,
+            volts,
+            volt_type: layout.volt_waveforms,
+            volt_buffer,
+            volt_conversion: vec![(1.0, 0.0); n_channels],
+            delta_encoding: layout.delta_encoding,
             n_channels,
             n_samples,
         })
     }
 
-    /// Append an event to the board’s buffers. When the buffer fills, flush it to disk.
-    pub fn append_event(
+    /// Convert the waveforms to volts with `conversion`, one volts per ADC
+    /// count and offset per row, recorded as the `volts_per_count` and
+    /// `volts_offset` attributes of `waveforms_volts`.
+    fn set_volt_conversion(&mut self, group: &Group, conversion: Vec<(f64, f64)>) -> Result<()> {
+        let dataset = group.dataset("waveforms_volts")?;
+        let per_count: Array1<f64> = conversion.iter().map(|&(scale, _)| scale).collect();
+        let offsets: Array1<f64> = conversion.iter().map(|&(_, offset)| offset).collect();
+        for (name, values) in [("volts_per_count", per_count), ("volts_offset", offsets)] {
+            dataset
+                .new_attr::<f64>()
+                .shape(values.len())
+                .create(name)?
+                .write(&values)?;
+        }
+        self.volt_conversion = conversion;
+        Ok(())
+    }
+
+    /// Write waveforms and their `suppressed_channels` masks starting at
+    /// event row `start`, dense or sparse.
+    fn write_waveforms(
         &mut self,
-        timestamp: u64,
-        waveforms: &Array2<u16>,
-        trigger_id: u32,
-        flag: u16,
-        fail: bool,
-        zs_flag: bool,
+        waveforms: &Array3<u16>,
+        masks: &Array2<u64>,
+        start: usize,
     ) -> Result<()> {
+        let rows = start..start + waveforms.len_of(Axis(0));
+        if let Some(dataset) = &self.waveforms {
+            dataset.write_slice(waveforms, (rows.clone(), .., ..))?;
+        }
+        if let Some(sparse) = &mut self.sparse {
+            sparse.write(waveforms, masks, start)?;
+        }
+        self.suppressed_channels.write_slice(masks, (rows, ..))?;
+        Ok(())
+    }
+
+    /// Write waveforms in volts starting at event row `start`.
+    fn write_volts(&self, volts: &Array3<f32>, start: usize) -> Result<()> {
+        let (Some(dataset), Some(float)) = (&self.volts, self.volt_type) else {
+            return Ok(());
+        };
+        let rows = (start..start + volts.len_of(Axis(0)), .., ..);
+        match float {
+            FloatType::F16 => dataset.write_slice(&volts.mapv(half::f16::from_f32), rows)?,
+            FloatType::F32 => dataset.write_slice(volts, rows)?,
+        }
+        Ok(())
+    }
+
+    fn set_timestamp_offset(&mut self, group: &Group, offset: i64) -> Result<()> {
+        group
+            .new_attr::<i64>()
+            .shape(())
+            .create("timestamp_offset")?
+            .write_scalar(&offset)?;
+        self.timestamp_offset = offset;
+        Ok(())
+    }
+
+    /// Write `timestamps` with the offset subtracted, starting at event row
+    /// `start`.
+    fn write_corrected_timestamps(&self, timestamps: &Array2<u64>, start: usize) -> Result<()> {
+        if let Some(dataset) = &self.timestamps_corrected {
+            let corrected = timestamps.mapv(|t| t.saturating_add_signed(-self.timestamp_offset));
+            dataset.write_slice(&corrected, (start..start + timestamps.nrows(), ..))?;
+        }
+        Ok(())
+    }
+
+    /// Append an event to the board’s buffers. When the buffer fills, flush it to disk.
+    pub fn append_event(&mut self, event: &EventRecord) -> Result<()> {
         // Verify that the incoming event has the expected shape.
-        let (channels, samples) = waveforms.dim();
+        let (channels, samples) = event.waveforms.dim();
         if channels != self.n_channels || samples != self.n_samples {
-            return Err(anyhow!("Event dimensions do not match dataset dimensions",));
+            return Err(anyhow!(
+                "Event has {} channels of {} samples but the run's files hold {} of {}, the record length can't change within a run",
+                channels,
+                samples,
+                self.n_channels,
+                self.n_samples
+            ));
+        }
+        let roi_offsets = event.roi_offsets;
+        if self.roi_buffer.is_some() != roi_offsets.is_some_and(|o| o.len() == channels) {
+            return Err(anyhow!("Event ROI offsets do not match the ROI mode"));
+        }
+        if event.pile_up.len() != channels {
+            return Err(anyhow!(
+                "Event pile-up flags do not match the channel count"
+            ));
+        }
+        if self.baseline_buffer.is_some() != event.baselines.is_some_and(|b| b.len() == channels) {
+            return Err(anyhow!("Event baselines do not match the baseline mode"));
+        }
+        if event
+            .unsuppressed
+            .is_some_and(|waveforms| waveforms.dim() != event.waveforms.dim())
+        {
+            return Err(anyhow!(
+                "Event unsuppressed waveforms do not match its waveforms"
+            ));
         }
         if self.current_event + self.buffer_count >= self.max_events {
             return Err(anyhow!("Maximum number of events reached"));
         }
 
         // Place the new data into the buffers.
-        self.ts_buffer[[self.buffer_count, 0]] = timestamp;
-        self.trigid_buffer[[self.buffer_count, 0]] = trigger_id;
-        self.flag_buffer[[self.buffer_count, 0]] = flag;
-        self.fail_buffer[[self.buffer_count, 0]] = fail;
-        self.zs_buffer[[self.buffer_count, 0]] = zs_flag;
+        self.ts_buffer[[self.buffer_count, 0]] = event.timestamp;
+        self.trigid_buffer[[self.buffer_count, 0]] = event.trigger_id;
+        self.flag_buffer[[self.buffer_count, 0]] = event.flags;
+        self.fail_buffer[[self.buffer_count, 0]] = event.fail;
+        self.zs_buffer[[self.buffer_count, 0]] = event.zero_suppressed;
+        if let Some(buffer) = &mut self.window_buffer {
+            buffer[[self.buffer_count, 0]] = event.window;
+        }
+        if let Some(buffer) = &mut self.ts_us_buffer {
+            buffer[[self.buffer_count, 0]] = event.timestamp_us;
+        }
+        if let Some(buffer) = &mut self.header_buffer {
+            buffer[self.buffer_count] = EventHeader {
+                timestamp: event.timestamp,
+                trigger_id: event.trigger_id,
+                flags: event.flags,
+                board_fail: event.fail,
+                event_size: event.size as u64,
+            };
+        }
+        for (ch, &pile_up) in event.pile_up.iter().enumerate() {
+            self.pile_up_buffer[[self.buffer_count, ch]] = pile_up;
+        }
+        if let (Some(buffer), Some(offsets)) = (&mut self.roi_buffer, roi_offsets) {
+            for (ch, &offset) in offsets.iter().enumerate() {
+                buffer[[self.buffer_count, ch]] = offset as u32;
+            }
+        }
+        if let (Some(buffer), Some(baselines)) = (&mut self.baseline_buffer, event.baselines) {
+            for (ch, &baseline) in baselines.iter().enumerate() {
+                buffer[[self.buffer_count, ch]] = baseline;
+            }
+        }
         // Copy the 2D waveform event into the corresponding slice of the buffer.
-        self.wf_buffer
-            .slice_mut(s![self.buffer_count, .., ..])
-            .assign(waveforms);
+        match self.decimation {
+            Some(factor) => self
+                .wf_buffer
+                .slice_mut(s![self.buffer_count, .., ..])
+                .assign(&decimate(event.waveforms, factor)),
+            None => self
+                .wf_buffer
+                .slice_mut(s![self.buffer_count, .., ..])
+                .assign(event.waveforms),
+        }
+        if let Some(full_res) = &mut self.full_res {
+            if event.full_res {
+                full_res.push(self.buffer_count, event.waveforms, self.delta_encoding);
+            }
+        }
+        // The audit copy is stored like the suppressed waveforms it is
+        // compared with
+        if let (Some(copies), Some(waveforms)) = (&mut self.unsuppressed, event.unsuppressed) {
+            match self.decimation {
+                Some(factor) => copies.push(
+                    self.buffer_count,
+                    &decimate(waveforms, factor),
+                    self.delta_encoding,
+                ),
+                None => copies.push(self.buffer_count, waveforms, self.delta_encoding),
+            }
+        }
+        // The firmware's suppression drops channels too, to zeros
+        let mut mask = 0;
+        if event.zero_suppressed {
+            let waveforms = self.wf_buffer.slice(s![self.buffer_count, .., ..]);
+            for (row, channel) in waveforms.outer_iter().enumerate().take(u64::BITS as usize) {
+                if channel.iter().all(|&x| x == 0) {
+                    mask |= 1 << row;
+                }
+            }
+        }
+        self.mask_buffer[[self.buffer_count, 0]] = mask;
+        if let Some(buffer) = &mut self.volt_buffer {
+            let waveforms = self.wf_buffer.slice(s![self.buffer_count, .., ..]);
+            let mut volts = buffer.slice_mut(s![self.buffer_count, .., ..]);
+            for ((mut row, samples), &(scale, offset)) in volts
+                .rows_mut()
+                .into_iter()
+                .zip(waveforms.rows())
+                .zip(&self.volt_conversion)
+            {
+                row.zip_mut_with(&samples, |v, &x| *v = (scale * x as f64 + offset) as f32);
+            }
+        }
+        if self.delta_encoding {
+            delta_encode(self.wf_buffer.slice_mut(s![self.buffer_count, .., ..]));
+        }
+        if let Some(buffer) = &mut self.crc_buffer {
+            let mut hasher = crc32fast::Hasher::new();
+            for sample in self.wf_buffer.slice(s![self.buffer_count, .., ..]) {
+                hasher.update(&sample.to_le_bytes());
+            }
+            buffer[[self.buffer_count, 0]] = hasher.finalize();
+        }
         self.buffer_count += 1;
 
         // Flush the buffers if they've reached capacity.
@@ -355,21 +2056,133 @@ impl BoardData {
                 ..,
             ),
         )?;
+        self.write_corrected_timestamps(&ts_to_write, self.current_event)?;
 
         // Write the waveform buffer.
         let wf_to_write = self
             .wf_buffer
             .slice(s![0..self.buffer_count, .., ..])
             .to_owned();
-        self.waveforms.write_slice(
-            &wf_to_write,
+        let masks = self
+            .mask_buffer
+            .slice(s![0..self.buffer_count, ..])
+            .to_owned();
+        self.write_waveforms(&wf_to_write, &masks, self.current_event)?;
+
+        if let Some(buffer) = &self.volt_buffer {
+            self.write_volts(
+                &buffer.slice(s![0..self.buffer_count, .., ..]).to_owned(),
+                self.current_event,
+            )?;
+        }
+
+        let rows = self.current_event..self.current_event + self.buffer_count;
+        self.trigids.write_slice(
+            &self
+                .trigid_buffer
+                .slice(s![0..self.buffer_count, ..])
+                .to_owned(),
+            (rows.clone(), ..),
+        )?;
+        self.flags.write_slice(
+            &self
+                .flag_buffer
+                .slice(s![0..self.buffer_count, ..])
+                .to_owned(),
+            (rows.clone(), ..),
+        )?;
+        self.fails.write_slice(
+            &self
+                .fail_buffer
+                .slice(s![0..self.buffer_count, ..])
+                .to_owned(),
+            (rows.clone(), ..),
+        )?;
+        self.zero_suppressed.write_slice(
+            &self
+                .zs_buffer
+                .slice(s![0..self.buffer_count, ..])
+                .to_owned(),
+            (rows, ..),
+        )?;
+
+        let pile_up_to_write = self
+            .pile_up_buffer
+            .slice(s![0..self.buffer_count, ..])
+            .to_owned();
+        self.pile_up.write_slice(
+            &pile_up_to_write,
             (
                 self.current_event..self.current_event + self.buffer_count,
                 ..,
-                ..,
             ),
         )?;
 
+        if let (Some(dataset), Some(buffer)) = (&self.event_header, &self.header_buffer) {
+            let headers_to_write = buffer.slice(s![0..self.buffer_count]).to_owned();
+            dataset.write_slice(
+                &headers_to_write,
+                self.current_event..self.current_event + self.buffer_count,
+            )?;
+        }
+        if let (Some(dataset), Some(buffer)) = (&self.timestamps_us, &self.ts_us_buffer) {
+            let ts_us_to_write = buffer.slice(s![0..self.buffer_count, ..]).to_owned();
+            dataset.write_slice(
+                &ts_us_to_write,
+                (
+                    self.current_event..self.current_event + self.buffer_count,
+                    ..,
+                ),
+            )?;
+        }
+        if let (Some(dataset), Some(buffer)) = (&self.windows, &self.window_buffer) {
+            let windows_to_write = buffer.slice(s![0..self.buffer_count, ..]).to_owned();
+            dataset.write_slice(
+                &windows_to_write,
+                (
+                    self.current_event..self.current_event + self.buffer_count,
+                    ..,
+                ),
+            )?;
+        }
+        if let (Some(dataset), Some(buffer)) = (&self.waveform_crc32, &self.crc_buffer) {
+            dataset.write_slice(
+                &buffer.slice(s![0..self.buffer_count, ..]).to_owned(),
+                (
+                    self.current_event..self.current_event + self.buffer_count,
+                    ..,
+                ),
+            )?;
+        }
+        if let (Some(dataset), Some(buffer)) = (&self.roi_offsets, &self.roi_buffer) {
+            let roi_to_write = buffer.slice(s![0..self.buffer_count, ..]).to_owned();
+            dataset.write_slice(
+                &roi_to_write,
+                (
+                    self.current_event..self.current_event + self.buffer_count,
+                    ..,
+                ),
+            )?;
+        }
+        if let (Some(dataset), Some(buffer)) = (&self.baselines, &self.baseline_buffer) {
+            let baselines_to_write = buffer.slice(s![0..self.buffer_count, ..]).to_owned();
+            dataset.write_slice(
+                &baselines_to_write,
+                (
+                    self.current_event..self.current_event + self.buffer_count,
+                    ..,
+                ),
+            )?;
+        }
+        // Waveform copies go out with their events, so the buffered ones
+        // always belong to events still in the buffer
+        for copies in [&mut self.full_res, &mut self.unsuppressed]
+            .into_iter()
+            .flatten()
+        {
+            copies.flush(self.current_event)?;
+        }
+
         // Update the overall event count and reset the buffer.
         self.current_event += self.buffer_count;
         self.buffer_count = 0;
@@ -377,23 +2190,57 @@ impl BoardData {
     }
 
     /// Take the current buffered events (without flushing them to disk) and reset the buffer.
-    /// Returns (timestamps, waveforms, number_of_events).
-    pub fn take_buffer(&mut self) -> (Array2<u64>, Array3<u16>, usize) {
+    pub fn take_buffer(&mut self) -> BufferedEvents {
         let count = self.buffer_count;
-        let ts = self.ts_buffer.slice(s![0..count, ..]).to_owned();
-        let wf = self.wf_buffer.slice(s![0..count, .., ..]).to_owned();
+        let buffered = BufferedEvents {
+            timestamps: self.ts_buffer.slice(s![0..count, ..]).to_owned(),
+            waveforms: self.wf_buffer.slice(s![0..count, .., ..]).to_owned(),
+            suppressed_channels: self.mask_buffer.slice(s![0..count, ..]).to_owned(),
+            trigger_ids: self.trigid_buffer.slice(s![0..count, ..]).to_owned(),
+            flags: self.flag_buffer.slice(s![0..count, ..]).to_owned(),
+            fails: self.fail_buffer.slice(s![0..count, ..]).to_owned(),
+            zero_suppressed: self.zs_buffer.slice(s![0..count, ..]).to_owned(),
+            roi_offsets: self
+                .roi_buffer
+                .as_ref()
+                .map(|buffer| buffer.slice(s![0..count, ..]).to_owned()),
+            pile_up: self.pile_up_buffer.slice(s![0..count, ..]).to_owned(),
+            waveform_crc32: self
+                .crc_buffer
+                .as_ref()
+                .map(|buffer| buffer.slice(s![0..count, ..]).to_owned()),
+            baselines: self
+                .baseline_buffer
+                .as_ref()
+                .map(|buffer| buffer.slice(s![0..count, ..]).to_owned()),
+            windows: self
+                .window_buffer
+                .as_ref()
+                .map(|buffer| buffer.slice(s![0..count, ..]).to_owned()),
+            timestamps_us: self
+                .ts_us_buffer
+                .as_ref()
+                .map(|buffer| buffer.slice(s![0..count, ..]).to_owned()),
+            headers: self
+                .header_buffer
+                .as_ref()
+                .map(|buffer| buffer.slice(s![0..count]).to_owned()),
+            full_res: self.full_res.as_mut().map(WaveformCopies::take),
+            unsuppressed: self.unsuppressed.as_mut().map(WaveformCopies::take),
+            volts: self
+                .volt_buffer
+                .as_ref()
+                .map(|buffer| buffer.slice(s![0..count, .., ..]).to_owned()),
+            count,
+        };
         self.buffer_count = 0;
-        (ts, wf, count)
+        buffered
     }
 
     /// Append a previously buffered set of events to the new datasets.
     /// This writes the provided arrays starting at the current event index.
-    pub fn append_buffer(
-        &mut self,
-        ts_buffer: Array2<u64>,
-        wf_buffer: Array3<u16>,
-        count: usize,
-    ) -> Result<()> {
+    pub fn append_buffer(&mut self, buffered: BufferedEvents) -> Result<()> {
+        let count = buffered.count;
         // Ensure we have enough room.
         if self.current_event + count > self.max_events {
             return Err(anyhow!(
@@ -401,13 +2248,68 @@ impl BoardData {
             ));
         }
         self.timestamps.write_slice(
-            &ts_buffer,
+            &buffered.timestamps,
             (self.current_event..self.current_event + count, ..),
         )?;
-        self.waveforms.write_slice(
-            &wf_buffer,
-            (self.current_event..self.current_event + count, .., ..),
+        self.write_corrected_timestamps(&buffered.timestamps, self.current_event)?;
+        self.write_waveforms(
+            &buffered.waveforms,
+            &buffered.suppressed_channels,
+            self.current_event,
         )?;
+        if let Some(volts) = &buffered.volts {
+            self.write_volts(volts, self.current_event)?;
+        }
+        let rows = self.current_event..self.current_event + count;
+        self.trigids
+            .write_slice(&buffered.trigger_ids, (rows.clone(), ..))?;
+        self.flags
+            .write_slice(&buffered.flags, (rows.clone(), ..))?;
+        self.fails
+            .write_slice(&buffered.fails, (rows.clone(), ..))?;
+        self.zero_suppressed
+            .write_slice(&buffered.zero_suppressed, (rows, ..))?;
+        self.pile_up.write_slice(
+            &buffered.pile_up,
+            (self.current_event..self.current_event + count, ..),
+        )?;
+        if let (Some(dataset), Some(headers)) = (&self.event_header, &buffered.headers) {
+            dataset.write_slice(headers, self.current_event..self.current_event + count)?;
+        }
+        if let (Some(dataset), Some(ts_us)) = (&self.timestamps_us, &buffered.timestamps_us) {
+            dataset.write_slice(ts_us, (self.current_event..self.current_event + count, ..))?;
+        }
+        if let (Some(dataset), Some(windows)) = (&self.windows, &buffered.windows) {
+            dataset.write_slice(
+                windows,
+                (self.current_event..self.current_event + count, ..),
+            )?;
+        }
+        if let (Some(dataset), Some(crcs)) = (&self.waveform_crc32, &buffered.waveform_crc32) {
+            dataset.write_slice(crcs, (self.current_event..self.current_event + count, ..))?;
+        }
+        if let (Some(dataset), Some(offsets)) = (&self.roi_offsets, &buffered.roi_offsets) {
+            dataset.write_slice(
+                offsets,
+                (self.current_event..self.current_event + count, ..),
+            )?;
+        }
+        if let (Some(dataset), Some(baselines)) = (&self.baselines, &buffered.baselines) {
+            dataset.write_slice(
+                baselines,
+                (self.current_event..self.current_event + count, ..),
+            )?;
+        }
+        let copies = [
+            (&mut self.full_res, &buffered.full_res),
+            (&mut self.unsuppressed, &buffered.unsuppressed),
+        ];
+        for (copies, buffered) in copies {
+            if let (Some(copies), Some((wf, rows))) = (copies, buffered) {
+                copies.write(wf, rows, self.current_event)?;
+                copies.current += rows.len();
+            }
+        }
         self.current_event += count;
         Ok(())
     }